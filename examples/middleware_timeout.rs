@@ -1,4 +1,3 @@
-use std::convert::Infallible;
 use std::error::Error;
 use std::future::Future;
 use std::net::SocketAddr;
@@ -26,7 +25,7 @@ async fn main() {
         .unwrap();
 }
 
-async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+async fn index(_req: Request<Body>) -> keiro::Result<Response<Body>> {
     sleep(tokio::time::Duration::from_secs(5)).await;
     Ok(Response::new(Body::from("Hello keiro!")))
 }
@@ -0,0 +1,240 @@
+//! Access logs in the Apache common and combined formats or as JSON lines.
+//!
+//! With an [`AccessLog`] set with [`Router::access_log`](crate::Router::access_log), a line is
+//! passed to its sink for every response once its body is finished, or abandoned by the
+//! client. The sink writes it wherever the application keeps its logs, e.g. to a file or a
+//! logging library.
+//! The Apache formats can be read by the usual log tools; JSON lines also have the latency,
+//! the matched route and the [request id](crate::request_id):
+//!
+//! ```text
+//! 203.0.113.7 - - [15/Oct/2026:01:18:43 +0000] "GET /users/42 HTTP/1.1" 200 512
+//! {"bytes":512,"latency_ms":1.873,"method":"GET","referer":null,"remote_addr":"203.0.113.7","request_id":"01M4YJ7R1M8D3T5N2QH6V0XKZB","route":"/users/:id","status":200,"time":"2026-10-15T01:18:43Z","uri":"/users/42","user_agent":"curl/8.5.0","version":"HTTP/1.1"}
+//! ```
+//!
+//! The remote address is the one of the client behind [trusted proxies](crate::forwarded), or
+//! else of the connection. Bytes count the response body as sent, after compression.
+//!
+//! Requires the `access-log` feature.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::access_log::{AccessLog, LogFormat};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.access_log(AccessLog::new(LogFormat::Combined, |line| eprintln!("{}", line)));
+//! router.get("/", index);
+//!
+//! async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("Hello world!")))
+//! }
+//! ```
+
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+
+use futures_util::StreamExt;
+use hyper::body::HttpBody;
+use hyper::header::{CONTENT_LENGTH, REFERER, USER_AGENT};
+use hyper::{Body, Request, Response, Version};
+
+use crate::ip_filter;
+use crate::request_id::RequestId;
+use crate::RouteSlot;
+
+/// The format of access log lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// The Apache common log format.
+    Common,
+    /// The Apache combined log format, which adds the referer and user agent.
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Passes a line for every request to a sink.
+#[derive(Clone)]
+pub struct AccessLog {
+    format: LogFormat,
+    sink: Arc<dyn Fn(&str) + Send + Sync>,
+}
+
+impl AccessLog {
+    /// Pass lines in `format`, without line breaks, to `sink`.
+    pub fn new(format: LogFormat, sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        Self {
+            format,
+            sink: Arc::new(sink),
+        }
+    }
+
+    /// Start the entry of `req`.
+    pub(crate) fn start(&self, req: &mut Request<Body>) -> Entry {
+        let route = RouteSlot::of(req);
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .map(|value| String::from_utf8_lossy(value.as_bytes()).into_owned())
+        };
+        Entry {
+            log: self.clone(),
+            started: Instant::now(),
+            time: SystemTime::now(),
+            remote_addr: ip_filter::client_ip(req),
+            method: req.method().to_string(),
+            uri: req
+                .uri()
+                .path_and_query()
+                .map_or_else(|| req.uri().path().to_string(), |pq| pq.to_string()),
+            version: req.version(),
+            referer: header(REFERER),
+            user_agent: header(USER_AGENT),
+            request_id: req.extensions().get::<RequestId>().cloned(),
+            route,
+            status: 0,
+            bytes: 0,
+        }
+    }
+}
+
+impl fmt::Debug for AccessLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The entry of a request, written when dropped, i.e. when the response body is finished or
+/// abandoned.
+pub(crate) struct Entry {
+    log: AccessLog,
+    started: Instant,
+    time: SystemTime,
+    remote_addr: Option<IpAddr>,
+    method: String,
+    uri: String,
+    version: Version,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    request_id: Option<RequestId>,
+    route: RouteSlot,
+    status: u16,
+    bytes: u64,
+}
+
+impl Entry {
+    /// Count the body of `res` into the entry, writing it once the body is done.
+    pub(crate) fn finish(mut self, res: Response<Body>) -> Response<Body> {
+        self.status = res.status().as_u16();
+        let (mut parts, body) = res.into_parts();
+        if let Some(length) = body.size_hint().exact() {
+            // Keep the response framed with `Content-Length` after wrapping the body.
+            parts.headers.entry(CONTENT_LENGTH).or_insert(length.into());
+        }
+        let body = body.inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                self.count(chunk.len());
+            }
+        });
+        Response::from_parts(parts, Body::wrap_stream(body))
+    }
+
+    fn count(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+
+    fn line(&self) -> String {
+        let remote_addr = self.remote_addr.map(|addr| addr.to_string());
+        let remote_addr = remote_addr.as_deref().unwrap_or("-");
+        // E.g. `Thu, 15 Oct 2026 01:18:43 GMT`.
+        let date = httpdate::fmt_http_date(self.time);
+        let fields: Vec<&str> = date.split(' ').collect();
+        let (day, month, year, time) = (fields[1], fields[2], fields[3], fields[4]);
+        match self.log.format {
+            LogFormat::Common | LogFormat::Combined => {
+                let bytes = match self.bytes {
+                    0 => "-".to_string(),
+                    bytes => bytes.to_string(),
+                };
+                let mut line = format!(
+                    "{} - - [{}/{}/{}:{} +0000] \"{} {} {:?}\" {} {}",
+                    remote_addr,
+                    day,
+                    month,
+                    year,
+                    time,
+                    escape(&self.method),
+                    escape(&self.uri),
+                    self.version,
+                    self.status,
+                    bytes
+                );
+                if self.log.format == LogFormat::Combined {
+                    let quoted = |value: &Option<String>| match value {
+                        Some(value) => format!("\"{}\"", escape(value)),
+                        None => "\"-\"".to_string(),
+                    };
+                    line.push(' ');
+                    line.push_str(&quoted(&self.referer));
+                    line.push(' ');
+                    line.push_str(&quoted(&self.user_agent));
+                }
+                line
+            }
+            LogFormat::Json => {
+                let month = MONTHS.iter().position(|m| *m == month).unwrap_or(0) + 1;
+                serde_json::json!({
+                    "time": format!("{}-{:02}-{}T{}Z", year, month, day, time),
+                    "remote_addr": self.remote_addr.map(|addr| addr.to_string()),
+                    "method": self.method,
+                    "uri": self.uri,
+                    "version": format!("{:?}", self.version),
+                    "status": self.status,
+                    "bytes": self.bytes,
+                    "latency_ms": self.started.elapsed().as_secs_f64() * 1000.0,
+                    "route": self.route.get(),
+                    "request_id": self.request_id.as_ref().map(RequestId::as_str),
+                    "referer": self.referer,
+                    "user_agent": self.user_agent,
+                })
+                .to_string()
+            }
+        }
+    }
+}
+
+impl Drop for Entry {
+    fn drop(&mut self) {
+        // Requests dropped before their response, e.g. by clients going away, have no status.
+        if self.status != 0 {
+            (self.log.sink)(&self.line());
+        }
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Escape quotes, backslashes and control characters, so clients can't forge lines.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,337 @@
+//! Byte accounting for usage-billed services.
+//!
+//! [`Accounting`] wraps a service and reports the bytes of every request and response: body
+//! bytes are counted as they are actually read and written, and heads are counted as they are
+//! serialized in HTTP/1.1. [`CountingIncoming`] wraps the listener and reports the exact
+//! bytes read from and written to each connection, including framing and anything sent by
+//! hyper itself.
+//!
+//! Bodies are counted as they pass through, so streamed responses are never buffered.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::net::SocketAddr;
+//!
+//! use hyper::server::conn::AddrIncoming;
+//! use hyper::{Body, Request, Response, Server};
+//! use keiro::accounting::{Accounting, CountingIncoming};
+//! use keiro::Router;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let mut router = Router::new();
+//!     router.get("/", index);
+//!     let svc = keiro::RouterService::new(router);
+//!     let svc = Accounting::new(svc, |usage| {
+//!         println!(
+//!             "{} {}: {} bytes in, {} bytes out",
+//!             usage.method, usage.path, usage.request_bytes, usage.response_bytes
+//!         );
+//!     });
+//!
+//!     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+//!     let incoming = CountingIncoming::new(AddrIncoming::bind(&addr).unwrap(), |usage| {
+//!         println!("connection: {} bytes in, {} bytes out", usage.read, usage.written);
+//!     });
+//!
+//!     Server::builder(incoming)
+//!         .serve(keiro::MakeRouterService { inner: svc })
+//!         .await
+//!         .unwrap();
+//! }
+//!
+//! async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("Hello keiro!")))
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_util::StreamExt;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, CONTENT_LENGTH};
+use hyper::server::accept::Accept;
+use hyper::service::Service;
+use hyper::{Body, Method, Request, Response};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// The bytes of a request and its response.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Usage {
+    pub method: Method,
+    pub path: String,
+    /// Bytes of the request head and the request body read by the handler.
+    pub request_bytes: u64,
+    /// Bytes of the response head and the response body written to the client.
+    pub response_bytes: u64,
+}
+
+/// A service reporting the [`Usage`] of every request once its response is finished.
+#[derive(Clone)]
+pub struct Accounting<Svc> {
+    inner: Svc,
+    report: Arc<dyn Fn(Usage) + Send + Sync>,
+}
+
+impl<Svc> Accounting<Svc> {
+    pub fn new<F>(inner: Svc, report: F) -> Self
+    where
+        F: Fn(Usage) + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            report: Arc::new(report),
+        }
+    }
+}
+
+impl<Svc> Service<Request<Body>> for Accounting<Svc>
+where
+    Svc: Service<Request<Body>, Response = Response<Body>>,
+    Svc::Error: Into<Box<dyn Error + Send + Sync>>,
+    Svc::Future: Send + Sync + 'static,
+{
+    type Response = Response<Body>;
+    type Error = Box<dyn Error + Send + Sync>;
+    #[allow(clippy::type_complexity)]
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + Sync>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let request_bytes = Arc::new(AtomicU64::new(request_head_size(&req)));
+        let mut report = Report {
+            usage: Some(Usage {
+                method: req.method().clone(),
+                path: req.uri().path().to_string(),
+                request_bytes: 0,
+                response_bytes: 0,
+            }),
+            request_bytes: request_bytes.clone(),
+            report: self.report.clone(),
+        };
+
+        let (parts, body) = req.into_parts();
+        let body = count(body, move |n| {
+            request_bytes.fetch_add(n, Ordering::Relaxed);
+        });
+        let fut = self.inner.call(Request::from_parts(parts, body));
+
+        Box::pin(async move {
+            let res = fut.await.map_err(Into::into)?;
+            let (mut parts, body) = res.into_parts();
+            if let Some(length) = body.size_hint().exact() {
+                // Keep the response framed with `Content-Length` after wrapping the body.
+                parts.headers.entry(CONTENT_LENGTH).or_insert(length.into());
+            }
+            if let Some(usage) = report.usage.as_mut() {
+                usage.response_bytes = response_head_size(&parts.status, &parts.headers);
+            }
+            let body = count(body, move |n| {
+                if let Some(usage) = report.usage.as_mut() {
+                    usage.response_bytes += n;
+                }
+            });
+            Ok(Response::from_parts(parts, body))
+        })
+    }
+}
+
+/// Reports the usage when dropped, i.e. when the response body is finished or abandoned.
+struct Report {
+    usage: Option<Usage>,
+    request_bytes: Arc<AtomicU64>,
+    report: Arc<dyn Fn(Usage) + Send + Sync>,
+}
+
+impl Drop for Report {
+    fn drop(&mut self) {
+        if let Some(mut usage) = self.usage.take() {
+            usage.request_bytes += self.request_bytes.load(Ordering::Relaxed);
+            (self.report)(usage);
+        }
+    }
+}
+
+fn count<F>(body: Body, mut on_data: F) -> Body
+where
+    F: FnMut(u64) + Send + 'static,
+{
+    Body::wrap_stream(body.inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            on_data(chunk.len() as u64);
+        }
+    }))
+}
+
+fn headers_size(headers: &HeaderMap) -> u64 {
+    headers
+        .iter()
+        .map(|(name, value)| (name.as_str().len() + value.len() + 4) as u64)
+        .sum()
+}
+
+fn request_head_size(req: &Request<Body>) -> u64 {
+    let target = req
+        .uri()
+        .path_and_query()
+        .map_or(req.uri().path().len(), |pq| pq.as_str().len());
+    // "METHOD target HTTP/1.1\r\n", headers and the final "\r\n".
+    (req.method().as_str().len() + target + 12) as u64 + headers_size(req.headers()) + 2
+}
+
+fn response_head_size(status: &hyper::StatusCode, headers: &HeaderMap) -> u64 {
+    let reason = status.canonical_reason().unwrap_or("").len();
+    // "HTTP/1.1 200 reason\r\n", headers and the final "\r\n".
+    (15 + reason) as u64 + headers_size(headers) + 2
+}
+
+/// The bytes read from and written to a connection.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct ConnectionUsage {
+    pub read: u64,
+    pub written: u64,
+}
+
+/// An [`Accept`] wrapper counting the bytes of every accepted connection. The usage is
+/// reported when the connection is closed.
+pub struct CountingIncoming<A> {
+    inner: A,
+    report: Arc<dyn Fn(ConnectionUsage) + Send + Sync>,
+}
+
+impl<A> CountingIncoming<A> {
+    pub fn new<F>(inner: A, report: F) -> Self
+    where
+        F: Fn(ConnectionUsage) + Send + Sync + 'static,
+    {
+        Self {
+            inner,
+            report: Arc::new(report),
+        }
+    }
+}
+
+impl<A> Accept for CountingIncoming<A>
+where
+    A: Accept + Unpin,
+{
+    type Conn = CountingStream<A::Conn>;
+    type Error = A::Error;
+
+    fn poll_accept(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let report = self.report.clone();
+        Pin::new(&mut self.inner)
+            .poll_accept(cx)
+            .map(|conn| conn.map(|conn| conn.map(|io| CountingStream::new(io, report))))
+    }
+}
+
+/// A connection counting the bytes read and written through it.
+pub struct CountingStream<IO> {
+    inner: IO,
+    usage: ConnectionUsage,
+    report: Arc<dyn Fn(ConnectionUsage) + Send + Sync>,
+}
+
+impl<IO> CountingStream<IO> {
+    fn new(inner: IO, report: Arc<dyn Fn(ConnectionUsage) + Send + Sync>) -> Self {
+        Self {
+            inner,
+            usage: ConnectionUsage {
+                read: 0,
+                written: 0,
+            },
+            report,
+        }
+    }
+
+    /// Get the wrapped connection.
+    pub fn get_ref(&self) -> &IO {
+        &self.inner
+    }
+
+    /// Get the bytes read and written so far.
+    pub fn usage(&self) -> ConnectionUsage {
+        self.usage
+    }
+}
+
+impl<IO> fmt::Debug for CountingStream<IO> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CountingStream")
+            .field("usage", &self.usage)
+            .finish()
+    }
+}
+
+impl<IO> Drop for CountingStream<IO> {
+    fn drop(&mut self) {
+        (self.report)(self.usage);
+    }
+}
+
+impl<IO: AsyncRead + Unpin> AsyncRead for CountingStream<IO> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        self.usage.read += (buf.filled().len() - before) as u64;
+        poll
+    }
+}
+
+impl<IO: AsyncWrite + Unpin> AsyncWrite for CountingStream<IO> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.usage.written += n as u64;
+        }
+        poll
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.usage.written += n as u64;
+        }
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
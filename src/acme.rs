@@ -0,0 +1,861 @@
+//! Automatic certificates from ACME certificate authorities, such as Let's Encrypt.
+//!
+//! An [`Acme`] provisions a certificate for its domains, keeps it in a
+//! [`Storage`](crate::storage::Storage) so restarts don't request new ones, and renews it
+//! before it expires. Domains are validated with HTTP-01 challenges: the key authorizations are
+//! put into a [`ChallengeStore`], which the router serves at
+//! `/.well-known/acme-challenge/:token` once it's registered with
+//! [`WellKnown::acme_challenges`](crate::well_known::WellKnown::acme_challenges), so the router
+//! must be reachable on port 80 of every domain.
+//!
+//! TLS listeners get the current certificate from [`Acme::resolver`], which swaps in renewed
+//! certificates without a restart. Requires the `acme` feature.
+//!
+//! Services only reachable on port 443 validate their domains with TLS-ALPN-01 challenges
+//! instead, by creating the [`Acme`] with [`Acme::with_tls_alpn`] and serving with
+//! [`ServerTls::from_acme`](crate::server::ServerTls::from_acme), which answers the challenge
+//! handshakes with the certificates of the resolver:
+//!
+//! ```rust,no_run
+//! use keiro::acme::Acme;
+//! use keiro::server::{ServerBuilder, ServerTls};
+//! use keiro::storage::LocalStorage;
+//! use keiro::Router;
+//!
+//! # async fn run() -> std::io::Result<()> {
+//! let acme = Acme::with_tls_alpn(&["example.com"], LocalStorage::new("/var/lib/example/certs"))
+//!     .contact("mailto:admin@example.com");
+//! let tls = ServerTls::from_acme(&acme);
+//! tokio::spawn(acme.run());
+//!
+//! let router = Router::new();
+//! let server = ServerBuilder::new()
+//!     .bind(([0, 0, 0, 0], 443))
+//!     .tls(tls)
+//!     .serve(router)
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ```rust,no_run
+//! use keiro::acme::{Acme, LETS_ENCRYPT_STAGING};
+//! use keiro::storage::LocalStorage;
+//! use keiro::well_known::{MemoryChallenges, WellKnown};
+//! use keiro::Router;
+//!
+//! # async fn run() {
+//! let challenges = MemoryChallenges::new();
+//! let acme = Acme::new(
+//!     &["example.com", "www.example.com"],
+//!     LocalStorage::new("/var/lib/example/certs"),
+//!     challenges.clone(),
+//! )
+//! .contact("mailto:admin@example.com")
+//! .directory(LETS_ENCRYPT_STAGING)
+//! .on_error(|err| eprintln!("certificate renewal failed: {}", err));
+//!
+//! let mut router = Router::new();
+//! router.well_known(WellKnown::new().acme_challenges(challenges));
+//!
+//! let tls = rustls::ServerConfig::builder()
+//!     .with_safe_defaults()
+//!     .with_no_client_auth()
+//!     .with_cert_resolver(acme.resolver());
+//! tokio::spawn(acme.run());
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hyper::body::Bytes;
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderMap, CONTENT_TYPE, LOCATION, RETRY_AFTER};
+use hyper::{Body, Client, Method, Request, StatusCode};
+use hyper_rustls::HttpsConnector;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use serde_json::{json, Value};
+
+use crate::proxy::UpstreamTls;
+use crate::storage::Storage;
+use crate::well_known::ChallengeStore;
+
+/// The directory of Let's Encrypt.
+pub const LETS_ENCRYPT: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// The directory of the Let's Encrypt staging environment, which has much higher rate limits
+/// but issues untrusted certificates. Use it while setting up.
+pub const LETS_ENCRYPT_STAGING: &str = "https://acme-staging-v02.api.letsencrypt.org/directory";
+
+/// The ALPN protocol of TLS-ALPN-01 challenge handshakes.
+pub(crate) const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+const ACCOUNT_KEY: &str = "acme/account.der";
+const ATTEMPTS: usize = 30;
+
+/// A certificate for a set of domains, provisioned and renewed automatically.
+pub struct Acme {
+    domains: Vec<String>,
+    contacts: Vec<String>,
+    directory: String,
+    storage: Arc<dyn Storage>,
+    /// Where HTTP-01 challenges are put, or `None` to answer TLS-ALPN-01 challenges.
+    challenges: Option<Arc<dyn ChallengeStore>>,
+    tls: UpstreamTls,
+    renew_before: Duration,
+    retry_interval: Duration,
+    #[allow(clippy::type_complexity)]
+    on_error: Option<Box<dyn Fn(&AcmeError) + Send + Sync>>,
+    resolver: Arc<CertResolver>,
+}
+
+impl Acme {
+    /// Provision a certificate for `domains` from Let's Encrypt, persisted in `storage`, and
+    /// put the HTTP-01 challenges to answer into `challenges`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `domains` is empty.
+    pub fn new(domains: &[&str], storage: impl Storage, challenges: impl ChallengeStore) -> Self {
+        Self::with_challenges(domains, storage, Some(Arc::new(challenges)))
+    }
+
+    /// Provision a certificate for `domains` from Let's Encrypt, persisted in `storage`, and
+    /// answer TLS-ALPN-01 challenges with the [resolver](Acme::resolver), so only port 443
+    /// has to be reachable.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `domains` is empty.
+    pub fn with_tls_alpn(domains: &[&str], storage: impl Storage) -> Self {
+        Self::with_challenges(domains, storage, None)
+    }
+
+    fn with_challenges(
+        domains: &[&str],
+        storage: impl Storage,
+        challenges: Option<Arc<dyn ChallengeStore>>,
+    ) -> Self {
+        assert!(!domains.is_empty(), "no domains");
+        Self {
+            domains: domains.iter().map(|domain| domain.to_string()).collect(),
+            contacts: Vec::new(),
+            directory: LETS_ENCRYPT.to_string(),
+            storage: Arc::new(storage),
+            challenges,
+            tls: UpstreamTls::new(),
+            renew_before: Duration::from_secs(30 * 24 * 60 * 60),
+            retry_interval: Duration::from_secs(60 * 60),
+            on_error: None,
+            resolver: Arc::new(CertResolver::default()),
+        }
+    }
+
+    /// Register the account with `contact`, such as `mailto:admin@example.com`, so the
+    /// certificate authority can send expiry notices.
+    pub fn contact(mut self, contact: impl Into<String>) -> Self {
+        self.contacts.push(contact.into());
+        self
+    }
+
+    /// Use the ACME server with the directory at `url`, instead of Let's Encrypt.
+    pub fn directory(mut self, url: impl Into<String>) -> Self {
+        self.directory = url.into();
+        self
+    }
+
+    /// Connect to the ACME server with `tls`, e.g. to trust the CA of a private ACME server.
+    pub fn tls(mut self, tls: UpstreamTls) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Renew the certificate when it expires within `duration`. Defaults to 30 days.
+    pub fn renew_before(mut self, duration: Duration) -> Self {
+        self.renew_before = duration;
+        self
+    }
+
+    /// Retry after `interval` when provisioning a certificate fails. Defaults to one hour.
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Call `on_error` when provisioning a certificate fails in [`Acme::run`].
+    pub fn on_error(mut self, on_error: impl Fn(&AcmeError) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(Box::new(on_error));
+        self
+    }
+
+    /// Get the resolver serving the current certificate to TLS listeners. It rejects
+    /// handshakes until the first certificate is loaded.
+    ///
+    /// With [TLS-ALPN-01](Acme::with_tls_alpn), it also answers the challenge handshakes,
+    /// which requires the listeners to offer the `acme-tls/1` protocol with ALPN.
+    pub fn resolver(&self) -> Arc<CertResolver> {
+        self.resolver.clone()
+    }
+
+    /// Keep the certificate up to date, forever.
+    pub async fn run(self) {
+        loop {
+            let wait = match self.refresh().await {
+                Ok(expires) => {
+                    let renew_at = expires - self.renew_before;
+                    let wait = renew_at
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default();
+                    // Check at least daily, so clock jumps are noticed.
+                    wait.min(Duration::from_secs(24 * 60 * 60))
+                }
+                Err(err) => {
+                    if let Some(on_error) = &self.on_error {
+                        on_error(&err);
+                    }
+                    self.retry_interval
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Load the stored certificate, or provision a new one if there is none or it expires
+    /// soon, and return when it expires.
+    pub async fn refresh(&self) -> Result<SystemTime, AcmeError> {
+        if let Some(cert) = self.load().await? {
+            if cert.expires > SystemTime::now() + self.renew_before {
+                let expires = cert.expires;
+                self.resolver.set(cert.key);
+                return Ok(expires);
+            }
+        }
+        let (chain, key) = self.provision().await?;
+        let cert = parse(&chain, key.as_bytes())?;
+        self.put(&self.cert_key(), chain.into_bytes()).await?;
+        self.put(&self.private_key_key(), key.into_bytes()).await?;
+        let expires = cert.expires;
+        self.resolver.set(cert.key);
+        Ok(expires)
+    }
+
+    async fn load(&self) -> Result<Option<Cert>, AcmeError> {
+        let chain = self.get(&self.cert_key()).await?;
+        let key = self.get(&self.private_key_key()).await?;
+        match (chain, key) {
+            (Some(chain), Some(key)) => {
+                let chain = String::from_utf8(chain.to_vec())
+                    .map_err(|_| AcmeError::Certificate("stored chain is not PEM".to_string()))?;
+                parse(&chain, &key).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Order a new certificate, returning the PEM encoded chain and private key.
+    async fn provision(&self) -> Result<(String, String), AcmeError> {
+        let key = match self.get(ACCOUNT_KEY).await? {
+            Some(key) => key,
+            None => {
+                let key = EcdsaKeyPair::generate_pkcs8(
+                    &ECDSA_P256_SHA256_FIXED_SIGNING,
+                    &SystemRandom::new(),
+                )
+                .map_err(|_| AcmeError::Certificate("generating account key".to_string()))?;
+                let key = Bytes::copy_from_slice(key.as_ref());
+                self.put(ACCOUNT_KEY, key.clone()).await?;
+                key
+            }
+        };
+        let mut session = Session::new(self, &key).await?;
+        session.register(&self.contacts).await?;
+        let identifiers = self
+            .domains
+            .iter()
+            .map(|domain| json!({ "type": "dns", "value": domain }))
+            .collect::<Vec<_>>();
+        let new_order = session.directory("newOrder")?;
+        let (headers, order) = session
+            .post(&new_order, Some(json!({ "identifiers": identifiers })))
+            .await?;
+        let order_url = location(&headers)?;
+        let order = json(&order)?;
+
+        let validation = match &self.challenges {
+            Some(challenges) => Validation::Http(&**challenges),
+            None => Validation::TlsAlpn(&self.resolver),
+        };
+        for authorization in order["authorizations"].as_array().into_iter().flatten() {
+            let url = authorization.as_str().unwrap_or_default().to_string();
+            session.authorize(&url, &validation).await?;
+        }
+
+        let (csr, private_key) = csr(&self.domains)?;
+        let finalize = order["finalize"].as_str().unwrap_or_default().to_string();
+        session
+            .post(
+                &finalize,
+                Some(json!({ "csr": URL_SAFE_NO_PAD.encode(csr) })),
+            )
+            .await?;
+        let order = session
+            .poll(&order_url, |order| order["status"] != "processing")
+            .await?;
+        if order["status"] != "valid" {
+            return Err(problem(&order, "order"));
+        }
+        let certificate = order["certificate"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let (_, chain) = session.post(&certificate, None).await?;
+        let chain = String::from_utf8(chain.to_vec())
+            .map_err(|_| AcmeError::Certificate("chain is not PEM".to_string()))?;
+        Ok((chain, private_key))
+    }
+
+    fn cert_key(&self) -> String {
+        format!("acme/{}/cert.pem", self.domains[0])
+    }
+
+    fn private_key_key(&self) -> String {
+        format!("acme/{}/key.pem", self.domains[0])
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Bytes>, AcmeError> {
+        match self.storage.get(key).await? {
+            Some(object) => Ok(Some(hyper::body::to_bytes(object.body).await?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn put(&self, key: &str, value: impl Into<Bytes>) -> Result<(), AcmeError> {
+        let value = value.into();
+        let length = value.len() as u64;
+        self.storage.put(key, Body::from(value), length).await?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Acme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Acme")
+            .field("domains", &self.domains)
+            .field("directory", &self.directory)
+            .finish()
+    }
+}
+
+/// Serves the current certificate of an [`Acme`] to TLS listeners.
+#[derive(Default)]
+pub struct CertResolver {
+    current: RwLock<Option<Arc<CertifiedKey>>>,
+    /// The certificates answering pending TLS-ALPN-01 challenges, by domain.
+    challenges: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+}
+
+impl CertResolver {
+    fn set(&self, key: Arc<CertifiedKey>) {
+        match self.current.write() {
+            Ok(mut current) => *current = Some(key),
+            Err(poisoned) => *poisoned.into_inner() = Some(key),
+        }
+    }
+
+    fn current(&self) -> Option<Arc<CertifiedKey>> {
+        match self.current.read() {
+            Ok(current) => current.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    fn set_challenge(&self, domain: &str, key: Option<Arc<CertifiedKey>>) {
+        let mut challenges = match self.challenges.write() {
+            Ok(challenges) => challenges,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match key {
+            Some(key) => challenges.insert(domain.to_ascii_lowercase(), key),
+            None => challenges.remove(&domain.to_ascii_lowercase()),
+        };
+    }
+
+    fn challenge(&self, domain: &str) -> Option<Arc<CertifiedKey>> {
+        let challenges = match self.challenges.read() {
+            Ok(challenges) => challenges,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        challenges.get(&domain.to_ascii_lowercase()).cloned()
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let is_challenge = client_hello
+            .alpn()
+            .is_some_and(|mut protocols| protocols.any(|protocol| protocol == ACME_TLS_ALPN));
+        if is_challenge {
+            // Challenge handshakes only get challenge certificates.
+            return self.challenge(client_hello.server_name()?);
+        }
+        self.current()
+    }
+}
+
+impl fmt::Debug for CertResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CertResolver")
+            .field("loaded", &self.current().is_some())
+            .finish()
+    }
+}
+
+/// An error while provisioning a certificate.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AcmeError {
+    /// Reading or writing the storage failed.
+    Io(io::Error),
+    /// The ACME server couldn't be reached.
+    Http(hyper::Error),
+    /// The ACME server rejected a request, with an error of `kind` such as
+    /// `urn:ietf:params:acme:error:rateLimited`.
+    Rejected { kind: String, detail: String },
+    /// The ACME server answered with something unexpected.
+    Protocol(String),
+    /// Creating or loading the certificate failed.
+    Certificate(String),
+}
+
+impl fmt::Display for AcmeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AcmeError::Io(err) => write!(f, "certificate storage failed: {}", err),
+            AcmeError::Http(err) => write!(f, "ACME request failed: {}", err),
+            AcmeError::Rejected { kind, detail } => {
+                write!(f, "ACME server rejected the request ({}): {}", kind, detail)
+            }
+            AcmeError::Protocol(message) => write!(f, "unexpected ACME response: {}", message),
+            AcmeError::Certificate(message) => write!(f, "invalid certificate: {}", message),
+        }
+    }
+}
+
+impl Error for AcmeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            AcmeError::Io(err) => Some(err),
+            AcmeError::Http(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AcmeError {
+    fn from(err: io::Error) -> Self {
+        AcmeError::Io(err)
+    }
+}
+
+impl From<hyper::Error> for AcmeError {
+    fn from(err: hyper::Error) -> Self {
+        AcmeError::Http(err)
+    }
+}
+
+/// How domains are validated.
+enum Validation<'a> {
+    /// HTTP-01, answered from a challenge store.
+    Http(&'a dyn ChallengeStore),
+    /// TLS-ALPN-01, answered by the resolver of the certificate.
+    TlsAlpn(&'a CertResolver),
+}
+
+impl Validation<'_> {
+    fn kind(&self) -> &'static str {
+        match self {
+            Validation::Http(_) => "http-01",
+            Validation::TlsAlpn(_) => "tls-alpn-01",
+        }
+    }
+}
+
+/// A conversation with the ACME server, signed with the account key.
+struct Session {
+    client: Client<HttpsConnector<HttpConnector>>,
+    directory: Value,
+    key: EcdsaKeyPair,
+    jwk: Value,
+    account: Option<String>,
+    nonce: Option<String>,
+}
+
+impl Session {
+    async fn new(acme: &Acme, key: &[u8]) -> Result<Self, AcmeError> {
+        let rng = SystemRandom::new();
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, key, &rng)
+            .map_err(|_| AcmeError::Certificate("invalid account key".to_string()))?;
+        // The public key is an uncompressed point: 0x04, then x and y.
+        let point = key.public_key().as_ref();
+        let jwk = json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+            "y": URL_SAFE_NO_PAD.encode(&point[33..]),
+        });
+        let client = Client::builder().build(acme.tls.connector());
+        let res = client.get(uri(&acme.directory)?).await?;
+        let (status, _, body) = read(res).await?;
+        if !status.is_success() {
+            return Err(AcmeError::Protocol(format!(
+                "directory answered {}",
+                status
+            )));
+        }
+        Ok(Self {
+            client,
+            directory: json(&body)?,
+            key,
+            jwk,
+            account: None,
+            nonce: None,
+        })
+    }
+
+    fn directory(&self, name: &str) -> Result<String, AcmeError> {
+        self.directory[name]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AcmeError::Protocol(format!("directory has no {}", name)))
+    }
+
+    async fn register(&mut self, contacts: &[String]) -> Result<(), AcmeError> {
+        let new_account = self.directory("newAccount")?;
+        let payload = json!({ "termsOfServiceAgreed": true, "contact": contacts });
+        let (headers, _) = self.post(&new_account, Some(payload)).await?;
+        self.account = Some(location(&headers)?);
+        Ok(())
+    }
+
+    /// Answer the challenge of the authorization at `url` as `validation` does.
+    async fn authorize(&mut self, url: &str, validation: &Validation<'_>) -> Result<(), AcmeError> {
+        let (_, authorization) = self.post(url, None).await?;
+        let authorization = json(&authorization)?;
+        if authorization["status"] == "valid" {
+            return Ok(());
+        }
+        let kind = validation.kind();
+        let challenge = authorization["challenges"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|challenge| challenge["type"] == kind)
+            .ok_or_else(|| AcmeError::Protocol(format!("no {} challenge offered", kind)))?;
+        let token = challenge["token"].as_str().unwrap_or_default().to_string();
+        let challenge_url = challenge["url"].as_str().unwrap_or_default().to_string();
+        let key_authorization = format!("{}.{}", token, self.thumbprint());
+        let domain = authorization["identifier"]["value"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+
+        match validation {
+            Validation::Http(store) => store.put(&token, &key_authorization).await?,
+            Validation::TlsAlpn(resolver) => {
+                let key = challenge_cert(&domain, &key_authorization)?;
+                resolver.set_challenge(&domain, Some(Arc::new(key)));
+            }
+        }
+        let result = async {
+            self.post(&challenge_url, Some(json!({}))).await?;
+            self.poll(url, |authorization| authorization["status"] != "pending")
+                .await
+        }
+        .await;
+        match validation {
+            Validation::Http(store) => store.remove(&token).await?,
+            Validation::TlsAlpn(resolver) => resolver.set_challenge(&domain, None),
+        }
+
+        let authorization = result?;
+        if authorization["status"] != "valid" {
+            let challenge = authorization["challenges"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .find(|challenge| challenge["type"] == kind)
+                .cloned()
+                .unwrap_or_default();
+            return Err(problem(&challenge["error"], "authorization"));
+        }
+        Ok(())
+    }
+
+    /// Fetch the object at `url` until `done` returns true for it.
+    async fn poll(&mut self, url: &str, done: impl Fn(&Value) -> bool) -> Result<Value, AcmeError> {
+        for _ in 0..ATTEMPTS {
+            let (headers, object) = self.post(url, None).await?;
+            let object = json(&object)?;
+            if done(&object) {
+                return Ok(object);
+            }
+            let wait = headers
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(2)
+                .min(60);
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+        }
+        Err(AcmeError::Protocol(format!("{} didn't finish", url)))
+    }
+
+    /// POST the signed `payload` to `url`, or a POST-as-GET without a payload. A request
+    /// rejected for its nonce is sent again.
+    async fn post(
+        &mut self,
+        url: &str,
+        payload: Option<Value>,
+    ) -> Result<(HeaderMap, Bytes), AcmeError> {
+        let mut retried = false;
+        loop {
+            let nonce = self.nonce().await?;
+            let body = self.sign(url, &nonce, payload.as_ref())?;
+            let req = Request::builder()
+                .method(Method::POST)
+                .uri(uri(url)?)
+                .header(CONTENT_TYPE, "application/jose+json")
+                .body(Body::from(body.to_string()))
+                .unwrap();
+            let (status, headers, body) = read(self.client.request(req).await?).await?;
+            self.nonce = headers
+                .get("replay-nonce")
+                .and_then(|nonce| nonce.to_str().ok())
+                .map(str::to_string);
+            if status.is_success() {
+                return Ok((headers, body));
+            }
+            let error = serde_json::from_slice(&body).unwrap_or(Value::Null);
+            if error["type"] == "urn:ietf:params:acme:error:badNonce" && !retried {
+                retried = true;
+                continue;
+            }
+            return Err(problem(&error, &status.to_string()));
+        }
+    }
+
+    async fn nonce(&mut self) -> Result<String, AcmeError> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let req = Request::builder()
+            .method(Method::HEAD)
+            .uri(uri(&self.directory("newNonce")?)?)
+            .body(Body::empty())
+            .unwrap();
+        let res = self.client.request(req).await?;
+        res.headers()
+            .get("replay-nonce")
+            .and_then(|nonce| nonce.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| AcmeError::Protocol("no nonce".to_string()))
+    }
+
+    /// Sign a request as a flattened JWS, identifying the account by its URL once registered.
+    fn sign(&self, url: &str, nonce: &str, payload: Option<&Value>) -> Result<Value, AcmeError> {
+        let protected = match &self.account {
+            Some(account) => json!({ "alg": "ES256", "kid": account, "nonce": nonce, "url": url }),
+            None => json!({ "alg": "ES256", "jwk": self.jwk, "nonce": nonce, "url": url }),
+        };
+        let protected = URL_SAFE_NO_PAD.encode(protected.to_string());
+        let payload = payload
+            .map(|payload| URL_SAFE_NO_PAD.encode(payload.to_string()))
+            .unwrap_or_default();
+        let signature = self
+            .key
+            .sign(
+                &SystemRandom::new(),
+                format!("{}.{}", protected, payload).as_bytes(),
+            )
+            .map_err(|_| AcmeError::Certificate("signing failed".to_string()))?;
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": URL_SAFE_NO_PAD.encode(signature.as_ref()),
+        }))
+    }
+
+    /// Get the JWK thumbprint of the account key, as defined by RFC 7638.
+    fn thumbprint(&self) -> String {
+        // `json!` sorts the members, which the thumbprint requires.
+        let digest = ring::digest::digest(&ring::digest::SHA256, self.jwk.to_string().as_bytes());
+        URL_SAFE_NO_PAD.encode(digest.as_ref())
+    }
+}
+
+/// A loaded certificate.
+struct Cert {
+    key: Arc<CertifiedKey>,
+    expires: SystemTime,
+}
+
+/// Parse the PEM encoded `chain` and private key.
+fn parse(chain: &str, key: &[u8]) -> Result<Cert, AcmeError> {
+    let invalid = |message: &str| AcmeError::Certificate(message.to_string());
+    let certs = rustls_pemfile::certs(&mut chain.as_bytes())?;
+    let first = certs.first().ok_or_else(|| invalid("no certificate"))?;
+    let expires = not_after(first).ok_or_else(|| invalid("no expiry"))?;
+    let key = rustls_pemfile::pkcs8_private_keys(&mut &*key)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| invalid("no private key"))?;
+    let key = rustls::sign::any_supported_type(&rustls::PrivateKey(key))
+        .map_err(|_| invalid("unsupported private key"))?;
+    let certs = certs.into_iter().map(rustls::Certificate).collect();
+    Ok(Cert {
+        key: Arc::new(CertifiedKey::new(certs, key)),
+        expires,
+    })
+}
+
+/// Create the self-signed certificate answering the TLS-ALPN-01 challenge for `domain`, as
+/// defined by RFC 8737.
+fn challenge_cert(domain: &str, key_authorization: &str) -> Result<CertifiedKey, AcmeError> {
+    let digest = ring::digest::digest(&ring::digest::SHA256, key_authorization.as_bytes());
+    let mut params = rcgen::CertificateParams::new(vec![domain.to_string()]);
+    params.custom_extensions = vec![rcgen::CustomExtension::new_acme_identifier(digest.as_ref())];
+    let cert = rcgen::Certificate::from_params(params)
+        .map_err(|err| AcmeError::Certificate(err.to_string()))?;
+    let der = cert
+        .serialize_der()
+        .map_err(|err| AcmeError::Certificate(err.to_string()))?;
+    let key =
+        rustls::sign::any_supported_type(&rustls::PrivateKey(cert.serialize_private_key_der()))
+            .map_err(|_| AcmeError::Certificate("unsupported private key".to_string()))?;
+    Ok(CertifiedKey::new(vec![rustls::Certificate(der)], key))
+}
+
+/// Create a CSR for `domains` with a new key, returning the DER encoded CSR and the PEM
+/// encoded key.
+fn csr(domains: &[String]) -> Result<(Vec<u8>, String), AcmeError> {
+    let mut params = rcgen::CertificateParams::new(domains.to_vec());
+    params.distinguished_name = rcgen::DistinguishedName::new();
+    let cert = rcgen::Certificate::from_params(params)
+        .map_err(|err| AcmeError::Certificate(err.to_string()))?;
+    let csr = cert
+        .serialize_request_der()
+        .map_err(|err| AcmeError::Certificate(err.to_string()))?;
+    Ok((csr, cert.serialize_private_key_pem()))
+}
+
+/// Read the `notAfter` time of the DER encoded certificate `cert`.
+fn not_after(cert: &[u8]) -> Option<SystemTime> {
+    // Certificate ::= SEQUENCE { tbsCertificate SEQUENCE { [0] version OPTIONAL,
+    // serialNumber, signature, issuer, validity SEQUENCE { notBefore, notAfter }, ... } }
+    let (_, cert, _) = der(cert)?;
+    let (_, mut tbs, _) = der(cert)?;
+    let (tag, _, rest) = der(tbs)?;
+    if tag == 0xa0 {
+        tbs = rest;
+    }
+    for _ in 0..3 {
+        tbs = der(tbs)?.2;
+    }
+    let (_, validity, _) = der(tbs)?;
+    let (_, _, validity) = der(validity)?;
+    let (tag, time, _) = der(validity)?;
+    let time = std::str::from_utf8(time).ok()?;
+    let time = match tag {
+        // UTCTime, YYMMDDHHMMSSZ, with years from 1950 to 2049.
+        0x17 => {
+            let year: i64 = time.get(..2)?.parse().ok()?;
+            let century = if year < 50 { "20" } else { "19" };
+            format!("{}{}", century, time)
+        }
+        // GeneralizedTime, YYYYMMDDHHMMSSZ.
+        0x18 => time.to_string(),
+        _ => return None,
+    };
+    let field = |range: std::ops::Range<usize>| time.get(range)?.parse::<i64>().ok();
+    let days = days_from_civil(field(0..4)?, field(4..6)?, field(6..8)?);
+    let secs = days * 86400 + field(8..10)? * 3600 + field(10..12)? * 60 + field(12..14)?;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(secs).ok()?))
+}
+
+/// Split the DER element at the start of `input` into its tag, contents and the rest.
+fn der(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *input.first()?;
+    let first = *input.get(1)? as usize;
+    let (length, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let bytes = first & 0x7f;
+        if bytes == 0 || bytes > 4 {
+            return None;
+        }
+        let length = input
+            .get(2..2 + bytes)?
+            .iter()
+            .fold(0, |length, b| (length << 8) | *b as usize);
+        (length, 2 + bytes)
+    };
+    let contents = input.get(header..header + length)?;
+    Some((tag, contents, &input[header + length..]))
+}
+
+/// Count the days from the epoch to a civil date, after Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+async fn read(res: hyper::Response<Body>) -> Result<(StatusCode, HeaderMap, Bytes), AcmeError> {
+    let (parts, body) = res.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    Ok((parts.status, parts.headers, body))
+}
+
+fn uri(url: &str) -> Result<hyper::Uri, AcmeError> {
+    url.parse()
+        .map_err(|_| AcmeError::Protocol(format!("invalid URL {:?}", url)))
+}
+
+fn json(body: &[u8]) -> Result<Value, AcmeError> {
+    serde_json::from_slice(body).map_err(|err| AcmeError::Protocol(err.to_string()))
+}
+
+fn location(headers: &HeaderMap) -> Result<String, AcmeError> {
+    headers
+        .get(LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .map(str::to_string)
+        .ok_or_else(|| AcmeError::Protocol("no Location header".to_string()))
+}
+
+/// Turn the problem document `error` into an error, describing it with `context` if it's not
+/// a problem document.
+fn problem(error: &Value, context: &str) -> AcmeError {
+    match (error["type"].as_str(), error["detail"].as_str()) {
+        (Some(kind), detail) => AcmeError::Rejected {
+            kind: kind.to_string(),
+            detail: detail.unwrap_or_default().to_string(),
+        },
+        (None, _) => AcmeError::Protocol(format!("{} failed", context)),
+    }
+}
@@ -0,0 +1,182 @@
+//! API key authentication.
+//!
+//! An [`ApiKey`] set on a route with [`Route::api_key`](crate::Route::api_key), or on all
+//! routes of a router with [`Router::api_key`](crate::Router::api_key), reads the key of each
+//! request from a header, `X-Api-Key` by default, or a query parameter, and resolves it to a
+//! client with a [`KeyValidator`]. Requests without a key the validator accepts are answered
+//! with `401 Unauthorized`; handlers find the resolved client in the request extensions.
+//!
+//! Validators are asynchronous, so keys can be looked up in a database or another service.
+//! Closures taking the key and returning a future of an `Option` are validators, as are
+//! `HashMap`s from keys to clients. Validators resolving keys to a
+//! [`Principal`](crate::authz::Principal) grant its scopes for [`authz`](crate::authz).
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::api_key::ApiKey;
+//! use keiro::Router;
+//!
+//! #[derive(Clone)]
+//! struct Client {
+//!     name: String,
+//! }
+//!
+//! async fn lookup(key: String) -> Option<Client> {
+//!     // Look up the key in the database.
+//!     (key == "secret").then(|| Client {
+//!         name: "reporting".to_string(),
+//!     })
+//! }
+//!
+//! let mut router = Router::new();
+//! router.api_key(ApiKey::new(lookup).query("api_key"));
+//! router.get("/reports", reports);
+//!
+//! async fn reports(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let client = req.extensions().get::<Client>().unwrap();
+//!     Ok(Response::new(Body::from(format!("Reports for {}", client.name))))
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use hyper::header::HeaderName;
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::query::QueryPairs;
+use crate::route::GuardFuture;
+
+/// The future returned by [`KeyValidator::validate`].
+pub type ValidateFuture<'a, T> = Pin<Box<dyn Future<Output = Option<T>> + Send + 'a>>;
+
+/// Resolves API keys to the clients they belong to.
+pub trait KeyValidator: Send + Sync + 'static {
+    /// The identity of a client, added to the request extensions.
+    type Client: Clone + Send + Sync + 'static;
+
+    /// Resolve `key` to its client, or return `None` if it isn't valid.
+    fn validate(&self, key: &str) -> ValidateFuture<'_, Self::Client>;
+}
+
+impl<F, Fut, C> KeyValidator for F
+where
+    F: Fn(String) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Option<C>> + Send + 'static,
+    C: Clone + Send + Sync + 'static,
+{
+    type Client = C;
+
+    fn validate(&self, key: &str) -> ValidateFuture<'_, C> {
+        Box::pin(self(key.to_string()))
+    }
+}
+
+impl<C: Clone + Send + Sync + 'static> KeyValidator for HashMap<String, C> {
+    type Client = C;
+
+    fn validate(&self, key: &str) -> ValidateFuture<'_, C> {
+        let client = self.get(key).cloned();
+        Box::pin(async move { client })
+    }
+}
+
+/// Checks the API key of requests with a [`KeyValidator`].
+#[derive(Clone)]
+pub struct ApiKey {
+    header: Option<HeaderName>,
+    query: Option<String>,
+    #[allow(clippy::type_complexity)]
+    resolve: Arc<dyn Fn(Request<Body>, String) -> GuardFuture + Send + Sync>,
+}
+
+impl ApiKey {
+    /// Accept the keys `validator` resolves, read from the `X-Api-Key` header.
+    pub fn new<V: KeyValidator>(validator: V) -> Self {
+        let validator = Arc::new(validator);
+        Self {
+            header: Some(HeaderName::from_static("x-api-key")),
+            query: None,
+            resolve: Arc::new(move |mut req, key| {
+                let validator = validator.clone();
+                Box::pin(async move {
+                    match validator.validate(&key).await {
+                        Some(client) => {
+                            req.extensions_mut().insert(client);
+                            Ok(req)
+                        }
+                        None => Err(unauthorized()),
+                    }
+                })
+            }),
+        }
+    }
+
+    /// Read the key from the header `name` instead of `X-Api-Key`.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.header = Some(name);
+        self
+    }
+
+    /// Read the key from the query parameter `name` if the request has no key header.
+    ///
+    /// Keys in URLs end up in access logs and browser histories, so prefer headers where
+    /// clients can set them.
+    pub fn query(mut self, name: impl Into<String>) -> Self {
+        self.query = Some(name.into());
+        self
+    }
+
+    /// Only read the key from the query parameter set with [`ApiKey::query`], not a header.
+    pub fn no_header(mut self) -> Self {
+        self.header = None;
+        self
+    }
+
+    /// Check the key of `req`, giving the request back with the resolved client added.
+    pub(crate) fn check(&self, req: Request<Body>) -> GuardFuture {
+        match self.key(&req) {
+            Some(key) => (self.resolve)(req, key),
+            None => Box::pin(async { Err(unauthorized()) }),
+        }
+    }
+
+    fn key(&self, req: &Request<Body>) -> Option<String> {
+        let header = self
+            .header
+            .as_ref()
+            .and_then(|name| req.headers().get(name))
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|key| !key.is_empty());
+        if let Some(key) = header {
+            return Some(key.to_string());
+        }
+        let name = self.query.as_deref()?;
+        QueryPairs::from_uri(req)
+            .get(name)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ApiKey")
+            .field("header", &self.header)
+            .field("query", &self.query)
+            .finish()
+    }
+}
+
+fn unauthorized() -> Response<Body> {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,91 @@
+//! Authorization by the scopes of the authenticated client.
+//!
+//! The authentication of [`basic_auth`](crate::basic_auth), [`api_key`](crate::api_key) and
+//! [`jwt`](crate::jwt) adds the [`Principal`] it authenticated to the request extensions, and
+//! routes declare the scopes they need with [`Route::require_scope`](crate::Route::require_scope)
+//! or, for all routes of a router, [`Router::require_scope`](crate::Router::require_scope).
+//! After authentication, requests to these routes without a principal are answered with
+//! `401 Unauthorized`, and requests whose principal lacks a required scope with
+//! `403 Forbidden`. Routes requiring no scopes don't need a principal.
+//!
+//! Where the scopes come from depends on the authentication:
+//!
+//! - Bearer tokens grant the scopes of their `scope` claim, separated by spaces, and of their
+//!   `scp` claim, or of the claim set with [`JwtAuth::scope_claim`](crate::jwt::JwtAuth::scope_claim).
+//! - Basic credentials grant the scopes returned by [`BasicAuth::scopes`](crate::basic_auth::BasicAuth::scopes).
+//! - API keys grant the scopes of the client if the [`KeyValidator`](crate::api_key::KeyValidator)
+//!   resolves keys to principals.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::api_key::ApiKey;
+//! use keiro::authz::Principal;
+//! use keiro::Router;
+//!
+//! async fn lookup(key: String) -> Option<Principal> {
+//!     (key == "secret").then(|| Principal::new("deploy-bot").scope("deploy"))
+//! }
+//!
+//! let mut router = Router::new();
+//! router.api_key(ApiKey::new(lookup));
+//! router.get("/status", handler);
+//! router.post("/deploy", handler).require_scope("deploy");
+//!
+//! async fn handler(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//! ```
+
+use hyper::{Body, Request, Response, StatusCode};
+
+/// An authenticated client and the scopes it was granted, added to the request extensions by
+/// authentication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// The name of the user or the id of the client.
+    pub id: String,
+    pub scopes: Vec<String>,
+}
+
+impl Principal {
+    /// Create a principal without scopes.
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            scopes: Vec::new(),
+        }
+    }
+
+    /// Grant `scope`.
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scopes.push(scope.into());
+        self
+    }
+
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
+}
+
+/// Check that the principal of `req` has all `required` scopes, returning the response for
+/// requests which are not authorized.
+pub(crate) fn check(req: &Request<Body>, required: &[String]) -> Option<Response<Body>> {
+    if required.is_empty() {
+        return None;
+    }
+    let status = match req.extensions().get::<Principal>() {
+        Some(principal) if required.iter().all(|scope| principal.has_scope(scope)) => {
+            return None;
+        }
+        Some(_) => StatusCode::FORBIDDEN,
+        None => StatusCode::UNAUTHORIZED,
+    };
+    Some(
+        Response::builder()
+            .status(status)
+            .body(Body::empty())
+            .unwrap(),
+    )
+}
@@ -0,0 +1,137 @@
+//! HTTP Basic authentication.
+//!
+//! A [`BasicAuth`] set on a route with [`Route::basic_auth`](crate::Route::basic_auth), or on
+//! all routes of a router with [`Router::basic_auth`](crate::Router::basic_auth), checks the
+//! credentials of the `Authorization` header before the handler is called. Requests without
+//! valid credentials are answered with `401 Unauthorized` and a `WWW-Authenticate` challenge,
+//! so browsers ask for a username and password. Handlers find the authenticated user in the
+//! [`Username`] request extension, and its [`Principal`](crate::authz::Principal) for
+//! [`authz`](crate::authz).
+//!
+//! Basic authentication sends passwords in the clear, so only use it over HTTPS.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::basic_auth::{BasicAuth, Username};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router
+//!     .get("/admin", admin)
+//!     .basic_auth(BasicAuth::users("admin", [("alice", "correct horse")]));
+//! router
+//!     .get("/reports", admin)
+//!     .basic_auth(BasicAuth::new("reports", |user, password| {
+//!         user == "reports" && password == std::env::var("REPORTS_PASSWORD").unwrap_or_default()
+//!     }));
+//!
+//! async fn admin(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let user = req.extensions().get::<Username>().unwrap();
+//!     Ok(Response::new(Body::from(format!("Hello {}", user.0))))
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use hyper::header::{HeaderValue, WWW_AUTHENTICATE};
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::authz::Principal;
+use crate::extract::Authorization;
+use crate::util;
+
+/// The name of the user authenticated by a [`BasicAuth`], added to the request extensions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Username(pub String);
+
+/// Checks Basic credentials for a protection space, the realm.
+#[derive(Clone)]
+pub struct BasicAuth {
+    realm: String,
+    #[allow(clippy::type_complexity)]
+    verify: Arc<dyn Fn(&str, &str) -> bool + Send + Sync>,
+    #[allow(clippy::type_complexity)]
+    scopes: Option<Arc<dyn Fn(&str) -> Vec<String> + Send + Sync>>,
+}
+
+impl BasicAuth {
+    /// Accept the credentials for which `verify` returns true when called with the username
+    /// and password. The realm is shown by browsers when they ask for credentials.
+    pub fn new(
+        realm: impl Into<String>,
+        verify: impl Fn(&str, &str) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            realm: realm.into(),
+            verify: Arc::new(verify),
+            scopes: None,
+        }
+    }
+
+    /// Accept the usernames and passwords of `users`.
+    pub fn users<U, P>(realm: impl Into<String>, users: impl IntoIterator<Item = (U, P)>) -> Self
+    where
+        U: Into<String>,
+        P: Into<String>,
+    {
+        let users = users
+            .into_iter()
+            .map(|(user, password)| (user.into(), password.into()))
+            .collect::<HashMap<String, String>>();
+        Self::new(realm, move |user, password| {
+            users.get(user).is_some_and(|expected| {
+                util::constant_time_eq(expected.as_bytes(), password.as_bytes())
+            })
+        })
+    }
+
+    /// Grant the authenticated users the scopes returned by `scopes` for their username. See
+    /// [`authz`](crate::authz).
+    pub fn scopes(mut self, scopes: impl Fn(&str) -> Vec<String> + Send + Sync + 'static) -> Self {
+        self.scopes = Some(Arc::new(scopes));
+        self
+    }
+
+    /// Check the credentials of `req`, adding the [`Username`] if they are valid, or return
+    /// the challenge to answer with.
+    pub(crate) fn check(&self, req: &mut Request<Body>) -> Option<Response<Body>> {
+        if let Some(Authorization::Basic { username, password }) = Authorization::from_headers(req)
+        {
+            if (self.verify)(&username, &password) {
+                let scopes = self.scopes.as_ref().map(|scopes| scopes(&username));
+                req.extensions_mut().insert(Principal {
+                    id: username.clone(),
+                    scopes: scopes.unwrap_or_default(),
+                });
+                req.extensions_mut().insert(Username(username));
+                return None;
+            }
+        }
+        Some(self.challenge())
+    }
+
+    fn challenge(&self) -> Response<Body> {
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+        let challenge = format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm);
+        let mut res = Response::builder()
+            .status(StatusCode::UNAUTHORIZED)
+            .body(Body::empty())
+            .unwrap();
+        if let Ok(challenge) = HeaderValue::from_str(&challenge) {
+            res.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+        }
+        res
+    }
+}
+
+impl fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("realm", &self.realm)
+            .finish()
+    }
+}
@@ -0,0 +1,282 @@
+//! Reading request bodies with size limits.
+//!
+//! The helpers on [`RequestExt`](crate::ext::RequestExt) aggregate the body of a request but
+//! stop reading as soon as it exceeds the given limit, so a client can't make a handler buffer
+//! an arbitrarily large body.
+//!
+//! [`RequestExt::tee_body`](crate::ext::RequestExt::tee_body) splits a body so a second
+//! consumer, such as a virus scanner or an archiver, receives a copy while the handler reads it.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::prelude::*;
+//!
+//! async fn echo(mut req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     match req.body_text(16 * 1024).await {
+//!         Ok(text) => Ok(Response::new(Body::from(text))),
+//!         Err(err) => Ok(err.into()),
+//!     }
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::CONTENT_LENGTH;
+use hyper::{Body, Request, Response, StatusCode};
+use tokio::sync::mpsc;
+
+use crate::limits::LengthLimitExceeded;
+use crate::util;
+
+/// The future returned by the body helpers.
+pub type BodyFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, BodyError>> + Send + Sync + 'a>>;
+
+/// An error while reading a request body.
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body is larger than the limit.
+    TooLarge { limit: usize },
+    /// The body couldn't be read from the connection.
+    Read(hyper::Error),
+    /// The `Content-Type` of the request is not the media type expected for the body.
+    UnsupportedMediaType { expected: &'static str },
+    /// The body is not valid UTF-8.
+    Utf8(std::string::FromUtf8Error),
+    /// The body couldn't be deserialized from JSON.
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+    /// The body couldn't be deserialized from MessagePack.
+    #[cfg(feature = "msgpack")]
+    MsgPack(rmp_serde::decode::Error),
+    /// The body couldn't be deserialized from CBOR.
+    #[cfg(feature = "cbor")]
+    Cbor(ciborium::de::Error<std::io::Error>),
+    /// The body couldn't be deserialized from XML.
+    #[cfg(feature = "xml")]
+    Xml(quick_xml::DeError),
+    /// The body couldn't be decoded from Protocol Buffers.
+    #[cfg(feature = "protobuf")]
+    Protobuf(prost::DecodeError),
+    /// The body doesn't match the checksum declared in `Content-MD5` or `Digest`.
+    #[cfg(feature = "checksum")]
+    Checksum { algorithm: &'static str },
+}
+
+impl BodyError {
+    /// Get the status of the response for this error, e.g. `413 Payload Too Large` for
+    /// [`BodyError::TooLarge`].
+    pub fn status(&self) -> StatusCode {
+        match self {
+            BodyError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            BodyError::UnsupportedMediaType { .. } => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            BodyError::Read(_) | BodyError::Utf8(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "json")]
+            BodyError::Json(err) if err.is_data() => StatusCode::UNPROCESSABLE_ENTITY,
+            #[cfg(feature = "json")]
+            BodyError::Json(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "msgpack")]
+            BodyError::MsgPack(
+                rmp_serde::decode::Error::TypeMismatch(_)
+                | rmp_serde::decode::Error::OutOfRange
+                | rmp_serde::decode::Error::LengthMismatch(_)
+                | rmp_serde::decode::Error::Syntax(_),
+            ) => StatusCode::UNPROCESSABLE_ENTITY,
+            #[cfg(feature = "msgpack")]
+            BodyError::MsgPack(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "cbor")]
+            BodyError::Cbor(ciborium::de::Error::Semantic(..)) => StatusCode::UNPROCESSABLE_ENTITY,
+            #[cfg(feature = "cbor")]
+            BodyError::Cbor(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "xml")]
+            BodyError::Xml(quick_xml::DeError::Custom(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+            #[cfg(feature = "xml")]
+            BodyError::Xml(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "protobuf")]
+            BodyError::Protobuf(_) => StatusCode::BAD_REQUEST,
+            #[cfg(feature = "checksum")]
+            BodyError::Checksum { .. } => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BodyError::TooLarge { limit } => write!(f, "body exceeds the limit of {} bytes", limit),
+            BodyError::UnsupportedMediaType { expected } => {
+                write!(f, "expected a body of type {}", expected)
+            }
+            BodyError::Read(err) => write!(f, "failed to read body: {}", err),
+            BodyError::Utf8(err) => write!(f, "body is not valid UTF-8: {}", err),
+            #[cfg(feature = "json")]
+            BodyError::Json(err) => write!(f, "failed to deserialize body: {}", err),
+            #[cfg(feature = "msgpack")]
+            BodyError::MsgPack(err) => write!(f, "failed to deserialize body: {}", err),
+            #[cfg(feature = "cbor")]
+            BodyError::Cbor(err) => write!(f, "failed to deserialize body: {}", err),
+            #[cfg(feature = "xml")]
+            BodyError::Xml(err) => write!(f, "failed to deserialize body: {}", err),
+            #[cfg(feature = "protobuf")]
+            BodyError::Protobuf(err) => write!(f, "failed to decode body: {}", err),
+            #[cfg(feature = "checksum")]
+            BodyError::Checksum { algorithm } => {
+                write!(f, "body doesn't match its {} checksum", algorithm)
+            }
+        }
+    }
+}
+
+impl Error for BodyError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BodyError::TooLarge { .. } | BodyError::UnsupportedMediaType { .. } => None,
+            BodyError::Read(err) => Some(err),
+            BodyError::Utf8(err) => Some(err),
+            #[cfg(feature = "json")]
+            BodyError::Json(err) => Some(err),
+            #[cfg(feature = "msgpack")]
+            BodyError::MsgPack(err) => Some(err),
+            #[cfg(feature = "cbor")]
+            BodyError::Cbor(err) => Some(err),
+            #[cfg(feature = "xml")]
+            BodyError::Xml(err) => Some(err),
+            #[cfg(feature = "protobuf")]
+            BodyError::Protobuf(err) => Some(err),
+            #[cfg(feature = "checksum")]
+            BodyError::Checksum { .. } => None,
+        }
+    }
+}
+
+impl From<BodyError> for Response<Body> {
+    fn from(err: BodyError) -> Self {
+        Response::builder()
+            .status(err.status())
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(Body::from(err.to_string()))
+            .unwrap()
+    }
+}
+
+/// Read the whole body of `req`, failing once more than `limit` bytes are received.
+pub(crate) async fn read(req: &mut Request<Body>, limit: usize) -> Result<Bytes, BodyError> {
+    let content_length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > limit as u64) {
+        return Err(BodyError::TooLarge { limit });
+    }
+
+    let mut body = std::mem::take(req.body_mut());
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|err| {
+            if let Some(exceeded) = err
+                .source()
+                .and_then(|source| source.downcast_ref::<LengthLimitExceeded>())
+            {
+                return BodyError::TooLarge {
+                    limit: exceeded.limit,
+                };
+            }
+            #[cfg(feature = "checksum")]
+            if let Some(mismatch) = err
+                .source()
+                .and_then(|source| source.downcast_ref::<crate::checksum::ChecksumMismatch>())
+            {
+                return BodyError::Checksum {
+                    algorithm: mismatch.algorithm,
+                };
+            }
+            BodyError::Read(err)
+        })?;
+        if buf.len() + chunk.len() > limit {
+            return Err(BodyError::TooLarge { limit });
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(buf))
+}
+
+/// An error in the copy of a body split with
+/// [`RequestExt::tee_body`](crate::ext::RequestExt::tee_body), available as the source of the
+/// `hyper::Error` the copy fails with.
+#[derive(Debug)]
+pub enum TeeError {
+    /// The request body couldn't be read. The handler receives the original error.
+    Read(String),
+    /// The handler stopped reading before the end of the body, or the consumer dropped its copy.
+    Closed,
+}
+
+impl fmt::Display for TeeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TeeError::Read(message) => write!(f, "failed to read body: {}", message),
+            TeeError::Closed => f.write_str("the other side of the tee was closed"),
+        }
+    }
+}
+
+impl Error for TeeError {}
+
+/// Split the body of `req`, returning a copy which receives every chunk the handler reads.
+pub(crate) fn tee(req: &mut Request<Body>, capacity: usize) -> Body {
+    let (sender, receiver) = mpsc::channel::<Result<Bytes, TeeError>>(capacity.max(1));
+    let finished = Arc::new(AtomicBool::new(false));
+
+    let body = std::mem::take(req.body_mut());
+    let done = finished.clone();
+    let original = futures_util::stream::unfold(Some((body, sender)), move |state| {
+        let done = done.clone();
+        async move {
+            let (mut body, sender) = state?;
+            match body.data().await {
+                Some(Ok(chunk)) => {
+                    // Wait for the consumer to catch up, so at most `capacity` chunks are
+                    // buffered.
+                    if sender.send(Ok(chunk.clone())).await.is_err() {
+                        let err: Box<dyn Error + Send + Sync> = Box::new(TeeError::Closed);
+                        return Some((Err(err), None));
+                    }
+                    Some((Ok(chunk), Some((body, sender))))
+                }
+                Some(Err(err)) => {
+                    let _ = sender.send(Err(TeeError::Read(err.to_string()))).await;
+                    done.store(true, Ordering::Release);
+                    Some((Err(util::into_cause(err)), None))
+                }
+                None => {
+                    done.store(true, Ordering::Release);
+                    None
+                }
+            }
+        }
+    });
+    *req.body_mut() = Body::wrap_stream(original);
+
+    let copy = futures_util::stream::unfold(Some(receiver), move |receiver| {
+        let finished = finished.clone();
+        async move {
+            let mut receiver = receiver?;
+            match receiver.recv().await {
+                Some(Err(err)) => Some((Err(err), None)),
+                Some(Ok(chunk)) => Some((Ok(chunk), Some(receiver))),
+                // The handler dropped its body before reaching the end.
+                None if !finished.load(Ordering::Acquire) => Some((Err(TeeError::Closed), None)),
+                None => None,
+            }
+        }
+    });
+    Body::wrap_stream(copy)
+}
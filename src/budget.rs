@@ -0,0 +1,154 @@
+//! Latency budgets for handlers aggregating several sub-calls.
+//!
+//! A [`Budget`] is the time a handler may take to answer. Sub-calls run with [`Budget::run`]
+//! are cut off once the budget is spent, and yield `None` instead of their result, so the
+//! handler can answer with the results which arrived in time instead of waiting for the slowest
+//! backend or failing completely. [`Budget::finish`] notes the sub-calls which were cut off in
+//! the [`DEGRADED`] header of the response, so clients and caches can tell partial responses
+//! apart.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::budget::Budget;
+//!
+//! async fn dashboard(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let budget = Budget::new(Duration::from_millis(300));
+//!     let (profile, orders, recommendations) = tokio::join!(
+//!         budget.run("profile", fetch("profile")),
+//!         budget.run("orders", fetch("orders")),
+//!         // Optional parts can get less than the whole budget.
+//!         budget.run_within("recommendations", Duration::from_millis(100), fetch("recommendations")),
+//!     );
+//!     let body = format!(
+//!         "{} {} {}",
+//!         profile.unwrap_or_default(),
+//!         orders.unwrap_or_default(),
+//!         recommendations.unwrap_or_default(),
+//!     );
+//!     // Sent with `x-degraded: recommendations` if the recommendations were too slow.
+//!     Ok(budget.finish(Response::new(Body::from(body))))
+//! }
+//!
+//! async fn fetch(part: &str) -> String {
+//!     part.to_string()
+//! }
+//! ```
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::Response;
+use tokio::time::Instant;
+
+/// The header listing the sub-calls which were cut off, separated by commas.
+pub const DEGRADED: HeaderName = HeaderName::from_static("x-degraded");
+
+/// A deadline shared by the sub-calls of a request. Clones share the deadline and the record
+/// of the sub-calls which were cut off, so they can be moved into spawned tasks.
+#[derive(Debug, Clone)]
+pub struct Budget {
+    deadline: Instant,
+    degraded: Arc<Mutex<Vec<String>>>,
+}
+
+impl Budget {
+    /// Start a budget of `budget` from now.
+    pub fn new(budget: Duration) -> Self {
+        Self::until(Instant::now() + budget)
+    }
+
+    /// Start a budget ending at `deadline`.
+    pub fn until(deadline: Instant) -> Self {
+        Self {
+            deadline,
+            degraded: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    /// The time left until the deadline, zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.deadline.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_spent(&self) -> bool {
+        Instant::now() >= self.deadline
+    }
+
+    /// Run `fut` until the deadline. If it isn't done by then it's dropped, recorded as
+    /// degraded under `name`, and `None` is returned.
+    pub async fn run<F: Future>(&self, name: &str, fut: F) -> Option<F::Output> {
+        self.run_until(name, self.deadline, fut).await
+    }
+
+    /// Run `fut` for at most `limit`, or until the deadline if it's sooner, like
+    /// [`Budget::run`].
+    pub async fn run_within<F: Future>(
+        &self,
+        name: &str,
+        limit: Duration,
+        fut: F,
+    ) -> Option<F::Output> {
+        let deadline = self.deadline.min(Instant::now() + limit);
+        self.run_until(name, deadline, fut).await
+    }
+
+    async fn run_until<F: Future>(
+        &self,
+        name: &str,
+        deadline: Instant,
+        fut: F,
+    ) -> Option<F::Output> {
+        match tokio::time::timeout_at(deadline, fut).await {
+            Ok(output) => Some(output),
+            Err(_) => {
+                self.degrade(name);
+                None
+            }
+        }
+    }
+
+    /// Record the sub-call `name` as degraded, e.g. when the handler drops a result which
+    /// failed, so the response is marked by [`Budget::finish`].
+    pub fn degrade(&self, name: &str) {
+        let mut degraded = match self.degraded.lock() {
+            Ok(degraded) => degraded,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if !degraded.iter().any(|degraded| degraded == name) {
+            degraded.push(name.to_string());
+        }
+    }
+
+    /// The names of the sub-calls recorded as degraded, in the order they were.
+    pub fn degraded(&self) -> Vec<String> {
+        match self.degraded.lock() {
+            Ok(degraded) => degraded.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        !self.degraded().is_empty()
+    }
+
+    /// Add the [`DEGRADED`] header to `res` if any sub-call was degraded.
+    pub fn finish<B>(&self, mut res: Response<B>) -> Response<B> {
+        let degraded = self.degraded();
+        if degraded.is_empty() {
+            return res;
+        }
+        if let Ok(value) = HeaderValue::from_str(&degraded.join(", ")) {
+            res.headers_mut().insert(DEGRADED, value);
+        }
+        res
+    }
+}
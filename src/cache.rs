@@ -0,0 +1,454 @@
+//! Caching responses in memory.
+//!
+//! A [`ResponseCache`] set on a route with
+//! [`Route::response_cache`](crate::Route::response_cache) stores the `200 OK` responses to
+//! `GET` requests, so hot read-only endpoints are answered without calling the handler until
+//! the response expires. Responses are keyed by method, path and query, and the request
+//! headers listed with [`ResponseCache::vary`].
+//!
+//! Once a response is older than its time to live, it can still be served for the
+//! [`ResponseCache::stale_while_revalidate`] window while the handler is called again in the
+//! background to refresh it.
+//!
+//! Requests with an `Authorization` header bypass the cache unless it varies on it, and
+//! responses marked with [`must_stream`](crate::streaming::must_stream) or larger than
+//! [`ResponseCache::max_body_size`] are never stored.
+//!
+//! By default, the cache also follows the [`CachePolicy`] safe for shared caches:
+//!
+//! - Responses with `Cache-Control: private`, `no-store` or `no-cache`, a `Set-Cookie` header
+//!   or `Vary: *` are not stored, and a shorter `s-maxage` or `max-age` than the cache's time
+//!   to live shortens it.
+//! - `HEAD` requests are answered with the headers of the cached `GET` response, for routes
+//!   registered for `HEAD` with the same cache.
+//!
+//! Routes can override the policy of their cache with
+//! [`Route::response_cache_policy`](crate::Route::response_cache_policy).
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::cache::{CachePolicy, ResponseCache};
+//! use keiro::Router;
+//!
+//! let cache = ResponseCache::new(Duration::from_secs(10))
+//!     .max_entries(1000)
+//!     .vary("accept-language")
+//!     .stale_while_revalidate(Duration::from_secs(60));
+//! let mut router = Router::new();
+//! router.get("/products", products).response_cache(cache.clone());
+//! router.head("/products", products).response_cache(cache.clone());
+//! // The handler marks the feed `private`, which is fine as it varies on the session cookie.
+//! router
+//!     .get("/feed", products)
+//!     .response_cache(ResponseCache::new(Duration::from_secs(5)).vary("cookie"))
+//!     .response_cache_policy(CachePolicy::new().respect_cache_control(false));
+//!
+//! async fn products(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("[]")))
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use hyper::body::Bytes;
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, AGE, AUTHORIZATION, CONTENT_LENGTH, SET_COOKIE, VARY,
+};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use crate::cache_control::CacheControl;
+use crate::{streaming, HandlerFuture};
+
+/// Which requests a [`ResponseCache`] answers and which responses it stores.
+#[derive(Debug, Clone, Copy)]
+pub struct CachePolicy {
+    head_from_get: bool,
+    respect_cache_control: bool,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CachePolicy {
+    /// Create the policy safe for shared caches, which answers `HEAD` requests from `GET`
+    /// responses and respects the `Cache-Control` headers of responses.
+    pub fn new() -> Self {
+        Self {
+            head_from_get: true,
+            respect_cache_control: true,
+        }
+    }
+
+    /// Answer `HEAD` requests with the headers of the cached response to a `GET` request.
+    pub fn head_from_get(mut self, enabled: bool) -> Self {
+        self.head_from_get = enabled;
+        self
+    }
+
+    /// Leave responses which are `private`, `no-store`, `no-cache`, set cookies or vary on `*`
+    /// uncached, and expire responses after their `s-maxage` or `max-age` if it's shorter than
+    /// the time to live of the cache.
+    ///
+    /// Only disable this for routes whose cache varies on everything responses depend on,
+    /// such as the `Cookie` header.
+    pub fn respect_cache_control(mut self, enabled: bool) -> Self {
+        self.respect_cache_control = enabled;
+        self
+    }
+}
+
+/// An in-memory cache for the responses of one or more routes.
+///
+/// Clones share their entries, so a cache set on several routes is bounded as a whole.
+#[derive(Clone)]
+pub struct ResponseCache {
+    config: Arc<Config>,
+    entries: Arc<Mutex<HashMap<Key, Entry>>>,
+}
+
+struct Config {
+    policy: CachePolicy,
+    ttl: Duration,
+    stale_while_revalidate: Duration,
+    max_entries: usize,
+    max_body_size: usize,
+    vary: Vec<HeaderName>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    method: Method,
+    uri: String,
+    vary: Vec<Option<HeaderValue>>,
+}
+
+struct Entry {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    ttl: Duration,
+    stored: Instant,
+    used: Instant,
+    revalidating: bool,
+}
+
+impl Entry {
+    fn response(&self, now: Instant, head: bool) -> Response<Body> {
+        let mut res = if head {
+            Response::new(Body::empty())
+        } else {
+            Response::new(Body::from(self.body.clone()))
+        };
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers.clone();
+        if head && !res.headers().contains_key(CONTENT_LENGTH) {
+            res.headers_mut()
+                .insert(CONTENT_LENGTH, HeaderValue::from(self.body.len()));
+        }
+        let age = now.duration_since(self.stored).as_secs();
+        res.headers_mut().insert(AGE, HeaderValue::from(age));
+        res
+    }
+}
+
+impl ResponseCache {
+    /// Create a cache keeping responses fresh for `ttl`, with up to 10000 entries of up to
+    /// 1 MiB each.
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_config(Config {
+            policy: CachePolicy::new(),
+            ttl,
+            stale_while_revalidate: Duration::ZERO,
+            max_entries: 10_000,
+            max_body_size: 1024 * 1024,
+            vary: Vec::new(),
+        })
+    }
+
+    fn with_config(config: Config) -> Self {
+        Self {
+            config: Arc::new(config),
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Change the configuration, starting over with an empty cache.
+    fn configure(self, configure: impl FnOnce(&mut Config)) -> Self {
+        let mut config = Config {
+            vary: self.config.vary.clone(),
+            ..*self.config
+        };
+        configure(&mut config);
+        Self::with_config(config)
+    }
+
+    /// Follow `policy` on the routes which don't override it. Defaults to
+    /// [`CachePolicy::new`].
+    pub fn policy(self, policy: CachePolicy) -> Self {
+        self.configure(|config| config.policy = policy)
+    }
+
+    /// Keep serving a response for `duration` after it expired, while it's refreshed in the
+    /// background. Defaults to zero, i.e. expired responses are fetched again before
+    /// answering.
+    pub fn stale_while_revalidate(self, duration: Duration) -> Self {
+        self.configure(|config| config.stale_while_revalidate = duration)
+    }
+
+    /// Store up to `limit` responses, evicting the least recently used one when it's reached.
+    /// Defaults to 10000.
+    pub fn max_entries(self, limit: usize) -> Self {
+        self.configure(|config| config.max_entries = limit)
+    }
+
+    /// Only store response bodies of up to `limit` bytes. Defaults to 1 MiB.
+    pub fn max_body_size(self, limit: usize) -> Self {
+        self.configure(|config| config.max_body_size = limit)
+    }
+
+    /// Store separate responses for each value of the request header `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid header name.
+    pub fn vary(self, name: &str) -> Self {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid header name");
+        self.configure(|config| config.vary.push(name))
+    }
+
+    /// Remove all stored responses.
+    pub fn clear(&self) {
+        self.entries().clear();
+    }
+
+    /// Get the number of stored responses, including expired ones which weren't evicted yet.
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn entries(&self) -> MutexGuard<'_, HashMap<Key, Entry>> {
+        match self.entries.lock() {
+            Ok(entries) => entries,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Get the key of the response to `req`, and whether only its headers are requested.
+    fn key(&self, req: &Request<Body>, policy: &CachePolicy) -> Option<(Key, bool)> {
+        let head = match *req.method() {
+            Method::GET => false,
+            Method::HEAD if policy.head_from_get => true,
+            _ => return None,
+        };
+        if req.headers().contains_key(AUTHORIZATION) && !self.config.vary.contains(&AUTHORIZATION) {
+            return None;
+        }
+        let uri = req
+            .uri()
+            .path_and_query()
+            .map_or_else(|| req.uri().path().to_string(), |path| path.to_string());
+        let vary = self
+            .config
+            .vary
+            .iter()
+            .map(|name| req.headers().get(name).cloned())
+            .collect();
+        let key = Key {
+            method: Method::GET,
+            uri,
+            vary,
+        };
+        Some((key, head))
+    }
+
+    /// Answer `req` from the cache, or with the response of `call`, which is stored. `policy`
+    /// overrides the policy of the cache.
+    pub(crate) fn serve<E: 'static>(
+        &self,
+        req: Request<Body>,
+        policy: Option<CachePolicy>,
+        call: impl FnOnce(Request<Body>) -> HandlerFuture<E>,
+    ) -> HandlerFuture<E> {
+        let policy = policy.unwrap_or(self.config.policy);
+        let (key, head) = match self.key(&req, &policy) {
+            Some(key) => key,
+            None => return call(req),
+        };
+
+        let now = Instant::now();
+        let mut entries = self.entries();
+        if let Some(entry) = entries.get_mut(&key) {
+            let age = now.duration_since(entry.stored);
+            if age < entry.ttl + self.config.stale_while_revalidate {
+                entry.used = now;
+                let res = entry.response(now, head);
+                // Only `GET` requests refresh stale entries, as a `HEAD` response has no body.
+                if age >= entry.ttl && !head && !entry.revalidating {
+                    entry.revalidating = true;
+                    drop(entries);
+                    self.revalidate(key, policy, call(req));
+                }
+                return Box::pin(async { Ok(res) });
+            }
+            entries.remove(&key);
+        }
+        drop(entries);
+
+        let fut = call(req);
+        if head {
+            return fut;
+        }
+        let cache = self.clone();
+        Box::pin(async move {
+            let res = fut.await?;
+            Ok(cache.store(key, res, &policy).await)
+        })
+    }
+
+    /// Refresh the entry `key` with the response of `fut` on a new task.
+    fn revalidate<E: 'static>(&self, key: Key, policy: CachePolicy, fut: HandlerFuture<E>) {
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let res = match fut.await {
+                Ok(res) => res,
+                Err(_) => {
+                    cache.finish_revalidating(&key);
+                    return;
+                }
+            };
+            // Stored responses replace the entry, others leave the stale one in place.
+            cache.store(key.clone(), res, &policy).await;
+            cache.finish_revalidating(&key);
+        });
+    }
+
+    fn finish_revalidating(&self, key: &Key) {
+        if let Some(entry) = self.entries().get_mut(key) {
+            entry.revalidating = false;
+        }
+    }
+
+    /// Store `res` under `key` if it can be cached, returning it to be sent.
+    async fn store(&self, key: Key, res: Response<Body>, policy: &CachePolicy) -> Response<Body> {
+        if res.status() != StatusCode::OK || streaming::is_must_stream(&res) {
+            return res;
+        }
+        let ttl = match self.ttl(&res, policy) {
+            Some(ttl) => ttl,
+            None => return res,
+        };
+        let (parts, body) = res.into_parts();
+        let body = match buffer(body, self.config.max_body_size).await {
+            Ok(body) => body,
+            Err(body) => return Response::from_parts(parts, body),
+        };
+
+        let now = Instant::now();
+        let mut entries = self.entries();
+        if !entries.contains_key(&key) && entries.len() >= self.config.max_entries {
+            self.evict(&mut entries, now);
+        }
+        if self.config.max_entries > 0 {
+            entries.insert(
+                key,
+                Entry {
+                    status: parts.status,
+                    headers: parts.headers.clone(),
+                    body: body.clone(),
+                    ttl,
+                    stored: now,
+                    used: now,
+                    revalidating: false,
+                },
+            );
+        }
+        drop(entries);
+        Response::from_parts(parts, Body::from(body))
+    }
+
+    /// Get how long `res` is fresh, or `None` if it must not be stored.
+    fn ttl(&self, res: &Response<Body>, policy: &CachePolicy) -> Option<Duration> {
+        if !policy.respect_cache_control {
+            return Some(self.config.ttl);
+        }
+        let headers = res.headers();
+        let vary_any = headers
+            .get_all(VARY)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|name| name.trim() == "*");
+        if vary_any || headers.contains_key(SET_COOKIE) {
+            return None;
+        }
+        let cache_control = match CacheControl::from_headers(headers) {
+            Some(cache_control) => cache_control,
+            None => return Some(self.config.ttl),
+        };
+        if cache_control.is_private() || cache_control.is_no_store() || cache_control.is_no_cache()
+        {
+            return None;
+        }
+        let ttl = match cache_control.freshness(true) {
+            Some(freshness) => freshness.min(self.config.ttl),
+            None => self.config.ttl,
+        };
+        Some(ttl).filter(|ttl| !ttl.is_zero())
+    }
+
+    /// Make room for an entry by removing the expired entries, or the least recently used
+    /// one if none are expired.
+    fn evict(&self, entries: &mut HashMap<Key, Entry>, now: Instant) {
+        let stale = self.config.stale_while_revalidate;
+        entries.retain(|_, entry| now.duration_since(entry.stored) < entry.ttl + stale);
+        if entries.len() < self.config.max_entries {
+            return;
+        }
+        let oldest = entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.used)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            entries.remove(&key);
+        }
+    }
+}
+
+/// Read `body` into memory if it's at most `limit` bytes, or return a body with the same
+/// bytes otherwise.
+async fn buffer(mut body: Body, limit: usize) -> Result<Bytes, Body> {
+    let mut chunks: Vec<Bytes> = Vec::new();
+    let mut size = 0;
+    while let Some(chunk) = body.next().await {
+        match chunk {
+            Ok(chunk) if size + chunk.len() <= limit => {
+                size += chunk.len();
+                chunks.push(chunk);
+            }
+            chunk => {
+                // Too large or failing, so send what was read followed by the rest.
+                let read = futures_util::stream::iter(chunks.into_iter().map(Ok));
+                let rest = futures_util::stream::iter(Some(chunk)).chain(body);
+                return Err(Body::wrap_stream(read.chain(rest)));
+            }
+        }
+    }
+    let mut bytes = Vec::with_capacity(size);
+    for chunk in chunks {
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(bytes))
+}
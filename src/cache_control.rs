@@ -0,0 +1,263 @@
+//! Typed `Cache-Control` headers.
+//!
+//! [`CacheControl`] builds `Cache-Control` values from directives instead of strings, and can
+//! be set on a route with [`Route::cache`](crate::Route::cache) or for all routes with
+//! [`Router::cache_control`](crate::Router::cache_control). The header is added to responses
+//! below `400` which don't set `Cache-Control` themselves, so errors are never cached by
+//! accident.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::cache_control::CacheControl;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.cache_control(CacheControl::new().no_store());
+//! router.get("/assets/*path", asset).cache(
+//!     CacheControl::new()
+//!         .public()
+//!         .max_age(Duration::from_secs(365 * 24 * 60 * 60))
+//!         .immutable(),
+//! );
+//! router.get("/feed", feed);
+//!
+//! async fn asset(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//!
+//! async fn feed(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let mut res = Response::new(Body::empty());
+//!     CacheControl::new()
+//!         .private()
+//!         .max_age(Duration::from_secs(60))
+//!         .apply(&mut res);
+//!     Ok(res)
+//! }
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use hyper::header::{HeaderMap, HeaderValue, CACHE_CONTROL};
+use hyper::{Body, Response};
+
+/// The directives of a `Cache-Control` header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    public: bool,
+    private: bool,
+    no_cache: bool,
+    no_store: bool,
+    no_transform: bool,
+    must_revalidate: bool,
+    proxy_revalidate: bool,
+    immutable: bool,
+    max_age: Option<Duration>,
+    s_maxage: Option<Duration>,
+    stale_while_revalidate: Option<Duration>,
+    stale_if_error: Option<Duration>,
+}
+
+impl CacheControl {
+    /// Create a header without directives.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow shared caches, such as CDNs, to store the response.
+    pub fn public(mut self) -> Self {
+        self.public = true;
+        self
+    }
+
+    /// Only allow the client's own cache to store the response.
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    /// Require caches to revalidate the response before every use.
+    pub fn no_cache(mut self) -> Self {
+        self.no_cache = true;
+        self
+    }
+
+    /// Forbid caches to store the response at all.
+    pub fn no_store(mut self) -> Self {
+        self.no_store = true;
+        self
+    }
+
+    pub fn no_transform(mut self) -> Self {
+        self.no_transform = true;
+        self
+    }
+
+    /// Forbid caches to use the response once it's stale without revalidating it.
+    pub fn must_revalidate(mut self) -> Self {
+        self.must_revalidate = true;
+        self
+    }
+
+    /// Like [`CacheControl::must_revalidate`], for shared caches only.
+    pub fn proxy_revalidate(mut self) -> Self {
+        self.proxy_revalidate = true;
+        self
+    }
+
+    /// Declare that the response never changes while it's fresh, so clients don't revalidate
+    /// it on reload. Suited to assets with a content hash in their URL.
+    pub fn immutable(mut self) -> Self {
+        self.immutable = true;
+        self
+    }
+
+    /// Keep the response fresh for `age`. Sent in whole seconds.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    /// Keep the response fresh for `age` in shared caches, overriding `max-age`.
+    pub fn s_maxage(mut self, age: Duration) -> Self {
+        self.s_maxage = Some(age);
+        self
+    }
+
+    /// Allow caches to use the response for `duration` after it's stale while revalidating it
+    /// in the background.
+    pub fn stale_while_revalidate(mut self, duration: Duration) -> Self {
+        self.stale_while_revalidate = Some(duration);
+        self
+    }
+
+    /// Allow caches to use the response for `duration` after it's stale if revalidating it
+    /// fails.
+    pub fn stale_if_error(mut self, duration: Duration) -> Self {
+        self.stale_if_error = Some(duration);
+        self
+    }
+
+    pub fn is_public(&self) -> bool {
+        self.public
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private
+    }
+
+    pub fn is_no_cache(&self) -> bool {
+        self.no_cache
+    }
+
+    pub fn is_no_store(&self) -> bool {
+        self.no_store
+    }
+
+    pub fn is_no_transform(&self) -> bool {
+        self.no_transform
+    }
+
+    pub fn is_immutable(&self) -> bool {
+        self.immutable
+    }
+
+    /// Get how long the response is fresh, from `s-maxage` for shared caches and `max-age`.
+    pub fn freshness(&self, shared: bool) -> Option<Duration> {
+        match self.s_maxage {
+            Some(age) if shared => Some(age),
+            _ => self.max_age,
+        }
+    }
+
+    /// Parse the `Cache-Control` headers of `headers`, or return `None` if there are none.
+    /// Unknown directives are ignored.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let mut values = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .peekable();
+        values.peek()?;
+        let mut cache_control = Self::new();
+        for directive in values.flat_map(|value| value.split(',')) {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds = || {
+                value
+                    .and_then(|value| value.parse().ok())
+                    .map(Duration::from_secs)
+            };
+            match name.to_ascii_lowercase().as_str() {
+                "public" => cache_control.public = true,
+                "private" => cache_control.private = true,
+                "no-cache" => cache_control.no_cache = true,
+                "no-store" => cache_control.no_store = true,
+                "no-transform" => cache_control.no_transform = true,
+                "must-revalidate" => cache_control.must_revalidate = true,
+                "proxy-revalidate" => cache_control.proxy_revalidate = true,
+                "immutable" => cache_control.immutable = true,
+                "max-age" => cache_control.max_age = seconds(),
+                "s-maxage" => cache_control.s_maxage = seconds(),
+                "stale-while-revalidate" => cache_control.stale_while_revalidate = seconds(),
+                "stale-if-error" => cache_control.stale_if_error = seconds(),
+                _ => {}
+            }
+        }
+        Some(cache_control)
+    }
+
+    /// Set the `Cache-Control` header of `res`, replacing an existing one.
+    pub fn apply(&self, res: &mut Response<Body>) {
+        if let Ok(value) = HeaderValue::from_str(&self.to_string()) {
+            res.headers_mut().insert(CACHE_CONTROL, value);
+        }
+    }
+}
+
+impl fmt::Display for CacheControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags = [
+            (self.public, "public"),
+            (self.private, "private"),
+            (self.no_cache, "no-cache"),
+            (self.no_store, "no-store"),
+            (self.no_transform, "no-transform"),
+            (self.must_revalidate, "must-revalidate"),
+            (self.proxy_revalidate, "proxy-revalidate"),
+            (self.immutable, "immutable"),
+        ];
+        let durations = [
+            (self.max_age, "max-age"),
+            (self.s_maxage, "s-maxage"),
+            (self.stale_while_revalidate, "stale-while-revalidate"),
+            (self.stale_if_error, "stale-if-error"),
+        ];
+        let directives = flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| name.to_string())
+            .chain(durations.iter().filter_map(|(duration, name)| {
+                duration.map(|duration| format!("{}={}", name, duration.as_secs()))
+            }));
+        for (i, directive) in directives.enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(&directive)?;
+        }
+        Ok(())
+    }
+}
+
+/// Add `cache_control` to `res` unless it's an error or already has a `Cache-Control` header.
+pub(crate) fn apply_default(cache_control: &CacheControl, res: &mut Response<Body>) {
+    if res.status().as_u16() < 400 && !res.headers().contains_key(CACHE_CONTROL) {
+        cache_control.apply(res);
+    }
+}
@@ -0,0 +1,137 @@
+use std::error::Error;
+use std::fmt;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use futures_util::StreamExt;
+use hyper::body::Bytes;
+use hyper::header::HeaderName;
+use hyper::{Body, Request, Response, StatusCode};
+use md5::Md5;
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::util;
+
+const CONTENT_MD5: HeaderName = HeaderName::from_static("content-md5");
+const DIGEST: HeaderName = HeaderName::from_static("digest");
+
+/// The error of a body which doesn't match its declared checksum.
+#[derive(Debug)]
+pub(crate) struct ChecksumMismatch {
+    pub(crate) algorithm: &'static str,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "body doesn't match its {} checksum", self.algorithm)
+    }
+}
+
+impl Error for ChecksumMismatch {}
+
+enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+/// A checksum declared by the client, hashed while the body is read.
+struct Expected {
+    algorithm: &'static str,
+    hasher: Hasher,
+    digest: Vec<u8>,
+}
+
+impl Expected {
+    fn new(algorithm: &str, encoded: &str) -> Option<Self> {
+        let (algorithm, hasher) = match algorithm.to_ascii_lowercase().as_str() {
+            "md5" => ("MD5", Hasher::Md5(Md5::new())),
+            "sha-256" => ("SHA-256", Hasher::Sha256(Sha256::new())),
+            "sha-512" => ("SHA-512", Hasher::Sha512(Sha512::new())),
+            _ => return None,
+        };
+        // Malformed checksums never match, rather than being ignored.
+        let digest = STANDARD.decode(encoded.trim()).unwrap_or_default();
+        Some(Self {
+            algorithm,
+            hasher,
+            digest,
+        })
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        match &mut self.hasher {
+            Hasher::Md5(hasher) => hasher.update(chunk),
+            Hasher::Sha256(hasher) => hasher.update(chunk),
+            Hasher::Sha512(hasher) => hasher.update(chunk),
+        }
+    }
+
+    fn matches(self) -> bool {
+        let digest = match self.hasher {
+            Hasher::Md5(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+        };
+        digest == self.digest
+    }
+}
+
+/// Collect the checksums declared in `Content-MD5` and `Digest`. Unsupported algorithms are
+/// ignored.
+fn expected(req: &Request<Body>) -> Vec<Expected> {
+    let headers = req.headers();
+    let content_md5 = headers
+        .get_all(CONTENT_MD5)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| Expected::new("md5", value));
+    let digest = headers
+        .get_all(DIGEST)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|item| item.split_once('='))
+        .filter_map(|(algorithm, value)| Expected::new(algorithm.trim(), value));
+    content_md5.chain(digest).collect()
+}
+
+/// Return `400 Bad Request` if `req` declares no supported checksum, or make reading its body
+/// fail at the end if it doesn't match.
+pub(crate) fn verify(req: &mut Request<Body>) -> Option<Response<Body>> {
+    let expected = expected(req);
+    if expected.is_empty() {
+        return Some(
+            Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::from("a Content-MD5 or Digest header is required"))
+                .unwrap(),
+        );
+    }
+
+    let body = std::mem::take(req.body_mut());
+    let stream = futures_util::stream::unfold(Some((body, expected)), |state| async move {
+        let (mut body, mut expected) = state?;
+        match body.next().await {
+            Some(Ok(chunk)) => {
+                for checksum in &mut expected {
+                    checksum.update(&chunk);
+                }
+                Some((Ok(chunk), Some((body, expected))))
+            }
+            Some(Err(err)) => Some((Err(util::into_cause(err)), None)),
+            None => {
+                let mismatch = expected.into_iter().find_map(|checksum| {
+                    let algorithm = checksum.algorithm;
+                    (!checksum.matches()).then_some(algorithm)
+                })?;
+                let err: Box<dyn Error + Send + Sync> = Box::new(ChecksumMismatch {
+                    algorithm: mismatch,
+                });
+                Some((Err::<Bytes, _>(err), None))
+            }
+        }
+    });
+    *req.body_mut() = Body::wrap_stream(stream);
+    None
+}
@@ -0,0 +1,385 @@
+//! Response compression.
+//!
+//! A [`Compression`] set with [`Router::compression`](crate::Router::compression) compresses
+//! the responses of a [`RouterService`](crate::RouterService) with the encoding the client
+//! prefers in its `Accept-Encoding` header. gzip and deflate are always supported, Brotli
+//! (`br`) with the `compression-brotli` feature and zstd with the `compression-zstd` feature;
+//! on ties they are preferred in the order `br`, `zstd`, `gzip`, `deflate`.
+//!
+//! Responses are left as they are if they:
+//!
+//! - are already encoded, or marked with
+//!   [`skip_compression`](crate::encoding::skip_compression),
+//! - are smaller than [`Compression::min_size`] according to their `Content-Length`,
+//! - have a content type which is compressed already, such as images and video, or one
+//!   excluded with [`Compression::skip_content_type`],
+//! - are partial, empty by their status, or answer a `HEAD` request,
+//! - have `Cache-Control: no-transform`.
+//!
+//! Bodies are compressed while they are streamed. Responses marked with
+//! [`must_stream`](crate::streaming::must_stream) are flushed after every chunk, so events
+//! reach the client without waiting for the compressor to fill a block.
+//!
+//! Requires the `compression` feature.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::compression::Compression;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.compression(
+//!     Compression::new()
+//!         .min_size(512)
+//!         .level(4)
+//!         .skip_content_type("application/x-ndjson"),
+//! );
+//! router.get("/report", report);
+//!
+//! async fn report(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("date,total\n".repeat(1000))))
+//! }
+//! ```
+
+use std::io::{self, Write};
+
+use flate2::write::{GzEncoder, ZlibEncoder};
+use futures_util::StreamExt;
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{
+    HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+    CONTENT_TYPE, ETAG, VARY,
+};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use crate::cache_control::CacheControl;
+use crate::encoding::{AcceptEncoding, Encoding, SkipCompression};
+use crate::{streaming, util};
+
+/// How responses are compressed.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    min_size: u64,
+    level: u32,
+    #[cfg(feature = "compression-brotli")]
+    brotli_quality: u32,
+    #[cfg(feature = "compression-zstd")]
+    zstd_level: i32,
+    skipped_types: Vec<String>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compression {
+    /// Compress responses of at least 1 KiB at the default levels.
+    pub fn new() -> Self {
+        Self {
+            min_size: 1024,
+            level: 6,
+            #[cfg(feature = "compression-brotli")]
+            brotli_quality: 4,
+            #[cfg(feature = "compression-zstd")]
+            zstd_level: 3,
+            skipped_types: Vec::new(),
+        }
+    }
+
+    /// Leave responses with a `Content-Length` below `bytes` uncompressed, as compressing
+    /// them saves little. Streamed responses of unknown length are always compressed. Defaults to 1 KiB.
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    /// Set the gzip and deflate level from `0` (none) to `9` (smallest, slowest). Defaults to
+    /// `6`.
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level.min(9);
+        self
+    }
+
+    /// Set the Brotli quality from `0` (fastest) to `11` (smallest). Defaults to `4`, as the
+    /// highest qualities are too slow for responses compressed on the fly. Requires the
+    /// `compression-brotli` feature.
+    #[cfg(feature = "compression-brotli")]
+    pub fn brotli_quality(mut self, quality: u32) -> Self {
+        self.brotli_quality = quality.min(11);
+        self
+    }
+
+    /// Set the zstd level from `1` (fastest) to `22` (smallest). Defaults to `3`. Requires the
+    /// `compression-zstd` feature.
+    #[cfg(feature = "compression-zstd")]
+    pub fn zstd_level(mut self, level: i32) -> Self {
+        self.zstd_level = level.clamp(1, 22);
+        self
+    }
+
+    /// Leave responses with the content type `content_type` uncompressed, in addition to
+    /// types which are compressed already. `content_type` is a type such as
+    /// `application/pdf`, or all types of a kind such as `image/*`.
+    pub fn skip_content_type(mut self, content_type: &str) -> Self {
+        self.skipped_types.push(content_type.to_ascii_lowercase());
+        self
+    }
+
+    /// Pick the encoding to compress the response to `req` with, or `None` for `identity`.
+    pub(crate) fn negotiate(&self, req: &Request<Body>) -> Option<Encoding> {
+        if req.method() == Method::HEAD {
+            return None;
+        }
+        let value = req
+            .headers()
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        // Unlike `AcceptEncoding::negotiate`, prefer any accepted compression over the implicit
+        // `identity`.
+        let accept = AcceptEncoding::parse(&value);
+        let mut best: Option<(Encoding, f32)> = None;
+        for &encoding in SUPPORTED {
+            let quality = accept.quality(encoding);
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((encoding, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// Compress `res` with `encoding` unless it should be left as it is.
+    pub(crate) fn compress(
+        &self,
+        mut res: Response<Body>,
+        encoding: Option<Encoding>,
+    ) -> Response<Body> {
+        if !self.is_compressible(&res) {
+            return res;
+        }
+        // The response differs by `Accept-Encoding` even if this client gets it uncompressed.
+        res.headers_mut()
+            .append(VARY, HeaderValue::from_static("accept-encoding"));
+        let encoding = match encoding {
+            Some(encoding) => encoding,
+            None => return res,
+        };
+        let encoder = match self.encoder(encoding) {
+            Ok(Some(encoder)) => encoder,
+            _ => return res,
+        };
+
+        let headers = res.headers_mut();
+        headers.insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+        headers.remove(CONTENT_LENGTH);
+        headers.remove(ACCEPT_RANGES);
+        // The compressed bytes differ, so a strong tag of the original ones would be wrong.
+        if let Some(etag) = headers.get(ETAG).and_then(|etag| etag.to_str().ok()) {
+            if !etag.starts_with("W/") {
+                if let Ok(weak) = HeaderValue::from_str(&format!("W/{}", etag)) {
+                    headers.insert(ETAG, weak);
+                }
+            }
+        }
+        let flush = streaming::is_must_stream(&res);
+        res.map(|body| encoder.encode(body, flush))
+    }
+
+    fn is_compressible(&self, res: &Response<Body>) -> bool {
+        let headers = res.headers();
+        let status = res.status();
+        if status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || status == StatusCode::PARTIAL_CONTENT
+            || res.extensions().get::<SkipCompression>().is_some()
+            || headers.contains_key(CONTENT_ENCODING)
+            || headers.contains_key(CONTENT_RANGE)
+        {
+            return false;
+        }
+        let length = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok())
+            .or_else(|| res.body().size_hint().exact());
+        if length.is_some_and(|length| length < self.min_size) {
+            return false;
+        }
+        if CacheControl::from_headers(headers)
+            .is_some_and(|cache_control| cache_control.is_no_transform())
+        {
+            return false;
+        }
+        let essence = match headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+        {
+            Some(content_type) => essence(content_type),
+            None => return true,
+        };
+        let skipped = self
+            .skipped_types
+            .iter()
+            .any(|skipped| match skipped.strip_suffix("/*") {
+                Some(kind) => essence.split('/').next() == Some(kind),
+                None => *skipped == essence,
+            });
+        !skipped && is_compressible_type(&essence)
+    }
+
+    fn encoder(&self, encoding: Encoding) -> io::Result<Option<Encoder>> {
+        let level = flate2::Compression::new(self.level);
+        let encoder = match encoding {
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), level)),
+            // `deflate` in HTTP is the zlib format, not raw deflate.
+            Encoding::Deflate => Encoder::Deflate(ZlibEncoder::new(Vec::new(), level)),
+            #[cfg(feature = "compression-brotli")]
+            Encoding::Brotli => Encoder::Brotli(Box::new(brotli::CompressorWriter::new(
+                Vec::new(),
+                4096,
+                self.brotli_quality,
+                22,
+            ))),
+            #[cfg(feature = "compression-zstd")]
+            Encoding::Zstd => Encoder::Zstd(zstd::stream::write::Encoder::new(
+                Vec::new(),
+                self.zstd_level,
+            )?),
+            _ => return Ok(None),
+        };
+        Ok(Some(encoder))
+    }
+}
+
+/// The encodings responses can be compressed with, in order of preference.
+const SUPPORTED: &[Encoding] = &[
+    #[cfg(feature = "compression-brotli")]
+    Encoding::Brotli,
+    #[cfg(feature = "compression-zstd")]
+    Encoding::Zstd,
+    Encoding::Gzip,
+    Encoding::Deflate,
+];
+
+/// Get the type and subtype of `content_type`, without parameters.
+fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// Check whether the content type `essence` isn't compressed already.
+fn is_compressible_type(essence: &str) -> bool {
+    let (kind, subtype) = essence.split_once('/').unwrap_or((essence, ""));
+    match kind {
+        "image" => subtype == "svg+xml" || subtype == "bmp" || subtype == "x-icon",
+        "video" | "audio" => false,
+        "font" => subtype == "ttf" || subtype == "otf",
+        "application" => !matches!(
+            subtype,
+            "zip"
+                | "gzip"
+                | "x-gzip"
+                | "zstd"
+                | "x-bzip2"
+                | "x-xz"
+                | "x-7z-compressed"
+                | "x-rar-compressed"
+                | "vnd.rar"
+                | "pdf"
+                | "octet-stream"
+        ),
+        _ => true,
+    }
+}
+
+enum Encoder {
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+    #[cfg(feature = "compression-brotli")]
+    Brotli(Box<brotli::CompressorWriter<Vec<u8>>>),
+    #[cfg(feature = "compression-zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+}
+
+impl Encoder {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Encoder::Gzip(encoder) => encoder,
+            Encoder::Deflate(encoder) => encoder,
+            #[cfg(feature = "compression-brotli")]
+            Encoder::Brotli(encoder) => encoder,
+            #[cfg(feature = "compression-zstd")]
+            Encoder::Zstd(encoder) => encoder,
+        }
+    }
+
+    fn output(&mut self) -> &mut Vec<u8> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.get_mut(),
+            Encoder::Deflate(encoder) => encoder.get_mut(),
+            #[cfg(feature = "compression-brotli")]
+            Encoder::Brotli(encoder) => encoder.get_mut(),
+            #[cfg(feature = "compression-zstd")]
+            Encoder::Zstd(encoder) => encoder.get_mut(),
+        }
+    }
+
+    /// Compress `chunk`, returning the compressed bytes which are ready.
+    fn write(&mut self, chunk: &[u8], flush: bool) -> io::Result<Bytes> {
+        let writer = self.writer();
+        writer.write_all(chunk)?;
+        if flush {
+            writer.flush()?;
+        }
+        Ok(Bytes::from(std::mem::take(self.output())))
+    }
+
+    fn finish(self) -> io::Result<Bytes> {
+        let output = match self {
+            Encoder::Gzip(encoder) => encoder.finish()?,
+            Encoder::Deflate(encoder) => encoder.finish()?,
+            // Taking the output finishes the stream.
+            #[cfg(feature = "compression-brotli")]
+            Encoder::Brotli(encoder) => encoder.into_inner(),
+            #[cfg(feature = "compression-zstd")]
+            Encoder::Zstd(encoder) => encoder.finish()?,
+        };
+        Ok(Bytes::from(output))
+    }
+
+    fn encode(self, body: Body, flush: bool) -> Body {
+        let stream = futures_util::stream::unfold(Some((body, self)), move |state| async move {
+            let (mut body, mut encoder) = state?;
+            loop {
+                let output = match body.next().await {
+                    Some(Ok(chunk)) => encoder.write(&chunk, flush),
+                    Some(Err(err)) => return Some((Err(util::into_cause(err)), None)),
+                    None => {
+                        let output = encoder.finish().map_err(Into::into);
+                        return Some((output, None));
+                    }
+                };
+                match output {
+                    Ok(output) if output.is_empty() => continue,
+                    Ok(output) => return Some((Ok(output), Some((body, encoder)))),
+                    Err(err) => return Some((Err(err.into()), None)),
+                }
+            }
+        });
+        Body::wrap_stream(stream)
+    }
+}
@@ -0,0 +1,269 @@
+//! Adaptive concurrency limits.
+//!
+//! A static [`Route::concurrency_limit`](crate::Route::concurrency_limit) has to be tuned to
+//! the capacity of the dependencies behind a route, which shifts with their load. An
+//! [`AdaptiveLimit`] set with
+//! [`Route::adaptive_concurrency`](crate::Route::adaptive_concurrency) instead adjusts the
+//! limit to the latency observed for the route's requests:
+//!
+//! - [`AdaptiveLimit::aimd`] raises the limit by one while requests are faster than a latency
+//!   threshold, and cuts it by a ratio once one is slower.
+//! - [`AdaptiveLimit::gradient`] compares the latency of each request with a long-term
+//!   average and lowers the limit as latency grows above it, without a fixed threshold.
+//!
+//! Requests answered with `503 Service Unavailable` or `504 Gateway Timeout`, and requests cut
+//! off before they finish, e.g. by the route's timeout, count as overload and lower the limit
+//! as well. The limit only grows while the route actually uses a good share of it.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::concurrency::AdaptiveLimit;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router
+//!     .get("/search", search)
+//!     .adaptive_concurrency(AdaptiveLimit::aimd(Duration::from_millis(200)).max_limit(200))
+//!     .load_shed();
+//! router
+//!     .post("/reports", search)
+//!     .adaptive_concurrency(AdaptiveLimit::gradient().initial_limit(10));
+//!
+//! async fn search(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//! ```
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Response, StatusCode};
+use tokio::sync::Notify;
+
+/// How the concurrency limit of a route adapts to its latency.
+#[derive(Debug, Clone)]
+pub struct AdaptiveLimit {
+    algorithm: Algorithm,
+    initial: usize,
+    min: usize,
+    max: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Algorithm {
+    Aimd { threshold: Duration, backoff: f64 },
+    Gradient { tolerance: f64, smoothing: f64 },
+}
+
+impl AdaptiveLimit {
+    /// Raise the limit by one for every request faster than `threshold`, and multiply it by
+    /// the backoff ratio of `0.9` for every slower or overloaded request.
+    pub fn aimd(threshold: Duration) -> Self {
+        Self::with_algorithm(Algorithm::Aimd {
+            threshold,
+            backoff: 0.9,
+        })
+    }
+
+    /// Lower the limit as the latency of requests grows above their long-term average, and
+    /// raise it while latency stays within `1.5` times of it.
+    pub fn gradient() -> Self {
+        Self::with_algorithm(Algorithm::Gradient {
+            tolerance: 1.5,
+            smoothing: 0.2,
+        })
+    }
+
+    fn with_algorithm(algorithm: Algorithm) -> Self {
+        Self {
+            algorithm,
+            initial: 20,
+            min: 1,
+            max: 1000,
+        }
+    }
+
+    /// Start with `limit` concurrent requests. Defaults to 20.
+    pub fn initial_limit(mut self, limit: usize) -> Self {
+        self.initial = limit;
+        self
+    }
+
+    /// Never go below `limit` concurrent requests. Defaults to 1.
+    pub fn min_limit(mut self, limit: usize) -> Self {
+        self.min = limit.max(1);
+        self
+    }
+
+    /// Never go above `limit` concurrent requests. Defaults to 1000.
+    pub fn max_limit(mut self, limit: usize) -> Self {
+        self.max = limit;
+        self
+    }
+
+    /// Set the ratio the limit is multiplied by when a request is slower than the threshold
+    /// of [`AdaptiveLimit::aimd`]. Ignored by [`AdaptiveLimit::gradient`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ratio` is not between 0 and 1.
+    pub fn backoff_ratio(mut self, ratio: f64) -> Self {
+        assert!(
+            ratio > 0.0 && ratio < 1.0,
+            "the backoff ratio must be between 0 and 1"
+        );
+        if let Algorithm::Aimd { backoff, .. } = &mut self.algorithm {
+            *backoff = ratio;
+        }
+        self
+    }
+}
+
+/// The permits for concurrent requests to a route with an adaptive limit.
+pub(crate) struct AdaptiveLimiter {
+    config: AdaptiveLimit,
+    state: Mutex<State>,
+    released: Notify,
+}
+
+struct State {
+    limit: f64,
+    in_flight: usize,
+    /// The long-term average latency in seconds, for the gradient algorithm.
+    average: Option<f64>,
+}
+
+impl AdaptiveLimiter {
+    pub(crate) fn new(config: AdaptiveLimit) -> Arc<Self> {
+        let limit = config.initial.clamp(config.min, config.max.max(config.min)) as f64;
+        Arc::new(Self {
+            config,
+            state: Mutex::new(State {
+                limit,
+                in_flight: 0,
+                average: None,
+            }),
+            released: Notify::new(),
+        })
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Take a permit if the limit isn't reached.
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Option<AdaptivePermit> {
+        let mut state = self.state();
+        if state.in_flight >= state.limit as usize {
+            return None;
+        }
+        state.in_flight += 1;
+        Some(AdaptivePermit {
+            limiter: self.clone(),
+            start: Instant::now(),
+            finished: false,
+        })
+    }
+
+    /// Wait until a permit can be taken.
+    pub(crate) async fn acquire(self: Arc<Self>) -> AdaptivePermit {
+        loop {
+            let released = self.released.notified();
+            futures_util::pin_mut!(released);
+            // Register for the notification before checking, so a permit released in
+            // between isn't missed.
+            released.as_mut().enable();
+            if let Some(permit) = self.try_acquire() {
+                return permit;
+            }
+            released.await;
+        }
+    }
+
+    fn release(&self, latency: Duration, overloaded: bool) {
+        let mut state = self.state();
+        // The requests in flight while this one ran, including itself.
+        let in_flight = state.in_flight;
+        state.in_flight -= 1;
+        let config = &self.config;
+        let min = config.min as f64;
+        let max = config.max.max(config.min) as f64;
+        // Only grow the limit while it's actually used, so it doesn't drift upwards while the
+        // route is idle.
+        let used = in_flight as f64 * 2.0 >= state.limit;
+
+        let limit = match &config.algorithm {
+            Algorithm::Aimd { threshold, backoff } => {
+                if overloaded || latency > *threshold {
+                    state.limit * backoff
+                } else if used {
+                    state.limit + 1.0
+                } else {
+                    state.limit
+                }
+            }
+            Algorithm::Gradient {
+                tolerance,
+                smoothing,
+            } => {
+                let sample = latency.as_secs_f64().max(1e-6);
+                let average = match state.average {
+                    Some(average) => average + (sample - average) / 100.0,
+                    None => sample,
+                };
+                state.average = Some(average);
+                let gradient = if overloaded {
+                    0.5
+                } else {
+                    (tolerance * average / sample).clamp(0.5, 1.0)
+                };
+                if gradient >= 1.0 && !used {
+                    state.limit
+                } else {
+                    // Leave room for a queue growing with the square root of the limit.
+                    let target = state.limit * gradient + state.limit.sqrt();
+                    state.limit * (1.0 - smoothing) + target * smoothing
+                }
+            }
+        };
+        state.limit = limit.clamp(min, max);
+        drop(state);
+        self.released.notify_waiters();
+    }
+}
+
+/// A permit of an [`AdaptiveLimiter`], which reports the latency of its request when it's
+/// finished or dropped.
+pub(crate) struct AdaptivePermit {
+    limiter: Arc<AdaptiveLimiter>,
+    start: Instant,
+    finished: bool,
+}
+
+impl AdaptivePermit {
+    /// Report the response of the handler.
+    pub(crate) fn finish<E>(mut self, res: &Result<Response<Body>, E>) {
+        let overloaded = matches!(
+            res,
+            Ok(res) if res.status() == StatusCode::SERVICE_UNAVAILABLE
+                || res.status() == StatusCode::GATEWAY_TIMEOUT
+        );
+        self.finished = true;
+        self.limiter.release(self.start.elapsed(), overloaded);
+    }
+}
+
+impl Drop for AdaptivePermit {
+    fn drop(&mut self) {
+        // A request dropped before it finished, e.g. by a timeout, counts as overload.
+        if !self.finished {
+            self.limiter.release(self.start.elapsed(), true);
+        }
+    }
+}
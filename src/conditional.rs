@@ -0,0 +1,366 @@
+//! Conditional requests with `ETag` and `Last-Modified` validators.
+//!
+//! A [`Conditional`] holds the validators of the current version of a resource and evaluates
+//! the preconditions of a request against them: reads with a matching `If-None-Match` or an
+//! `If-Modified-Since` at or after the last modification are answered with
+//! `304 Not Modified`, and writes whose `If-Match` or `If-Unmodified-Since` doesn't match are
+//! answered with `412 Precondition Failed`, so concurrent updates can't overwrite each other.
+//!
+//! [`ServeDir`](crate::files::ServeDir) uses it for file responses.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::conditional::{Conditional, ETag};
+//!
+//! async fn get_document(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let (version, document) = (7, "...");
+//!     let validators = Conditional::new().etag(ETag::strong(version.to_string()));
+//!     if let Some(res) = validators.evaluate(&req) {
+//!         return Ok(res);
+//!     }
+//!     let mut res = Response::new(Body::from(document));
+//!     validators.apply(&mut res);
+//!     Ok(res)
+//! }
+//!
+//! async fn put_document(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let version = 7;
+//!     // Clients must send the version they are updating in `If-Match`.
+//!     let validators = Conditional::new()
+//!         .etag(ETag::strong(version.to_string()))
+//!         .require_if_match();
+//!     if let Some(res) = validators.evaluate(&req) {
+//!         return Ok(res);
+//!     }
+//!     // Store the document...
+//!     let mut res = Response::new(Body::empty());
+//!     Conditional::new()
+//!         .etag(ETag::strong((version + 1).to_string()))
+//!         .apply(&mut res);
+//!     Ok(res)
+//! }
+//! ```
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::header::{
+    HeaderValue, ETAG, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_UNMODIFIED_SINCE,
+    LAST_MODIFIED,
+};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+/// An entity tag identifying a version of a resource.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ETag {
+    tag: String,
+    weak: bool,
+}
+
+impl ETag {
+    /// Create a strong tag, which changes whenever the bytes of the resource change.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` contains a `"` or characters which are not allowed in headers.
+    pub fn strong(tag: impl Into<String>) -> Self {
+        Self::new(tag.into(), false)
+    }
+
+    /// Create a weak tag, which only changes when the resource changes meaningfully, e.g. not
+    /// when it's re-encoded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tag` contains a `"` or characters which are not allowed in headers.
+    pub fn weak(tag: impl Into<String>) -> Self {
+        Self::new(tag.into(), true)
+    }
+
+    fn new(tag: String, weak: bool) -> Self {
+        assert!(
+            tag.bytes()
+                .all(|b| b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80),
+            "invalid entity tag"
+        );
+        Self { tag, weak }
+    }
+
+    /// Create a strong tag from a hash of `bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        // FNV-1a, which is stable across builds and restarts.
+        let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, b| {
+            (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+        });
+        Self::strong(format!("{:016x}", hash))
+    }
+
+    /// Get the opaque tag, without quotes.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Compare with `other`, where both have to be strong.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Compare with `other`, ignoring whether they are weak.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Parse a single tag such as `"abc"` or `W/"abc"`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (weak, quoted) = match value.strip_prefix("W/") {
+            Some(quoted) => (true, quoted),
+            None => (false, value),
+        };
+        let tag = quoted.strip_prefix('"')?.strip_suffix('"')?;
+        if tag.contains('"') {
+            return None;
+        }
+        Some(Self {
+            tag: tag.to_string(),
+            weak,
+        })
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+/// The tags listed in an `If-Match` or `If-None-Match` header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tags {
+    /// `*`, matching any current version.
+    Any,
+    List(Vec<ETag>),
+}
+
+impl Tags {
+    /// Check whether `etag` is listed, ignoring whether the tags are weak, as for
+    /// `If-None-Match`.
+    pub fn contains(&self, etag: &ETag) -> bool {
+        self.matches(Some(etag), true, false)
+    }
+
+    pub(crate) fn from_request(
+        req: &Request<Body>,
+        name: hyper::header::HeaderName,
+    ) -> Option<Self> {
+        let mut values = req
+            .headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .peekable();
+        values.peek()?;
+        let mut tags = Vec::new();
+        for value in values {
+            for tag in split_tags(value) {
+                if tag == "*" {
+                    return Some(Tags::Any);
+                }
+                tags.extend(ETag::parse(tag));
+            }
+        }
+        Some(Tags::List(tags))
+    }
+
+    fn matches(&self, etag: Option<&ETag>, exists: bool, strong: bool) -> bool {
+        match self {
+            Tags::Any => exists,
+            Tags::List(tags) => etag.is_some_and(|etag| {
+                tags.iter().any(|tag| {
+                    if strong {
+                        tag.strong_eq(etag)
+                    } else {
+                        tag.weak_eq(etag)
+                    }
+                })
+            }),
+        }
+    }
+}
+
+/// Split a list of entity tags at the commas between them. Tags can't contain quotes, so
+/// commas within them are found by tracking quotes.
+fn split_tags(value: &str) -> impl Iterator<Item = &str> {
+    let mut quoted = false;
+    value
+        .split(move |c| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c == ',' && !quoted
+        })
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+}
+
+/// The validators of the current version of a resource.
+#[derive(Debug, Clone)]
+pub struct Conditional {
+    etag: Option<ETag>,
+    last_modified: Option<SystemTime>,
+    exists: bool,
+    require_if_match: bool,
+}
+
+impl Default for Conditional {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Conditional {
+    /// Create validators for an existing resource without an entity tag or modification time.
+    pub fn new() -> Self {
+        Self {
+            etag: None,
+            last_modified: None,
+            exists: true,
+            require_if_match: false,
+        }
+    }
+
+    /// Create validators for a resource which doesn't exist yet, so `If-None-Match: *` can
+    /// be used to only create it, and `If-Match` always fails.
+    pub fn absent() -> Self {
+        Self {
+            exists: false,
+            ..Self::new()
+        }
+    }
+
+    pub fn etag(mut self, etag: ETag) -> Self {
+        self.etag = Some(etag);
+        self
+    }
+
+    pub fn last_modified(mut self, time: SystemTime) -> Self {
+        self.last_modified = Some(time);
+        self
+    }
+
+    pub(crate) fn etag_ref(&self) -> Option<&ETag> {
+        self.etag.as_ref()
+    }
+
+    /// Answer requests without `If-Match` or `If-Unmodified-Since` with
+    /// `428 Precondition Required`, e.g. for updates which must not overwrite changes made by
+    /// other clients.
+    pub fn require_if_match(mut self) -> Self {
+        self.require_if_match = true;
+        self
+    }
+
+    /// Evaluate the preconditions of `req`, returning the response to send instead of
+    /// handling the request if they fail: `304 Not Modified` for reads and
+    /// `412 Precondition Failed` for writes, or `428 Precondition Required`.
+    pub fn evaluate(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        let read = req.method() == Method::GET || req.method() == Method::HEAD;
+        let if_match = Tags::from_request(req, IF_MATCH);
+        let if_unmodified_since = date(req, IF_UNMODIFIED_SINCE);
+        if self.require_if_match && if_match.is_none() && if_unmodified_since.is_none() {
+            return Some(self.response(StatusCode::PRECONDITION_REQUIRED));
+        }
+
+        // The order of evaluation follows RFC 9110, section 13.2.2.
+        if let Some(tags) = if_match {
+            if !tags.matches(self.etag.as_ref(), self.exists, true) {
+                return Some(self.response(StatusCode::PRECONDITION_FAILED));
+            }
+        } else if let Some(since) = if_unmodified_since {
+            if !self.exists || self.modified_since(since) == Some(true) {
+                return Some(self.response(StatusCode::PRECONDITION_FAILED));
+            }
+        }
+
+        if let Some(tags) = Tags::from_request(req, IF_NONE_MATCH) {
+            if tags.matches(self.etag.as_ref(), self.exists, false) {
+                let status = if read {
+                    StatusCode::NOT_MODIFIED
+                } else {
+                    StatusCode::PRECONDITION_FAILED
+                };
+                return Some(self.response(status));
+            }
+        } else if let (true, Some(since)) = (read, date(req, IF_MODIFIED_SINCE)) {
+            if self.exists && self.modified_since(since) == Some(false) {
+                return Some(self.response(StatusCode::NOT_MODIFIED));
+            }
+        }
+        None
+    }
+
+    /// Set the `ETag` and `Last-Modified` headers of `res`.
+    pub fn apply(&self, res: &mut Response<Body>) {
+        if let Some(etag) = &self.etag {
+            if let Ok(value) = HeaderValue::from_str(&etag.to_string()) {
+                res.headers_mut().insert(ETAG, value);
+            }
+        }
+        if let Some(modified) = self.last_modified {
+            if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+                res.headers_mut().insert(LAST_MODIFIED, value);
+            }
+        }
+    }
+
+    /// Check whether the resource was modified after `since`, at the one second resolution
+    /// of HTTP dates, or `None` if its modification time isn't known.
+    fn modified_since(&self, since: SystemTime) -> Option<bool> {
+        let seconds = |time: SystemTime| {
+            time.duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs())
+        };
+        let modified = self.last_modified?;
+        Some(seconds(modified) > seconds(since))
+    }
+
+    fn response(&self, status: StatusCode) -> Response<Body> {
+        let mut res = Response::builder()
+            .status(status)
+            .body(Body::empty())
+            .unwrap();
+        if status == StatusCode::NOT_MODIFIED {
+            self.apply(&mut res);
+        }
+        res
+    }
+}
+
+/// Check whether `req` has any preconditions.
+pub(crate) fn has_preconditions(req: &Request<Body>) -> bool {
+    let headers = req.headers();
+    [
+        IF_MATCH,
+        IF_NONE_MATCH,
+        IF_MODIFIED_SINCE,
+        IF_UNMODIFIED_SINCE,
+    ]
+    .iter()
+    .any(|name| headers.contains_key(name))
+}
+
+fn date(req: &Request<Body>, name: hyper::header::HeaderName) -> Option<SystemTime> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+}
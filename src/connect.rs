@@ -0,0 +1,163 @@
+//! Information about the connections requests arrive on.
+//!
+//! The [`MakeRouterService`](crate::MakeRouterService) serves connections of any listener,
+//! and adds the remote address of the connections it knows to the extensions of every request
+//! on the connection as [`ConnectInfo`]: those of hyper's [`AddrIncoming`], also when wrapped
+//! in a [`CountingIncoming`](crate::accounting::CountingIncoming) or, with the `tls` feature,
+//! a `tokio-rustls` TLS stream. The [`server`](crate::server) knows the addresses of all its
+//! connections. Handlers get it with
+//! [`RequestExt::remote_addr`](crate::ext::RequestExt::remote_addr), or by extracting
+//! [`ConnectInfo`], which fails with `500 Internal Server Error` if the listener doesn't
+//! know its peers.
+//!
+//! [`AddrIncoming`]: hyper::server::conn::AddrIncoming
+//!
+//! With the `tls` feature, connections whose clients authenticated with a certificate, as
+//! [`ServerTls::require_client_cert`](crate::server::ServerTls::require_client_cert) asks
+//! them to, also add the verified certificate chain of the client as [`PeerCertificates`].
+//! Handlers get it with
+//! [`RequestExt::peer_certificates`](crate::ext::RequestExt::peer_certificates), or by
+//! extracting [`PeerCertificates`], which fails with `401 Unauthorized` for clients without a
+//! certificate.
+
+use std::any::Any;
+use std::net::SocketAddr;
+#[cfg(feature = "tls")]
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::server::conn::AddrStream;
+use hyper::service::Service;
+use hyper::{Body, Request, StatusCode};
+
+use crate::accounting::CountingStream;
+use crate::extract::{FromRequest, Rejection};
+
+/// The remote address of the connection a request arrived on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectInfo(pub SocketAddr);
+
+impl FromRequest for ConnectInfo {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        req.extensions()
+            .get::<ConnectInfo>()
+            .copied()
+            .ok_or_else(|| {
+                Rejection::new(StatusCode::INTERNAL_SERVER_ERROR, "remote address unknown")
+            })
+    }
+}
+
+/// The certificate chain a client authenticated with, verified against the client roots of
+/// the server.
+///
+/// Requires the `tls` feature.
+#[cfg(feature = "tls")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerCertificates(Arc<[rustls::Certificate]>);
+
+#[cfg(feature = "tls")]
+impl PeerCertificates {
+    /// Wrap the `chain` of a client, or return `None` if it's empty.
+    pub(crate) fn new(chain: &[rustls::Certificate]) -> Option<Self> {
+        if chain.is_empty() {
+            return None;
+        }
+        Some(Self(chain.into()))
+    }
+
+    /// Get the certificate of the client itself, whose DER encoding identifies the client.
+    pub fn end_entity(&self) -> &rustls::Certificate {
+        &self.0[0]
+    }
+
+    /// Get the whole chain, starting with the certificate of the client, followed by the
+    /// intermediates it sent.
+    pub fn chain(&self) -> &[rustls::Certificate] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "tls")]
+impl FromRequest for PeerCertificates {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        req.extensions()
+            .get::<PeerCertificates>()
+            .cloned()
+            .ok_or_else(|| Rejection::new(StatusCode::UNAUTHORIZED, "client certificate required"))
+    }
+}
+
+/// Get the remote address of `conn`, if it's a connection of one of the listeners whose
+/// connections know their peers.
+pub(crate) fn remote_addr(conn: &dyn Any) -> Option<SocketAddr> {
+    if let Some(conn) = conn.downcast_ref::<AddrStream>() {
+        return Some(conn.remote_addr());
+    }
+    if let Some(conn) = conn.downcast_ref::<CountingStream<AddrStream>>() {
+        return Some(conn.get_ref().remote_addr());
+    }
+    #[cfg(feature = "tls")]
+    {
+        use tokio_rustls::server::TlsStream;
+
+        if let Some(conn) = conn.downcast_ref::<TlsStream<AddrStream>>() {
+            return Some(conn.get_ref().0.remote_addr());
+        }
+        if let Some(conn) = conn.downcast_ref::<TlsStream<CountingStream<AddrStream>>>() {
+            return Some(conn.get_ref().0.get_ref().remote_addr());
+        }
+    }
+    None
+}
+
+/// The service of a connection, adding its [`ConnectInfo`] to every request.
+#[derive(Debug, Clone)]
+pub struct ConnectedService<Svc> {
+    inner: Svc,
+    info: Option<ConnectInfo>,
+    #[cfg(feature = "tls")]
+    peer_certificates: Option<PeerCertificates>,
+}
+
+impl<Svc> ConnectedService<Svc> {
+    pub(crate) fn new(inner: Svc, remote_addr: Option<SocketAddr>) -> Self {
+        Self {
+            inner,
+            info: remote_addr.map(ConnectInfo),
+            #[cfg(feature = "tls")]
+            peer_certificates: None,
+        }
+    }
+
+    /// Add `peer_certificates` to every request too.
+    #[cfg(feature = "tls")]
+    pub(crate) fn peer_certificates(mut self, peer_certificates: Option<PeerCertificates>) -> Self {
+        self.peer_certificates = peer_certificates;
+        self
+    }
+}
+
+impl<Svc> Service<Request<Body>> for ConnectedService<Svc>
+where
+    Svc: Service<Request<Body>>,
+{
+    type Response = Svc::Response;
+    type Error = Svc::Error;
+    type Future = Svc::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        if let Some(info) = self.info {
+            req.extensions_mut().insert(info);
+        }
+        #[cfg(feature = "tls")]
+        if let Some(peer_certificates) = &self.peer_certificates {
+            req.extensions_mut().insert(peer_certificates.clone());
+        }
+        self.inner.call(req)
+    }
+}
@@ -0,0 +1,303 @@
+//! Cookies.
+//!
+//! [`RequestExt::cookies`](crate::ext::RequestExt::cookies) parses the `Cookie` headers of a
+//! request into a [`CookieJar`], and
+//! [`ResponseExt::set_cookie`](crate::ext::ResponseExt::set_cookie) adds a [`Cookie`] to a
+//! response as a `Set-Cookie` header. Values are percent-encoded where they contain
+//! characters cookies can't hold, such as spaces, commas or semicolons, and decoded again
+//! when parsed.
+//!
+//! With the `secure-cookies` feature, cookies can be signed with [`SignedCookies`], so
+//! clients can't change them, or encrypted with [`PrivateCookies`], so clients can't read
+//! them either. Both use keys derived from the secret set with
+//! [`Router::cookie_key`](crate::Router::cookie_key).
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::cookie::{Cookie, SameSite};
+//! use keiro::prelude::*;
+//!
+//! async fn visit(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let visits = req
+//!         .cookies()
+//!         .get("visits")
+//!         .and_then(|visits| visits.parse::<u64>().ok())
+//!         .unwrap_or(0);
+//!     let mut res = Response::text(format!("visit #{}", visits + 1));
+//!     res.set_cookie(
+//!         Cookie::new("visits", (visits + 1).to_string())
+//!             .max_age(Duration::from_secs(365 * 24 * 60 * 60))
+//!             .http_only()
+//!             .same_site(SameSite::Lax),
+//!     );
+//!     Ok(res)
+//! }
+//! ```
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "secure-cookies")]
+//! # {
+//! use hyper::{Body, Request, Response};
+//! use keiro::cookie::{Cookie, PrivateCookies};
+//! use keiro::prelude::*;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.cookie_key(b"a long random secret of at least 32 bytes".to_vec());
+//! router.post("/login", login);
+//!
+//! async fn login(req: Request<Body>) -> keiro::Result<Response<Body>> {
+//!     let cookies = req.extract::<PrivateCookies>()?;
+//!     let mut res = Response::no_content();
+//!     res.set_cookie(cookies.encrypt(Cookie::new("session", "user 42").http_only()));
+//!     Ok(res)
+//! }
+//! # }
+//! ```
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use hyper::header::COOKIE;
+use hyper::{Body, Request};
+
+use crate::extract::{FromRequest, Rejection};
+use crate::url;
+
+#[cfg(feature = "secure-cookies")]
+mod secure;
+
+#[cfg(feature = "secure-cookies")]
+pub use self::secure::{Key, PrivateCookies, SignedCookies};
+
+/// The `SameSite` attribute of a cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    /// Sent with cross-site requests too, which requires the cookie to be
+    /// [`secure`](Cookie::secure) in browsers.
+    None,
+}
+
+/// A cookie to set on a response, built like `Cookie::new("name", "value").http_only()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// Create a session cookie, which browsers drop when they're closed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is empty or contains characters which are not allowed in cookie names,
+    /// such as spaces, `=` or `;`.
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        assert!(
+            !name.is_empty() && name.bytes().all(is_token),
+            "invalid cookie name"
+        );
+        Self {
+            name,
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// Create a cookie telling browsers to delete the cookie `name`. The path and domain have
+    /// to be the ones it was set with.
+    pub fn removal(name: impl Into<String>) -> Self {
+        Self::new(name, "")
+            .max_age(Duration::ZERO)
+            .expires(SystemTime::UNIX_EPOCH)
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `path` contains `;` or control characters.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(attribute(path.into()));
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `domain` contains `;` or control characters.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(attribute(domain.into()));
+        self
+    }
+
+    /// Keep the cookie for `max_age`, at the one second resolution of cookies.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Keep the cookie until `expires`. Browsers prefer [`Cookie::max_age`] when both are set.
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Only send the cookie over HTTPS.
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    /// Hide the cookie from scripts.
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+/// Formats the value of a `Set-Cookie` header.
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}=", self.name)?;
+        for byte in self.value.bytes() {
+            if is_cookie_octet(byte) {
+                write!(f, "{}", byte as char)?;
+            } else {
+                write!(f, "%{:02X}", byte)?;
+            }
+        }
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        match self.same_site {
+            Some(SameSite::Strict) => f.write_str("; SameSite=Strict")?,
+            Some(SameSite::Lax) => f.write_str("; SameSite=Lax")?,
+            Some(SameSite::None) => f.write_str("; SameSite=None")?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// The cookies sent with a request, with decoded values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CookieJar {
+    cookies: Vec<(String, String)>,
+}
+
+impl CookieJar {
+    /// Parse the `Cookie` headers of `req`. Pairs without `=` are skipped.
+    pub(crate) fn from_headers(req: &Request<Body>) -> Self {
+        let cookies = req
+            .headers()
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .map(|(name, value)| {
+                let value = value.trim().trim_matches('"');
+                let value = url::decode(value).unwrap_or_else(|| value.to_string());
+                (name.trim().to_string(), value)
+            })
+            .collect();
+        Self { cookies }
+    }
+
+    /// Get the value of the cookie `name`. Browsers send the cookie with the most specific
+    /// path first when several have the same name.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Iterate over the names and values of all cookies, in the order they were sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+impl FromRequest for CookieJar {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        Ok(Self::from_headers(req))
+    }
+}
+
+/// Characters allowed in tokens by RFC 9110, section 5.6.2.
+fn is_token(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$&'*+-.^_`|~".contains(&byte)
+}
+
+/// Characters allowed in cookie values by RFC 6265, section 4.1.1, except `%` which starts
+/// encoded characters.
+fn is_cookie_octet(byte: u8) -> bool {
+    matches!(byte, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e) && byte != b'%'
+}
+
+fn attribute(value: String) -> String {
+    assert!(
+        !value.contains(';') && !value.chars().any(char::is_control),
+        "invalid cookie attribute"
+    );
+    value
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use hyper::{Body, Request, StatusCode};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use sha2::Sha256;
+
+use super::{Cookie, CookieJar};
+use crate::extract::{FromRequest, Rejection};
+
+/// The length of a base64 encoded HMAC-SHA256 tag, which prefixes signed values.
+const SIGNATURE_LEN: usize = 43;
+
+/// The keys signing and encrypting cookies, derived from a secret set with
+/// [`Router::cookie_key`](crate::Router::cookie_key).
+#[derive(Clone)]
+pub struct Key {
+    signing: Arc<[u8; 32]>,
+    encryption: Arc<[u8; 32]>,
+}
+
+impl Key {
+    /// Derive the keys from `secret`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `secret` is shorter than 32 bytes.
+    pub fn new(secret: &[u8]) -> Self {
+        assert!(secret.len() >= 32, "cookie keys need at least 32 bytes");
+        let derive = |label: &[u8]| {
+            let mut mac =
+                Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+            mac.update(label);
+            Arc::new(<[u8; 32]>::from(mac.finalize().into_bytes()))
+        };
+        Self {
+            signing: derive(b"keiro signed cookies"),
+            encryption: derive(b"keiro private cookies"),
+        }
+    }
+
+    fn mac(&self, name: &str, value: &str) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&*self.signing).expect("HMAC accepts keys of any size");
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        mac.update(value.as_bytes());
+        mac
+    }
+
+    fn aead(&self) -> LessSafeKey {
+        LessSafeKey::new(
+            UnboundKey::new(&AES_256_GCM, &*self.encryption).expect("AES-256 keys are 32 bytes"),
+        )
+    }
+}
+
+// Don't leak the keys into logs.
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Key").finish_non_exhaustive()
+    }
+}
+
+impl FromRequest for Key {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        req.extensions().get::<Key>().cloned().ok_or_else(|| {
+            Rejection::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "no cookie key configured",
+            )
+        })
+    }
+}
+
+/// The cookies of a request which are signed, so clients can read but not change them.
+///
+/// Extracting it fails with `500 Internal Server Error` if the router has no
+/// [cookie key](crate::Router::cookie_key).
+#[derive(Debug, Clone)]
+pub struct SignedCookies {
+    jar: CookieJar,
+    key: Key,
+}
+
+impl SignedCookies {
+    /// Get the value of the cookie `name`, or `None` if it's missing or its signature is
+    /// invalid.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let signed = self.jar.get(name)?;
+        let signature = URL_SAFE_NO_PAD.decode(signed.get(..SIGNATURE_LEN)?).ok()?;
+        let value = &signed[SIGNATURE_LEN..];
+        self.key
+            .mac(name, value)
+            .verify_slice(&signature)
+            .ok()
+            .map(|_| value)
+    }
+
+    /// Sign the value of `cookie`, so it can be read with [`SignedCookies::get`].
+    pub fn sign(&self, mut cookie: Cookie) -> Cookie {
+        let signature = self.key.mac(&cookie.name, &cookie.value).finalize();
+        cookie.value = format!(
+            "{}{}",
+            URL_SAFE_NO_PAD.encode(signature.into_bytes()),
+            cookie.value
+        );
+        cookie
+    }
+}
+
+impl FromRequest for SignedCookies {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        Ok(Self {
+            jar: CookieJar::from_headers(req),
+            key: Key::from_request(req)?,
+        })
+    }
+}
+
+/// The cookies of a request which are encrypted and authenticated, so clients can neither
+/// read nor change them.
+///
+/// Extracting it fails with `500 Internal Server Error` if the router has no
+/// [cookie key](crate::Router::cookie_key).
+#[derive(Debug, Clone)]
+pub struct PrivateCookies {
+    jar: CookieJar,
+    key: Key,
+}
+
+impl PrivateCookies {
+    /// Decrypt the value of the cookie `name`, or return `None` if it's missing or was
+    /// tampered with.
+    pub fn get(&self, name: &str) -> Option<String> {
+        let sealed = URL_SAFE_NO_PAD.decode(self.jar.get(name)?).ok()?;
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut in_out = ciphertext.to_vec();
+        let value = self
+            .key
+            .aead()
+            .open_in_place(nonce, Aad::from(name.as_bytes()), &mut in_out)
+            .ok()?;
+        String::from_utf8(value.to_vec()).ok()
+    }
+
+    /// Encrypt the value of `cookie`, so it can be read with [`PrivateCookies::get`].
+    pub fn encrypt(&self, mut cookie: Cookie) -> Cookie {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .expect("the system random number generator is available");
+        let mut in_out = cookie.value.into_bytes();
+        self.key
+            .aead()
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(cookie.name.as_bytes()),
+                &mut in_out,
+            )
+            .expect("cookie values fit into AES-GCM");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(in_out);
+        cookie.value = URL_SAFE_NO_PAD.encode(sealed);
+        cookie
+    }
+}
+
+impl FromRequest for PrivateCookies {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        Ok(Self {
+            jar: CookieJar::from_headers(req),
+            key: Key::from_request(req)?,
+        })
+    }
+}
@@ -0,0 +1,271 @@
+//! Cross-origin resource sharing.
+//!
+//! A [`Cors`] set with [`Router::cors`](crate::Router::cors) lets browsers call the router from
+//! other origins. Preflight requests, i.e. `OPTIONS` requests with an
+//! `Access-Control-Request-Method` header, are answered for every path with a registered
+//! route, without calling a handler; requests from origins which are not allowed are answered
+//! with `403 Forbidden`. All other responses to allowed origins get the CORS headers added,
+//! including errors, so the browser can read them.
+//!
+//! Unless methods are set with [`Cors::allow_methods`], preflights allow the methods with a
+//! route registered for the path.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::cors::Cors;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.cors(
+//!     Cors::new()
+//!         .allow_origin("https://app.example.com")
+//!         .allow_headers(&["content-type", "authorization"])
+//!         .expose_headers(&["x-request-id"])
+//!         .allow_credentials()
+//!         .max_age(Duration::from_secs(600)),
+//! );
+//! router.get("/todos", todos);
+//! router.post("/todos", todos);
+//!
+//! async fn todos(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("[]")))
+//! }
+//! ```
+
+use std::time::Duration;
+
+use hyper::header::{
+    HeaderName, HeaderValue, ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
+    ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_EXPOSE_HEADERS,
+    ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD, ORIGIN,
+    VARY,
+};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+/// Which cross-origin requests are allowed.
+#[derive(Debug, Clone, Default)]
+pub struct Cors {
+    any_origin: bool,
+    origins: Vec<HeaderValue>,
+    methods: Option<Vec<Method>>,
+    any_header: bool,
+    headers: Vec<HeaderName>,
+    expose_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Create a configuration which allows no origins yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow requests from `origin`, such as `https://app.example.com`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `origin` is not a valid header value.
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        let origin = HeaderValue::from_str(origin.trim_end_matches('/')).expect("invalid origin");
+        self.origins.push(origin);
+        self
+    }
+
+    /// Allow requests from any origin.
+    ///
+    /// # Panics
+    ///
+    /// Panics if credentials are allowed, as any site could then make requests with the
+    /// cookies of users and read the responses.
+    pub fn allow_any_origin(mut self) -> Self {
+        assert!(
+            !self.credentials,
+            "credentials can't be allowed for any origin"
+        );
+        self.any_origin = true;
+        self
+    }
+
+    /// Allow `methods` in preflights, instead of the methods registered for the path.
+    pub fn allow_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = Some(methods.to_vec());
+        self
+    }
+
+    /// Allow requests to set `headers`, in addition to the headers browsers always allow.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a header is not a valid header name.
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers
+            .extend(headers.iter().map(|name| header_name(name)));
+        self
+    }
+
+    /// Allow requests to set any header.
+    pub fn allow_any_header(mut self) -> Self {
+        self.any_header = true;
+        self
+    }
+
+    /// Let scripts read `headers` of responses, in addition to the headers browsers always
+    /// expose.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a header is not a valid header name.
+    pub fn expose_headers(mut self, headers: &[&str]) -> Self {
+        self.expose_headers
+            .extend(headers.iter().map(|name| header_name(name)));
+        self
+    }
+
+    /// Allow requests with cookies and `Authorization` headers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any origin is allowed, as any site could then make requests with the
+    /// cookies of users and read the responses. Allow the origins with
+    /// [`Cors::allow_origin`] instead.
+    pub fn allow_credentials(mut self) -> Self {
+        assert!(
+            !self.any_origin,
+            "credentials can't be allowed for any origin"
+        );
+        self.credentials = true;
+        self
+    }
+
+    /// Let browsers cache preflight responses for `age`. Sent in whole seconds.
+    pub fn max_age(mut self, age: Duration) -> Self {
+        self.max_age = Some(age);
+        self
+    }
+
+    fn is_allowed(&self, origin: &HeaderValue) -> bool {
+        self.any_origin || self.origins.contains(origin)
+    }
+
+    /// Get the value of `Access-Control-Allow-Origin` for `origin`.
+    fn allow_origin_value(&self, origin: &HeaderValue) -> HeaderValue {
+        if self.any_origin {
+            HeaderValue::from_static("*")
+        } else {
+            origin.clone()
+        }
+    }
+
+    /// Answer the preflight `req` to a path with routes for `registered` methods.
+    pub(crate) fn preflight(&self, req: &Request<Body>, registered: Vec<Method>) -> Response<Body> {
+        let origin = match req.headers().get(ORIGIN) {
+            Some(origin) if self.is_allowed(origin) => origin,
+            _ => return status(StatusCode::FORBIDDEN),
+        };
+        let methods = self.methods.clone().unwrap_or(registered);
+        let requested = req
+            .headers()
+            .get(ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|method| Method::from_bytes(method.as_bytes()).ok());
+        if !requested.is_some_and(|requested| methods.contains(&requested)) {
+            return status(StatusCode::FORBIDDEN);
+        }
+
+        let mut res = status(StatusCode::NO_CONTENT);
+        let headers = res.headers_mut();
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, self.allow_origin_value(origin));
+        let methods = methods
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+        if let Ok(methods) = HeaderValue::from_str(&methods) {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        let requested_headers = req.headers().get(ACCESS_CONTROL_REQUEST_HEADERS);
+        let allowed_headers = if self.any_header {
+            requested_headers.cloned()
+        } else if self.headers.is_empty() {
+            None
+        } else {
+            let names = self
+                .headers
+                .iter()
+                .map(HeaderName::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            HeaderValue::from_str(&names).ok()
+        };
+        if let Some(allowed_headers) = allowed_headers {
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allowed_headers);
+        }
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if let Some(age) = self.max_age {
+            headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(age.as_secs()));
+        }
+        self.vary(headers);
+        res
+    }
+
+    /// Add the CORS headers for a request from `origin` to `res`.
+    pub(crate) fn apply(&self, origin: Option<&HeaderValue>, res: &mut Response<Body>) {
+        let headers = res.headers_mut();
+        self.vary(headers);
+        let origin = match origin {
+            Some(origin) if self.is_allowed(origin) => origin,
+            _ => return,
+        };
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, self.allow_origin_value(origin));
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if !self.expose_headers.is_empty() {
+            let names = self
+                .expose_headers
+                .iter()
+                .map(HeaderName::as_str)
+                .collect::<Vec<_>>()
+                .join(", ");
+            if let Ok(names) = HeaderValue::from_str(&names) {
+                headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, names);
+            }
+        }
+    }
+
+    /// Tell caches that responses differ by origin, unless every origin gets the same `*`.
+    fn vary(&self, headers: &mut hyper::HeaderMap) {
+        if !self.any_origin {
+            headers.append(VARY, HeaderValue::from_static("origin"));
+        }
+    }
+}
+
+/// Check whether `req` is a preflight request.
+pub(crate) fn is_preflight(req: &Request<Body>) -> bool {
+    req.method() == Method::OPTIONS
+        && req.headers().contains_key(ORIGIN)
+        && req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+fn header_name(name: &str) -> HeaderName {
+    HeaderName::from_bytes(name.as_bytes()).expect("invalid header name")
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,90 @@
+use std::any::type_name;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use hyper::{Body, Request, StatusCode};
+
+use crate::extract::{FromRequest, Rejection};
+
+/// A shared state wrapped in an `Arc`, so it isn't cloned for every request.
+///
+/// Add it with [`Router::data`](crate::Router::data) and extract it with
+/// `req.extract::<Data<T>>()`. Unlike `req.state::<T>()`, a missing state is a rejection
+/// with `500 Internal Server Error` naming the missing type, instead of `None`.
+///
+/// It can also be the state of a router made with
+/// [`Router::with_data`](crate::Router::with_data). Handlers registered with
+/// [`Router::route_with_state`](crate::Router::route_with_state) then get it as an argument,
+/// checked against the router at compile time:
+///
+/// ```rust,compile_fail
+/// # use std::convert::Infallible;
+/// # use hyper::{Body, Method, Request, Response};
+/// use keiro::{Data, Router};
+///
+/// struct Config;
+/// struct Other;
+///
+/// let mut router = Router::with_data(Config);
+/// // `Data<Other>` isn't the state of the router.
+/// router.route_with_state(Method::GET, "/", index);
+///
+/// async fn index(
+///     _req: Request<Body>,
+///     _other: Data<Other>,
+/// ) -> Result<Response<Body>, Infallible> {
+///     Ok(Response::new(Body::empty()))
+/// }
+/// ```
+pub struct Data<T: ?Sized>(Arc<T>);
+
+impl<T> Data<T> {
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+}
+
+impl<T: ?Sized> Data<T> {
+    /// Get the inner `Arc`.
+    pub fn into_inner(self) -> Arc<T> {
+        self.0
+    }
+}
+
+impl<T: ?Sized> Clone for Data<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: ?Sized> Deref for Data<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ?Sized> From<Arc<T>> for Data<T> {
+    fn from(arc: Arc<T>) -> Self {
+        Self(arc)
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Data<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Data").field(&self.0).finish()
+    }
+}
+
+impl<T: ?Sized + Send + Sync + 'static> FromRequest for Data<T> {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        req.extensions().get::<Data<T>>().cloned().ok_or_else(|| {
+            Rejection::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("missing state: {}", type_name::<T>()),
+            )
+        })
+    }
+}
@@ -0,0 +1,158 @@
+//! Error pages for development.
+//!
+//! With [`Router::debug_errors`](crate::Router::debug_errors), server errors of handlers and
+//! panics are answered with an HTML page showing the error and its sources, the backtrace and
+//! the request, with credentials redacted, instead of a bare `500 Internal Server Error`. The
+//! pages expose the internals of the service, so only enable them in development, e.g.
+//! depending on a configuration flag.
+//!
+//! Backtraces are captured when an [`Error`](crate::Error) is created or a handler panics, if
+//! the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variable enables them.
+//!
+//! Requires the `debug-errors` feature.
+//!
+//! ```rust,no_run
+//! use hyper::{Body, Request, Response};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! if std::env::var("APP_ENV").as_deref() == Ok("development") {
+//!     router.debug_errors();
+//! }
+//! router.get("/config", config);
+//!
+//! async fn config(_req: Request<Body>) -> keiro::Result<Response<Body>> {
+//!     let config = tokio::fs::read_to_string("config.toml").await?;
+//!     Ok(Response::new(Body::from(config)))
+//! }
+//! ```
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::sync::Once;
+
+use hyper::http::request::Parts;
+use hyper::{header, Body, Response, StatusCode};
+
+use crate::panic::Panic;
+use crate::Error;
+
+thread_local! {
+    /// The backtrace of the last panic on this thread, taken when the panic is caught.
+    static PANIC_BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// A caught panic, added to the extensions of its response for the error page.
+pub(crate) struct CaughtPanic {
+    pub(crate) panic: Panic,
+    pub(crate) backtrace: Option<Backtrace>,
+}
+
+/// Install a panic hook recording the backtraces of panics, keeping the previous hook.
+pub(crate) fn install_panic_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            PANIC_BACKTRACE.with(|backtrace| *backtrace.borrow_mut() = Some(Backtrace::capture()));
+            previous(info);
+        }));
+    });
+}
+
+/// Take the backtrace of the panic just caught on this thread.
+pub(crate) fn take_panic_backtrace() -> Option<Backtrace> {
+    PANIC_BACKTRACE.with(|backtrace| backtrace.borrow_mut().take())
+}
+
+/// Render the page for `err`, returned by the handler of the request `head`.
+pub(crate) fn error_page(err: &Error, head: &Parts) -> Response<Body> {
+    let mut html = String::new();
+    section(&mut html, "Error", &err.to_string());
+    let mut source = err.get_ref().source();
+    if source.is_some() {
+        html.push_str("<h2>Caused by</h2>\n<ol>\n");
+        while let Some(err) = source {
+            let _ = writeln!(html, "<li>{}</li>", escape(&err.to_string()));
+            source = err.source();
+        }
+        html.push_str("</ol>\n");
+    }
+    backtrace(&mut html, Some(err.backtrace()));
+    page(err.status(), html, head)
+}
+
+/// Render the page for the panic `caught` while handling the request `head`.
+pub(crate) fn panic_page(caught: &CaughtPanic, head: &Parts) -> Response<Body> {
+    let mut html = String::new();
+    section(&mut html, "Panic", &caught.panic.message);
+    backtrace(&mut html, caught.backtrace.as_ref());
+    page(StatusCode::INTERNAL_SERVER_ERROR, html, head)
+}
+
+fn section(html: &mut String, title: &str, text: &str) {
+    let _ = writeln!(html, "<h2>{}</h2>\n<pre>{}</pre>", title, escape(text));
+}
+
+fn backtrace(html: &mut String, backtrace: Option<&Backtrace>) {
+    match backtrace {
+        Some(backtrace) if backtrace.status() == BacktraceStatus::Captured => {
+            section(html, "Backtrace", &backtrace.to_string());
+        }
+        _ => html.push_str(
+            "<h2>Backtrace</h2>\n<p>Set <code>RUST_BACKTRACE=1</code> to capture backtraces.</p>\n",
+        ),
+    }
+}
+
+fn page(status: StatusCode, body: String, head: &Parts) -> Response<Body> {
+    let title = format!(
+        "{} {}",
+        status.as_u16(),
+        status.canonical_reason().unwrap_or("Unknown Status")
+    );
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, title
+    );
+    html.push_str(&body);
+    let _ = writeln!(
+        html,
+        "<h2>Request</h2>\n<pre>{} {} {:?}",
+        head.method,
+        escape(&head.uri.to_string()),
+        head.version
+    );
+    for (name, value) in &head.headers {
+        let value = match *name {
+            header::AUTHORIZATION | header::COOKIE | header::PROXY_AUTHORIZATION => {
+                "[redacted]".into()
+            }
+            _ => String::from_utf8_lossy(value.as_bytes()),
+        };
+        let _ = writeln!(html, "{}: {}", name, escape(&value));
+    }
+    html.push_str("</pre>\n</body>\n</html>\n");
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(Body::from(html))
+        .unwrap()
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,163 @@
+//! Content encoding negotiation.
+//!
+//! [`AcceptEncoding`] parses the `Accept-Encoding` header of a request, so handlers can tell
+//! which encoding a response will be compressed with. Responses which shouldn't be compressed,
+//! such as already compressed images, can be marked with [`skip_compression`].
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::encoding::{self, AcceptEncoding, Encoding};
+//! use keiro::prelude::*;
+//!
+//! async fn image(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let accept = req.extract::<AcceptEncoding>().unwrap();
+//!     let _encoding = accept.negotiate(&[Encoding::Gzip, Encoding::Deflate]);
+//!
+//!     let mut res = Response::new(Body::from(&b"\x89PNG"[..]));
+//!     encoding::skip_compression(&mut res);
+//!     Ok(res)
+//! }
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use hyper::header::ACCEPT_ENCODING;
+use hyper::{Body, Request, Response};
+
+use crate::extract::{FromRequest, Rejection};
+
+/// A content coding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Deflate,
+    Brotli,
+    Zstd,
+}
+
+impl Encoding {
+    /// Get the token used in `Accept-Encoding` and `Content-Encoding` headers.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Identity => "identity",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "identity" => Ok(Encoding::Identity),
+            "gzip" | "x-gzip" => Ok(Encoding::Gzip),
+            "deflate" => Ok(Encoding::Deflate),
+            "br" => Ok(Encoding::Brotli),
+            "zstd" => Ok(Encoding::Zstd),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The parsed `Accept-Encoding` header of a request.
+#[derive(Debug, Clone, Default)]
+pub struct AcceptEncoding {
+    /// Codings with their quality values. `None` stands for `*`.
+    codings: Vec<(Option<Encoding>, f32)>,
+}
+
+impl AcceptEncoding {
+    /// Parse the value of an `Accept-Encoding` header. Unknown codings are ignored.
+    pub fn parse(value: &str) -> Self {
+        let codings = value
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let token = parts.next()?.trim();
+                let coding = if token == "*" {
+                    None
+                } else {
+                    Some(token.parse().ok()?)
+                };
+                let quality = parts
+                    .filter_map(|param| {
+                        let (key, value) = param.split_once('=')?;
+                        if key.trim().eq_ignore_ascii_case("q") {
+                            value.trim().parse::<f32>().ok()
+                        } else {
+                            None
+                        }
+                    })
+                    .next()
+                    .unwrap_or(1.0);
+                Some((coding, quality))
+            })
+            .collect();
+        Self { codings }
+    }
+
+    /// Get the quality value of `encoding`, between `0.0` (not acceptable) and `1.0`.
+    pub fn quality(&self, encoding: Encoding) -> f32 {
+        let explicit = self
+            .codings
+            .iter()
+            .find(|(coding, _)| *coding == Some(encoding));
+        let wildcard = self.codings.iter().find(|(coding, _)| coding.is_none());
+        match (explicit, wildcard) {
+            (Some((_, q)), _) | (None, Some((_, q))) => *q,
+            // `identity` is always acceptable unless it is excluded explicitly.
+            (None, None) if encoding == Encoding::Identity => 1.0,
+            (None, None) => 0.0,
+        }
+    }
+
+    /// Pick the acceptable encoding from `supported` with the highest quality value,
+    /// preferring earlier ones on ties. Falls back to `identity`, and returns `None` if even
+    /// `identity` is not acceptable.
+    pub fn negotiate(&self, supported: &[Encoding]) -> Option<Encoding> {
+        let mut best: Option<(Encoding, f32)> = None;
+        for &encoding in supported.iter().chain(&[Encoding::Identity]) {
+            let quality = self.quality(encoding);
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((encoding, quality));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+}
+
+impl FromRequest for AcceptEncoding {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        let value = req
+            .headers()
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        Ok(Self::parse(&value))
+    }
+}
+
+/// A response extension which tells the compression layer to leave the response as it is.
+#[derive(Debug, Clone, Copy)]
+pub struct SkipCompression;
+
+/// Mark `res` so it isn't compressed.
+pub fn skip_compression(res: &mut Response<Body>) {
+    res.extensions_mut().insert(SkipCompression);
+}
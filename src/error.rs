@@ -0,0 +1,255 @@
+use std::any::Any;
+#[cfg(feature = "debug-errors")]
+use std::backtrace::Backtrace;
+use std::error::Error as StdError;
+use std::fmt;
+use std::sync::Arc;
+
+use hyper::http::request::Parts;
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::body::BodyError;
+use crate::extract::Rejection;
+
+/// A boxed error, as returned by [`RouterService`](crate::RouterService).
+pub type BoxError = Box<dyn StdError + Send + Sync>;
+
+/// Converts the errors returned by handlers into responses. See
+/// [`Router::error_handler`](crate::Router::error_handler).
+pub(crate) type ErrorHandler = Arc<dyn Fn(Error, &Parts) -> Response<Body> + Send + Sync>;
+
+/// Decorates the responses keiro generated with a status. See
+/// [`Router::handle_status`](crate::Router::handle_status).
+pub(crate) type StatusHandler = Arc<dyn Fn(Response<Body>, &Parts) -> Response<Body> + Send + Sync>;
+
+/// Marks responses generated by keiro rather than returned by a handler, so they are passed to
+/// the [`StatusHandler`] of their status.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Generated;
+
+/// Mark `res` as [`Generated`].
+pub(crate) fn generated(mut res: Response<Body>) -> Response<Body> {
+    res.extensions_mut().insert(Generated);
+    res
+}
+
+/// A `Result` defaulting to keiro's [`Error`], so handlers can be declared as
+/// `async fn handler(req: Request<Body>) -> keiro::Result<Response<Body>>`.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// An error returned by a handler, with the status of the response it's answered with.
+///
+/// Any error type can be converted into it with `?`. Boxed errors, which can't, are converted
+/// with [`Error::new`] or [`ResultExt::err_into`]. Converted errors are answered with
+/// `500 Internal Server Error`, except for keiro's errors which know their status, such as
+/// [`BodyError`](crate::body::BodyError), [`Rejection`](crate::extract::Rejection) and
+/// [`Problem`](crate::problem::Problem), which is answered as problem details; other
+/// statuses are set with [`ResultExt::or_status`] or [`Error::with_status`].
+///
+/// Handlers of [`RouterService`](crate::RouterService) returning an `Error` are answered with
+/// its status and [message](Error::message), unless the router has an
+/// [error handler](crate::Router::error_handler).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use hyper::{Body, Request, Response, StatusCode};
+/// use keiro::prelude::*;
+/// use keiro::Error;
+///
+/// async fn config(req: Request<Body>) -> keiro::Result<Response<Body>> {
+///     let name = req.params().and_then(|params| params.find("name")).unwrap_or_default();
+///     if name.contains('/') {
+///         return Err(Error::with_status(StatusCode::BAD_REQUEST, "invalid config name"));
+///     }
+///     let config = tokio::fs::read_to_string(format!("{}.toml", name))
+///         .await
+///         .or_status(StatusCode::NOT_FOUND)?;
+///     let port: u16 = config.trim().parse()?;
+///     let upstream = connect(port).err_into()?;
+///     Ok(Response::new(Body::from(upstream)))
+/// }
+///
+/// fn connect(port: u16) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+///     Ok(format!("127.0.0.1:{}", port))
+/// }
+/// ```
+pub struct Error {
+    status: StatusCode,
+    message: Option<String>,
+    inner: BoxError,
+    #[cfg(feature = "debug-errors")]
+    backtrace: Backtrace,
+}
+
+impl Error {
+    pub fn new(err: impl Into<BoxError>) -> Self {
+        let inner = err.into();
+        match status_of(&*inner) {
+            Some(status) => Self::from_parts(status, Some(inner.to_string()), inner),
+            None => Self::from_parts(StatusCode::INTERNAL_SERVER_ERROR, None, inner),
+        }
+    }
+
+    /// Create an error answered with `status` and `message`.
+    pub fn with_status(status: StatusCode, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self::from_parts(status, Some(message.clone()), message.into())
+    }
+
+    /// Create an error answered with `status` and its canonical reason.
+    pub fn from_status(status: StatusCode) -> Self {
+        let reason = status.canonical_reason().unwrap_or("Unknown Status");
+        Self::from_parts(status, None, reason.into())
+    }
+
+    fn from_parts(status: StatusCode, message: Option<String>, inner: BoxError) -> Self {
+        Self {
+            status,
+            message,
+            inner,
+            #[cfg(feature = "debug-errors")]
+            backtrace: Backtrace::capture(),
+        }
+    }
+
+    /// Get the status of the response for this error.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Get the message used as the response body: the message given to
+    /// [`Error::with_status`], the wrapped error if it's one of keiro's errors, or the
+    /// canonical reason of the status otherwise, so details of other errors are not leaked to
+    /// clients.
+    pub fn message(&self) -> &str {
+        match &self.message {
+            Some(message) => message,
+            None => self.status.canonical_reason().unwrap_or("Unknown Status"),
+        }
+    }
+
+    /// Get the wrapped error.
+    pub fn get_ref(&self) -> &(dyn StdError + Send + Sync + 'static) {
+        &*self.inner
+    }
+
+    /// Get the wrapped error if it's a `T`.
+    pub fn downcast_ref<T: StdError + 'static>(&self) -> Option<&T> {
+        self.inner.downcast_ref()
+    }
+
+    pub fn into_inner(self) -> BoxError {
+        self.inner
+    }
+
+    /// Get the backtrace captured when the error was created, if backtraces are enabled with
+    /// the `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` environment variables. Requires the
+    /// `debug-errors` feature.
+    #[cfg(feature = "debug-errors")]
+    pub fn backtrace(&self) -> &Backtrace {
+        &self.backtrace
+    }
+}
+
+impl<E> From<E> for Error
+where
+    E: StdError + Send + Sync + 'static,
+{
+    fn from(err: E) -> Self {
+        Self::new(err)
+    }
+}
+
+impl From<Error> for BoxError {
+    fn from(err: Error) -> Self {
+        err.inner
+    }
+}
+
+impl From<Error> for Response<Body> {
+    fn from(err: Error) -> Self {
+        #[cfg(feature = "json")]
+        if let Some(problem) = err.downcast_ref::<crate::problem::Problem>() {
+            return problem.clone().into();
+        }
+        Response::builder()
+            .status(err.status)
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(Body::from(err.message().to_string()))
+            .unwrap()
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.inner, f)
+    }
+}
+
+/// Conversions of results into keiro's [`Result`].
+pub trait ResultExt<T> {
+    /// Convert the error into an [`Error`], also for boxed errors which `?` can't convert.
+    fn err_into(self) -> Result<T>;
+
+    /// Convert the error into an [`Error`] answered with `status`.
+    fn or_status(self, status: StatusCode) -> Result<T>;
+}
+
+impl<T, E: Into<BoxError>> ResultExt<T> for std::result::Result<T, E> {
+    fn err_into(self) -> Result<T> {
+        self.map_err(Error::new)
+    }
+
+    fn or_status(self, status: StatusCode) -> Result<T> {
+        self.map_err(|err| Error::from_parts(status, None, err.into()))
+    }
+}
+
+/// The status of keiro's errors which know it.
+fn status_of(err: &(dyn StdError + 'static)) -> Option<StatusCode> {
+    if let Some(err) = err.downcast_ref::<BodyError>() {
+        return Some(err.status());
+    }
+    if let Some(rejection) = err.downcast_ref::<Rejection>() {
+        return Some(rejection.status());
+    }
+    #[cfg(feature = "multipart")]
+    if let Some(err) = err.downcast_ref::<crate::multipart::MultipartError>() {
+        return Some(err.status());
+    }
+    #[cfg(feature = "json")]
+    if let Some(problem) = err.downcast_ref::<crate::problem::Problem>() {
+        return Some(problem.status());
+    }
+    None
+}
+
+/// Get the [`Error`] returned by a handler if `E` is keiro's error type.
+pub(crate) fn downcast<E: 'static>(err: E) -> std::result::Result<Error, E> {
+    let mut err = Some(err);
+    match (&mut err as &mut dyn Any).downcast_mut::<Option<Error>>() {
+        Some(keiro) => Ok(keiro.take().unwrap()),
+        None => Err(err.unwrap()),
+    }
+}
+
+/// Copy the method, URI, version and headers of `req` for an [`ErrorHandler`], as the request
+/// itself is consumed by the handler.
+pub(crate) fn head(req: &Request<Body>) -> Parts {
+    let (mut parts, ()) = Request::builder()
+        .method(req.method().clone())
+        .uri(req.uri().clone())
+        .version(req.version())
+        .body(())
+        .unwrap()
+        .into_parts();
+    parts.headers = req.headers().clone();
+    parts
+}
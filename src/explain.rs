@@ -0,0 +1,197 @@
+//! Explaining how requests are routed.
+//!
+//! With [`Router::explain`](crate::Router::explain), the router reports how it routed every
+//! request: the method tables it looked in, the patterns it considered, the nested routers it
+//! descended into, the checks which rejected the request, and the route or fallback which
+//! answered it. It's meant for finding out why a request ended up with a `404` or at an
+//! unexpected route, and walks the route table a second time for every request, so leave it
+//! off in production.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.get("/users/:id", user);
+//! router.get("/users/new", user);
+//! router.explain(|explanation| eprintln!("{}", explanation));
+//!
+//! // A `POST /users/42` logs:
+//! //
+//! // POST /users/42
+//! //   no POST routes
+//! //   GET /users/:id matches the path
+//! //   -> not found
+//!
+//! async fn user(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//! ```
+
+use std::fmt;
+
+use hyper::{Method, StatusCode};
+
+/// How a request was routed.
+#[derive(Debug, Clone)]
+pub struct Explanation {
+    pub(crate) method: Method,
+    pub(crate) path: String,
+    pub(crate) steps: Vec<Step>,
+    pub(crate) outcome: Outcome,
+}
+
+impl Explanation {
+    pub(crate) fn new(method: Method, path: String) -> Self {
+        Self {
+            method,
+            path,
+            steps: Vec::new(),
+            outcome: Outcome::NotFound { handler: false },
+        }
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Get the decisions made while matching the request, in order.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn outcome(&self) -> &Outcome {
+        &self.outcome
+    }
+}
+
+/// A decision made while matching a request.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Step {
+    /// The routes registered for the method of the request were looked up, under the prefix
+    /// of a nested router. There are none if `routes` is zero.
+    MethodTable {
+        prefix: String,
+        method: Method,
+        routes: usize,
+    },
+    /// A route of the method table was checked against the path.
+    Candidate { pattern: String, matched: bool },
+    /// A nested router was considered, and descended into if its prefix `matched`.
+    Mount { prefix: String, matched: bool },
+    /// A route for another method matches the path, e.g. when a `POST` is sent to a route
+    /// which only handles `GET`.
+    OtherMethod { method: Method, pattern: String },
+}
+
+/// Where a request ended up.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Outcome {
+    /// The request was handled by the route with the pattern `route`.
+    Matched { route: String },
+    /// The request was answered with `status` by `check` before reaching a handler.
+    Rejected { check: Check, status: StatusCode },
+    /// No route matched, and the request was answered by the not found handler, or with a
+    /// plain `404` if there is none.
+    NotFound { handler: bool },
+}
+
+/// A check which can reject a request before it reaches a handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Check {
+    HeaderLimits,
+    Signature,
+    ProbeFilter,
+    /// A CORS preflight, answered without calling a handler.
+    Preflight,
+    /// The authentication required by the route.
+    Auth,
+    /// The client addresses allowed by the route.
+    IpFilter,
+    /// The rate limit of the route.
+    RateLimit,
+    /// The scopes required by the route.
+    Scopes,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Check::HeaderLimits => "header limits",
+            Check::Signature => "signature",
+            Check::ProbeFilter => "probe filter",
+            Check::Preflight => "CORS preflight",
+            Check::Auth => "authentication",
+            Check::IpFilter => "IP filter",
+            Check::RateLimit => "rate limit",
+            Check::Scopes => "required scopes",
+        })
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {}", self.method, self.path)?;
+        for step in &self.steps {
+            match step {
+                Step::MethodTable {
+                    prefix,
+                    method,
+                    routes: 0,
+                } => writeln!(f, "  no {} routes{}", method, under(prefix))?,
+                Step::MethodTable {
+                    prefix,
+                    method,
+                    routes,
+                } => {
+                    let noun = if *routes == 1 { "route" } else { "routes" };
+                    writeln!(f, "  {} {} {}{}", routes, method, noun, under(prefix))?
+                }
+                Step::Candidate {
+                    pattern,
+                    matched: true,
+                } => writeln!(f, "  {} matches", pattern)?,
+                Step::Candidate {
+                    pattern,
+                    matched: false,
+                } => writeln!(f, "  {} doesn't match", pattern)?,
+                Step::Mount {
+                    prefix,
+                    matched: true,
+                } => writeln!(f, "  mount {} entered", prefix)?,
+                Step::Mount {
+                    prefix,
+                    matched: false,
+                } => writeln!(f, "  mount {} skipped: no match", prefix)?,
+                Step::OtherMethod { method, pattern } => {
+                    writeln!(f, "  {} {} matches the path", method, pattern)?
+                }
+            }
+        }
+        match &self.outcome {
+            Outcome::Matched { route } => write!(f, "  -> route {}", route),
+            Outcome::Rejected { check, status } => {
+                write!(f, "  -> rejected by {} with {}", check, status)
+            }
+            Outcome::NotFound { handler: true } => write!(f, "  -> not found handler"),
+            Outcome::NotFound { handler: false } => write!(f, "  -> not found"),
+        }
+    }
+}
+
+fn under(prefix: &str) -> String {
+    if prefix.is_empty() {
+        String::new()
+    } else {
+        format!(" under {}", prefix)
+    }
+}
@@ -1,5 +1,20 @@
-use crate::Params;
-use hyper::{Body, Request};
+use crate::body::{self, BodyError, BodyFuture};
+use crate::conditional::Tags;
+use crate::connect::ConnectInfo;
+use crate::cookie::{Cookie, CookieJar};
+use crate::extract::{Authorization, FromRequest, Rejection};
+use crate::forwarded::ClientInfo;
+use crate::metrics::Metrics;
+use crate::negotiation::Accept;
+use crate::query::QueryPairs;
+use crate::request_id::RequestId;
+use crate::server_timing::ServerTiming;
+use crate::{MatchedPath, Params};
+use hyper::body::Bytes;
+use hyper::header::{self, HeaderValue};
+use hyper::{Body, Request, Response, StatusCode};
+use mime_guess::Mime;
+use std::net::SocketAddr;
 
 /// An extension trait for [`hyper::Request`](https://docs.rs/hyper/0.14/hyper/struct.Request.html).
 pub trait RequestExt {
@@ -76,6 +91,167 @@ pub trait RequestExt {
     /// }
     /// ```
     fn state<T: Clone + Send + Sync + 'static>(&self) -> Option<&T>;
+
+    /// Extract a typed value such as [`Data`](crate::Data). See [`extract`](crate::extract).
+    fn extract<T: FromRequest>(&self) -> Result<T, Rejection>;
+
+    /// Get the pattern of the matched route including the prefixes of nested routers, such as
+    /// `/users/:id`, or `None` if no route matched. Unlike the path, it makes a label with few
+    /// values for metrics, traces and logs.
+    fn matched_path(&self) -> Option<&str>;
+
+    /// Get the remote address of the connection the request arrived on, or `None` if the
+    /// listener doesn't know its peers. See [`connect`](crate::connect).
+    fn remote_addr(&self) -> Option<SocketAddr>;
+
+    /// Get the verified certificate chain the client authenticated with, or `None` if it sent
+    /// none. See [`connect`](crate::connect).
+    #[cfg(feature = "tls")]
+    fn peer_certificates(&self) -> Option<&crate::connect::PeerCertificates>;
+
+    /// Get the client of the request behind the router's trusted proxies, or `None` if it has
+    /// none. See [`forwarded`](crate::forwarded).
+    fn client_info(&self) -> Option<&ClientInfo>;
+
+    /// Get the id of the request, or `None` if the router doesn't have
+    /// [`RequestIds`](crate::request_id::RequestIds). See [`request_id`](crate::request_id).
+    fn request_id(&self) -> Option<&str>;
+
+    /// Get the timings sent in the `Server-Timing` header of the response, or `None` if the
+    /// router doesn't [send them](crate::Router::server_timing). See
+    /// [`server_timing`](crate::server_timing).
+    fn server_timing(&self) -> Option<&ServerTiming>;
+
+    /// Get the context of the span serving the request in its distributed trace, or `None` if
+    /// the router doesn't have [`Propagation`](crate::otel::Propagation). See
+    /// [`otel`](crate::otel).
+    #[cfg(feature = "otel")]
+    fn span_context(&self) -> Option<&crate::otel::SpanContext>;
+
+    /// Get the decoded pairs of the query string. See [`query`](crate::query).
+    fn query_pairs(&self) -> QueryPairs;
+
+    /// Get the cookies sent with the request. See [`cookie`](crate::cookie).
+    fn cookies(&self) -> CookieJar;
+
+    /// Get the session of the request, or `None` if the route has no
+    /// [`Sessions`](crate::sessions::Sessions). See [`sessions`](crate::sessions).
+    #[cfg(feature = "sessions")]
+    fn session(&self) -> Option<crate::sessions::Session>;
+
+    /// Add a flash message for the next request of the client to its session. See
+    /// [`sessions`](crate::sessions).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the route has no [`Sessions`](crate::sessions::Sessions).
+    #[cfg(feature = "sessions")]
+    fn flash(&self, message: impl Into<String>);
+
+    /// Get the media type of the body from `Content-Type`, or `None` if it's missing or
+    /// malformed.
+    fn content_type(&self) -> Option<Mime>;
+
+    /// Get the parsed `Accept` header, accepting every type if it's missing.
+    fn accept(&self) -> Accept;
+
+    /// Get the parsed `Authorization` header, or `None` if it's missing or malformed.
+    fn authorization(&self) -> Option<Authorization>;
+
+    /// Get the tags of the `If-None-Match` header, or `None` if it's missing. See
+    /// [`conditional`](crate::conditional) to evaluate all preconditions at once.
+    fn if_none_match(&self) -> Option<Tags>;
+
+    /// Get a handle to record metrics labeled with the matched route. See
+    /// [`metrics`](crate::metrics).
+    fn metrics(&self) -> Metrics;
+
+    /// Read the whole body, failing with [`BodyError::TooLarge`] if it exceeds `limit` bytes.
+    /// See [`body`](crate::body).
+    fn body_bytes(&mut self, limit: usize) -> BodyFuture<'_, Bytes>;
+
+    /// Read the whole body as UTF-8 text, failing if it exceeds `limit` bytes.
+    fn body_text(&mut self, limit: usize) -> BodyFuture<'_, String>;
+
+    /// Read the whole body and deserialize it from JSON, failing if it exceeds `limit` bytes.
+    #[cfg(feature = "json")]
+    fn body_json<T: serde::de::DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T>;
+
+    /// Read the whole body and deserialize it from MessagePack, failing if it exceeds `limit`
+    /// bytes.
+    #[cfg(feature = "msgpack")]
+    fn body_msgpack<T: serde::de::DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T>;
+
+    /// Read the whole body and deserialize it from CBOR, failing if it exceeds `limit` bytes.
+    #[cfg(feature = "cbor")]
+    fn body_cbor<T: serde::de::DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T>;
+
+    /// Read the whole body and deserialize it from XML, failing if it exceeds `limit` bytes.
+    #[cfg(feature = "xml")]
+    fn body_xml<T: serde::de::DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T>;
+
+    /// Read the whole body and decode it from Protocol Buffers, failing with
+    /// [`BodyError::UnsupportedMediaType`] unless the request has the content type
+    /// `application/x-protobuf` or `application/protobuf`, or if it exceeds `limit` bytes.
+    #[cfg(feature = "protobuf")]
+    fn body_protobuf<T: prost::Message + Default + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T>;
+
+    /// Split the body so the returned copy receives every chunk the handler reads from the
+    /// request, e.g. for scanning or archiving uploads in another task.
+    ///
+    /// At most `capacity` chunks are buffered for the copy; reading the request waits while
+    /// the copy lags behind. Errors are coordinated between both sides: if the copy is dropped
+    /// before the end, reading the request fails, and if the handler stops reading or the body
+    /// can't be read, the copy fails with a [`TeeError`](crate::body::TeeError) as the source of its error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use std::convert::Infallible;
+    ///
+    /// use hyper::body::HttpBody;
+    /// use hyper::{Body, Request, Response};
+    /// use keiro::prelude::*;
+    ///
+    /// async fn upload(mut req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    ///     let mut copy = req.tee_body(16);
+    ///     let scan = tokio::spawn(async move {
+    ///         let mut scanned = 0;
+    ///         while let Some(chunk) = copy.data().await {
+    ///             scanned += chunk?.len();
+    ///         }
+    ///         Ok::<_, hyper::Error>(scanned)
+    ///     });
+    ///
+    ///     let stored = match req.body_bytes(16 * 1024 * 1024).await {
+    ///         Ok(bytes) => bytes.len(),
+    ///         Err(err) => return Ok(err.into()),
+    ///     };
+    ///     let _ = scan.await;
+    ///     Ok(Response::new(Body::from(format!("stored {} bytes", stored))))
+    /// }
+    /// ```
+    fn tee_body(&mut self, capacity: usize) -> Body;
+
+    /// Deserialize the claims of the bearer token the request was authenticated with, or
+    /// return `None` if it wasn't or they don't fit `T`. See [`jwt`](crate::jwt).
+    #[cfg(feature = "jwt")]
+    fn claims<T: serde::de::DeserializeOwned>(&self) -> Option<T>;
 }
 
 impl RequestExt for Request<Body> {
@@ -86,4 +262,336 @@ impl RequestExt for Request<Body> {
     fn state<T: Clone + Send + Sync + 'static>(&self) -> Option<&T> {
         self.extensions().get::<T>()
     }
+
+    fn extract<T: FromRequest>(&self) -> Result<T, Rejection> {
+        T::from_request(self)
+    }
+
+    fn matched_path(&self) -> Option<&str> {
+        self.extensions()
+            .get::<MatchedPath>()
+            .map(|matched| matched.0.as_str())
+    }
+
+    fn remote_addr(&self) -> Option<SocketAddr> {
+        self.extensions().get::<ConnectInfo>().map(|info| info.0)
+    }
+
+    #[cfg(feature = "tls")]
+    fn peer_certificates(&self) -> Option<&crate::connect::PeerCertificates> {
+        self.extensions().get::<crate::connect::PeerCertificates>()
+    }
+
+    fn client_info(&self) -> Option<&ClientInfo> {
+        self.extensions().get::<ClientInfo>()
+    }
+
+    fn request_id(&self) -> Option<&str> {
+        self.extensions().get::<RequestId>().map(RequestId::as_str)
+    }
+
+    fn server_timing(&self) -> Option<&ServerTiming> {
+        self.extensions().get::<ServerTiming>()
+    }
+
+    #[cfg(feature = "otel")]
+    fn span_context(&self) -> Option<&crate::otel::SpanContext> {
+        self.extensions().get::<crate::otel::SpanContext>()
+    }
+
+    fn query_pairs(&self) -> QueryPairs {
+        QueryPairs::from_uri(self)
+    }
+
+    fn cookies(&self) -> CookieJar {
+        CookieJar::from_headers(self)
+    }
+
+    #[cfg(feature = "sessions")]
+    fn session(&self) -> Option<crate::sessions::Session> {
+        self.extensions().get::<crate::sessions::Session>().cloned()
+    }
+
+    #[cfg(feature = "sessions")]
+    fn flash(&self, message: impl Into<String>) {
+        self.extensions()
+            .get::<crate::sessions::Session>()
+            .expect("no sessions configured")
+            .flash(message);
+    }
+
+    fn content_type(&self) -> Option<Mime> {
+        self.headers()
+            .get(header::CONTENT_TYPE)?
+            .to_str()
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn accept(&self) -> Accept {
+        Accept::from_headers(self)
+    }
+
+    fn authorization(&self) -> Option<Authorization> {
+        Authorization::from_headers(self)
+    }
+
+    fn if_none_match(&self) -> Option<Tags> {
+        Tags::from_request(self, header::IF_NONE_MATCH)
+    }
+
+    fn metrics(&self) -> Metrics {
+        self.extensions()
+            .get::<Metrics>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn body_bytes(&mut self, limit: usize) -> BodyFuture<'_, Bytes> {
+        Box::pin(body::read(self, limit))
+    }
+
+    fn body_text(&mut self, limit: usize) -> BodyFuture<'_, String> {
+        Box::pin(async move {
+            let bytes = body::read(self, limit).await?;
+            String::from_utf8(bytes.to_vec()).map_err(BodyError::Utf8)
+        })
+    }
+
+    #[cfg(feature = "json")]
+    fn body_json<T: serde::de::DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T> {
+        Box::pin(async move {
+            let bytes = body::read(self, limit).await?;
+            serde_json::from_slice(&bytes).map_err(BodyError::Json)
+        })
+    }
+
+    #[cfg(feature = "msgpack")]
+    fn body_msgpack<T: serde::de::DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T> {
+        Box::pin(async move {
+            let bytes = body::read(self, limit).await?;
+            rmp_serde::from_slice(&bytes).map_err(BodyError::MsgPack)
+        })
+    }
+
+    #[cfg(feature = "cbor")]
+    fn body_cbor<T: serde::de::DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T> {
+        Box::pin(async move {
+            let bytes = body::read(self, limit).await?;
+            ciborium::from_reader(&bytes[..]).map_err(BodyError::Cbor)
+        })
+    }
+
+    #[cfg(feature = "xml")]
+    fn body_xml<T: serde::de::DeserializeOwned + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T> {
+        Box::pin(async move {
+            let bytes = body::read(self, limit).await?;
+            quick_xml::de::from_reader(&bytes[..]).map_err(BodyError::Xml)
+        })
+    }
+
+    #[cfg(feature = "protobuf")]
+    fn body_protobuf<T: prost::Message + Default + Send + Sync + 'static>(
+        &mut self,
+        limit: usize,
+    ) -> BodyFuture<'_, T> {
+        Box::pin(async move {
+            let content_type = self
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(';').next())
+                .map(str::trim);
+            let is_protobuf = content_type.is_some_and(|content_type| {
+                content_type.eq_ignore_ascii_case("application/x-protobuf")
+                    || content_type.eq_ignore_ascii_case("application/protobuf")
+            });
+            if !is_protobuf {
+                return Err(BodyError::UnsupportedMediaType {
+                    expected: "application/x-protobuf",
+                });
+            }
+            let bytes = body::read(self, limit).await?;
+            T::decode(bytes).map_err(BodyError::Protobuf)
+        })
+    }
+
+    fn tee_body(&mut self, capacity: usize) -> Body {
+        body::tee(self, capacity)
+    }
+
+    #[cfg(feature = "jwt")]
+    fn claims<T: serde::de::DeserializeOwned>(&self) -> Option<T> {
+        let claims = self.extensions().get::<crate::jwt::Claims>()?;
+        T::deserialize(&claims.0).ok()
+    }
+}
+
+/// An extension trait for [`hyper::Response`](https://docs.rs/hyper/0.14/hyper/struct.Response.html)
+/// building common responses.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::convert::Infallible;
+///
+/// use hyper::{Body, Request, Response, StatusCode};
+/// use keiro::prelude::*;
+///
+/// #[derive(serde::Serialize)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// async fn user(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+///     Ok(Response::json(&User {
+///         name: "giraffate".to_string(),
+///     }))
+/// }
+///
+/// async fn old_user(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+///     Ok(Response::redirect("/users/giraffate", StatusCode::MOVED_PERMANENTLY))
+/// }
+/// ```
+pub trait ResponseExt: Sized {
+    /// Respond with `value` serialized into JSON. Values which can't be serialized, such as
+    /// maps with non-string keys, are answered with `500 Internal Server Error`.
+    #[cfg(feature = "json")]
+    fn json<T: serde::Serialize + ?Sized>(value: &T) -> Self;
+
+    /// Respond with `value` serialized into MessagePack, with structs as maps keyed by field
+    /// names. Values which can't be serialized are answered with
+    /// `500 Internal Server Error`.
+    #[cfg(feature = "msgpack")]
+    fn msgpack<T: serde::Serialize + ?Sized>(value: &T) -> Self;
+
+    /// Respond with `value` serialized into CBOR. Values which can't be serialized are
+    /// answered with `500 Internal Server Error`.
+    #[cfg(feature = "cbor")]
+    fn cbor<T: serde::Serialize + ?Sized>(value: &T) -> Self;
+
+    /// Respond with `value` serialized into an XML document. The root element is named after
+    /// the type of `value`, or the name given with `#[serde(rename = "...")]`. Values which
+    /// can't be serialized, such as bare sequences, are answered with
+    /// `500 Internal Server Error`.
+    #[cfg(feature = "xml")]
+    fn xml<T: serde::Serialize + ?Sized>(value: &T) -> Self;
+
+    /// Respond with `message` encoded into Protocol Buffers as `application/x-protobuf`.
+    #[cfg(feature = "protobuf")]
+    fn protobuf<T: prost::Message>(message: &T) -> Self;
+
+    /// Respond with an HTML document.
+    fn html(html: impl Into<Body>) -> Self;
+
+    /// Respond with plain text.
+    fn text(text: impl Into<Body>) -> Self;
+
+    /// Redirect to `location` with `status`, e.g. `303 See Other`. Locations which are not
+    /// valid header values are answered with `500 Internal Server Error`.
+    fn redirect(location: &str, status: StatusCode) -> Self;
+
+    /// Respond with `204 No Content`.
+    fn no_content() -> Self;
+
+    /// Add a `Set-Cookie` header for `cookie`, keeping cookies set before. See
+    /// [`cookie`](crate::cookie).
+    fn set_cookie(&mut self, cookie: Cookie);
+}
+
+impl ResponseExt for Response<Body> {
+    #[cfg(feature = "json")]
+    fn json<T: serde::Serialize + ?Sized>(value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(json) => with_content_type(json.into(), "application/json"),
+            Err(_) => with_status(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    #[cfg(feature = "msgpack")]
+    fn msgpack<T: serde::Serialize + ?Sized>(value: &T) -> Self {
+        match rmp_serde::to_vec_named(value) {
+            Ok(msgpack) => with_content_type(msgpack.into(), "application/msgpack"),
+            Err(_) => with_status(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    #[cfg(feature = "cbor")]
+    fn cbor<T: serde::Serialize + ?Sized>(value: &T) -> Self {
+        let mut cbor = Vec::new();
+        match ciborium::into_writer(value, &mut cbor) {
+            Ok(()) => with_content_type(cbor.into(), "application/cbor"),
+            Err(_) => with_status(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    #[cfg(feature = "xml")]
+    fn xml<T: serde::Serialize + ?Sized>(value: &T) -> Self {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        match quick_xml::se::to_writer(&mut xml, value) {
+            Ok(_) => with_content_type(xml.into(), "application/xml; charset=utf-8"),
+            Err(_) => with_status(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    #[cfg(feature = "protobuf")]
+    fn protobuf<T: prost::Message>(message: &T) -> Self {
+        with_content_type(message.encode_to_vec().into(), "application/x-protobuf")
+    }
+
+    fn html(html: impl Into<Body>) -> Self {
+        with_content_type(html.into(), "text/html; charset=utf-8")
+    }
+
+    fn text(text: impl Into<Body>) -> Self {
+        with_content_type(text.into(), "text/plain; charset=utf-8")
+    }
+
+    fn redirect(location: &str, status: StatusCode) -> Self {
+        match HeaderValue::from_str(location) {
+            Ok(location) => {
+                let mut res = with_status(status);
+                res.headers_mut().insert(header::LOCATION, location);
+                res
+            }
+            Err(_) => with_status(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    fn no_content() -> Self {
+        with_status(StatusCode::NO_CONTENT)
+    }
+
+    fn set_cookie(&mut self, cookie: Cookie) {
+        // Names are tokens and values are encoded, so only attributes could be invalid.
+        if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+            self.headers_mut().append(header::SET_COOKIE, value);
+        }
+    }
+}
+
+fn with_content_type(body: Body, content_type: &'static str) -> Response<Body> {
+    let mut res = Response::new(body);
+    res.headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    res
+}
+
+fn with_status(status: StatusCode) -> Response<Body> {
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = status;
+    res
 }
@@ -0,0 +1,218 @@
+//! Typed values extracted from requests.
+//!
+//! Types implementing [`FromRequest`] can be extracted with
+//! [`RequestExt::extract`](crate::ext::RequestExt::extract). When extraction fails, the
+//! [`Rejection`] can be converted into the response to send back.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::prelude::*;
+//! use keiro::{Data, Router};
+//!
+//! struct Config {
+//!     greeting: String,
+//! }
+//!
+//! let mut router = Router::new();
+//! router.data(Config {
+//!     greeting: "Hello".to_string(),
+//! });
+//! router.get("/", index);
+//!
+//! async fn index(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let config = match req.extract::<Data<Config>>() {
+//!         Ok(config) => config,
+//!         Err(rejection) => return Ok(rejection.into()),
+//!     };
+//!     Ok(Response::new(Body::from(config.greeting.clone())))
+//! }
+//! ```
+
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+#[cfg(feature = "headers")]
+use std::ops::Deref;
+
+use hyper::header::AUTHORIZATION;
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::util;
+
+/// Types which can be extracted from requests.
+pub trait FromRequest: Sized {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection>;
+}
+
+/// The reason why a value couldn't be extracted from a request.
+#[derive(Debug, Clone)]
+pub struct Rejection {
+    status: StatusCode,
+    message: Cow<'static, str>,
+}
+
+impl Rejection {
+    pub fn new(status: StatusCode, message: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    /// Get the status of the response for this rejection.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Get the message of this rejection, which is used as the response body.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl Error for Rejection {}
+
+impl From<Rejection> for Response<Body> {
+    fn from(rejection: Rejection) -> Self {
+        Response::builder()
+            .status(rejection.status)
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(Body::from(rejection.message))
+            .unwrap()
+    }
+}
+
+/// The parsed `Authorization` header of a request.
+///
+/// Extracting it fails with `401 Unauthorized` if the header is missing or malformed.
+#[derive(Clone, PartialEq, Eq)]
+pub enum Authorization {
+    /// Credentials of the `Basic` scheme, decoded from base64.
+    Basic { username: String, password: String },
+    /// The token of the `Bearer` scheme.
+    Bearer(String),
+    /// Credentials of another scheme, as sent.
+    Other { scheme: String, credentials: String },
+}
+
+impl Authorization {
+    /// Parse the value of an `Authorization` header, or return `None` if it's malformed.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (scheme, credentials) = value.trim().split_once(' ')?;
+        let credentials = credentials.trim();
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(util::decode_base64(credentials)?).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+            Some(Authorization::Basic {
+                username: username.to_string(),
+                password: password.to_string(),
+            })
+        } else if scheme.eq_ignore_ascii_case("bearer") {
+            Some(Authorization::Bearer(credentials.to_string()))
+        } else {
+            Some(Authorization::Other {
+                scheme: scheme.to_string(),
+                credentials: credentials.to_string(),
+            })
+        }
+    }
+
+    /// Get the `Authorization` header of `req`, or `None` if it's missing or malformed.
+    pub(crate) fn from_headers(req: &Request<Body>) -> Option<Self> {
+        req.headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Self::parse)
+    }
+}
+
+// Don't leak credentials into logs.
+impl fmt::Debug for Authorization {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Authorization::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .finish_non_exhaustive(),
+            Authorization::Bearer(_) => f.write_str("Bearer(..)"),
+            Authorization::Other { scheme, .. } => f
+                .debug_struct("Other")
+                .field("scheme", scheme)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+impl FromRequest for Authorization {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        Self::from_headers(req).ok_or_else(|| {
+            Rejection::new(StatusCode::UNAUTHORIZED, "missing or invalid authorization")
+        })
+    }
+}
+
+/// A header parsed with the `headers` crate, such as `TypedHeader<ContentLength>` or
+/// `TypedHeader<Authorization<Bearer>>`.
+///
+/// A missing or malformed header is a rejection with `400 Bad Request` naming the header.
+///
+/// Requires the `headers` feature.
+///
+/// ```rust,no_run
+/// use headers::authorization::Bearer;
+/// use headers::Authorization;
+/// use hyper::{Body, Request, Response};
+/// use keiro::extract::TypedHeader;
+/// use keiro::prelude::*;
+///
+/// async fn me(req: Request<Body>) -> keiro::Result<Response<Body>> {
+///     let TypedHeader(Authorization(bearer)) =
+///         req.extract::<TypedHeader<Authorization<Bearer>>>()?;
+///     Ok(Response::new(Body::from(bearer.token().to_string())))
+/// }
+/// ```
+#[cfg(feature = "headers")]
+#[derive(Debug, Clone)]
+pub struct TypedHeader<T>(pub T);
+
+#[cfg(feature = "headers")]
+impl<T> TypedHeader<T> {
+    /// Get the parsed header.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "headers")]
+impl<T> Deref for TypedHeader<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[cfg(feature = "headers")]
+impl<T: headers::Header> FromRequest for TypedHeader<T> {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        let name = T::name();
+        let mut values = req.headers().get_all(name).iter().peekable();
+        if values.peek().is_none() {
+            return Err(Rejection::new(
+                StatusCode::BAD_REQUEST,
+                format!("missing header: {}", name),
+            ));
+        }
+        T::decode(&mut values).map(Self).map_err(|_| {
+            Rejection::new(StatusCode::BAD_REQUEST, format!("invalid header: {}", name))
+        })
+    }
+}
@@ -0,0 +1,555 @@
+//! Serving files from a [`Storage`].
+//!
+//! [`ServeDir`] answers GET and HEAD requests with the objects of a storage, using the path
+//! below its mount point as key. Keys which would escape the storage, such as ones containing
+//! `..`, are never served.
+//!
+//! Requests for a directory are answered with its `index.html`, or with a generated listing
+//! of its files if [`ServeDir::listing`] is enabled. Directories requested without a trailing
+//! slash are redirected to it, so relative links in the index resolve correctly.
+//!
+//! File responses support `Range` requests for a single range of bytes, answered with
+//! `206 Partial Content`, so downloads can be resumed and media can be seeked. `If-Range`
+//! with an entity tag or date falls back to the whole file if it has changed since.
+//!
+//! File responses carry `Last-Modified` and a strong `ETag`, and conditional requests are
+//! answered with `304 Not Modified` or `412 Precondition Failed`, see
+//! [`conditional`](crate::conditional).
+//!
+//! For single-page applications, [`ServeDir::spa_fallback`] answers requests for missing files
+//! with the application's `index.html`, so client-side routes can be loaded directly.
+//!
+//! ```rust,no_run
+//! use keiro::files::ServeDir;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.nest("/", ServeDir::dir("public").router());
+//! router.nest("/downloads", ServeDir::dir("downloads").no_index().listing(true).router());
+//! router.nest("/app", ServeDir::dir("app/dist").spa_fallback("index.html").router());
+//! ```
+//!
+//! Handlers answer with a single file, such as a generated report, with [`NamedFile`]. It's
+//! streamed from disk with the same headers, range and conditional request support, and
+//! offered as a download by default.
+//!
+//! ```rust,no_run
+//! use hyper::{Body, Request, Response};
+//! use keiro::files::NamedFile;
+//!
+//! async fn invoice(req: Request<Body>) -> keiro::Result<Response<Body>> {
+//!     let res = NamedFile::new("invoices/2024-001.pdf")
+//!         .filename("Rechnung 2024-001.pdf")
+//!         .respond(&req)
+//!         .await?;
+//!     Ok(res)
+//! }
+//! ```
+
+use std::error::Error;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use hyper::header::{
+    HeaderValue, ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE,
+    IF_RANGE, LOCATION, RANGE,
+};
+use hyper::{Body, Method, Request, Response, StatusCode};
+
+use crate::conditional::{self, Conditional, ETag};
+use crate::prelude::*;
+use crate::storage::{Entry, LocalStorage, Metadata, Object, Storage};
+use crate::{url, util, Router};
+
+const FILE_PARAM: &str = "keiro_file";
+
+/// A handler serving the objects of a storage.
+#[derive(Clone)]
+pub struct ServeDir {
+    storage: Arc<dyn Storage>,
+    index: Option<String>,
+    listing: bool,
+    fallback: Option<String>,
+}
+
+impl ServeDir {
+    pub fn new(storage: impl Storage) -> Self {
+        Self {
+            storage: Arc::new(storage),
+            index: Some("index.html".to_string()),
+            listing: false,
+            fallback: None,
+        }
+    }
+
+    /// Serve the files below the directory `root`.
+    pub fn dir(root: impl Into<std::path::PathBuf>) -> Self {
+        Self::new(LocalStorage::new(root))
+    }
+
+    /// Answer requests for directories with the file `name` in them instead of `index.html`.
+    pub fn index(mut self, name: impl Into<String>) -> Self {
+        self.index = Some(name.into());
+        self
+    }
+
+    /// Don't answer requests for directories with an index file.
+    pub fn no_index(mut self) -> Self {
+        self.index = None;
+        self
+    }
+
+    /// Answer requests for directories without an index file with a generated listing of
+    /// their files. Disabled by default.
+    pub fn listing(mut self, enabled: bool) -> Self {
+        self.listing = enabled;
+        self
+    }
+
+    /// Answer requests for missing files and directories with the file `key` instead of
+    /// `404 Not Found`, e.g. the `index.html` of a single-page application.
+    pub fn spa_fallback(mut self, key: impl Into<String>) -> Self {
+        self.fallback = Some(key.into());
+        self
+    }
+
+    /// Build a router serving the files, to be nested at their URL prefix with
+    /// [`Router::nest`](crate::Router::nest).
+    pub fn router<E>(self) -> Router<E, ()>
+    where
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        let serve = Arc::new(self);
+        let mut router: Router<E, ()> = Router::with_error_type(());
+        let pattern = format!("/*{}", FILE_PARAM);
+        for path in ["/", pattern.as_str()] {
+            let get = serve.clone();
+            router.get(path, move |req| {
+                let serve = get.clone();
+                async move { Ok::<_, E>(serve.serve(req).await) }
+            });
+            let head = serve.clone();
+            router.head(path, move |req| {
+                let serve = head.clone();
+                async move { Ok::<_, E>(serve.serve(req).await) }
+            });
+        }
+        router
+    }
+
+    /// Answer `req` with the file named by the path below the mount point.
+    pub async fn serve(&self, req: Request<Body>) -> Response<Body> {
+        let res = self.lookup(&req).await;
+        let fallback = match &self.fallback {
+            Some(fallback) if res.status() == StatusCode::NOT_FOUND => fallback,
+            _ => return res,
+        };
+        match self.file(&req, fallback).await {
+            Ok(Some(fallback)) => fallback,
+            Ok(None) => res,
+            Err(_) => status(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    async fn lookup(&self, req: &Request<Body>) -> Response<Body> {
+        let raw = req
+            .params()
+            .and_then(|params| params.find(FILE_PARAM))
+            .unwrap_or("");
+        let key = match url::decode(raw) {
+            Some(key) => key,
+            None => return status(StatusCode::BAD_REQUEST),
+        };
+        if key.is_empty() && !req.uri().path().ends_with('/') {
+            // The mount point itself, e.g. `/static`.
+            if self.is_directory("").await {
+                return redirect_to_slash(req);
+            }
+            return status(StatusCode::NOT_FOUND);
+        }
+        if key.is_empty() || key.ends_with('/') {
+            return self.directory(req, &key).await;
+        }
+
+        match self.file(req, &key).await {
+            Ok(Some(res)) => res,
+            Ok(None) if self.is_directory(&format!("{}/", key)).await => redirect_to_slash(req),
+            Ok(None) => status(StatusCode::NOT_FOUND),
+            Err(_) => status(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    /// Answer `req` with the object `key`, or its requested range, or return `None` if it
+    /// doesn't exist.
+    async fn file(&self, req: &Request<Body>, key: &str) -> io::Result<Option<Response<Body>>> {
+        let range = req
+            .headers()
+            .get(RANGE)
+            .filter(|_| req.method() == Method::GET);
+        if range.is_some() || conditional::has_preconditions(req) {
+            let metadata = match self.storage.head(key).await? {
+                Some(metadata) => metadata,
+                None => return Ok(None),
+            };
+            if let Some(res) = validators(&metadata).evaluate(req) {
+                return Ok(Some(res));
+            }
+            if let Some(range) = range.filter(|_| if_range(req, &metadata)) {
+                match parse_range(range.as_bytes(), metadata.size) {
+                    ByteRange::Satisfiable(range) => {
+                        let object = self.storage.get_range(key, range.clone()).await?;
+                        return Ok(object.map(|object| partial(key, object, range)));
+                    }
+                    ByteRange::Unsatisfiable => {
+                        let mut res = status(StatusCode::RANGE_NOT_SATISFIABLE);
+                        if let Ok(value) =
+                            HeaderValue::from_str(&format!("bytes */{}", metadata.size))
+                        {
+                            res.headers_mut().insert(CONTENT_RANGE, value);
+                        }
+                        return Ok(Some(res));
+                    }
+                    ByteRange::Ignored => {}
+                }
+            }
+        }
+        Ok(self
+            .storage
+            .get(key)
+            .await?
+            .map(|object| complete(req, key, object)))
+    }
+
+    /// Check whether the directory `dir`, which is empty or ends with a slash, would be served.
+    async fn is_directory(&self, dir: &str) -> bool {
+        if let Some(index) = &self.index {
+            let index = format!("{}{}", dir, index);
+            if let Ok(Some(_)) = self.storage.head(&index).await {
+                return true;
+            }
+        }
+        self.listing
+            && self
+                .storage
+                .list(dir)
+                .await
+                .is_ok_and(|entries| !entries.is_empty())
+    }
+
+    /// Answer a request for the directory `dir`, which is empty or ends with a slash.
+    async fn directory(&self, req: &Request<Body>, dir: &str) -> Response<Body> {
+        if let Some(index) = &self.index {
+            let key = format!("{}{}", dir, index);
+            match self.file(req, &key).await {
+                Ok(Some(res)) => return res,
+                Ok(None) => {}
+                Err(_) => return status(StatusCode::INTERNAL_SERVER_ERROR),
+            }
+        }
+        if !self.listing {
+            return status(StatusCode::NOT_FOUND);
+        }
+        let entries = match self.storage.list(dir).await {
+            // The root is listed even when it's empty, other directories only exist while they
+            // have entries.
+            Ok(entries) if entries.is_empty() && !dir.is_empty() => {
+                return status(StatusCode::NOT_FOUND)
+            }
+            Ok(entries) => entries,
+            // Keys the storage refuses, such as ones escaping its root, name no directory.
+            Err(err) if err.kind() == io::ErrorKind::InvalidInput => {
+                return status(StatusCode::NOT_FOUND)
+            }
+            Err(_) => return status(StatusCode::INTERNAL_SERVER_ERROR),
+        };
+
+        let body = if req.method() == Method::HEAD {
+            Body::empty()
+        } else {
+            Body::from(listing(req.uri().path(), dir.is_empty(), &entries))
+        };
+        let mut res = Response::new(body);
+        res.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/html; charset=utf-8"),
+        );
+        res
+    }
+}
+
+/// A single file on disk, answered as a download.
+#[derive(Debug, Clone)]
+pub struct NamedFile {
+    path: PathBuf,
+    filename: Option<String>,
+    content_type: Option<String>,
+    inline: bool,
+}
+
+impl NamedFile {
+    /// Answer with the file at `path`, named after its file name.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            filename: None,
+            content_type: None,
+            inline: false,
+        }
+    }
+
+    /// Name the file `filename` in `Content-Disposition`, e.g. for files stored under a
+    /// generated name. Non-ASCII names are encoded as specified in RFC 5987.
+    pub fn filename(mut self, filename: impl Into<String>) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    /// Answer with the content type `content_type` instead of the one guessed from the
+    /// extension of the path.
+    pub fn content_type(mut self, content_type: impl Into<String>) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Let browsers display the file, such as a PDF or an image, instead of downloading it.
+    pub fn inline(mut self) -> Self {
+        self.inline = true;
+        self
+    }
+
+    /// Answer `req` with the file, or its requested range. Missing files are answered with
+    /// `404 Not Found`; the file failing to be read fails with its error.
+    pub async fn respond(&self, req: &Request<Body>) -> io::Result<Response<Body>> {
+        let (dir, name) = match (self.path.parent(), self.path.file_name()) {
+            (Some(dir), Some(name)) => (dir, name.to_string_lossy()),
+            _ => return Ok(status(StatusCode::NOT_FOUND)),
+        };
+        let serve = ServeDir::dir(dir);
+        let mut res = match serve.file(req, &name).await? {
+            Some(res) => res,
+            None => return Ok(status(StatusCode::NOT_FOUND)),
+        };
+        if !res.status().is_success() {
+            return Ok(res);
+        }
+        if let Some(content_type) = &self.content_type {
+            let content_type = match HeaderValue::from_str(content_type) {
+                Ok(content_type) => content_type,
+                Err(_) => return Ok(status(StatusCode::INTERNAL_SERVER_ERROR)),
+            };
+            res.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+        let disposition = if self.inline { "inline" } else { "attachment" };
+        let filename = self.filename.as_deref().unwrap_or(&name);
+        res.headers_mut().insert(
+            CONTENT_DISPOSITION,
+            util::content_disposition(disposition, filename),
+        );
+        Ok(res)
+    }
+}
+
+/// Redirect to the path of `req` with a trailing slash, keeping the query.
+fn redirect_to_slash(req: &Request<Body>) -> Response<Body> {
+    let location = match req.uri().query() {
+        Some(query) => format!("{}/?{}", req.uri().path(), query),
+        None => format!("{}/", req.uri().path()),
+    };
+    let mut res = status(StatusCode::MOVED_PERMANENTLY);
+    if let Ok(location) = HeaderValue::from_str(&location) {
+        res.headers_mut().insert(LOCATION, location);
+    }
+    res
+}
+
+/// Answer with the whole object.
+fn complete(req: &Request<Body>, key: &str, object: Object) -> Response<Body> {
+    let body = if req.method() == Method::HEAD {
+        Body::empty()
+    } else {
+        object.body
+    };
+    let mut res = Response::new(body);
+    res.headers_mut()
+        .insert(CONTENT_LENGTH, object.metadata.size.into());
+    file_headers(&mut res, key, &object.metadata);
+    res
+}
+
+/// Answer with the bytes in `range` of the object.
+fn partial(key: &str, object: Object, range: Range<u64>) -> Response<Body> {
+    let mut res = Response::new(object.body);
+    *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+    let content_range = format!(
+        "bytes {}-{}/{}",
+        range.start,
+        range.end - 1,
+        object.metadata.size
+    );
+    if let Ok(value) = HeaderValue::from_str(&content_range) {
+        res.headers_mut().insert(CONTENT_RANGE, value);
+    }
+    res.headers_mut()
+        .insert(CONTENT_LENGTH, (range.end - range.start).into());
+    file_headers(&mut res, key, &object.metadata);
+    res
+}
+
+fn file_headers(res: &mut Response<Body>, key: &str, metadata: &Metadata) {
+    let content_type = metadata.content_type.clone().unwrap_or_else(|| {
+        mime_guess::from_path(key)
+            .first_or_octet_stream()
+            .to_string()
+    });
+    validators(metadata).apply(res);
+    let headers = res.headers_mut();
+    if let Ok(content_type) = HeaderValue::from_str(&content_type) {
+        headers.insert(CONTENT_TYPE, content_type);
+    }
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+}
+
+/// Get the validators of an object: its modification time, and a strong entity tag derived
+/// from it and the size of the object.
+fn validators(metadata: &Metadata) -> Conditional {
+    let modified = match metadata.modified {
+        Some(modified) => modified,
+        None => return Conditional::new(),
+    };
+    let micros = modified
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros());
+    Conditional::new()
+        .etag(ETag::strong(format!("{:x}-{:x}", micros, metadata.size)))
+        .last_modified(modified)
+}
+
+/// Check whether the `Range` of `req` applies, i.e. there's no `If-Range` or it names the
+/// current version of the object.
+fn if_range(req: &Request<Body>, metadata: &Metadata) -> bool {
+    let value = match req.headers().get(IF_RANGE) {
+        Some(value) => value,
+        None => return true,
+    };
+    let value = match value.to_str() {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    // Entity tags must match strongly, and dates exactly.
+    match ETag::parse(value) {
+        Some(etag) => validators(metadata)
+            .etag_ref()
+            .is_some_and(|current| current.strong_eq(&etag)),
+        None => metadata
+            .modified
+            .is_some_and(|modified| value == httpdate::fmt_http_date(modified)),
+    }
+}
+
+/// A `Range` header checked against the size of an object.
+enum ByteRange {
+    Satisfiable(Range<u64>),
+    /// None of the requested bytes are within the object.
+    Unsatisfiable,
+    /// The header is malformed or requests several ranges, so the whole object is sent.
+    Ignored,
+}
+
+fn parse_range(value: &[u8], size: u64) -> ByteRange {
+    let spec = match std::str::from_utf8(value)
+        .ok()
+        .and_then(|value| value.trim().strip_prefix("bytes="))
+    {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return ByteRange::Ignored,
+    };
+    let (first, last) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return ByteRange::Ignored,
+    };
+    let number = |value: &str| -> Option<u64> {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        value.parse().ok()
+    };
+
+    let range = match (first, last) {
+        // The last `n` bytes, e.g. `bytes=-500`.
+        ("", suffix) => match number(suffix) {
+            Some(0) => return ByteRange::Unsatisfiable,
+            Some(n) => size.saturating_sub(n)..size,
+            None => return ByteRange::Ignored,
+        },
+        // From an offset to the end, e.g. `bytes=500-`.
+        (first, "") => match number(first) {
+            Some(first) => first..size,
+            None => return ByteRange::Ignored,
+        },
+        (first, last) => match (number(first), number(last)) {
+            (Some(first), Some(last)) if first <= last => first..size.min(last + 1),
+            _ => return ByteRange::Ignored,
+        },
+    };
+    if range.start >= size {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Satisfiable(range)
+}
+
+/// Render the listing of a directory at `path`.
+fn listing(path: &str, root: bool, entries: &[Entry]) -> String {
+    let title = escape(path);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n<body>\n<h1>Index of {0}</h1>\n<ul>\n",
+        title
+    );
+    if !root {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for entry in entries {
+        let mut href = String::new();
+        url::encode(&mut href, &entry.name, false);
+        let slash = if entry.is_dir { "/" } else { "" };
+        let size = entry
+            .metadata
+            .as_ref()
+            .map(|metadata| format!(" ({} bytes)", metadata.size))
+            .unwrap_or_default();
+        html.push_str(&format!(
+            "<li><a href=\"{}{}\">{}{}</a>{}</li>\n",
+            href,
+            slash,
+            escape(&entry.name),
+            slash,
+            size
+        ));
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    html
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
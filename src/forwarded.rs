@@ -0,0 +1,246 @@
+//! The client behind reverse proxies, from `Forwarded` and `X-Forwarded-*` headers.
+//!
+//! Behind reverse proxies, the remote address of the connection is the one of the nearest
+//! proxy. With [`TrustedProxies`] set with
+//! [`Router::trusted_proxies`](crate::Router::trusted_proxies), the router adds the
+//! [`ClientInfo`] of every request to its extensions, with the address, scheme and host the
+//! client used according to the headers the proxies add: `X-Forwarded-For`,
+//! `X-Forwarded-Proto` and `X-Forwarded-Host` by default, or the `Forwarded` header when set
+//! with [`TrustedProxies::headers`]. The other headers are ignored, as proxies pass them on
+//! from clients unchanged. Handlers get it with
+//! [`RequestExt::client_info`](crate::ext::RequestExt::client_info), and the
+//! [`ByClientIp`](crate::rate_limit::ByClientIp) rate limit key and
+//! [`IpFilter`](crate::ip_filter::IpFilter) use its address.
+//!
+//! Clients can send these headers too, so only the entries added by the number of proxies
+//! set with [`TrustedProxies::hops`] are trusted: the client is the address the outermost of
+//! them received the request from, and the scheme and host are only taken from the entry of
+//! that proxy. Without the headers, the client is the peer of the connection, and without
+//! a scheme or host in the entry, they come from the request itself.
+//!
+//! The entries of `X-Forwarded-Proto` and `X-Forwarded-Host` can only be matched to the
+//! proxies which added them by position, so they are ignored unless they have as many entries
+//! as `X-Forwarded-For`. Trusted proxies using them should set them on every request, or
+//! remove the ones sent by clients, as a client could otherwise send the entry of a proxy
+//! which sets none.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::forwarded::TrustedProxies;
+//! use keiro::prelude::*;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! // A load balancer in front of an nginx on the private network, both adding
+//! // `X-Forwarded-For`.
+//! router.trusted_proxies(TrustedProxies::hops(2).trust("10.0.0.0/8"));
+//! router.get("/", index);
+//!
+//! async fn index(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let client = req.client_info().unwrap();
+//!     Ok(Response::new(Body::from(format!("Hello {}!", client.ip))))
+//! }
+//! ```
+
+use std::iter;
+use std::net::{IpAddr, SocketAddr};
+
+use hyper::header::{HeaderName, FORWARDED, HOST};
+use hyper::{Body, Request};
+
+use crate::connect::ConnectInfo;
+use crate::ip_filter::{self, IpRange};
+
+/// The client of a request, as seen by the outermost trusted proxy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// The address of the client.
+    pub ip: IpAddr,
+    /// The scheme the client used, such as `https`, in lowercase.
+    pub scheme: String,
+    /// The host the client requested, or `None` if it didn't send one.
+    pub host: Option<String>,
+}
+
+/// The headers which trusted proxies forward the client with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForwardingHeaders {
+    /// `X-Forwarded-For`, `X-Forwarded-Proto` and `X-Forwarded-Host`.
+    XForwarded,
+    /// The `Forwarded` header of RFC 7239.
+    Forwarded,
+}
+
+/// The reverse proxies whose forwarding headers are trusted.
+#[derive(Debug, Clone)]
+pub struct TrustedProxies {
+    hops: usize,
+    ranges: Vec<IpRange>,
+    headers: ForwardingHeaders,
+}
+
+/// An entry of the forwarding headers, added by one proxy.
+#[derive(Default)]
+struct Hop {
+    ip: Option<IpAddr>,
+    scheme: Option<String>,
+    host: Option<String>,
+}
+
+impl TrustedProxies {
+    /// Trust the entries added by `hops` proxies, counting from the nearest one.
+    pub fn hops(hops: usize) -> Self {
+        Self {
+            hops,
+            ranges: Vec::new(),
+            headers: ForwardingHeaders::XForwarded,
+        }
+    }
+
+    /// Read the client from `headers` instead of the `X-Forwarded-*` headers.
+    pub fn headers(mut self, headers: ForwardingHeaders) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Only trust the headers of connections from the addresses of `range` and the other
+    /// trusted ranges, instead of from any address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is not an IP address or a CIDR range. Use
+    /// [`TrustedProxies::trust_range`] for ranges which aren't known to be valid.
+    pub fn trust(self, range: &str) -> Self {
+        self.trust_range(ip_filter::parse(range))
+    }
+
+    pub fn trust_range(mut self, range: IpRange) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Add the [`ClientInfo`] of `req` to its extensions, if its address is known.
+    pub(crate) fn apply(&self, req: &mut Request<Body>) {
+        if let Some(info) = self.client_info(req) {
+            req.extensions_mut().insert(info);
+        }
+    }
+
+    fn client_info(&self, req: &Request<Body>) -> Option<ClientInfo> {
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo>()
+            .map(|info| info.0.ip());
+        let trusted = match peer {
+            Some(peer) => self.ranges.is_empty() || self.ranges.iter().any(|r| r.contains(peer)),
+            None => self.ranges.is_empty(),
+        };
+        let hops = if trusted && self.hops > 0 {
+            Hop::from_headers(req, self.headers)
+        } else {
+            Vec::new()
+        };
+        // The peer is the last proxy, or the client itself without forwarding headers.
+        let client = hops.len().saturating_sub(self.hops);
+        let ip = hops[client..]
+            .iter()
+            .map(|hop| hop.ip)
+            .chain(iter::once(peer))
+            .find_map(|ip| ip)?;
+        // The entry of the outermost trusted proxy, the one the client connected to.
+        let outermost = hops.get(client);
+        let scheme = outermost
+            .and_then(|hop| hop.scheme.as_deref())
+            .map(str::to_ascii_lowercase)
+            .or_else(|| req.uri().scheme_str().map(str::to_string))
+            .unwrap_or_else(|| "http".to_string());
+        let host = outermost
+            .and_then(|hop| hop.host.clone())
+            .or_else(|| {
+                let host = req.headers().get(HOST)?.to_str().ok()?;
+                Some(host.to_string())
+            })
+            .or_else(|| req.uri().authority().map(|authority| authority.to_string()));
+        Some(ClientInfo { ip, scheme, host })
+    }
+}
+
+impl Hop {
+    /// The entries of `headers`, from the client to the nearest proxy.
+    fn from_headers(req: &Request<Body>, headers: ForwardingHeaders) -> Vec<Self> {
+        if headers == ForwardingHeaders::Forwarded {
+            return list(req, &FORWARDED)
+                .map(|element| {
+                    let mut hop = Self::default();
+                    for pair in element.split(';') {
+                        let (key, value) = match pair.split_once('=') {
+                            Some((key, value)) => (key.trim(), unquote(value.trim())),
+                            None => continue,
+                        };
+                        if key.eq_ignore_ascii_case("for") {
+                            hop.ip = parse_ip(value);
+                        } else if key.eq_ignore_ascii_case("proto") {
+                            hop.scheme = Some(value.to_string());
+                        } else if key.eq_ignore_ascii_case("host") {
+                            hop.host = Some(value.to_string());
+                        }
+                    }
+                    hop
+                })
+                .collect();
+        }
+        let header = HeaderName::from_static;
+        let mut hops: Vec<Self> = list(req, &header("x-forwarded-for"))
+            .map(|node| Self {
+                ip: parse_ip(node),
+                ..Self::default()
+            })
+            .collect();
+        let schemes: Vec<_> = list(req, &header("x-forwarded-proto")).collect();
+        if schemes.len() == hops.len() {
+            for (hop, scheme) in hops.iter_mut().zip(schemes) {
+                hop.scheme = Some(scheme.to_string());
+            }
+        }
+        let hosts: Vec<_> = list(req, &header("x-forwarded-host")).collect();
+        if hosts.len() == hops.len() {
+            for (hop, host) in hops.iter_mut().zip(hosts) {
+                hop.host = Some(host.to_string());
+            }
+        }
+        hops
+    }
+}
+
+/// The comma-separated entries of all the headers `name`.
+fn list<'a>(req: &'a Request<Body>, name: &HeaderName) -> impl Iterator<Item = &'a str> {
+    req.headers()
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// Parse a node such as `192.0.2.43`, `192.0.2.43:47011` or `[2001:db8::1]:4711`, or return
+/// `None` for obfuscated and unknown ones.
+fn parse_ip(node: &str) -> Option<IpAddr> {
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    let ip = node.strip_prefix('[')?.split(']').next()?;
+    ip.parse().ok()
+}
@@ -0,0 +1,239 @@
+//! Liveness and readiness endpoints with named health checks.
+//!
+//! A [`Health`] holds named async checks, such as pinging the database or comparing the depth
+//! of a queue to a limit, and [`Router::health`](crate::Router::health) answers two endpoints
+//! with it:
+//!
+//! - `/healthz`, the liveness probe, answering `200 OK` whenever the service is running.
+//! - `/readyz`, the readiness probe, running all checks concurrently and answering `200 OK`
+//!   if they all pass, or `503 Service Unavailable` otherwise.
+//!
+//! Both report as JSON, `/readyz` with the status of every check:
+//!
+//! ```text
+//! {"checks":{"database":{"duration_ms":1.204,"status":"ok"},"queue":{"duration_ms":0.318,"error":"5210 jobs waiting","status":"fail"}},"status":"fail"}
+//! ```
+//!
+//! Checks taking longer than the [timeout](Health::timeout) fail, and so do checks which
+//! panic. With a [`Readiness`], e.g. one flipped by a [`Warmup`](crate::warmup::Warmup),
+//! `/readyz` fails while it isn't ready, whatever the checks report. It also fails while the
+//! router [drains](crate::Router::serve_with_shutdown) its connections.
+//!
+//! Requires the `json` feature.
+//!
+//! ```rust,no_run
+//! use keiro::health::Health;
+//! use keiro::Router;
+//!
+//! # async fn ping() -> Result<(), std::io::Error> { Ok(()) }
+//! # async fn queue_depth() -> usize { 0 }
+//! let health = Health::new()
+//!     .check("database", || async { ping().await })
+//!     .check("queue", || async {
+//!         match queue_depth().await {
+//!             depth if depth > 1000 => Err(format!("{} jobs waiting", depth)),
+//!             _ => Ok(()),
+//!         }
+//!     });
+//!
+//! let mut router = Router::new();
+//! router.health(health);
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Response, StatusCode};
+
+use crate::warmup::Readiness;
+
+type CheckFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+
+type CheckFn = Arc<dyn Fn() -> CheckFuture + Send + Sync>;
+
+/// Named health checks, reported by the readiness endpoint. Clones share the checks.
+#[derive(Clone)]
+pub struct Health {
+    checks: Arc<Vec<(String, CheckFn)>>,
+    timeout: Duration,
+    readiness: Vec<Readiness>,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Health {
+    /// Create a health without checks, whose checks time out after 5 seconds.
+    pub fn new() -> Self {
+        Self {
+            checks: Arc::default(),
+            timeout: Duration::from_secs(5),
+            readiness: Vec::new(),
+        }
+    }
+
+    /// Add the check `name`, which passes if the future returned by `check` succeeds.
+    pub fn check<F, R, CE>(mut self, name: &str, check: F) -> Self
+    where
+        F: Fn() -> R + Send + Sync + 'static,
+        R: Future<Output = Result<(), CE>> + Send + 'static,
+        CE: fmt::Display,
+    {
+        let check: CheckFn = Arc::new(move || {
+            let fut = check();
+            Box::pin(async move { fut.await.map_err(|err| err.to_string()) })
+        });
+        Arc::make_mut(&mut self.checks).push((name.to_string(), check));
+        self
+    }
+
+    /// Fail checks which take longer than `timeout`.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Report not ready while `readiness` isn't ready.
+    pub fn readiness(mut self, readiness: &Readiness) -> Self {
+        self.readiness.push(readiness.clone());
+        self
+    }
+
+    /// Run all checks concurrently and report their results.
+    pub async fn report(&self) -> Report {
+        let timeout = self.timeout;
+        let running: Vec<_> = self
+            .checks
+            .iter()
+            .map(|(name, check)| {
+                let fut = check();
+                // Spawned, so checks run concurrently and a panicking check fails on its own.
+                let handle = tokio::spawn(async move {
+                    let started = Instant::now();
+                    let result = match tokio::time::timeout(timeout, fut).await {
+                        Ok(result) => result,
+                        Err(_) => Err(format!("timed out after {:?}", timeout)),
+                    };
+                    (result, started.elapsed())
+                });
+                (name.clone(), handle)
+            })
+            .collect();
+        let mut checks = Vec::with_capacity(running.len());
+        for (name, handle) in running {
+            let (error, duration) = match handle.await {
+                Ok((result, duration)) => (result.err(), duration),
+                Err(_) => (Some("panicked".to_string()), Duration::ZERO),
+            };
+            checks.push(CheckStatus {
+                name,
+                error,
+                duration,
+            });
+        }
+        Report {
+            ready: self.readiness.iter().all(Readiness::is_ready),
+            checks,
+        }
+    }
+
+    /// Answer the readiness probe.
+    pub(crate) async fn readiness_response(&self) -> Response<Body> {
+        let report = self.report().await;
+        let status = if report.is_healthy() {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        json(status, report.to_json())
+    }
+}
+
+impl fmt::Debug for Health {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = self.checks.iter().map(|(name, _)| name.as_str()).collect();
+        f.debug_struct("Health")
+            .field("checks", &names)
+            .field("timeout", &self.timeout)
+            .field("readiness", &self.readiness)
+            .finish()
+    }
+}
+
+/// The results of the checks of a [`Health`].
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Whether the [`Readiness`] of the health, if any, are ready.
+    pub ready: bool,
+    pub checks: Vec<CheckStatus>,
+}
+
+impl Report {
+    /// Check whether the service is ready and all checks passed.
+    pub fn is_healthy(&self) -> bool {
+        self.ready && self.checks.iter().all(CheckStatus::is_ok)
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let checks: serde_json::Map<_, _> = self
+            .checks
+            .iter()
+            .map(|check| {
+                let mut status = serde_json::json!({
+                    "status": if check.is_ok() { "ok" } else { "fail" },
+                    "duration_ms": check.duration.as_secs_f64() * 1000.0,
+                });
+                if let Some(error) = &check.error {
+                    status["error"] = error.as_str().into();
+                }
+                (check.name.clone(), status)
+            })
+            .collect();
+        let mut report = serde_json::json!({
+            "status": if self.is_healthy() { "ok" } else { "fail" },
+            "checks": checks,
+        });
+        if !self.ready {
+            report["ready"] = false.into();
+        }
+        report
+    }
+}
+
+/// The result of a single check.
+#[derive(Debug, Clone)]
+pub struct CheckStatus {
+    pub name: String,
+    /// Why the check failed, or `None` if it passed.
+    pub error: Option<String>,
+    pub duration: Duration,
+}
+
+impl CheckStatus {
+    pub fn is_ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Answer the liveness probe.
+pub(crate) fn liveness() -> Response<Body> {
+    json(StatusCode::OK, serde_json::json!({ "status": "ok" }))
+}
+
+fn json(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    let mut res = Response::new(Body::from(body.to_string()));
+    *res.status_mut() = status;
+    res.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    // Probes must see the current state, not one cached on the way.
+    res.headers_mut()
+        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    res
+}
@@ -0,0 +1,219 @@
+//! Filtering of requests by the IP address of the client.
+//!
+//! An [`IpFilter`] set on a route with [`Route::ip_filter`](crate::Route::ip_filter), or on
+//! all routes of a router with [`Router::ip_filter`](crate::Router::ip_filter), answers
+//! requests from denied addresses with `403 Forbidden` before authentication and the handler.
+//! Addresses are taken from the [`ClientInfo`] of the request behind
+//! [trusted proxies](crate::forwarded), or else the [`ConnectInfo`] of the connection;
+//! requests without either, e.g. from a listener which doesn't know its peers, are denied by
+//! filters with an allow list.
+//!
+//! Denied ranges take precedence over allowed ones. IPv4 addresses mapped into IPv6, as seen
+//! on dual-stack listeners, match IPv4 ranges.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::ip_filter::IpFilter;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.get("/admin", admin).ip_filter(
+//!     IpFilter::new()
+//!         .allow("10.0.0.0/8")
+//!         .allow("fd00::/8")
+//!         .allow("127.0.0.1")
+//!         .deny("10.13.0.0/16"),
+//! );
+//!
+//! async fn admin(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("Hello admin!")))
+//! }
+//! ```
+
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::connect::ConnectInfo;
+use crate::forwarded::ClientInfo;
+
+/// An IP address or a CIDR range, such as `192.168.0.0/16` or `2001:db8::/32`.
+///
+/// Ranges of IPv4 addresses mapped into IPv6, such as `::ffff:10.0.0.0/104`, are the IPv4
+/// ranges they map, so their prefix must be at least 96.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, canonical(addr)) {
+            (IpAddr::V4(range), IpAddr::V4(addr)) => mask(
+                u32::from(range).into(),
+                u32::from(addr).into(),
+                self.prefix,
+                32,
+            ),
+            (IpAddr::V6(range), IpAddr::V6(addr)) => {
+                mask(range.into(), addr.into(), self.prefix, 128)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Whether the first `prefix` of the `bits` bits of `a` and `b` are equal.
+fn mask(a: u128, b: u128, prefix: u8, bits: u8) -> bool {
+    let host_bits = u32::from(bits - prefix);
+    (a ^ b).checked_shr(host_bits).unwrap_or(0) == 0
+}
+
+/// `addr`, with IPv4 addresses mapped into IPv6 turned back into IPv4.
+fn canonical(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => IpAddr::V4(v4),
+            None => IpAddr::V6(v6),
+        },
+        addr => addr,
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = InvalidIpRange;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| InvalidIpRange)?;
+        let bits: u8 = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse().map_err(|_| InvalidIpRange)?,
+            None => bits,
+        };
+        if prefix > bits {
+            return Err(InvalidIpRange);
+        }
+        // Mapped ranges are matched as IPv4, like the addresses. Shorter prefixes would cover
+        // addresses which aren't mapped, which could never match.
+        match canonical(addr) {
+            IpAddr::V4(v4) if addr.is_ipv6() => match prefix.checked_sub(96) {
+                Some(prefix) => Ok(Self {
+                    addr: IpAddr::V4(v4),
+                    prefix,
+                }),
+                None => Err(InvalidIpRange),
+            },
+            _ => Ok(Self { addr, prefix }),
+        }
+    }
+}
+
+impl fmt::Display for IpRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The error returned when parsing an [`IpRange`] fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidIpRange;
+
+impl fmt::Display for InvalidIpRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid IP address or CIDR range")
+    }
+}
+
+impl std::error::Error for InvalidIpRange {}
+
+/// Lists of the client addresses which are allowed and denied.
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<IpRange>,
+    deny: Vec<IpRange>,
+}
+
+impl IpFilter {
+    /// Create a filter allowing every address.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only allow the addresses of `range` and the other allowed ranges.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is not an IP address or a CIDR range. Use [`IpFilter::allow_range`]
+    /// for ranges which aren't known to be valid, e.g. read from the configuration.
+    pub fn allow(self, range: &str) -> Self {
+        self.allow_range(parse(range))
+    }
+
+    /// Deny the addresses of `range`, even if they are allowed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is not an IP address or a CIDR range.
+    pub fn deny(self, range: &str) -> Self {
+        self.deny_range(parse(range))
+    }
+
+    pub fn allow_range(mut self, range: IpRange) -> Self {
+        self.allow.push(range);
+        self
+    }
+
+    pub fn deny_range(mut self, range: IpRange) -> Self {
+        self.deny.push(range);
+        self
+    }
+
+    pub fn is_allowed(&self, addr: IpAddr) -> bool {
+        !self.deny.iter().any(|range| range.contains(addr))
+            && (self.allow.is_empty() || self.allow.iter().any(|range| range.contains(addr)))
+    }
+
+    /// Return the response for `req` if its client is denied.
+    pub(crate) fn check(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        let allowed = match client_ip(req) {
+            Some(ip) => self.is_allowed(ip),
+            None => self.allow.is_empty(),
+        };
+        if allowed {
+            return None;
+        }
+        Some(
+            Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    }
+}
+
+/// The address of the client of `req`, behind trusted proxies or else of the connection, with
+/// IPv4 addresses mapped into IPv6 turned back into IPv4.
+pub(crate) fn client_ip(req: &Request<Body>) -> Option<IpAddr> {
+    if let Some(info) = req.extensions().get::<ClientInfo>() {
+        return Some(canonical(info.ip));
+    }
+    req.extensions()
+        .get::<ConnectInfo>()
+        .map(|info| canonical(info.0.ip()))
+}
+
+pub(crate) fn parse(range: &str) -> IpRange {
+    match range.parse() {
+        Ok(range) => range,
+        Err(_) => panic!("invalid IP address or CIDR range: {:?}", range),
+    }
+}
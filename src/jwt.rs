@@ -0,0 +1,615 @@
+//! Bearer token authentication with JSON Web Tokens.
+//!
+//! A [`JwtAuth`] set on a route with [`Route::jwt`](crate::Route::jwt), or on all routes of a
+//! router with [`Router::jwt`](crate::Router::jwt), requires an `Authorization: Bearer` header
+//! carrying a JWT signed by one of its keys. The signature, the `exp` and `nbf` times and,
+//! when configured, the issuer and audience are checked before the handler is called; other
+//! requests are answered with `401 Unauthorized` and a `WWW-Authenticate: Bearer` challenge as
+//! defined by [RFC 6750](https://www.rfc-editor.org/rfc/rfc6750). Handlers get the claims of
+//! the token with [`RequestExt::claims`](crate::ext::RequestExt::claims), and the `sub` claim
+//! and scopes of the token as its [`Principal`] for [`authz`](crate::authz).
+//!
+//! Tokens are verified with shared secrets (`HS256`, `HS384`, `HS512`) or with the public keys
+//! of a JWKS document (`RS256`, `RS384`, `RS512`, `ES256`), which is fetched again when it's
+//! older than the refresh interval or a token is signed by a key it doesn't have yet. Tokens
+//! signed with the algorithm `none` are never accepted.
+//!
+//! Requires the `jwt` feature.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::jwt::JwtAuth;
+//! use keiro::prelude::*;
+//! use keiro::Router;
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct User {
+//!     sub: String,
+//! }
+//!
+//! let mut router = Router::new();
+//! router.jwt(
+//!     JwtAuth::new()
+//!         .jwks_url("https://auth.example.com/.well-known/jwks.json")
+//!         .issuer("https://auth.example.com/")
+//!         .audience("reports"),
+//! );
+//! router.get("/reports", reports);
+//!
+//! async fn reports(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let user = req.claims::<User>().unwrap();
+//!     Ok(Response::new(Body::from(format!("Reports of {}", user.sub))))
+//! }
+//! ```
+
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use base64::alphabet::URL_SAFE;
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use hyper::header::{HeaderValue, WWW_AUTHENTICATE};
+use hyper::{Body, Client, Request, Response, StatusCode};
+use ring::hmac;
+use ring::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use crate::authz::Principal;
+use crate::extract::Authorization;
+use crate::proxy::UpstreamTls;
+
+/// Base64url, accepting values with or without padding as some JWKS documents pad them.
+const BASE64URL: GeneralPurpose = GeneralPurpose::new(
+    &URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// The least time between two fetches of the JWKS document for tokens with an unknown key.
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The claims of the token a request was authenticated with, added to the request extensions.
+/// [`RequestExt::claims`](crate::ext::RequestExt::claims) deserializes them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claims(pub Value);
+
+/// Checks bearer tokens against keys and an expected issuer and audience.
+pub struct JwtAuth {
+    keys: Vec<Key>,
+    jwks_url: Option<String>,
+    tls: UpstreamTls,
+    refresh_interval: Duration,
+    issuer: Option<String>,
+    audiences: Vec<String>,
+    leeway: Duration,
+    scope_claim: Option<String>,
+}
+
+impl Default for JwtAuth {
+    fn default() -> Self {
+        Self {
+            keys: Vec::new(),
+            jwks_url: None,
+            tls: UpstreamTls::default(),
+            refresh_interval: Duration::from_secs(60 * 60),
+            issuer: None,
+            audiences: Vec::new(),
+            leeway: Duration::from_secs(60),
+            scope_claim: None,
+        }
+    }
+}
+
+impl JwtAuth {
+    /// Create a check accepting no keys yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept tokens signed with `secret` by `HS256`, `HS384` or `HS512`.
+    pub fn hmac_secret(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.keys.push(Key {
+            kid: None,
+            alg: None,
+            material: Material::Hmac(secret.as_ref().to_vec()),
+        });
+        self
+    }
+
+    /// Accept tokens signed by the public keys of the JWKS document at `url`. Symmetric `oct`
+    /// keys in the document are ignored; use [`hmac_secret`](JwtAuth::hmac_secret) for them.
+    pub fn jwks_url(mut self, url: impl Into<String>) -> Self {
+        self.jwks_url = Some(url.into());
+        self
+    }
+
+    /// Verify the server of the JWKS document with `tls` instead of the Mozilla root
+    /// certificates.
+    pub fn tls(mut self, tls: UpstreamTls) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    /// Fetch the JWKS document again once it's older than `interval`, one hour by default.
+    pub fn refresh_interval(mut self, interval: Duration) -> Self {
+        self.refresh_interval = interval;
+        self
+    }
+
+    /// Require the `iss` claim to be `issuer`.
+    pub fn issuer(mut self, issuer: impl Into<String>) -> Self {
+        self.issuer = Some(issuer.into());
+        self
+    }
+
+    /// Require the `aud` claim to contain `audience`, or any of the audiences if called more
+    /// than once.
+    pub fn audience(mut self, audience: impl Into<String>) -> Self {
+        self.audiences.push(audience.into());
+        self
+    }
+
+    /// Allow for `leeway` of clock skew when checking the `exp` and `nbf` claims, one minute
+    /// by default.
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Grant the scopes of the claim `name`, a string of scopes separated by spaces or an array,
+    /// instead of the `scope` and `scp` claims. E.g. `roles` for tokens listing roles.
+    pub fn scope_claim(mut self, name: impl Into<String>) -> Self {
+        self.scope_claim = Some(name.into());
+        self
+    }
+
+    pub(crate) fn verifier(self) -> Arc<Verifier> {
+        let (tls, refresh_interval) = (self.tls, self.refresh_interval);
+        let jwks = self.jwks_url.map(|url| Jwks {
+            url,
+            tls,
+            refresh_interval,
+            state: RwLock::new(JwksState::default()),
+            refreshing: Mutex::new(()),
+        });
+        Arc::new(Verifier {
+            keys: self.keys,
+            jwks,
+            issuer: self.issuer,
+            audiences: self.audiences,
+            leeway: self.leeway,
+            scope_claim: self.scope_claim,
+        })
+    }
+}
+
+impl fmt::Debug for JwtAuth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("JwtAuth")
+            .field("secrets", &self.keys.len())
+            .field("jwks_url", &self.jwks_url)
+            .field("refresh_interval", &self.refresh_interval)
+            .field("issuer", &self.issuer)
+            .field("audiences", &self.audiences)
+            .field("leeway", &self.leeway)
+            .field("scope_claim", &self.scope_claim)
+            .finish()
+    }
+}
+
+/// The checks of a [`JwtAuth`] with the keys fetched so far, shared by the requests.
+pub(crate) struct Verifier {
+    keys: Vec<Key>,
+    jwks: Option<Jwks>,
+    issuer: Option<String>,
+    audiences: Vec<String>,
+    leeway: Duration,
+    scope_claim: Option<String>,
+}
+
+impl Verifier {
+    /// Check the token of `req`, adding its [`Claims`] if it's valid, or return the challenge
+    /// to answer with.
+    pub(crate) async fn check(
+        self: Arc<Self>,
+        mut req: Request<Body>,
+    ) -> Result<Request<Body>, Response<Body>> {
+        let token = match Authorization::from_headers(&req) {
+            Some(Authorization::Bearer(token)) => token,
+            _ => return Err(challenge(None)),
+        };
+        match self.verify(&token).await {
+            Ok(claims) => {
+                req.extensions_mut().insert(self.principal(&claims));
+                req.extensions_mut().insert(Claims(claims));
+                Ok(req)
+            }
+            Err(invalid) => Err(challenge(Some(invalid))),
+        }
+    }
+
+    async fn verify(&self, token: &str) -> Result<Value, Invalid> {
+        let mut parts = token.split('.');
+        let (header, payload, sig) = match (parts.next(), parts.next(), parts.next(), parts.next())
+        {
+            (Some(header), Some(payload), Some(sig), None) => (header, payload, sig),
+            _ => return Err(Invalid::Malformed),
+        };
+        // The signed part is everything before the signature.
+        let message = &token[..header.len() + 1 + payload.len()];
+        let header = decode_json(header)?;
+        let claims = decode_json(payload)?;
+        let sig = BASE64URL.decode(sig).map_err(|_| Invalid::Malformed)?;
+        let alg = header["alg"]
+            .as_str()
+            .and_then(Algorithm::from_name)
+            .ok_or(Invalid::Algorithm)?;
+        let kid = header["kid"].as_str();
+
+        let verified = verify_with(&self.keys, alg, kid, message, &sig);
+        if verified != Some(true) {
+            match &self.jwks {
+                Some(jwks) => jwks.verify(alg, kid, message, &sig).await?,
+                None if verified.is_none() => return Err(Invalid::Key),
+                None => return Err(Invalid::Signature),
+            }
+        }
+        if !claims.is_object() {
+            return Err(Invalid::Malformed);
+        }
+        self.check_claims(&claims)?;
+        Ok(claims)
+    }
+
+    fn principal(&self, claims: &Value) -> Principal {
+        let scopes = match &self.scope_claim {
+            Some(name) => scopes(&claims[name.as_str()]),
+            None => [scopes(&claims["scope"]), scopes(&claims["scp"])].concat(),
+        };
+        Principal {
+            id: claims["sub"].as_str().unwrap_or_default().to_string(),
+            scopes,
+        }
+    }
+
+    fn check_claims(&self, claims: &Value) -> Result<(), Invalid> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+        let leeway = self.leeway.as_secs_f64();
+        if let Some(exp) = claims.get("exp") {
+            let exp = exp.as_f64().ok_or(Invalid::Malformed)?;
+            if now > exp + leeway {
+                return Err(Invalid::Expired);
+            }
+        }
+        if let Some(nbf) = claims.get("nbf") {
+            let nbf = nbf.as_f64().ok_or(Invalid::Malformed)?;
+            if now + leeway < nbf {
+                return Err(Invalid::NotYetValid);
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims["iss"].as_str() != Some(issuer.as_str()) {
+                return Err(Invalid::Issuer);
+            }
+        }
+        if !self.audiences.is_empty() {
+            let accepted = |aud: &Value| {
+                aud.as_str()
+                    .is_some_and(|aud| self.audiences.iter().any(|expected| expected == aud))
+            };
+            let valid = match &claims["aud"] {
+                Value::Array(auds) => auds.iter().any(accepted),
+                aud => accepted(aud),
+            };
+            if !valid {
+                return Err(Invalid::Audience);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verify `sig` with the `keys` matching the token, or return `None` if none of them does.
+fn verify_with(
+    keys: &[Key],
+    alg: Algorithm,
+    kid: Option<&str>,
+    message: &str,
+    sig: &[u8],
+) -> Option<bool> {
+    let mut keys = keys.iter().filter(|key| key.matches(alg, kid)).peekable();
+    keys.peek()?;
+    Some(keys.any(|key| key.verify(alg, message.as_bytes(), sig)))
+}
+
+/// The keys of a JWKS document, fetched once they're stale.
+struct Jwks {
+    url: String,
+    tls: UpstreamTls,
+    refresh_interval: Duration,
+    state: RwLock<JwksState>,
+    /// Held while fetching, so concurrent requests wait for one fetch.
+    refreshing: Mutex<()>,
+}
+
+#[derive(Default)]
+struct JwksState {
+    keys: Arc<Vec<Key>>,
+    fetched: Option<Instant>,
+    attempted: Option<Instant>,
+}
+
+impl Jwks {
+    async fn verify(
+        &self,
+        alg: Algorithm,
+        kid: Option<&str>,
+        message: &str,
+        sig: &[u8],
+    ) -> Result<(), Invalid> {
+        let (keys, stale) = self.keys();
+        let found = keys.iter().any(|key| key.matches(alg, kid));
+        let keys = if stale || !found {
+            self.refresh().await
+        } else {
+            keys
+        };
+        match verify_with(&keys, alg, kid, message, sig) {
+            Some(true) => Ok(()),
+            Some(false) => Err(Invalid::Signature),
+            None => Err(Invalid::Key),
+        }
+    }
+
+    /// The current keys and whether they're older than the refresh interval.
+    fn keys(&self) -> (Arc<Vec<Key>>, bool) {
+        let state = match self.state.read() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let stale = state
+            .fetched
+            .is_none_or(|fetched| fetched.elapsed() >= self.refresh_interval);
+        (state.keys.clone(), stale)
+    }
+
+    /// Fetch the document again, keeping the old keys if fetching fails. Fetches are at
+    /// least [`MIN_REFETCH_INTERVAL`] apart, so requests waiting for another fetch use its keys
+    /// and tokens with unknown keys can't make the server fetch constantly.
+    async fn refresh(&self) -> Arc<Vec<Key>> {
+        let _refreshing = self.refreshing.lock().await;
+        {
+            let state = match self.state.read() {
+                Ok(state) => state,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            if state
+                .attempted
+                .is_some_and(|attempted| attempted.elapsed() < MIN_REFETCH_INTERVAL)
+            {
+                return state.keys.clone();
+            }
+        }
+        let fetched = self.fetch().await;
+        let mut state = match self.state.write() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = Instant::now();
+        state.attempted = Some(now);
+        if let Some(keys) = fetched {
+            state.keys = Arc::new(keys);
+            state.fetched = Some(now);
+        }
+        state.keys.clone()
+    }
+
+    async fn fetch(&self) -> Option<Vec<Key>> {
+        let client = Client::builder().build::<_, Body>(self.tls.connector());
+        let res = client.get(self.url.parse().ok()?).await.ok()?;
+        if !res.status().is_success() {
+            return None;
+        }
+        let body = hyper::body::to_bytes(res.into_body()).await.ok()?;
+        let jwks: Value = serde_json::from_slice(&body).ok()?;
+        let keys = jwks["keys"].as_array()?;
+        Some(keys.iter().filter_map(Key::from_jwk).collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    Hs256,
+    Hs384,
+    Hs512,
+    Rs256,
+    Rs384,
+    Rs512,
+    Es256,
+}
+
+impl Algorithm {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "HS256" => Algorithm::Hs256,
+            "HS384" => Algorithm::Hs384,
+            "HS512" => Algorithm::Hs512,
+            "RS256" => Algorithm::Rs256,
+            "RS384" => Algorithm::Rs384,
+            "RS512" => Algorithm::Rs512,
+            "ES256" => Algorithm::Es256,
+            _ => return None,
+        })
+    }
+}
+
+struct Key {
+    kid: Option<String>,
+    /// The only algorithm the key may be used with, if it names one.
+    alg: Option<Algorithm>,
+    material: Material,
+}
+
+enum Material {
+    Hmac(Vec<u8>),
+    Rsa {
+        n: Vec<u8>,
+        e: Vec<u8>,
+    },
+    /// An uncompressed P-256 point.
+    Ec(Vec<u8>),
+}
+
+impl Key {
+    fn from_jwk(jwk: &Value) -> Option<Self> {
+        if jwk["use"].as_str().is_some_and(|use_| use_ != "sig") {
+            return None;
+        }
+        let param = |name: &str| BASE64URL.decode(jwk[name].as_str()?).ok();
+        let material = match jwk["kty"].as_str()? {
+            "RSA" => Material::Rsa {
+                n: param("n")?,
+                e: param("e")?,
+            },
+            "EC" if jwk["crv"] == "P-256" => {
+                let (x, y) = (param("x")?, param("y")?);
+                if x.len() != 32 || y.len() != 32 {
+                    return None;
+                }
+                Material::Ec([&[0x04][..], &x, &y].concat())
+            }
+            // Shared secrets such as `oct` keys are only accepted from the configuration: anyone
+            // fetching the document could sign tokens with them.
+            _ => return None,
+        };
+        let alg = match jwk["alg"].as_str() {
+            Some(alg) => Some(Algorithm::from_name(alg)?),
+            None => None,
+        };
+        Some(Self {
+            kid: jwk["kid"].as_str().map(str::to_string),
+            alg,
+            material,
+        })
+    }
+
+    /// Whether the key may verify a token signed by `alg` with the key id `kid`.
+    fn matches(&self, alg: Algorithm, kid: Option<&str>) -> bool {
+        let family = matches!(
+            (&self.material, alg),
+            (
+                Material::Hmac(_),
+                Algorithm::Hs256 | Algorithm::Hs384 | Algorithm::Hs512
+            ) | (
+                Material::Rsa { .. },
+                Algorithm::Rs256 | Algorithm::Rs384 | Algorithm::Rs512
+            ) | (Material::Ec(_), Algorithm::Es256)
+        );
+        let kid = match (self.kid.as_deref(), kid) {
+            (Some(expected), Some(kid)) => expected == kid,
+            _ => true,
+        };
+        family && kid && self.alg.is_none_or(|expected| expected == alg)
+    }
+
+    fn verify(&self, alg: Algorithm, message: &[u8], sig: &[u8]) -> bool {
+        match &self.material {
+            Material::Hmac(secret) => {
+                let alg = match alg {
+                    Algorithm::Hs256 => hmac::HMAC_SHA256,
+                    Algorithm::Hs384 => hmac::HMAC_SHA384,
+                    _ => hmac::HMAC_SHA512,
+                };
+                hmac::verify(&hmac::Key::new(alg, secret), message, sig).is_ok()
+            }
+            Material::Rsa { n, e } => {
+                let params = match alg {
+                    Algorithm::Rs256 => &signature::RSA_PKCS1_2048_8192_SHA256,
+                    Algorithm::Rs384 => &signature::RSA_PKCS1_2048_8192_SHA384,
+                    _ => &signature::RSA_PKCS1_2048_8192_SHA512,
+                };
+                RsaPublicKeyComponents { n, e }
+                    .verify(params, message, sig)
+                    .is_ok()
+            }
+            Material::Ec(point) => {
+                UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, point)
+                    .verify(message, sig)
+                    .is_ok()
+            }
+        }
+    }
+}
+
+/// Why a token was rejected, told to the client in the challenge.
+#[derive(Debug, Clone, Copy)]
+enum Invalid {
+    Malformed,
+    Algorithm,
+    Key,
+    Signature,
+    Expired,
+    NotYetValid,
+    Issuer,
+    Audience,
+}
+
+impl fmt::Display for Invalid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Invalid::Malformed => "malformed token",
+            Invalid::Algorithm => "unsupported algorithm",
+            Invalid::Key => "unknown signing key",
+            Invalid::Signature => "invalid signature",
+            Invalid::Expired => "token expired",
+            Invalid::NotYetValid => "token not yet valid",
+            Invalid::Issuer => "invalid issuer",
+            Invalid::Audience => "invalid audience",
+        })
+    }
+}
+
+/// The scopes of a claim which is a string of scopes separated by spaces, or an array.
+fn scopes(claim: &Value) -> Vec<String> {
+    match claim {
+        Value::String(scopes) => scopes.split_whitespace().map(str::to_string).collect(),
+        Value::Array(scopes) => scopes
+            .iter()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn decode_json(part: &str) -> Result<Value, Invalid> {
+    let bytes = BASE64URL.decode(part).map_err(|_| Invalid::Malformed)?;
+    serde_json::from_slice(&bytes).map_err(|_| Invalid::Malformed)
+}
+
+/// The `401 Unauthorized` response for a request without a token or with an `invalid` one.
+fn challenge(invalid: Option<Invalid>) -> Response<Body> {
+    let challenge = match invalid {
+        Some(invalid) => format!(
+            "Bearer error=\"invalid_token\", error_description=\"{}\"",
+            invalid
+        ),
+        None => "Bearer".to_string(),
+    };
+    let mut res = Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .body(Body::empty())
+        .unwrap();
+    if let Ok(challenge) = HeaderValue::from_str(&challenge) {
+        res.headers_mut().insert(WWW_AUTHENTICATE, challenge);
+    }
+    res
+}
@@ -0,0 +1,154 @@
+//! Post-processing the responses of a scope.
+//!
+//! [`Router::map_response`](crate::Router::map_response) transforms every response of a
+//! router's routes and not found handler, after the handler returned it. Routers nested with
+//! [`Router::nest`](crate::Router::nest) are scopes of their own: a transformer set on a nested
+//! router replaces the one of the outer router for its routes, like the other router-wide
+//! options.
+//!
+//! [`Layout`] is such a transformer for simple server-rendered sites: handlers return HTML
+//! fragments, and the layout wraps them into the shared page with the header and footer.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::header::CONTENT_TYPE;
+//! use hyper::{Body, Request, Response};
+//! use keiro::layout::Layout;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.layout(Layout::new(|content| {
+//!     format!(
+//!         "<!doctype html><html><body><nav>My site</nav><main>{}</main></body></html>",
+//!         content
+//!     )
+//! }));
+//! router.get("/", index);
+//!
+//! async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::builder()
+//!         .header(CONTENT_TYPE, "text/html; charset=utf-8")
+//!         .body(Body::from("<h1>Welcome</h1>"))
+//!         .unwrap())
+//! }
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use hyper::header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Response, StatusCode};
+
+use crate::streaming;
+
+type ResponseFuture = Pin<Box<dyn Future<Output = Response<Body>> + Send>>;
+
+/// A transformer of the responses of a scope, set with
+/// [`Router::map_response`](crate::Router::map_response).
+#[derive(Clone)]
+pub(crate) struct MapResponse(Arc<dyn Fn(Response<Body>) -> ResponseFuture + Send + Sync>);
+
+impl MapResponse {
+    pub(crate) fn new<F, Fut>(map: F) -> Self
+    where
+        F: Fn(Response<Body>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response<Body>> + Send + 'static,
+    {
+        Self(Arc::new(move |res| Box::pin(map(res))))
+    }
+
+    pub(crate) fn call(&self, res: Response<Body>) -> ResponseFuture {
+        (self.0)(res)
+    }
+}
+
+impl fmt::Debug for MapResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("MapResponse")
+    }
+}
+
+/// A page layout wrapping the HTML fragments returned by handlers.
+///
+/// Only successful `text/html` responses are wrapped. Responses whose body is already a whole
+/// document, starting with `<!doctype` or `<html`, are sent as they are, and so are empty,
+/// encoded and [streamed](crate::streaming::must_stream) ones.
+#[derive(Clone)]
+pub struct Layout {
+    render: Arc<dyn Fn(&str) -> String + Send + Sync>,
+}
+
+impl Layout {
+    /// Create a layout placing fragments in the page returned by `render`.
+    pub fn new(render: impl Fn(&str) -> String + Send + Sync + 'static) -> Self {
+        Self {
+            render: Arc::new(render),
+        }
+    }
+
+    /// Create a layout placing fragments between `header` and `footer`.
+    pub fn wrap(header: impl Into<String>, footer: impl Into<String>) -> Self {
+        let (header, footer) = (header.into(), footer.into());
+        Self::new(move |content| format!("{}{}{}", header, content, footer))
+    }
+
+    /// Wrap the body of `res` if it's an HTML fragment.
+    pub async fn apply(&self, res: Response<Body>) -> Response<Body> {
+        if !is_fragment_response(&res) {
+            return res;
+        }
+        let (mut parts, body) = res.into_parts();
+        let body = match hyper::body::to_bytes(body).await {
+            Ok(body) => body,
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::empty())
+                    .unwrap()
+            }
+        };
+        let content = match std::str::from_utf8(&body) {
+            Ok(content) if !is_document(content) => content,
+            _ => return Response::from_parts(parts, Body::from(body)),
+        };
+        let page = (self.render)(content);
+        parts.headers.insert(CONTENT_LENGTH, page.len().into());
+        Response::from_parts(parts, Body::from(page))
+    }
+}
+
+impl fmt::Debug for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Layout").finish()
+    }
+}
+
+fn is_fragment_response(res: &Response<Body>) -> bool {
+    use hyper::body::HttpBody;
+
+    let is_html = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|essence| essence.trim().eq_ignore_ascii_case("text/html"));
+    is_html
+        && res.status().is_success()
+        && res.status() != StatusCode::NO_CONTENT
+        && !res.headers().contains_key(CONTENT_ENCODING)
+        && !streaming::is_must_stream(res)
+        && res.body().size_hint().exact() != Some(0)
+}
+
+fn is_document(content: &str) -> bool {
+    let start = content.trim_start().as_bytes();
+    let starts_with = |tag: &str| {
+        start
+            .get(..tag.len())
+            .is_some_and(|s| s.eq_ignore_ascii_case(tag.as_bytes()))
+    };
+    starts_with("<!doctype") || starts_with("<html")
+}
@@ -127,6 +127,32 @@
 //! }
 //! ```
 //!
+//! ### Errors
+//!
+//! Handlers can return any error type converting into keiro's [`Error`], which
+//! [`keiro::Result`](Result) uses and any error converts into with `?`. The errors are converted
+//! when handlers are registered, so infallible and fallible handlers can share a router. Routers
+//! created with [`Router::with_error_type`] convert them into another type instead.
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.get("/", index);
+//! router.get("/motd", motd);
+//!
+//! async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("Hello keiro!")))
+//! }
+//!
+//! async fn motd(_req: Request<Body>) -> keiro::Result<Response<Body>> {
+//!     let motd = tokio::fs::read_to_string("/etc/motd").await?;
+//!     Ok(Response::new(Body::from(motd)))
+//! }
+//! ```
+//!
 //! ### Share states
 //!
 //! Handler can use share states.
@@ -164,141 +190,921 @@
 //! }
 //! ```
 
+#[cfg(feature = "access-log")]
+pub mod access_log;
+pub mod accounting;
+#[cfg(feature = "acme")]
+pub mod acme;
+pub mod api_key;
+pub mod authz;
+pub mod basic_auth;
+pub mod body;
+pub mod budget;
+pub mod cache;
+pub mod cache_control;
+#[cfg(feature = "checksum")]
+mod checksum;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod concurrency;
+pub mod conditional;
+pub mod connect;
+pub mod cookie;
+pub mod cors;
+mod data;
+#[cfg(feature = "debug-errors")]
+pub mod debug_errors;
+pub mod encoding;
+mod error;
+pub mod explain;
 pub mod ext;
+pub mod extract;
+pub mod files;
+pub mod forwarded;
+#[cfg(feature = "json")]
+pub mod health;
+pub mod ip_filter;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod layout;
+pub mod limits;
+pub mod method_override;
+pub mod metrics;
+#[cfg(feature = "multipart")]
+pub mod multipart;
+pub mod negotiation;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod pagination;
+pub mod panic;
 pub mod prelude;
+pub mod probe;
+#[cfg(feature = "json")]
+pub mod problem;
+pub mod proxy;
+pub mod query;
+pub mod rate_limit;
+#[cfg(feature = "redis")]
+mod redis;
+pub mod request_id;
+mod route;
+pub mod sampling;
+pub mod server;
+pub mod server_timing;
+#[cfg(feature = "sessions")]
+pub mod sessions;
+pub mod sse;
+mod state;
+pub mod storage;
+pub mod streaming;
+pub mod swap;
+pub mod tarpit;
+#[cfg(any(feature = "askama", feature = "tera"))]
+pub mod templates;
+#[cfg(feature = "tracing")]
+pub mod trace;
+#[cfg(feature = "tus")]
+pub mod tus;
+pub mod upgrade;
+pub mod url;
+mod util;
+pub mod warmup;
+pub mod well_known;
 
 use std::collections::HashMap;
-use std::error::Error;
+use std::error::Error as StdError;
 use std::fmt;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use hyper::service::Service;
-use hyper::{Body, Method, Request, Response};
+use hyper::{header, Body, Method, Request, Response, StatusCode};
 use route_recognizer::Router as InnerRouter;
 
-pub struct Router<E, State> {
-    inner: HashMap<Method, InnerRouter<Box<dyn Handler<E>>>>,
+use crate::api_key::ApiKey;
+use crate::basic_auth::BasicAuth;
+use crate::cache_control::CacheControl;
+use crate::connect::ConnectedService;
+use crate::cors::Cors;
+pub use crate::data::Data;
+pub use crate::error::{BoxError, Error, Result};
+use crate::error::{ErrorHandler, Generated, StatusHandler};
+use crate::explain::{Check, Explanation, Outcome, Step};
+use crate::forwarded::TrustedProxies;
+use crate::ip_filter::IpFilter;
+#[cfg(feature = "jwt")]
+use crate::jwt::JwtAuth;
+use crate::layout::{Layout, MapResponse};
+use crate::limits::HeaderLimits;
+use crate::method_override::MethodOverride;
+use crate::metrics::{Metrics, MetricsRecorder, Recorder, RequestMetrics};
+use crate::negotiation::Locales;
+use crate::probe::ProbeFilter;
+use crate::rate_limit::RateLimit;
+use crate::request_id::RequestIds;
+pub use crate::route::Route;
+use crate::route::{Endpoint, Limiter, Matched, RouteOptions};
+pub use crate::server::serve;
+#[cfg(feature = "tls")]
+pub use crate::server::serve_tls;
+use crate::server_timing::ServerTiming;
+use crate::state::StateMap;
+use crate::swap::Swappable;
+use crate::tarpit::Tarpit;
+use crate::url::UrlError;
+use crate::util::SyncFuture;
+use crate::warmup::Readiness;
+use crate::well_known::WellKnown;
+
+pub struct Router<E = Error, State = ()> {
+    inner: HashMap<Method, InnerRouter<usize>>,
+    endpoints: Vec<Arc<Endpoint<E>>>,
+    nested: InnerRouter<Arc<Mount<E>>>,
+    mounts: Vec<Arc<Mount<E>>>,
     not_found: Option<Box<dyn Handler<E>>>,
+    probe_filter: Option<ProbeFilter>,
+    header_limits: Option<HeaderLimits>,
+    defaults: RouteOptions,
+    recorder: Option<Arc<dyn Recorder>>,
+    request_metrics: Option<Arc<dyn MetricsRecorder>>,
+    cors: Option<Arc<Cors>>,
+    method_override: Option<Arc<MethodOverride>>,
+    trusted_proxies: Option<Arc<TrustedProxies>>,
+    request_ids: Option<Arc<RequestIds>>,
+    server_timing: bool,
+    drain_timeout: Duration,
+    /// Ready until the router drains its connections.
+    accepting: Readiness,
+    #[cfg(feature = "otel")]
+    propagation: Option<Arc<otel::Propagation>>,
+    #[cfg(feature = "access-log")]
+    access_log: Option<access_log::AccessLog>,
+    locales: Option<Arc<Locales>>,
+    #[allow(clippy::type_complexity)]
+    explain: Option<Arc<dyn Fn(&Explanation) + Send + Sync>>,
+    on_panic: Option<panic::PanicHook>,
+    error_handler: Option<ErrorHandler>,
+    status_handlers: HashMap<StatusCode, StatusHandler>,
+    #[cfg(feature = "json")]
+    problem_details: bool,
+    #[cfg(feature = "debug-errors")]
+    debug_errors: bool,
+    #[cfg(feature = "compression")]
+    compression: Option<compression::Compression>,
+    #[cfg(feature = "signed-urls")]
+    signing_key: Option<Arc<[u8]>>,
+    #[cfg(feature = "tracing")]
+    trace_context: Option<trace::TraceContext>,
     state: State,
+    states: StateMap,
 }
 
-impl<E> Default for Router<E, ()>
-where
-    E: Into<Box<dyn Error + Send + Sync>> + 'static,
-{
+impl Default for Router {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<E> Router<E, ()>
-where
-    E: Into<Box<dyn Error + Send + Sync>> + 'static,
-{
+impl Router {
     pub fn new() -> Self {
         Router::with_state(())
     }
 }
 
-impl<E, State> Router<E, State>
+impl<State> Router<Error, State>
 where
-    E: Into<Box<dyn Error + Send + Sync>> + 'static,
     State: Clone + Send + Sync + 'static,
 {
     pub fn with_state(state: State) -> Self {
+        Router::with_error_type(state)
+    }
+}
+
+impl<T> Router<Error, Data<T>>
+where
+    T: Send + Sync + 'static,
+{
+    /// Create a router whose state is `value` in a [`Data`], so it isn't cloned for every
+    /// request. Handlers registered with [`Router::route_with_state`] get it as an argument.
+    pub fn with_data(value: T) -> Self {
+        Router::with_state(Data::new(value))
+    }
+}
+
+impl<E, State> Router<E, State>
+where
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    State: Clone + Send + Sync + 'static,
+{
+    /// Create a router with `state` whose handlers' errors are converted into `E` instead of
+    /// [`Error`], e.g. `Router::<Infallible, _>::with_error_type(())` for a router which can
+    /// be nested into routers of any error type.
+    pub fn with_error_type(state: State) -> Self {
         Self {
             inner: HashMap::new(),
+            endpoints: Vec::new(),
+            nested: InnerRouter::new(),
+            mounts: Vec::new(),
             not_found: None,
+            probe_filter: None,
+            header_limits: None,
+            defaults: RouteOptions::default(),
+            recorder: None,
+            request_metrics: None,
+            cors: None,
+            method_override: None,
+            trusted_proxies: None,
+            request_ids: None,
+            server_timing: false,
+            drain_timeout: Duration::from_secs(30),
+            accepting: Readiness::ready(),
+            #[cfg(feature = "otel")]
+            propagation: None,
+            #[cfg(feature = "access-log")]
+            access_log: None,
+            locales: None,
+            explain: None,
+            on_panic: None,
+            error_handler: None,
+            status_handlers: HashMap::new(),
+            #[cfg(feature = "json")]
+            problem_details: false,
+            #[cfg(feature = "debug-errors")]
+            debug_errors: false,
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(feature = "signed-urls")]
+            signing_key: None,
+            #[cfg(feature = "tracing")]
+            trace_context: None,
             state,
+            states: StateMap::default(),
         }
     }
 
+    /// Add a shared state which handlers can get with `req.state::<T>()`.
+    ///
+    /// Unlike the state passed to `with_state`, any number of states with distinct types can be
+    /// added. Adding a state of an already added type replaces it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::convert::Infallible;
+    /// # use hyper::{Body, Request, Response};
+    /// use keiro::prelude::*;
+    /// use keiro::Router;
+    ///
+    /// #[derive(Clone)]
+    /// struct Config {
+    ///     greeting: String,
+    /// }
+    ///
+    /// #[derive(Clone)]
+    /// struct Name(String);
+    ///
+    /// let mut router = Router::new();
+    /// router.manage(Config {
+    ///     greeting: "Hello".to_string(),
+    /// });
+    /// router.manage(Name("giraffate".to_string()));
+    /// router.get("/", index);
+    ///
+    /// async fn index(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    ///     let config = req.state::<Config>().unwrap();
+    ///     let name = req.state::<Name>().unwrap();
+    ///     Ok(Response::new(Body::from(format!("{} {}", config.greeting, name.0))))
+    /// }
+    /// ```
+    pub fn manage<T: Clone + Send + Sync + 'static>(&mut self, value: T) {
+        self.states.insert(value);
+    }
+
+    /// Add a shared state which handlers can get with `req.extract::<Data<T>>()`.
+    ///
+    /// The state is wrapped in [`Data`], so only a reference count is updated per request
+    /// instead of cloning the state.
+    pub fn data<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.states.insert(Data::new(value));
+    }
+
     /// Register a handler for GET requests
-    pub fn get<H, R>(&mut self, path: &str, handler: H)
+    pub fn get<H, R, HE>(&mut self, path: &str, handler: H) -> Route<'_, E>
     where
         H: Fn(Request<Body>) -> R + Send + Sync + 'static,
-        R: Future<Output = Result<Response<Body>, E>> + Send + Sync + 'static,
-        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
     {
-        let h = move |req| Box::pin(handler(req));
-        let entry = self
-            .inner
-            .entry(Method::GET)
-            .or_insert_with(InnerRouter::new);
-        entry.add(path, Box::new(h));
+        self.add(Method::GET, path, handler)
     }
 
     /// Register a handler for POST requests
-    pub fn post<H, R>(&mut self, path: &str, handler: H)
+    pub fn post<H, R, HE>(&mut self, path: &str, handler: H) -> Route<'_, E>
     where
         H: Fn(Request<Body>) -> R + Send + Sync + 'static,
-        R: Future<Output = Result<Response<Body>, E>> + Send + Sync + 'static,
-        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
     {
-        let h = move |req| Box::pin(handler(req));
-        let entry = self
-            .inner
-            .entry(Method::POST)
-            .or_insert_with(InnerRouter::new);
-        entry.add(path, Box::new(h));
+        self.add(Method::POST, path, handler)
     }
 
     /// Register a handler for PUT requests
-    pub fn put<H, R>(&mut self, path: &str, handler: H)
+    pub fn put<H, R, HE>(&mut self, path: &str, handler: H) -> Route<'_, E>
     where
         H: Fn(Request<Body>) -> R + Send + Sync + 'static,
-        R: Future<Output = Result<Response<Body>, E>> + Send + Sync + 'static,
-        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
     {
-        let h = move |req| Box::pin(handler(req));
-        let entry = self
-            .inner
-            .entry(Method::PUT)
-            .or_insert_with(InnerRouter::new);
-        entry.add(path, Box::new(h));
+        self.add(Method::PUT, path, handler)
     }
 
     /// Register a handler for DELETE requests
-    pub fn delete<H, R>(&mut self, path: &str, handler: H)
+    pub fn delete<H, R, HE>(&mut self, path: &str, handler: H) -> Route<'_, E>
     where
         H: Fn(Request<Body>) -> R + Send + Sync + 'static,
-        R: Future<Output = Result<Response<Body>, E>> + Send + Sync + 'static,
-        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
     {
-        let h = move |req| Box::pin(handler(req));
-        let entry = self
-            .inner
-            .entry(Method::DELETE)
-            .or_insert_with(InnerRouter::new);
-        entry.add(path, Box::new(h));
+        self.add(Method::DELETE, path, handler)
     }
 
     /// Register a handler for PATCH requests
-    pub fn patch<H, R>(&mut self, path: &str, handler: H)
+    pub fn patch<H, R, HE>(&mut self, path: &str, handler: H) -> Route<'_, E>
     where
         H: Fn(Request<Body>) -> R + Send + Sync + 'static,
-        R: Future<Output = Result<Response<Body>, E>> + Send + Sync + 'static,
-        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
     {
-        let h = move |req| Box::pin(handler(req));
-        let entry = self
-            .inner
-            .entry(Method::PATCH)
-            .or_insert_with(InnerRouter::new);
-        entry.add(path, Box::new(h));
+        self.add(Method::PATCH, path, handler)
+    }
+
+    /// Register a handler for HEAD requests
+    pub fn head<H, R, HE>(&mut self, path: &str, handler: H) -> Route<'_, E>
+    where
+        H: Fn(Request<Body>) -> R + Send + Sync + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
+    {
+        self.add(Method::HEAD, path, handler)
+    }
+
+    /// Register a handler for OPTIONS requests
+    pub fn options<H, R, HE>(&mut self, path: &str, handler: H) -> Route<'_, E>
+    where
+        H: Fn(Request<Body>) -> R + Send + Sync + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
+    {
+        self.add(Method::OPTIONS, path, handler)
+    }
+
+    /// Register a handler for `method` requests which gets the state of this router as an
+    /// argument, instead of looking it up with `req.state::<State>()`. A handler taking
+    /// another type doesn't compile.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::convert::Infallible;
+    /// # use hyper::{Body, Method, Request, Response};
+    /// use keiro::{Data, Router};
+    ///
+    /// struct Config {
+    ///     greeting: String,
+    /// }
+    ///
+    /// let mut router = Router::with_data(Config {
+    ///     greeting: "Hello".to_string(),
+    /// });
+    /// router.route_with_state(Method::GET, "/", index);
+    ///
+    /// async fn index(
+    ///     _req: Request<Body>,
+    ///     config: Data<Config>,
+    /// ) -> Result<Response<Body>, Infallible> {
+    ///     Ok(Response::new(Body::from(config.greeting.clone())))
+    /// }
+    /// ```
+    pub fn route_with_state<H, R, HE>(
+        &mut self,
+        method: Method,
+        path: &str,
+        handler: H,
+    ) -> Route<'_, E>
+    where
+        H: Fn(Request<Body>, State) -> R + Send + Sync + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
+    {
+        let state = self.state.clone();
+        self.add(method, path, move |req| handler(req, state.clone()))
+    }
+
+    fn add<H, R, HE>(&mut self, method: Method, path: &str, handler: H) -> Route<'_, E>
+    where
+        H: Fn(Request<Body>) -> R + Send + Sync + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
+    {
+        let h = erase(handler);
+        let index = self.endpoints.len();
+        self.endpoints.push(Arc::new(Endpoint {
+            method: method.clone(),
+            pattern: path.to_string(),
+            name: None,
+            handler: Box::new(h),
+            options: RouteOptions::default(),
+            concurrency: None,
+            load_shed: false,
+            cache: None,
+            cache_policy: None,
+            sampler: None,
+        }));
+        self.inner.entry(method).or_default().add(path, index);
+        // Endpoints are only shared while a request is routed, which needs `&self`.
+        Route::new(Arc::get_mut(&mut self.endpoints[index]).expect("endpoint in use"))
+    }
+
+    /// Answer the liveness probe at `/healthz` and the readiness probe at `/readyz` with
+    /// `health`. See [`health`](crate::health).
+    #[cfg(feature = "json")]
+    pub fn health(&mut self, health: health::Health) {
+        let health = health.readiness(&self.accepting);
+        self.get("/healthz", |_req| async { Ok::<_, E>(health::liveness()) });
+        self.get("/readyz", move |_req| {
+            let health = health.clone();
+            async move { Ok::<_, E>(health.readiness_response().await) }
+        });
+    }
+
+    /// Register the endpoints of `well_known` under `/.well-known/`. See
+    /// [`well_known`](crate::well_known).
+    pub fn well_known(&mut self, well_known: WellKnown) {
+        if let Some(readiness) = well_known.health {
+            let accepting = self.accepting.clone();
+            self.get("/.well-known/health", move |_req| {
+                let ready = readiness
+                    .as_ref()
+                    .is_none_or(|readiness| readiness.is_ready() && accepting.is_ready());
+                let res = well_known::health(ready);
+                async { Ok::<_, E>(res) }
+            });
+        }
+        if let Some(security_txt) = well_known.security_txt {
+            self.get("/.well-known/security.txt", move |_req| {
+                let res = well_known::text(security_txt.clone());
+                async { Ok::<_, E>(res) }
+            });
+        }
+        if let Some(store) = well_known.challenges {
+            self.get(
+                "/.well-known/acme-challenge/:token",
+                move |req: Request<Body>| {
+                    let store = store.clone();
+                    async move {
+                        let token = req
+                            .extensions()
+                            .get::<Params>()
+                            .and_then(|params| params.find("token"))
+                            .unwrap_or_default();
+                        Ok::<_, E>(well_known::challenge(&*store, token).await)
+                    }
+                },
+            );
+        }
     }
 
     /// Register a handler when no routes are matched
-    pub fn not_found<H, R>(&mut self, handler: H)
+    ///
+    /// If routes with other methods match the path, the request has their
+    /// [`AllowedMethods`], so the handler can answer with `405 Method Not Allowed`.
+    pub fn not_found<H, R, HE>(&mut self, handler: H)
     where
         H: Fn(Request<Body>) -> R + Send + Sync + 'static,
-        R: Future<Output = Result<Response<Body>, E>> + Send + Sync + 'static,
-        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+        R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+        HE: Into<E> + 'static,
+    {
+        self.not_found = Some(Box::new(erase(handler)));
+    }
+
+    /// Answer requests matched by `filter` before the not found handler is called
+    pub fn probe_filter(&mut self, filter: ProbeFilter) {
+        self.probe_filter = Some(filter);
+    }
+
+    /// Set the maximum request body size in bytes. Requests declaring a larger
+    /// `Content-Length` are answered with `413 Payload Too Large` before the handler is
+    /// called, and reading a larger body fails. Routes can override it with
+    /// [`Route::max_body_size`].
+    pub fn max_body_size(&mut self, limit: usize) {
+        self.defaults.max_body_size = Some(limit);
+    }
+
+    /// Cut off handlers which don't respond within `timeout`, including the not found handler.
+    /// Routes can override it with [`Route::timeout`].
+    pub fn timeout(&mut self, timeout: Duration) {
+        self.defaults.timeout = Some(timeout);
+    }
+
+    /// Set the status of the response sent when a handler times out. Defaults to
+    /// `503 Service Unavailable`. Routes can override it with [`Route::timeout_status`].
+    pub fn timeout_status(&mut self, status: StatusCode) {
+        self.defaults.timeout_status = Some(status);
+    }
+
+    /// Add `cache_control` to responses which are not errors and don't set `Cache-Control`
+    /// themselves. Routes can override it with [`Route::cache`].
+    pub fn cache_control(&mut self, cache_control: CacheControl) {
+        self.defaults.cache_control = Some(cache_control);
+    }
+
+    /// Transform the responses of this router's routes and not found handler with `map` once
+    /// handlers returned them. See [`layout`].
+    pub fn map_response<F, Fut>(&mut self, map: F)
+    where
+        F: Fn(Response<Body>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Response<Body>> + Send + 'static,
     {
-        self.not_found = Some(Box::new(handler));
+        self.defaults.map_response = Some(MapResponse::new(map));
+    }
+
+    /// Wrap the HTML fragments returned by this router's handlers into `layout`.
+    pub fn layout(&mut self, layout: Layout) {
+        self.map_response(move |res| {
+            let layout = layout.clone();
+            async move { layout.apply(res).await }
+        });
+    }
+
+    /// Answer preflights and allow cross-origin requests as configured by `cors`. See
+    /// [`cors`](crate::cors).
+    pub fn cors(&mut self, cors: Cors) {
+        self.cors = Some(Arc::new(cors));
+    }
+
+    /// Route `POST` requests naming another method in a header or form field with that
+    /// method, as configured by `method_override`. See
+    /// [`method_override`](crate::method_override).
+    pub fn method_override(&mut self, method_override: MethodOverride) {
+        self.method_override = Some(Arc::new(method_override));
+    }
+
+    /// Add the [`ClientInfo`](forwarded::ClientInfo) of every request to its extensions,
+    /// trusting the forwarding headers of `proxies`. See [`forwarded`].
+    pub fn trusted_proxies(&mut self, proxies: TrustedProxies) {
+        self.trusted_proxies = Some(Arc::new(proxies));
+    }
+
+    /// Give every request a [`RequestId`](request_id::RequestId), taken from its headers or
+    /// generated as configured by `ids`, and set it on the response. See [`request_id`].
+    pub fn request_ids(&mut self, ids: RequestIds) {
+        self.request_ids = Some(Arc::new(ids));
+    }
+
+    /// Add a `Server-Timing` header with the time spent routing, authenticating and handling
+    /// each request, and the timings added by handlers, to responses. See [`server_timing`].
+    pub fn server_timing(&mut self) {
+        self.server_timing = true;
+    }
+
+    /// Give every request a [`SpanContext`](otel::SpanContext) continuing the trace
+    /// propagated in its headers as configured by `propagation`. See [`otel`].
+    ///
+    /// Requires the `otel` feature.
+    #[cfg(feature = "otel")]
+    pub fn propagation(&mut self, propagation: otel::Propagation) {
+        self.propagation = Some(Arc::new(propagation));
+    }
+
+    /// Write a line in the access log `log` for every response. See [`access_log`].
+    #[cfg(feature = "access-log")]
+    pub fn access_log(&mut self, log: access_log::AccessLog) {
+        self.access_log = Some(log);
+    }
+
+    /// Add the [`Locale`](negotiation::Locale) each request prefers among `locales` to its
+    /// extensions, and note that responses vary by `Accept-Language`. See
+    /// [`negotiation`].
+    pub fn locales(&mut self, locales: Locales) {
+        self.locales = Some(Arc::new(locales));
+    }
+
+    /// Call `log` with an explanation of how every request was routed. See
+    /// [`explain`](crate::explain).
+    pub fn explain(&mut self, log: impl Fn(&Explanation) + Send + Sync + 'static) {
+        self.explain = Some(Arc::new(log));
+    }
+
+    /// Call `hook` with the panics caught while handling requests, which are answered with
+    /// `500 Internal Server Error`. See [`panic`].
+    pub fn on_panic(&mut self, hook: impl Fn(&panic::Panic) + Send + Sync + 'static) {
+        self.on_panic = Some(Arc::new(hook));
+    }
+
+    /// Answer requests whose handler returned an error with the response `handler` makes of
+    /// the error, converted into an [`Error`], and the head of the request, instead of failing
+    /// the connection or answering with the status of the [`Error`].
+    ///
+    /// Only the error handler of the router served by [`RouterService`] is used, so it applies
+    /// to the handlers of nested routers as well.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use hyper::{header, Body, Request, Response};
+    /// use keiro::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/config", config);
+    /// router.error_handler(|err, parts| {
+    ///     if err.status().is_server_error() {
+    ///         eprintln!("{} {} failed: {}", parts.method, parts.uri, err);
+    ///     }
+    ///     let body = format!(r#"{{"error":{:?}}}"#, err.message());
+    ///     Response::builder()
+    ///         .status(err.status())
+    ///         .header(header::CONTENT_TYPE, "application/json")
+    ///         .body(Body::from(body))
+    ///         .unwrap()
+    /// });
+    ///
+    /// async fn config(_req: Request<Body>) -> keiro::Result<Response<Body>> {
+    ///     let config = tokio::fs::read_to_string("config.toml").await?;
+    ///     Ok(Response::new(Body::from(config)))
+    /// }
+    /// ```
+    pub fn error_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(Error, &hyper::http::request::Parts) -> Response<Body> + Send + Sync + 'static,
+    {
+        self.error_handler = Some(Arc::new(handler));
+    }
+
+    /// Pass the responses with `status` which keiro generated, rather than a handler returned,
+    /// to `handler`, with the head of the request, to decorate them, e.g. with a branded page.
+    /// These are the responses to requests no route matches, without a not found handler, and
+    /// the responses for [`Error`]s and panics of handlers.
+    ///
+    /// Only the status handlers of the router served by [`RouterService`] are used.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use hyper::{header, Body, Response, StatusCode};
+    /// use keiro::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.handle_status(StatusCode::NOT_FOUND, |mut res, parts| {
+    ///     let page = format!("<h1>Nothing at {}</h1>", parts.uri.path());
+    ///     res.headers_mut()
+    ///         .insert(header::CONTENT_TYPE, "text/html; charset=utf-8".parse().unwrap());
+    ///     *res.body_mut() = Body::from(page);
+    ///     res
+    /// });
+    /// router.handle_status(StatusCode::INTERNAL_SERVER_ERROR, |res, _parts| {
+    ///     let (parts, _) = res.into_parts();
+    ///     Response::from_parts(parts, Body::from("Something went wrong, we're on it."))
+    /// });
+    /// ```
+    pub fn handle_status<F>(&mut self, status: StatusCode, handler: F)
+    where
+        F: Fn(Response<Body>, &hyper::http::request::Parts) -> Response<Body>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.status_handlers.insert(status, Arc::new(handler));
+    }
+
+    /// Answer server errors and panics of handlers with pages showing the error, its
+    /// backtrace and the request, for development. See [`debug_errors`]. Requires the
+    /// `debug-errors` feature.
+    #[cfg(feature = "debug-errors")]
+    pub fn debug_errors(&mut self) {
+        debug_errors::install_panic_hook();
+        self.debug_errors = true;
+    }
+
+    /// Answer requests no route matches, without a not found handler, and the [`Error`]s of
+    /// handlers which are answered by [`RouterService`] with
+    /// [problem details](crate::problem) instead of plain text. Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn problem_details(&mut self) {
+        self.problem_details = true;
+    }
+
+    /// Compress responses sent through [`RouterService`] as described in
+    /// [`compression`](crate::compression). Requires the `compression` feature.
+    #[cfg(feature = "compression")]
+    pub fn compression(&mut self, compression: compression::Compression) {
+        self.compression = Some(compression);
+    }
+
+    /// Require the Basic credentials accepted by `auth` for all routes of this router which
+    /// don't set their own. See [`basic_auth`].
+    pub fn basic_auth(&mut self, auth: BasicAuth) {
+        self.defaults.guard = Some(auth.into());
+    }
+
+    /// Answer requests from the clients denied by `filter` with `403 Forbidden` for all routes
+    /// of this router which don't set their own filter. See [`ip_filter`].
+    pub fn ip_filter(&mut self, filter: IpFilter) {
+        self.defaults.ip_filter = Some(Arc::new(filter));
+    }
+
+    /// Limit the rate of requests to all routes of this router which don't set their own limit
+    /// with `limit`. The routes share the buckets of `limit`. See [`rate_limit`].
+    pub fn rate_limit(&mut self, limit: RateLimit) {
+        self.defaults.rate_limit = Some(limit);
+    }
+
+    /// Load and save the sessions of requests to all routes of this router which don't set
+    /// their own with `sessions`. See [`sessions`].
+    ///
+    /// Requires the `sessions` feature.
+    #[cfg(feature = "sessions")]
+    pub fn sessions(&mut self, sessions: sessions::Sessions) {
+        self.defaults.sessions = Some(sessions);
+    }
+
+    /// Require the authenticated principal to have `scope` for all routes of this router, in
+    /// addition to the scopes required by the routes. See [`authz`].
+    pub fn require_scope(&mut self, scope: impl Into<String>) {
+        self.defaults.required_scopes.push(scope.into());
+    }
+
+    /// Answer requests rejected by the authentication or the required scopes of all routes of
+    /// this router which don't set their own tarpit slowly with `tarpit`. See [`tarpit`].
+    pub fn tarpit(&mut self, tarpit: Tarpit) {
+        self.defaults.tarpit = Some(tarpit);
+    }
+
+    /// Require an API key accepted by `auth` for all routes of this router which don't set
+    /// their own authentication. See [`api_key`].
+    pub fn api_key(&mut self, auth: ApiKey) {
+        self.defaults.guard = Some(auth.into());
+    }
+
+    /// Require a bearer token accepted by `auth` for all routes of this router which don't set
+    /// their own authentication. See [`jwt`].
+    ///
+    /// Requires the `jwt` feature.
+    #[cfg(feature = "jwt")]
+    pub fn jwt(&mut self, auth: JwtAuth) {
+        self.defaults.guard = Some(auth.into());
+    }
+
+    /// Check and normalize request headers with `limits` before handlers are called
+    pub fn header_limits(&mut self, limits: HeaderLimits) {
+        self.header_limits = Some(limits);
+    }
+
+    /// Send the metrics recorded by handlers to `recorder`, labeled with the matched route.
+    /// See [`metrics`].
+    pub fn metrics(&mut self, recorder: impl Recorder) {
+        self.recorder = Some(Arc::new(recorder));
+    }
+
+    /// Send the count, latency and number in flight of requests to `recorder`, labeled with
+    /// their method, matched route and status class. See [`metrics`].
+    pub fn request_metrics(&mut self, recorder: impl MetricsRecorder) {
+        self.request_metrics = Some(Arc::new(recorder));
+    }
+
+    /// Run handlers, including the not found handler, inside a span carrying the request id,
+    /// route template, tenant and principal of each request, and the status, latency and
+    /// error of its response. See [`trace`].
+    #[cfg(feature = "tracing")]
+    pub fn trace_context(&mut self, context: trace::TraceContext) {
+        self.trace_context = Some(context);
+    }
+
+    /// Serve requests under `prefix` with `router`.
+    ///
+    /// The states of `router` are injected only into requests handled by its routes, in addition
+    /// to the states of this router. Parameters in `prefix` are available to the handlers of
+    /// `router`. Requests which `router` can't match are handled by this router's not found
+    /// handler.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::convert::Infallible;
+    /// # use hyper::{Body, Request, Response};
+    /// use keiro::prelude::*;
+    /// use keiro::Router;
+    ///
+    /// #[derive(Clone)]
+    /// struct Users {
+    ///     names: Vec<String>,
+    /// }
+    ///
+    /// let mut users = Router::with_state(Users {
+    ///     names: vec!["giraffate".to_string()],
+    /// });
+    /// users.get("/:id", user);
+    ///
+    /// let mut router = Router::new();
+    /// router.nest("/users", users);
+    ///
+    /// async fn user(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    ///     let users = req.state::<Users>().unwrap();
+    ///     let id: usize = req.params().unwrap().find("id").unwrap().parse().unwrap();
+    ///     Ok(Response::new(Body::from(users.names[id].clone())))
+    /// }
+    /// ```
+    pub fn nest<S>(&mut self, prefix: &str, router: Router<E, S>)
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        self.mount(prefix, Box::new(router));
+    }
+
+    /// Serve requests under `prefix` with the current router of `section`, which can be
+    /// replaced while this router is served. Otherwise like [`Router::nest`].
+    pub fn nest_swappable(&mut self, prefix: &str, section: &Swappable<E>) {
+        self.mount(prefix, Box::new(section.clone()));
+    }
+
+    fn mount(&mut self, prefix: &str, router: Box<dyn Routes<E>>) {
+        let prefix = prefix.trim_end_matches('/');
+        let mount = Arc::new(Mount {
+            prefix: prefix.to_string(),
+            router,
+        });
+        self.nested
+            .add(if prefix.is_empty() { "/" } else { prefix }, mount.clone());
+        if !prefix.is_empty() {
+            self.nested.add(&format!("{}/", prefix), mount.clone());
+        }
+        self.nested
+            .add(&format!("{}/*{}", prefix, NESTED_PATH_PARAM), mount.clone());
+        self.mounts.push(mount);
+    }
+
+    /// Build the URL of the route named `name`, filling its parameters with `params`.
+    /// Routes of nested routers are found as well.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::convert::Infallible;
+    /// # use hyper::{Body, Request, Response};
+    /// use keiro::Router;
+    ///
+    /// let mut router = Router::new();
+    /// router.get("/users/:id", user).name("user");
+    /// assert_eq!(router.url_for("user", &[("id", "42")]).unwrap(), "/users/42");
+    ///
+    /// async fn user(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    ///     Ok(Response::new(Body::empty()))
+    /// }
+    /// ```
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        let pattern = self
+            .named(name)
+            .ok_or_else(|| UrlError::UnknownRoute(name.to_string()))?;
+        url::build(&pattern, params)
+    }
+
+    /// Set the key signing URLs minted with [`Router::signed_url_for`]. Requires the
+    /// `signed-urls` feature.
+    #[cfg(feature = "signed-urls")]
+    pub fn signing_key(&mut self, key: impl Into<Vec<u8>>) {
+        self.signing_key = Some(key.into().into());
+    }
+
+    /// Set the secret which the keys of [`SignedCookies`](cookie::SignedCookies) and
+    /// [`PrivateCookies`](cookie::PrivateCookies) are derived from. See [`cookie`].
+    ///
+    /// Requires the `secure-cookies` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `secret` is shorter than 32 bytes.
+    #[cfg(feature = "secure-cookies")]
+    pub fn cookie_key(&mut self, secret: impl AsRef<[u8]>) {
+        self.manage(cookie::Key::new(secret.as_ref()));
+    }
+
+    /// Build the URL of the route named `name` like [`Router::url_for`], with a signature which
+    /// expires after `ttl`. See [`url`] and [`Route::require_signature`].
+    ///
+    /// Requires the `signed-urls` feature.
+    #[cfg(feature = "signed-urls")]
+    pub fn signed_url_for(
+        &self,
+        name: &str,
+        params: &[(&str, &str)],
+        ttl: Duration,
+    ) -> Result<String, UrlError> {
+        let key = self.signing_key.as_ref().ok_or(UrlError::NoSigningKey)?;
+        let url = self.url_for(name, params)?;
+        Ok(url::sign(&url, key, ttl))
     }
 
     pub fn serve(
@@ -306,28 +1112,338 @@ where
         mut req: Request<Body>,
     ) -> Pin<Box<dyn Future<Output = Result<Response<Body>, E>> + Send + Sync>>
     where
-        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+        E: Into<Box<dyn StdError + Send + Sync>> + 'static,
     {
-        match self.inner.get(req.method()) {
-            Some(inner_router) => match inner_router.recognize(req.uri().path()) {
-                Ok(matcher) => {
-                    let handler = matcher.handler();
-                    let params = matcher.params().clone();
-                    req.extensions_mut().insert(Params(Box::new(params)));
-                    req.extensions_mut().insert(self.state.clone());
-                    handler.call(req)
+        let locales = match &self.locales {
+            Some(locales) => locales,
+            None => return self.serve_cors(req),
+        };
+        let locale = locales.negotiate(&req);
+        req.extensions_mut().insert(locale);
+        let fut = self.serve_cors(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            res.headers_mut().append(
+                header::VARY,
+                header::HeaderValue::from_static("accept-language"),
+            );
+            Ok(res)
+        })
+    }
+
+    fn serve_cors(&self, req: Request<Body>) -> HandlerFuture<E> {
+        let cors = match &self.cors {
+            Some(cors) => cors.clone(),
+            None => return self.dispatch(req),
+        };
+        if cors::is_preflight(&req) {
+            let mut methods = Vec::new();
+            self.methods(req.uri().path(), &mut methods);
+            if !methods.is_empty() {
+                methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+                let res = cors.preflight(&req, methods);
+                let explanation = self
+                    .explain
+                    .as_ref()
+                    .map(|_| Explanation::new(req.method().clone(), req.uri().path().to_string()));
+                self.report(explanation, rejected(Check::Preflight, &res));
+                return Box::pin(async { Ok(res) });
+            }
+        }
+        let origin = req.headers().get(header::ORIGIN).cloned();
+        let fut = self.dispatch(req);
+        Box::pin(async move {
+            let mut res = fut.await?;
+            cors.apply(origin.as_ref(), &mut res);
+            Ok(res)
+        })
+    }
+
+    fn dispatch(&self, req: Request<Body>) -> HandlerFuture<E> {
+        let path = req.uri().path().to_string();
+        let explanation = self.explain.as_ref().map(|_| {
+            let mut explanation = Explanation::new(req.method().clone(), path.clone());
+            self.explain_route(req.method(), &path, "", &mut explanation.steps);
+            explanation
+        });
+        let started = Instant::now();
+        let req = match self.route(req, &path, route_recognizer::Params::new(), "") {
+            Ok(Matched {
+                mut req,
+                endpoint,
+                options,
+            }) => {
+                let timing = req.extensions().get::<ServerTiming>().cloned();
+                if let Some(timing) = &timing {
+                    timing.record("match", started.elapsed());
                 }
-                Err(_) => match &self.not_found {
-                    Some(handler) => handler.call(req),
-                    None => Box::pin(async {
-                        Ok(Response::builder().status(404).body(Body::empty()).unwrap())
-                    }),
-                },
-            },
-            None => {
-                Box::pin(async { Ok(Response::builder().status(404).body(Body::empty()).unwrap()) })
+                if let Some(res) = options
+                    .ip_filter
+                    .as_ref()
+                    .and_then(|filter| filter.check(&req))
+                {
+                    self.report(explanation, rejected(Check::IpFilter, &res));
+                    return Box::pin(async { Ok(res) });
+                }
+                if let Some(res) = self.check_limits(&mut req, &options) {
+                    self.report(explanation, rejected(Check::HeaderLimits, &res));
+                    return Box::pin(async { Ok(res) });
+                }
+                #[cfg(feature = "signed-urls")]
+                if options.require_signature == Some(true) {
+                    if let Some(res) = url::verify(&req, self.signing_key.as_deref()) {
+                        self.report(explanation, rejected(Check::Signature, &res));
+                        return Box::pin(async { Ok(res) });
+                    }
+                }
+                let route = req
+                    .extensions()
+                    .get::<MatchedPath>()
+                    .map(|matched| matched.0.clone());
+                let matched = Outcome::Matched {
+                    route: route.clone().unwrap_or_default(),
+                };
+                self.inject_metrics(&mut req, route.as_deref());
+                let guard = options.guard.clone();
+                let rate_limit = options.rate_limit.clone();
+                let required_scopes = options.required_scopes.clone();
+                let tarpit = options.tarpit.clone();
+                let dispatch = {
+                    let endpoint = endpoint.clone();
+                    let timing = timing.clone();
+                    move |req| -> HandlerFuture<E> {
+                        let call = {
+                            let endpoint = endpoint.clone();
+                            move |req| -> HandlerFuture<E> {
+                                match &endpoint.concurrency {
+                                    Some(limiter) => {
+                                        match limit_concurrency(&endpoint, limiter, req) {
+                                            Ok(fut) => fut,
+                                            Err(res) => Box::pin(async { Ok(res) }),
+                                        }
+                                    }
+                                    None => endpoint.handler.call(req),
+                                }
+                            }
+                        };
+                        #[cfg(feature = "sessions")]
+                        let fut = match &options.sessions {
+                            Some(sessions) => with_sessions(req, sessions.clone(), call),
+                            None => call(req),
+                        };
+                        #[cfg(not(feature = "sessions"))]
+                        let fut = call(req);
+                        let fut = match timing {
+                            Some(timing) => server_timing::timed(fut, timing, "handler"),
+                            None => fut,
+                        };
+                        let fut = match options.timeout {
+                            Some(timeout) => with_timeout(fut, timeout, options.timeout_status),
+                            None => fut,
+                        };
+                        let fut = match options.cache_control {
+                            Some(cache_control) => with_cache_control(fut, cache_control),
+                            None => fut,
+                        };
+                        match options.map_response {
+                            Some(map) => with_map_response(fut, map),
+                            None => fut,
+                        }
+                    }
+                };
+                let serve = {
+                    let route = route.clone();
+                    move |req| -> HandlerFuture<E> {
+                        let cached = |req| match &endpoint.cache {
+                            Some(cache) => cache.serve(req, endpoint.cache_policy, dispatch),
+                            None => dispatch(req),
+                        };
+                        match &endpoint.sampler {
+                            Some(sampler) => sampler.capture(req, route.as_deref(), cached),
+                            None => cached(req),
+                        }
+                    }
+                };
+                if guard.is_none() && rate_limit.is_none() && required_scopes.is_empty() {
+                    self.report(explanation, matched);
+                    return self.instrument(req, route.as_deref(), serve);
+                }
+                // The outcome is only known once the rate limit and the authentication are done.
+                let log = self.explain.clone();
+                return self.instrument(req, route.as_deref(), move |req| {
+                    let key = rate_limit.as_ref().and_then(|limit| limit.key_of(&req));
+                    Box::pin(SyncFuture::new(async move {
+                        let decision = match &rate_limit {
+                            Some(limit) => {
+                                match timed(&timing, "rate-limit", limit.acquire(key)).await {
+                                    Ok(decision) => decision,
+                                    Err(res) => {
+                                        report(
+                                            log.as_ref(),
+                                            explanation,
+                                            rejected(Check::RateLimit, &res),
+                                        );
+                                        return Ok(res);
+                                    }
+                                }
+                            }
+                            None => None,
+                        };
+                        let req = match guard {
+                            Some(guard) => match timed(&timing, "auth", guard.check(req)).await {
+                                Ok(req) => req,
+                                Err(mut res) => {
+                                    report(log.as_ref(), explanation, rejected(Check::Auth, &res));
+                                    if let (Some(limit), Some(decision)) = (&rate_limit, &decision)
+                                    {
+                                        limit.annotate(decision, &mut res);
+                                    }
+                                    return Ok(drip(tarpit.as_ref(), res));
+                                }
+                            },
+                            None => req,
+                        };
+                        if let Some(mut res) = authz::check(&req, &required_scopes) {
+                            report(log.as_ref(), explanation, rejected(Check::Scopes, &res));
+                            if let (Some(limit), Some(decision)) = (&rate_limit, &decision) {
+                                limit.annotate(decision, &mut res);
+                            }
+                            return Ok(drip(tarpit.as_ref(), res));
+                        }
+                        report(log.as_ref(), explanation, matched);
+                        let mut res = serve(req).await?;
+                        if let (Some(limit), Some(decision)) = (&rate_limit, &decision) {
+                            limit.annotate(decision, &mut res);
+                        }
+                        Ok(res)
+                    }))
+                });
+            }
+            Err(req) => {
+                if let Some(timing) = req.extensions().get::<ServerTiming>() {
+                    timing.record("match", started.elapsed());
+                }
+                req
             }
+        };
+
+        let mut req = req;
+        if let Some(res) = self.check_limits(&mut req, &self.defaults) {
+            self.report(explanation, rejected(Check::HeaderLimits, &res));
+            return Box::pin(async { Ok(res) });
+        }
+        self.inject_metrics(&mut req, None);
+        let allowed = self.allowed_methods(req.uri().path());
+        if let Some(allowed) = &allowed {
+            req.extensions_mut().insert(allowed.clone());
         }
+        match (self.filter_probe(&req), &self.not_found) {
+            (Some(res), _) => {
+                self.report(explanation, rejected(Check::ProbeFilter, &res));
+                Box::pin(async { Ok(res) })
+            }
+            (None, Some(handler)) => {
+                self.report(explanation, Outcome::NotFound { handler: true });
+                self.instrument(req, None, |req| {
+                    let fut = match self.defaults.timeout {
+                        Some(timeout) => {
+                            with_timeout(handler.call(req), timeout, self.defaults.timeout_status)
+                        }
+                        None => handler.call(req),
+                    };
+                    match self.defaults.map_response.clone() {
+                        Some(map) => with_map_response(fut, map),
+                        None => fut,
+                    }
+                })
+            }
+            (None, None) => {
+                self.report(explanation, Outcome::NotFound { handler: false });
+                #[cfg(feature = "json")]
+                let res = if self.problem_details {
+                    problem::Problem::new(StatusCode::NOT_FOUND).into()
+                } else {
+                    Response::builder().status(404).body(Body::empty()).unwrap()
+                };
+                #[cfg(not(feature = "json"))]
+                let res = Response::builder().status(404).body(Body::empty()).unwrap();
+                let mut res = error::generated(res);
+                if let Some(allowed) = allowed {
+                    res.extensions_mut().insert(allowed);
+                }
+                Box::pin(async { Ok(res) })
+            }
+        }
+    }
+
+    /// Get the methods of the routes matching `path`, or `None` if there are none.
+    fn allowed_methods(&self, path: &str) -> Option<AllowedMethods> {
+        let mut methods = Vec::new();
+        self.methods(path, &mut methods);
+        if methods.is_empty() {
+            return None;
+        }
+        methods.sort_by(|a, b| a.as_str().cmp(b.as_str()));
+        Some(AllowedMethods(methods))
+    }
+
+    /// Log `explanation` with the `outcome` of the request, if explaining is enabled.
+    fn report(&self, explanation: Option<Explanation>, outcome: Outcome) {
+        report(self.explain.as_ref(), explanation, outcome);
+    }
+
+    fn inject_metrics(&self, req: &mut Request<Body>, route: Option<&str>) {
+        if let Some(recorder) = &self.recorder {
+            req.extensions_mut()
+                .insert(Metrics::new(recorder.clone(), route));
+        }
+    }
+
+    /// Call `call` with `req` inside the span of the trace context, if one is installed.
+    #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+    fn instrument(
+        &self,
+        req: Request<Body>,
+        route: Option<&str>,
+        call: impl FnOnce(Request<Body>) -> HandlerFuture<E>,
+    ) -> HandlerFuture<E> {
+        #[cfg(feature = "tracing")]
+        if let Some(context) = &self.trace_context {
+            return trace::instrument(context, req, route, call);
+        }
+        call(req)
+    }
+
+    fn check_limits(
+        &self,
+        req: &mut Request<Body>,
+        options: &RouteOptions,
+    ) -> Option<Response<Body>> {
+        if let Some(res) = self
+            .header_limits
+            .as_ref()
+            .and_then(|limits| limits.check(req))
+        {
+            return Some(res);
+        }
+        if let Some(res) = options
+            .max_body_size
+            .and_then(|limit| limits::limit_body(req, limit))
+        {
+            return Some(res);
+        }
+        #[cfg(feature = "checksum")]
+        if options.require_checksum == Some(true) {
+            return checksum::verify(req);
+        }
+        None
+    }
+
+    fn filter_probe(&self, req: &Request<Body>) -> Option<Response<Body>> {
+        self.probe_filter
+            .as_ref()
+            .filter(|filter| filter.is_match(req.uri().path()))
+            .map(ProbeFilter::response)
     }
 
     pub fn into_service(self) -> MakeRouterService<RouterService<E, State>> {
@@ -335,9 +1451,477 @@ where
             inner: RouterService::new(self),
         }
     }
+
+    /// Set how long [`serve_with_shutdown`](Router::serve_with_shutdown) waits for requests
+    /// in flight once shutting down, 30 seconds by default.
+    pub fn drain_timeout(&mut self, timeout: Duration) {
+        self.drain_timeout = timeout;
+    }
+
+    /// Serve the router on `addr` until `signal` completes, then shut down gracefully.
+    ///
+    /// Once `signal` completes, the server stops accepting connections and closes idle ones,
+    /// and the readiness endpoints of [`health`](Router::health) and
+    /// [`well_known`](Router::well_known) fail, so load balancers stop sending requests. The
+    /// requests in flight are then answered, up to the [drain timeout](Router::drain_timeout),
+    /// after which the remaining connections are closed.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::convert::Infallible;
+    /// use std::net::SocketAddr;
+    /// use std::time::Duration;
+    ///
+    /// # use hyper::{Body, Request, Response};
+    /// use keiro::Router;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let mut router = Router::new();
+    ///     router.get("/", index);
+    ///     router.drain_timeout(Duration::from_secs(10));
+    ///
+    ///     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+    ///     let signal = async {
+    ///         tokio::signal::ctrl_c().await.unwrap();
+    ///     };
+    ///     router.serve_with_shutdown(addr, signal).await.unwrap();
+    /// }
+    /// # async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    /// #     Ok(Response::new(Body::from("Hello keiro!")))
+    /// # }
+    /// ```
+    pub async fn serve_with_shutdown(
+        self,
+        addr: SocketAddr,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> std::io::Result<()> {
+        server::ServerBuilder::new()
+            .bind(addr)
+            .shutdown_signal(signal)
+            .serve(self)
+            .await?
+            .wait()
+            .await
+    }
+}
+
+type HandlerFuture<E> = Pin<Box<dyn Future<Output = Result<Response<Body>, E>> + Send + Sync>>;
+
+/// Convert the errors of `handler` into the error type of the router it's registered with.
+fn erase<H, R, HE, E>(handler: H) -> impl Fn(Request<Body>) -> HandlerFuture<E> + Send + Sync
+where
+    H: Fn(Request<Body>) -> R + Send + Sync + 'static,
+    R: Future<Output = Result<Response<Body>, HE>> + Send + Sync + 'static,
+    HE: Into<E> + 'static,
+    E: 'static,
+{
+    move |req| {
+        let fut = handler(req);
+        Box::pin(async move { fut.await.map_err(Into::into) })
+    }
+}
+
+/// Record the time `fut` takes as `name` in `timing`, if there is one.
+async fn timed<F: Future>(timing: &Option<ServerTiming>, name: &str, fut: F) -> F::Output {
+    let started = Instant::now();
+    let output = fut.await;
+    if let Some(timing) = timing {
+        timing.record(name, started.elapsed());
+    }
+    output
+}
+
+/// Answer with `status`, or `503 Service Unavailable`, if `fut` doesn't finish within `timeout`.
+fn with_timeout<E: 'static>(
+    fut: HandlerFuture<E>,
+    timeout: Duration,
+    status: Option<StatusCode>,
+) -> HandlerFuture<E> {
+    Box::pin(async move {
+        match tokio::time::timeout(timeout, fut).await {
+            Ok(res) => res,
+            Err(_) => Ok(Response::builder()
+                .status(status.unwrap_or(StatusCode::SERVICE_UNAVAILABLE))
+                .body(Body::empty())
+                .unwrap()),
+        }
+    })
+}
+
+#[allow(clippy::type_complexity)]
+fn report(
+    log: Option<&Arc<dyn Fn(&Explanation) + Send + Sync>>,
+    explanation: Option<Explanation>,
+    outcome: Outcome,
+) {
+    if let (Some(log), Some(mut explanation)) = (log, explanation) {
+        explanation.outcome = outcome;
+        log(&explanation);
+    }
+}
+
+fn rejected(check: Check, res: &Response<Body>) -> Outcome {
+    Outcome::Rejected {
+        check,
+        status: res.status(),
+    }
+}
+
+/// Add `cache_control` to the response of `fut` unless it's an error or sets its own.
+fn with_cache_control<E: 'static>(
+    fut: HandlerFuture<E>,
+    cache_control: CacheControl,
+) -> HandlerFuture<E> {
+    Box::pin(async move {
+        let mut res = fut.await?;
+        cache_control::apply_default(&cache_control, &mut res);
+        Ok(res)
+    })
+}
+
+/// Load the session of `req` before calling `call`, and save it afterwards.
+#[cfg(feature = "sessions")]
+fn with_sessions<E: 'static>(
+    mut req: Request<Body>,
+    sessions: sessions::Sessions,
+    call: impl FnOnce(Request<Body>) -> HandlerFuture<E> + Send + 'static,
+) -> HandlerFuture<E> {
+    Box::pin(SyncFuture::new(async move {
+        if let Err(res) = sessions.load(&mut req).await {
+            return Ok(res);
+        }
+        let session = req.extensions().get::<sessions::Session>().cloned();
+        let mut res = call(req).await?;
+        if let Some(session) = session {
+            sessions.save(&session, &mut res).await;
+        }
+        Ok(res)
+    }))
+}
+
+/// Transform the response of `fut` with `map`.
+/// Drip the rejection `res` with `tarpit`, if the route has one.
+fn drip(tarpit: Option<&Tarpit>, res: Response<Body>) -> Response<Body> {
+    match tarpit {
+        Some(tarpit) => tarpit.drip(res),
+        None => res,
+    }
+}
+
+fn with_map_response<E: 'static>(fut: HandlerFuture<E>, map: MapResponse) -> HandlerFuture<E> {
+    Box::pin(SyncFuture::new(async move {
+        let res = fut.await?;
+        Ok(map.call(res).await)
+    }))
+}
+
+/// Call the handler of `endpoint` once a permit of `limiter` is acquired, or return
+/// `503 Service Unavailable` if no permit is available and excess requests are shed.
+#[allow(clippy::result_large_err)]
+fn limit_concurrency<E>(
+    endpoint: &Arc<Endpoint<E>>,
+    limiter: &Limiter,
+    req: Request<Body>,
+) -> Result<HandlerFuture<E>, Response<Body>>
+where
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+{
+    let unavailable = || {
+        Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Body::empty())
+            .unwrap()
+    };
+    match limiter {
+        Limiter::Fixed(semaphore) => {
+            let semaphore = semaphore.clone();
+            if endpoint.load_shed {
+                let permit = semaphore.try_acquire_owned().map_err(|_| unavailable())?;
+                let fut = endpoint.handler.call(req);
+                return Ok(Box::pin(async move {
+                    let res = fut.await;
+                    drop(permit);
+                    res
+                }));
+            }
+
+            // Called once a permit is acquired, as handlers may do work before returning their
+            // future.
+            let endpoint = endpoint.clone();
+            Ok(Box::pin(async move {
+                let _permit = semaphore.acquire_owned().await;
+                endpoint.handler.call(req).await
+            }))
+        }
+        Limiter::Adaptive(limiter) => {
+            let limiter = limiter.clone();
+            if endpoint.load_shed {
+                let permit = limiter.try_acquire().ok_or_else(unavailable)?;
+                let fut = endpoint.handler.call(req);
+                return Ok(Box::pin(async move {
+                    let res = fut.await;
+                    permit.finish(&res);
+                    res
+                }));
+            }
+
+            let endpoint = endpoint.clone();
+            Ok(Box::pin(async move {
+                let permit = limiter.acquire().await;
+                let res = endpoint.handler.call(req).await;
+                permit.finish(&res);
+                res
+            }))
+        }
+    }
+}
+
+const NESTED_PATH_PARAM: &str = "keiro_nested_path";
+
+struct Mount<E> {
+    prefix: String,
+    router: Box<dyn Routes<E>>,
+}
+
+/// The route pattern matched by a request, including the prefixes of nested routers.
+#[derive(Debug, Clone)]
+pub(crate) struct MatchedPath(pub(crate) String);
+
+/// A slot for the [`MatchedPath`] of a request, shared with the service so it can be read
+/// once the request is gone.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RouteSlot(Arc<OnceLock<String>>);
+
+impl RouteSlot {
+    /// Get the slot of `req`, adding one if it has none.
+    pub(crate) fn of(req: &mut Request<Body>) -> Self {
+        if let Some(slot) = req.extensions().get::<RouteSlot>() {
+            return slot.clone();
+        }
+        let slot = RouteSlot::default();
+        req.extensions_mut().insert(slot.clone());
+        slot
+    }
+
+    pub(crate) fn get(&self) -> Option<&str> {
+        self.0.get().map(String::as_str)
+    }
+}
+
+trait Routes<E>: Send + Sync + 'static {
+    /// Match `path` and prepare the request for the matched endpoint, or give the request back
+    /// if no routes are matched.
+    #[allow(clippy::result_large_err)]
+    fn route(
+        &self,
+        req: Request<Body>,
+        path: &str,
+        params: route_recognizer::Params,
+        prefix: &str,
+    ) -> Result<Matched<E>, Request<Body>>;
+
+    /// Get the pattern of the route named `name`, including the prefixes of nested routers.
+    fn named(&self, name: &str) -> Option<String>;
+
+    /// Record the decisions [`Routes::route`] makes for `path` in `steps`, returning whether a
+    /// route matched.
+    fn explain_route(
+        &self,
+        method: &Method,
+        path: &str,
+        prefix: &str,
+        steps: &mut Vec<Step>,
+    ) -> bool;
+
+    /// Add the methods with a route matching `path` to `methods`.
+    fn methods(&self, path: &str, methods: &mut Vec<Method>);
+}
+
+impl<E, State> Routes<E> for Router<E, State>
+where
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    State: Clone + Send + Sync + 'static,
+{
+    #[allow(clippy::result_large_err)]
+    fn route(
+        &self,
+        mut req: Request<Body>,
+        path: &str,
+        mut params: route_recognizer::Params,
+        prefix: &str,
+    ) -> Result<Matched<E>, Request<Body>> {
+        if let Some(inner_router) = self.inner.get(req.method()) {
+            if let Ok(matcher) = inner_router.recognize(path) {
+                let endpoint = self.endpoints[**matcher.handler()].clone();
+                for (key, value) in matcher.params() {
+                    params.insert(key.to_string(), value.to_string());
+                }
+                let pattern = if prefix.is_empty() || endpoint.pattern != "/" {
+                    format!("{}{}", prefix, endpoint.pattern)
+                } else {
+                    prefix.to_string()
+                };
+                req.extensions_mut().insert(Params(Box::new(params)));
+                if let Some(slot) = req.extensions().get::<RouteSlot>() {
+                    let _ = slot.0.set(pattern.clone());
+                }
+                req.extensions_mut().insert(MatchedPath(pattern));
+                req.extensions_mut().insert(query::Cached::default());
+                req.extensions_mut().insert(self.state.clone());
+                self.states.inject(req.extensions_mut());
+                let options = endpoint.options.clone().or(&self.defaults);
+                return Ok(Matched {
+                    req,
+                    endpoint,
+                    options,
+                });
+            }
+        }
+
+        match self.nested.recognize(path) {
+            Ok(matcher) => {
+                let mount = *matcher.handler();
+                let mut rest = "/".to_string();
+                for (key, value) in matcher.params() {
+                    if key == NESTED_PATH_PARAM {
+                        rest.push_str(value);
+                    } else {
+                        params.insert(key.to_string(), value.to_string());
+                    }
+                }
+                req.extensions_mut().insert(self.state.clone());
+                self.states.inject(req.extensions_mut());
+                let prefix = format!("{}{}", prefix, mount.prefix);
+                mount
+                    .router
+                    .route(req, &rest, params, &prefix)
+                    .map(|matched| Matched {
+                        options: matched.options.or(&self.defaults),
+                        ..matched
+                    })
+            }
+            Err(_) => Err(req),
+        }
+    }
+
+    fn explain_route(
+        &self,
+        method: &Method,
+        path: &str,
+        prefix: &str,
+        steps: &mut Vec<Step>,
+    ) -> bool {
+        let pattern = |endpoint: &Endpoint<E>| {
+            if prefix.is_empty() || endpoint.pattern != "/" {
+                format!("{}{}", prefix, endpoint.pattern)
+            } else {
+                prefix.to_string()
+            }
+        };
+        let matches = |endpoint: &Endpoint<E>| {
+            let mut router = InnerRouter::new();
+            router.add(&endpoint.pattern, ());
+            router.recognize(path).is_ok()
+        };
+
+        let (candidates, others): (Vec<_>, Vec<_>) = self
+            .endpoints
+            .iter()
+            .partition(|endpoint| endpoint.method == *method);
+        steps.push(Step::MethodTable {
+            prefix: prefix.to_string(),
+            method: method.clone(),
+            routes: candidates.len(),
+        });
+        for endpoint in candidates {
+            steps.push(Step::Candidate {
+                pattern: pattern(endpoint),
+                matched: matches(endpoint),
+            });
+        }
+        let matched = self
+            .inner
+            .get(method)
+            .is_some_and(|inner_router| inner_router.recognize(path).is_ok());
+        if matched {
+            return true;
+        }
+
+        if let Ok(matcher) = self.nested.recognize(path) {
+            let mount = *matcher.handler();
+            let mut rest = "/".to_string();
+            for (key, value) in matcher.params() {
+                if key == NESTED_PATH_PARAM {
+                    rest.push_str(value);
+                }
+            }
+            let prefix = format!("{}{}", prefix, mount.prefix);
+            steps.push(Step::Mount {
+                prefix: prefix.clone(),
+                matched: true,
+            });
+            if mount.router.explain_route(method, &rest, &prefix, steps) {
+                return true;
+            }
+        } else {
+            for mount in &self.mounts {
+                steps.push(Step::Mount {
+                    prefix: format!("{}{}", prefix, mount.prefix),
+                    matched: false,
+                });
+            }
+        }
+
+        for endpoint in others {
+            if matches(endpoint) {
+                steps.push(Step::OtherMethod {
+                    method: endpoint.method.clone(),
+                    pattern: pattern(endpoint),
+                });
+            }
+        }
+        false
+    }
+
+    fn methods(&self, path: &str, methods: &mut Vec<Method>) {
+        for (method, inner_router) in &self.inner {
+            if !methods.contains(method) && inner_router.recognize(path).is_ok() {
+                methods.push(method.clone());
+            }
+        }
+        if let Ok(matcher) = self.nested.recognize(path) {
+            let mut rest = "/".to_string();
+            for (key, value) in matcher.params() {
+                if key == NESTED_PATH_PARAM {
+                    rest.push_str(value);
+                }
+            }
+            matcher.handler().router.methods(&rest, methods);
+        }
+    }
+
+    fn named(&self, name: &str) -> Option<String> {
+        if let Some(endpoint) = self
+            .endpoints
+            .iter()
+            .find(|endpoint| endpoint.name.as_deref() == Some(name))
+        {
+            return Some(endpoint.pattern.clone());
+        }
+        self.mounts.iter().find_map(|mount| {
+            let pattern = mount.router.named(name)?;
+            Some(if mount.prefix.is_empty() || pattern != "/" {
+                format!("{}{}", mount.prefix, pattern)
+            } else {
+                mount.prefix.clone()
+            })
+        })
+    }
 }
 
-pub trait Handler<E: Into<Box<dyn Error + Send + Sync>>>: Send + Sync + 'static {
+pub trait Handler<E: Into<Box<dyn StdError + Send + Sync>>>: Send + Sync + 'static {
     fn call(
         &self,
         req: Request<Body>,
@@ -348,7 +1932,7 @@ impl<F: Send + Sync + 'static, R, E> Handler<E> for F
 where
     F: Fn(Request<Body>) -> R + Send + Sync,
     R: Future<Output = Result<Response<Body>, E>> + Send + Sync + 'static,
-    E: Into<Box<dyn Error + Send + Sync>>,
+    E: Into<Box<dyn StdError + Send + Sync>>,
 {
     fn call(
         &self,
@@ -364,16 +1948,22 @@ impl<E> fmt::Debug for dyn Handler<E> {
     }
 }
 
-#[derive(Clone)]
 pub struct RouterService<E, State>(Arc<Router<E, State>>);
 
+// Not derived, as that would require the error and state types to be `Clone`.
+impl<E, State> Clone for RouterService<E, State> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
 impl<E, State> Service<Request<Body>> for RouterService<E, State>
 where
-    E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
     State: Clone + Send + Sync + 'static,
 {
     type Response = Response<Body>;
-    type Error = Box<dyn Error + Send + Sync>;
+    type Error = Box<dyn StdError + Send + Sync>;
     #[allow(clippy::type_complexity)]
     type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + Sync>>;
 
@@ -381,17 +1971,123 @@ where
         Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, req: Request<Body>) -> Self::Future {
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
         let router = self.0.clone();
-        let fut = router.serve(req);
-        let fut = async { fut.await.map_err(Into::into) };
+        let started = Instant::now();
+        let timing = router.server_timing.then(ServerTiming::default);
+        if let Some(timing) = &timing {
+            req.extensions_mut().insert(timing.clone());
+        }
+        let request_id = router.request_ids.as_ref().map(|ids| ids.apply(&mut req));
+        #[cfg(feature = "otel")]
+        if let Some(propagation) = &router.propagation {
+            propagation.apply(&mut req);
+        }
+        #[cfg(feature = "access-log")]
+        let entry = router.access_log.as_ref().map(|log| log.start(&mut req));
+        let request_metrics = router
+            .request_metrics
+            .clone()
+            .map(|recorder| RequestMetrics::start(recorder, &mut req));
+        #[cfg(feature = "compression")]
+        let encoding = router
+            .compression
+            .as_ref()
+            .and_then(|compression| compression.negotiate(&req));
+        let method = req.method().clone();
+        let path = req.uri().path().to_string();
+        #[cfg(feature = "debug-errors")]
+        let debug_errors = router.debug_errors;
+        #[cfg(not(feature = "debug-errors"))]
+        let debug_errors = false;
+        let head =
+            (router.error_handler.is_some() || !router.status_handlers.is_empty() || debug_errors)
+                .then(|| error::head(&req));
+        let serve = {
+            let router = router.clone();
+            move || -> HandlerFuture<E> {
+                if let Some(proxies) = &router.trusted_proxies {
+                    proxies.apply(&mut req);
+                }
+                match router.method_override.clone() {
+                    Some(method_override) if method_override.apply_header(&mut req) => {
+                        Box::pin(SyncFuture::new(async move {
+                            let req = method_override.apply_form(req).await;
+                            router.serve(req).await
+                        }))
+                    }
+                    _ => router.serve(req),
+                }
+            }
+        };
+        let fut = SyncFuture::new(panic::catch(method, path, router.on_panic.clone(), serve));
+        let fut = async move {
+            let res = match (fut.await, &router.error_handler, &head) {
+                (Ok(res), _, _) => res,
+                (Err(err), Some(handler), Some(head)) => {
+                    handler(error::downcast(err).unwrap_or_else(Error::new), head)
+                }
+                #[cfg(feature = "debug-errors")]
+                (Err(err), _, Some(head)) if router.debug_errors => {
+                    match error::downcast(err).unwrap_or_else(Error::new) {
+                        err if err.status().is_server_error() => {
+                            debug_errors::error_page(&err, head)
+                        }
+                        err => error::generated(err.into()),
+                    }
+                }
+                (Err(err), _, _) => match error::downcast(err) {
+                    #[cfg(feature = "json")]
+                    Ok(err) if router.problem_details => {
+                        error::generated(problem::Problem::from(&err).into())
+                    }
+                    Ok(err) => error::generated(err.into()),
+                    Err(err) => return Err(err.into()),
+                },
+            };
+            #[cfg(feature = "debug-errors")]
+            let res = match (&head, res.extensions().get::<debug_errors::CaughtPanic>()) {
+                (Some(head), Some(caught)) if router.debug_errors => {
+                    debug_errors::panic_page(caught, head)
+                }
+                _ => res,
+            };
+            let res = match (router.status_handlers.get(&res.status()), &head) {
+                (Some(handler), Some(head)) if res.extensions().get::<Generated>().is_some() => {
+                    handler(res, head)
+                }
+                _ => res,
+            };
+            #[cfg(feature = "compression")]
+            let mut res = match &router.compression {
+                Some(compression) => compression.compress(res, encoding),
+                None => res,
+            };
+            #[cfg(not(feature = "compression"))]
+            let mut res = res;
+            if let (Some(ids), Some(id)) = (&router.request_ids, &request_id) {
+                ids.set_header(&mut res, id);
+            }
+            if let Some(timing) = timing {
+                timing.record("total", started.elapsed());
+                timing.apply(&mut res);
+            }
+            if let Some(request_metrics) = request_metrics {
+                request_metrics.finish(res.status());
+            }
+            #[cfg(feature = "access-log")]
+            if let Some(entry) = entry {
+                res = entry.finish(res);
+            }
+            Ok(res)
+        };
         Box::pin(fut)
     }
 }
 
 impl<E, State> RouterService<E, State>
 where
-    E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
     State: Clone + Send + Sync + 'static,
 {
     pub fn new(router: Router<E, State>) -> Self {
@@ -399,27 +2095,50 @@ where
     }
 }
 
+/// Makes the service of each connection, adding its [`ConnectInfo`](connect::ConnectInfo) to
+/// the requests if the connection knows its remote address. See [`connect`].
 pub struct MakeRouterService<Svc> {
     pub inner: Svc,
 }
 
-impl<T, Svc> Service<T> for MakeRouterService<Svc>
+impl<'a, T, Svc> Service<&'a T> for MakeRouterService<Svc>
 where
+    T: 'static,
     Svc: Service<Request<Body>> + Clone,
     Svc::Response: 'static,
-    Svc::Error: Into<Box<dyn Error + Send + Sync>>,
+    Svc::Error: Into<Box<dyn StdError + Send + Sync>>,
     Svc::Future: 'static,
 {
-    type Response = Svc;
-    type Error = Box<dyn Error + Send + Sync>;
+    type Response = ConnectedService<Svc>;
+    type Error = Box<dyn StdError + Send + Sync>;
     type Future = futures_util::future::Ready<Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         Poll::Ready(Ok(()))
     }
 
-    fn call(&mut self, _req: T) -> Self::Future {
-        futures_util::future::ok(self.inner.clone())
+    fn call(&mut self, conn: &'a T) -> Self::Future {
+        futures_util::future::ok(ConnectedService::new(
+            self.inner.clone(),
+            connect::remote_addr(conn),
+        ))
+    }
+}
+
+/// The methods of the routes matching the path of a request which no route matched with its
+/// method, e.g. to answer with `405 Method Not Allowed` and an `Allow` header.
+///
+/// It's added to the extensions of the request passed to the
+/// [not found handler](Router::not_found), and of the response passed to the
+/// [status handler](Router::handle_status) of `404 Not Found`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllowedMethods(pub Vec<Method>);
+
+impl AllowedMethods {
+    /// Get the value of an `Allow` header listing the methods.
+    pub fn header_value(&self) -> header::HeaderValue {
+        let methods = self.0.iter().map(Method::as_str).collect::<Vec<_>>();
+        header::HeaderValue::from_str(&methods.join(", ")).expect("methods are valid headers")
     }
 }
 
@@ -0,0 +1,189 @@
+//! Limits applied to requests before handlers are called.
+//!
+//! Request body sizes are limited with [`Router::max_body_size`](crate::Router::max_body_size)
+//! and [`Route::max_body_size`](crate::Route::max_body_size).
+//!
+//! Hyper rejects requests whose headers exceed its buffer, but it doesn't know which route is
+//! requested. [`HeaderLimits`] checks header count and size in the router, answers
+//! `431 Request Header Fields Too Large`, and counts rejections per route pattern.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::limits::HeaderLimits;
+//! use keiro::Router;
+//!
+//! let limits = HeaderLimits::new()
+//!     .max_count(64)
+//!     .max_size(8 * 1024)
+//!     .normalize(true);
+//!
+//! let mut router = Router::new();
+//! router.get("/", index);
+//! router.header_limits(limits.clone());
+//!
+//! // Later, e.g. in a metrics handler.
+//! for (route, count) in limits.rejections() {
+//!     println!("{:?}: {}", route, count);
+//! }
+//!
+//! async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("Hello keiro!")))
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use futures_util::StreamExt;
+use hyper::header::{HeaderValue, CONTENT_LENGTH};
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::util;
+use crate::MatchedPath;
+
+/// Limits on the number and size of request headers.
+#[derive(Debug, Clone, Default)]
+pub struct HeaderLimits {
+    max_count: Option<usize>,
+    max_size: Option<usize>,
+    normalize: bool,
+    rejections: Arc<Mutex<HashMap<Option<String>, u64>>>,
+}
+
+impl HeaderLimits {
+    /// Create limits which accept any headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of headers.
+    pub fn max_count(mut self, max_count: usize) -> Self {
+        self.max_count = Some(max_count);
+        self
+    }
+
+    /// Set the maximum total size in bytes of header names and values.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Trim surrounding whitespace from header values and strip bytes outside of visible
+    /// ASCII, such as obsolete `obs-text` bytes.
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Return the number of rejected requests for each matched route pattern.
+    /// Requests which didn't match any routes are counted under `None`.
+    pub fn rejections(&self) -> HashMap<Option<String>, u64> {
+        self.rejections.lock().unwrap().clone()
+    }
+
+    /// Return the rejection response if `req` exceeds the limits, or normalize its headers.
+    pub(crate) fn check(&self, req: &mut Request<Body>) -> Option<Response<Body>> {
+        let headers = req.headers();
+        let too_many = self.max_count.is_some_and(|max| headers.len() > max);
+        let too_large = self.max_size.is_some_and(|max| {
+            let size: usize = headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len())
+                .sum();
+            size > max
+        });
+
+        if too_many || too_large {
+            let route = req
+                .extensions()
+                .get::<MatchedPath>()
+                .map(|matched| matched.0.clone());
+            *self.rejections.lock().unwrap().entry(route).or_insert(0) += 1;
+            return Some(
+                Response::builder()
+                    .status(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+                    .body(Body::empty())
+                    .unwrap(),
+            );
+        }
+
+        if self.normalize {
+            for value in req.headers_mut().values_mut() {
+                if let Some(normalized) = normalize(value) {
+                    *value = normalized;
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Return the normalized value, or `None` if `value` is already normalized.
+fn normalize(value: &HeaderValue) -> Option<HeaderValue> {
+    let bytes = value.as_bytes();
+    let is_visible = |b: &u8| *b == b'\t' || (0x20..0x7f).contains(b);
+    let is_trimmed = bytes.first().is_none_or(|b| !b.is_ascii_whitespace())
+        && bytes.last().is_none_or(|b| !b.is_ascii_whitespace());
+    if is_trimmed && bytes.iter().all(is_visible) {
+        return None;
+    }
+
+    let stripped: Vec<u8> = bytes.iter().copied().filter(is_visible).collect();
+    let start = stripped
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(stripped.len());
+    let end = stripped
+        .iter()
+        .rposition(|b| !b.is_ascii_whitespace())
+        .map_or(start, |i| i + 1);
+    HeaderValue::from_bytes(&stripped[start..end]).ok()
+}
+
+/// The error of a body stream which exceeded the maximum body size.
+#[derive(Debug)]
+pub(crate) struct LengthLimitExceeded {
+    pub(crate) limit: usize,
+}
+
+impl fmt::Display for LengthLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "body exceeds the limit of {} bytes", self.limit)
+    }
+}
+
+impl Error for LengthLimitExceeded {}
+
+/// Return `413 Payload Too Large` if `req` declares a body larger than `limit`, or make
+/// reading its body fail once `limit` is exceeded.
+pub(crate) fn limit_body(req: &mut Request<Body>, limit: usize) -> Option<Response<Body>> {
+    let content_length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if content_length.is_some_and(|length| length > limit as u64) {
+        return Some(
+            Response::builder()
+                .status(StatusCode::PAYLOAD_TOO_LARGE)
+                .body(Body::empty())
+                .unwrap(),
+        );
+    }
+
+    let mut read = 0;
+    let body = std::mem::take(req.body_mut()).map(move |chunk| {
+        let chunk = chunk.map_err(util::into_cause)?;
+        read += chunk.len();
+        if read > limit {
+            return Err(Box::new(LengthLimitExceeded { limit }) as Box<dyn Error + Send + Sync>);
+        }
+        Ok(chunk)
+    });
+    *req.body_mut() = Body::wrap_stream(body);
+    None
+}
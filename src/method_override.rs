@@ -0,0 +1,166 @@
+//! Overriding the method of `POST` requests.
+//!
+//! HTML forms can only send `GET` and `POST` requests. With a [`MethodOverride`] set with
+//! [`Router::method_override`](crate::Router::method_override), a `POST` request naming
+//! another method in the `X-HTTP-Method-Override` header, or in the `_method` field of a
+//! URL-encoded form, is routed as a request with that method, so forms can drive `PUT`,
+//! `PATCH` and `DELETE` routes. Only the allowed methods can be chosen, `PUT`, `PATCH` and
+//! `DELETE` by default. Handlers can get the method the request was sent with from its
+//! [`OriginalMethod`].
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::method_override::MethodOverride;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.method_override(MethodOverride::new());
+//! // `<form method="post" action="/posts/1"><input type="hidden" name="_method" value="DELETE">`
+//! router.delete("/posts/:id", delete_post);
+//!
+//! async fn delete_post(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//! ```
+
+use hyper::header::{HeaderName, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Method, Request};
+
+use crate::body;
+use crate::query::QueryPairs;
+
+/// The method a request was sent with, added to requests whose method was overridden.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalMethod(pub Method);
+
+/// Routes `POST` requests with the method they name instead.
+#[derive(Debug, Clone)]
+pub struct MethodOverride {
+    methods: Vec<Method>,
+    header: Option<HeaderName>,
+    form_field: Option<String>,
+    form_limit: usize,
+}
+
+impl Default for MethodOverride {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MethodOverride {
+    /// Allow overriding the method with `PUT`, `PATCH` and `DELETE`, in the
+    /// `X-HTTP-Method-Override` header or the `_method` form field.
+    pub fn new() -> Self {
+        Self {
+            methods: vec![Method::PUT, Method::PATCH, Method::DELETE],
+            header: Some(HeaderName::from_static("x-http-method-override")),
+            form_field: Some("_method".to_string()),
+            form_limit: 64 * 1024,
+        }
+    }
+
+    /// Only allow overriding the method with `methods`.
+    pub fn methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Take the method from the header `name` instead of `X-HTTP-Method-Override`.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.header = Some(name);
+        self
+    }
+
+    /// Ignore method override headers.
+    pub fn without_header(mut self) -> Self {
+        self.header = None;
+        self
+    }
+
+    /// Take the method from the form field `name` instead of `_method`.
+    pub fn form_field(mut self, name: impl Into<String>) -> Self {
+        self.form_field = Some(name.into());
+        self
+    }
+
+    /// Ignore form fields, so bodies are never read before routing.
+    pub fn without_form_field(mut self) -> Self {
+        self.form_field = None;
+        self
+    }
+
+    /// Only look for the form field in forms with a `Content-Length` of at most `limit` bytes,
+    /// 64 KiB by default, as the form has to be buffered before routing.
+    pub fn form_limit(mut self, limit: usize) -> Self {
+        self.form_limit = limit;
+        self
+    }
+
+    /// Override the method of `req` from its header, returning whether the form still has to
+    /// be read with [`MethodOverride::apply_form`].
+    pub(crate) fn apply_header(&self, req: &mut Request<Body>) -> bool {
+        if req.method() != Method::POST {
+            return false;
+        }
+        let method = self
+            .header
+            .as_ref()
+            .and_then(|name| req.headers().get(name))
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        if let Some(method) = method {
+            self.set(req, &method);
+            return false;
+        }
+        self.form_field.is_some() && self.is_small_form(req)
+    }
+
+    /// Override the method of `req` from its form, keeping the body for the handler.
+    pub(crate) async fn apply_form(&self, mut req: Request<Body>) -> Request<Body> {
+        let form = match body::read(&mut req, self.form_limit).await {
+            Ok(form) => form,
+            Err(_) => return req,
+        };
+        let method = self.form_field.as_deref().and_then(|field| {
+            let form = std::str::from_utf8(&form).ok()?;
+            QueryPairs::parse(form).get(field).map(str::to_string)
+        });
+        *req.body_mut() = Body::from(form);
+        if let Some(method) = method {
+            self.set(&mut req, &method);
+        }
+        req
+    }
+
+    fn set(&self, req: &mut Request<Body>, method: &str) {
+        let method = match Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes()) {
+            Ok(method) if self.methods.contains(&method) => method,
+            _ => return,
+        };
+        let original = std::mem::replace(req.method_mut(), method);
+        req.extensions_mut().insert(OriginalMethod(original));
+    }
+
+    fn is_small_form(&self, req: &Request<Body>) -> bool {
+        let form = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .is_some_and(|value| {
+                value
+                    .trim()
+                    .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+            });
+        let small = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<usize>().ok())
+            .is_some_and(|length| length <= self.form_limit);
+        form && small
+    }
+}
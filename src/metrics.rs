@@ -0,0 +1,219 @@
+//! Metrics recorded by handlers.
+//!
+//! A [`Recorder`] installed with [`Router::metrics`](crate::Router::metrics) receives the
+//! counters and timings handlers record through
+//! [`RequestExt::metrics`](crate::ext::RequestExt::metrics). Every metric is labeled with the
+//! route template matched by the request, so handlers don't need to carry metric handles or
+//! labels around.
+//!
+//! Without a recorder, recording metrics does nothing.
+//!
+//! Independently, a [`MetricsRecorder`] installed with
+//! [`Router::request_metrics`](crate::Router::request_metrics) receives metrics of every
+//! request recorded by the router itself:
+//!
+//! - `http_requests_in_flight`, a gauge labeled with the `method`.
+//! - `http_requests_total`, a counter labeled with the `method`, the matched `route` and the
+//!   `status` class, such as `2xx`.
+//! - `http_request_duration_seconds`, a histogram of the time until the response head,
+//!   labeled like the counter.
+//!
+//! Requests which didn't match a route have the route `unmatched`, so paths probed by
+//! scanners don't add labels.
+//!
+//! [`Prometheus`] collects both kinds of metrics, to be scraped from the handler returned by
+//! [`prometheus`]:
+//!
+//! ```rust,no_run
+//! use keiro::metrics::{self, Prometheus};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.request_metrics(Prometheus::global());
+//! router.metrics(Prometheus::global());
+//! router.get("/metrics", metrics::prometheus());
+//! ```
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::metrics::{Labels, Recorder};
+//! use keiro::prelude::*;
+//! use keiro::Router;
+//!
+//! struct Stdout;
+//!
+//! impl Recorder for Stdout {
+//!     fn counter(&self, name: &str, labels: &Labels, value: u64) {
+//!         println!("{} {:?} +{}", name, labels, value);
+//!     }
+//!
+//!     fn timing(&self, name: &str, labels: &Labels, value: Duration) {
+//!         println!("{} {:?} {:?}", name, labels, value);
+//!     }
+//! }
+//!
+//! let mut router = Router::new();
+//! router.metrics(Stdout);
+//! router.post("/exports", export);
+//!
+//! async fn export(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let metrics = req.metrics();
+//!     let timer = metrics.timer("export_duration");
+//!     let items = 42;
+//!     metrics.incr("items_exported", items);
+//!     drop(timer);
+//!     Ok(Response::new(Body::from(format!("exported {} items", items))))
+//! }
+//! ```
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use hyper::{Body, Method, Request, StatusCode};
+
+use crate::RouteSlot;
+
+mod prometheus;
+
+pub use self::prometheus::{prometheus, Prometheus};
+
+/// The labels of a metric as name and value pairs, e.g. `[("route", "/exports")]`.
+pub type Labels<'a> = [(&'a str, &'a str)];
+
+/// A destination for the metrics recorded by handlers, e.g. a metrics registry.
+pub trait Recorder: Send + Sync + 'static {
+    /// Increment the counter `name` by `value`.
+    fn counter(&self, name: &str, labels: &Labels, value: u64);
+
+    /// Record `value` in the timing `name`.
+    fn timing(&self, name: &str, labels: &Labels, value: Duration);
+}
+
+/// A destination for the request metrics recorded by the router, e.g. a metrics registry.
+pub trait MetricsRecorder: Send + Sync + 'static {
+    /// Increment the counter `name` by `value`.
+    fn counter(&self, name: &str, labels: &Labels, value: u64);
+
+    /// Add `delta` to the gauge `name`.
+    fn gauge(&self, name: &str, labels: &Labels, delta: i64);
+
+    /// Record `value` in the histogram `name`.
+    fn histogram(&self, name: &str, labels: &Labels, value: f64);
+}
+
+/// The request metrics of a request, recorded once its response head is ready.
+pub(crate) struct RequestMetrics {
+    recorder: Arc<dyn MetricsRecorder>,
+    method: Method,
+    route: RouteSlot,
+    start: Instant,
+}
+
+impl RequestMetrics {
+    /// Start recording the metrics of `req`.
+    pub(crate) fn start(recorder: Arc<dyn MetricsRecorder>, req: &mut Request<Body>) -> Self {
+        let method = req.method().clone();
+        recorder.gauge(IN_FLIGHT, &[("method", method.as_str())], 1);
+        Self {
+            recorder,
+            method,
+            route: RouteSlot::of(req),
+            start: Instant::now(),
+        }
+    }
+
+    pub(crate) fn finish(self, status: StatusCode) {
+        let class = match status.as_u16() / 100 {
+            1 => "1xx",
+            2 => "2xx",
+            3 => "3xx",
+            4 => "4xx",
+            _ => "5xx",
+        };
+        let labels = [
+            ("method", self.method.as_str()),
+            ("route", self.route.get().unwrap_or("unmatched")),
+            ("status", class),
+        ];
+        self.recorder.counter("http_requests_total", &labels, 1);
+        self.recorder.histogram(
+            "http_request_duration_seconds",
+            &labels,
+            self.start.elapsed().as_secs_f64(),
+        );
+    }
+}
+
+// Requests dropped before their response, e.g. by clients going away, are no longer in
+// flight either.
+impl Drop for RequestMetrics {
+    fn drop(&mut self) {
+        self.recorder
+            .gauge(IN_FLIGHT, &[("method", self.method.as_str())], -1);
+    }
+}
+
+const IN_FLIGHT: &str = "http_requests_in_flight";
+
+/// A handle to record metrics for the current request, labeled with its route.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    recorder: Option<Arc<dyn Recorder>>,
+    route: Option<Arc<str>>,
+}
+
+impl Metrics {
+    pub(crate) fn new(recorder: Arc<dyn Recorder>, route: Option<&str>) -> Self {
+        Self {
+            recorder: Some(recorder),
+            route: route.map(Arc::from),
+        }
+    }
+
+    /// Increment the counter `name` by `value`.
+    pub fn incr(&self, name: &str, value: u64) {
+        if let Some(recorder) = &self.recorder {
+            recorder.counter(name, &self.labels(), value);
+        }
+    }
+
+    /// Record `value` in the timing `name`.
+    pub fn timing(&self, name: &str, value: Duration) {
+        if let Some(recorder) = &self.recorder {
+            recorder.timing(name, &self.labels(), value);
+        }
+    }
+
+    /// Start timing `name`, which is recorded once the returned timer is dropped.
+    pub fn timer(&self, name: &str) -> Timer {
+        Timer {
+            metrics: self.clone(),
+            name: name.to_string(),
+            start: Instant::now(),
+        }
+    }
+
+    fn labels(&self) -> Vec<(&str, &str)> {
+        self.route
+            .as_deref()
+            .map(|route| ("route", route))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// A running timing started with [`Metrics::timer`].
+pub struct Timer {
+    metrics: Metrics,
+    name: String,
+    start: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.metrics.timing(&self.name, self.start.elapsed());
+    }
+}
@@ -0,0 +1,244 @@
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::future::{ready, Ready};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request, Response};
+
+use super::{Labels, MetricsRecorder, Recorder};
+
+/// The bucket bounds of histograms by default, in seconds.
+const DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// A registry collecting metrics to be scraped by Prometheus. Clones share the metrics.
+///
+/// It records both the [request metrics](crate::Router::request_metrics) and the
+/// [metrics of handlers](crate::Router::metrics), whose timings become histograms in seconds.
+/// Its [handler](Prometheus::handler) renders them in the Prometheus text exposition format.
+#[derive(Debug, Clone)]
+pub struct Prometheus {
+    families: Arc<Mutex<BTreeMap<String, Family>>>,
+    buckets: Arc<[f64]>,
+}
+
+#[derive(Debug)]
+struct Family {
+    kind: &'static str,
+    series: BTreeMap<Vec<(String, String)>, Value>,
+}
+
+#[derive(Debug)]
+enum Value {
+    Counter(u64),
+    Gauge(i64),
+    Histogram {
+        counts: Vec<u64>,
+        sum: f64,
+        count: u64,
+    },
+}
+
+impl Default for Prometheus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Prometheus {
+    /// Create an empty registry with buckets from 5 milliseconds to 10 seconds.
+    pub fn new() -> Self {
+        Self {
+            families: Arc::default(),
+            buckets: Arc::new(DEFAULT_BUCKETS),
+        }
+    }
+
+    /// Get the registry of the process, which is rendered by [`prometheus`].
+    pub fn global() -> Self {
+        static GLOBAL: OnceLock<Prometheus> = OnceLock::new();
+        GLOBAL.get_or_init(Prometheus::new).clone()
+    }
+
+    /// Sort the values of histograms into buckets with the upper bounds `buckets` instead.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buckets` are not in increasing order.
+    pub fn buckets(mut self, buckets: &[f64]) -> Self {
+        assert!(
+            buckets.windows(2).all(|pair| pair[0] < pair[1]),
+            "buckets must be in increasing order"
+        );
+        self.buckets = buckets.into();
+        self
+    }
+
+    /// Render the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let families = self.families.lock().unwrap();
+        let mut out = String::new();
+        for (name, family) in families.iter() {
+            let _ = writeln!(out, "# TYPE {} {}", name, family.kind);
+            for (labels, value) in &family.series {
+                match value {
+                    Value::Counter(value) => {
+                        let _ = writeln!(out, "{}{} {}", name, render_labels(labels, None), value);
+                    }
+                    Value::Gauge(value) => {
+                        let _ = writeln!(out, "{}{} {}", name, render_labels(labels, None), value);
+                    }
+                    Value::Histogram { counts, sum, count } => {
+                        let mut cumulative = 0;
+                        for (bound, bucket) in self.buckets.iter().zip(counts) {
+                            cumulative += bucket;
+                            let le = Some(bound.to_string());
+                            let labels = render_labels(labels, le.as_deref());
+                            let _ = writeln!(out, "{}_bucket{} {}", name, labels, cumulative);
+                        }
+                        let labels_inf = render_labels(labels, Some("+Inf"));
+                        let _ = writeln!(out, "{}_bucket{} {}", name, labels_inf, count);
+                        let labels = render_labels(labels, None);
+                        let _ = writeln!(out, "{}_sum{} {}", name, labels, sum);
+                        let _ = writeln!(out, "{}_count{} {}", name, labels, count);
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Make a handler answering with the rendered metrics.
+    pub fn handler(
+        &self,
+    ) -> impl Fn(Request<Body>) -> Ready<Result<Response<Body>, Infallible>> + Send + Sync {
+        let registry = self.clone();
+        move |_req| {
+            ready(Ok(Response::builder()
+                .header(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")
+                .body(Body::from(registry.render()))
+                .unwrap()))
+        }
+    }
+
+    fn update(
+        &self,
+        name: &str,
+        labels: &Labels,
+        kind: &'static str,
+        update: impl FnOnce(&mut Value),
+    ) {
+        let buckets = self.buckets.len();
+        let mut families = self.families.lock().unwrap();
+        let family = families.entry(sanitize(name)).or_insert_with(|| Family {
+            kind,
+            series: BTreeMap::new(),
+        });
+        // A name recorded as another kind of metric would render an invalid exposition.
+        if family.kind != kind {
+            return;
+        }
+        let labels = labels
+            .iter()
+            .map(|(name, value)| (sanitize(name), value.to_string()))
+            .collect();
+        let value = family.series.entry(labels).or_insert_with(|| match kind {
+            "counter" => Value::Counter(0),
+            "gauge" => Value::Gauge(0),
+            _ => Value::Histogram {
+                counts: vec![0; buckets],
+                sum: 0.0,
+                count: 0,
+            },
+        });
+        update(value);
+    }
+
+    fn observe(&self, name: &str, labels: &Labels, value: f64) {
+        let bucket = self.buckets.iter().position(|bound| value <= *bound);
+        self.update(name, labels, "histogram", |histogram| {
+            if let Value::Histogram { counts, sum, count } = histogram {
+                if let Some(bucket) = bucket {
+                    counts[bucket] += 1;
+                }
+                *sum += value;
+                *count += 1;
+            }
+        });
+    }
+}
+
+impl MetricsRecorder for Prometheus {
+    fn counter(&self, name: &str, labels: &Labels, value: u64) {
+        self.update(name, labels, "counter", |counter| {
+            if let Value::Counter(total) = counter {
+                *total += value;
+            }
+        });
+    }
+
+    fn gauge(&self, name: &str, labels: &Labels, delta: i64) {
+        self.update(name, labels, "gauge", |gauge| {
+            if let Value::Gauge(total) = gauge {
+                *total += delta;
+            }
+        });
+    }
+
+    fn histogram(&self, name: &str, labels: &Labels, value: f64) {
+        self.observe(name, labels, value);
+    }
+}
+
+impl Recorder for Prometheus {
+    fn counter(&self, name: &str, labels: &Labels, value: u64) {
+        MetricsRecorder::counter(self, name, labels, value);
+    }
+
+    fn timing(&self, name: &str, labels: &Labels, value: Duration) {
+        self.observe(name, labels, value.as_secs_f64());
+    }
+}
+
+/// Make a handler answering with the metrics of the [global](Prometheus::global) registry in
+/// the Prometheus text exposition format.
+pub fn prometheus(
+) -> impl Fn(Request<Body>) -> Ready<Result<Response<Body>, Infallible>> + Send + Sync {
+    Prometheus::global().handler()
+}
+
+/// Replace the characters which aren't allowed in metric and label names with `_`.
+fn sanitize(name: &str) -> String {
+    name.char_indices()
+        .map(|(i, c)| match c {
+            'a'..='z' | 'A'..='Z' | '_' | ':' => c,
+            '0'..='9' if i > 0 => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+fn render_labels(labels: &[(String, String)], le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        return String::new();
+    }
+    format!("{{{}}}", pairs.join(","))
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
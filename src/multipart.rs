@@ -0,0 +1,491 @@
+//! Parsing `multipart/form-data` request bodies.
+//!
+//! [`Multipart`] streams the fields of a form one by one, or collects them into a [`Form`].
+//! Fields and the whole body are limited in size, and uploaded files larger than the spill
+//! threshold are written to temporary files instead of being kept in memory. Uploaded files
+//! can be persisted to a path or stored in a [`Storage`].
+//!
+//! Requires the `multipart` feature.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::multipart::{Multipart, MultipartLimits};
+//!
+//! async fn upload(mut req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let limits = MultipartLimits::new().field_limit(64 * 1024 * 1024);
+//!     let multipart = match Multipart::with_limits(&mut req, limits) {
+//!         Ok(multipart) => multipart,
+//!         Err(rejection) => return Ok(rejection.into()),
+//!     };
+//!     let form = match multipart.collect().await {
+//!         Ok(form) => form,
+//!         Err(err) => return Ok(err.into()),
+//!     };
+//!
+//!     let album: u64 = match form.parse("album") {
+//!         Ok(album) => album,
+//!         Err(rejection) => return Ok(rejection.into()),
+//!     };
+//!     for (field, photo) in form.into_files() {
+//!         if field != "photo" {
+//!             continue;
+//!         }
+//!         let path = format!("/srv/albums/{}/{}", album, photo.file_name().unwrap_or("photo"));
+//!         if photo.persist(path).await.is_err() {
+//!             return Ok(Response::builder().status(500).body(Body::empty()).unwrap());
+//!         }
+//!     }
+//!     Ok(Response::new(Body::from("uploaded")))
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request, Response, StatusCode};
+use multer::{Constraints, SizeLimit};
+use tokio::io::AsyncWriteExt;
+
+use crate::extract::Rejection;
+use crate::limits::LengthLimitExceeded;
+use crate::storage::{self, Storage};
+
+/// Size limits for multipart bodies.
+#[derive(Debug, Clone)]
+pub struct MultipartLimits {
+    field: usize,
+    total: usize,
+    spill: usize,
+}
+
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultipartLimits {
+    /// Create limits of 16 MiB per field and 64 MiB in total, spilling files larger than
+    /// 256 KiB to temporary files.
+    pub fn new() -> Self {
+        Self {
+            field: 16 * 1024 * 1024,
+            total: 64 * 1024 * 1024,
+            spill: 256 * 1024,
+        }
+    }
+
+    /// Set the maximum size of a single field in bytes.
+    pub fn field_limit(mut self, limit: usize) -> Self {
+        self.field = limit;
+        self
+    }
+
+    /// Set the maximum size of the whole body in bytes.
+    pub fn total_limit(mut self, limit: usize) -> Self {
+        self.total = limit;
+        self
+    }
+
+    /// Set the size in bytes above which [`Multipart::collect`] writes uploaded files to
+    /// temporary files.
+    pub fn spill_threshold(mut self, threshold: usize) -> Self {
+        self.spill = threshold;
+        self
+    }
+}
+
+/// An error while reading a multipart body.
+#[derive(Debug)]
+pub enum MultipartError {
+    /// A field or the whole body is larger than its limit.
+    TooLarge { limit: usize },
+    /// The body is not valid multipart data, or a text field is not valid UTF-8.
+    Malformed(String),
+    /// An uploaded file couldn't be written to a temporary file.
+    Io(io::Error),
+}
+
+impl MultipartError {
+    /// Get the status of the response for this error, e.g. `413 Payload Too Large` for
+    /// [`MultipartError::TooLarge`].
+    pub fn status(&self) -> StatusCode {
+        match self {
+            MultipartError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            MultipartError::Malformed(_) => StatusCode::BAD_REQUEST,
+            MultipartError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MultipartError::TooLarge { limit } => {
+                write!(f, "multipart data exceeds the limit of {} bytes", limit)
+            }
+            MultipartError::Malformed(message) => write!(f, "invalid multipart data: {}", message),
+            MultipartError::Io(err) => write!(f, "failed to store uploaded file: {}", err),
+        }
+    }
+}
+
+impl Error for MultipartError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MultipartError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<MultipartError> for Response<Body> {
+    fn from(err: MultipartError) -> Self {
+        Response::builder()
+            .status(err.status())
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(Body::from(err.to_string()))
+            .unwrap()
+    }
+}
+
+impl From<multer::Error> for MultipartError {
+    fn from(err: multer::Error) -> Self {
+        match err {
+            multer::Error::FieldSizeExceeded { limit, .. }
+            | multer::Error::StreamSizeExceeded { limit } => MultipartError::TooLarge {
+                limit: limit as usize,
+            },
+            multer::Error::StreamReadFailed(err) => {
+                // The body may already be limited by the router.
+                let exceeded = err
+                    .downcast_ref::<hyper::Error>()
+                    .and_then(|err| err.source())
+                    .and_then(|source| source.downcast_ref::<LengthLimitExceeded>());
+                match exceeded {
+                    Some(exceeded) => MultipartError::TooLarge {
+                        limit: exceeded.limit,
+                    },
+                    None => MultipartError::Malformed(err.to_string()),
+                }
+            }
+            err => MultipartError::Malformed(err.to_string()),
+        }
+    }
+}
+
+/// The fields of a `multipart/form-data` body.
+pub struct Multipart {
+    inner: multer::Multipart<'static>,
+    spill: usize,
+}
+
+impl Multipart {
+    /// Take the body of `req` with the default [`MultipartLimits`]. Requests which are not
+    /// `multipart/form-data` are rejected with `415 Unsupported Media Type`.
+    pub fn from_request(req: &mut Request<Body>) -> Result<Self, Rejection> {
+        Self::with_limits(req, MultipartLimits::new())
+    }
+
+    /// Take the body of `req` with `limits`.
+    pub fn with_limits(
+        req: &mut Request<Body>,
+        limits: MultipartLimits,
+    ) -> Result<Self, Rejection> {
+        let boundary = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| multer::parse_boundary(value).ok())
+            .ok_or_else(|| {
+                Rejection::new(
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    "expected a multipart/form-data body",
+                )
+            })?;
+        let constraints = Constraints::new().size_limit(
+            SizeLimit::new()
+                .per_field(limits.field as u64)
+                .whole_stream(limits.total as u64),
+        );
+        let body = std::mem::take(req.body_mut());
+        Ok(Self {
+            inner: multer::Multipart::with_constraints(body, boundary, constraints),
+            spill: limits.spill,
+        })
+    }
+
+    /// Get the next field, or `None` at the end of the body.
+    pub async fn next_field(&mut self) -> Result<Option<Field>, MultipartError> {
+        Ok(self.inner.next_field().await?.map(|inner| Field { inner }))
+    }
+
+    /// Read all fields into a [`Form`]. Fields with a file name are collected as files, other
+    /// fields as text.
+    pub async fn collect(mut self) -> Result<Form, MultipartError> {
+        let mut form = Form {
+            texts: Vec::new(),
+            files: Vec::new(),
+        };
+        while let Some(field) = self.next_field().await? {
+            let name = field.name().unwrap_or("").to_string();
+            if field.file_name().is_some() {
+                let file = UploadedFile::read(field, self.spill).await?;
+                form.files.push((name, file));
+            } else {
+                form.texts.push((name, field.text().await?));
+            }
+        }
+        Ok(form)
+    }
+}
+
+/// A field of a multipart body, read chunk by chunk.
+pub struct Field {
+    inner: multer::Field<'static>,
+}
+
+impl Field {
+    /// Get the name of the field in the form.
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// Get the file name of an uploaded file.
+    pub fn file_name(&self) -> Option<&str> {
+        self.inner.file_name()
+    }
+
+    /// Get the content type of the field.
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner.content_type().map(|mime| mime.as_ref())
+    }
+
+    /// Read the next chunk of the field, or `None` at its end.
+    pub async fn chunk(&mut self) -> Result<Option<Bytes>, MultipartError> {
+        Ok(self.inner.chunk().await?)
+    }
+
+    /// Read the whole field.
+    pub async fn bytes(self) -> Result<Bytes, MultipartError> {
+        Ok(self.inner.bytes().await?)
+    }
+
+    /// Read the whole field as UTF-8 text.
+    pub async fn text(self) -> Result<String, MultipartError> {
+        let bytes = self.bytes().await?;
+        String::from_utf8(bytes.to_vec())
+            .map_err(|_| MultipartError::Malformed("field is not valid UTF-8".to_string()))
+    }
+}
+
+/// The fields of a multipart body collected with [`Multipart::collect`].
+pub struct Form {
+    texts: Vec<(String, String)>,
+    files: Vec<(String, UploadedFile)>,
+}
+
+impl Form {
+    /// Get the first text field named `name`.
+    pub fn text(&self, name: &str) -> Option<&str> {
+        self.texts
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Get all text fields named `name`.
+    pub fn texts<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.texts
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parse the first text field named `name`. A missing field is rejected with
+    /// `400 Bad Request`, an invalid one with `422 Unprocessable Entity`.
+    pub fn parse<T: FromStr>(&self, name: &str) -> Result<T, Rejection> {
+        let value = self.text(name).ok_or_else(|| {
+            Rejection::new(
+                StatusCode::BAD_REQUEST,
+                format!("missing form field {:?}", name),
+            )
+        })?;
+        value.parse().map_err(|_| {
+            Rejection::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("invalid form field {:?}", name),
+            )
+        })
+    }
+
+    /// Get the first file uploaded as `name`.
+    pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+        self.files
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, file)| file)
+    }
+
+    /// Get all files uploaded as `name`.
+    pub fn files<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a UploadedFile> + 'a {
+        self.files
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .map(|(_, file)| file)
+    }
+
+    /// Take all uploaded files with the names of their fields, e.g. to persist them.
+    pub fn into_files(self) -> impl Iterator<Item = (String, UploadedFile)> {
+        self.files.into_iter()
+    }
+}
+
+/// An uploaded file, kept in memory or spilled to a temporary file. Temporary files are
+/// removed when the upload is dropped, unless it is persisted.
+#[derive(Debug)]
+pub struct UploadedFile {
+    file_name: Option<String>,
+    content_type: Option<String>,
+    size: usize,
+    data: Data,
+}
+
+#[derive(Debug)]
+enum Data {
+    Memory(Bytes),
+    Temp(TempFile),
+}
+
+impl UploadedFile {
+    async fn read(mut field: Field, spill: usize) -> Result<Self, MultipartError> {
+        let file_name = field.file_name().map(str::to_string);
+        let content_type = field.content_type().map(str::to_string);
+        let mut size = 0;
+        let mut buf = Vec::new();
+        let mut temp: Option<(TempFile, tokio::fs::File)> = None;
+        while let Some(chunk) = field.chunk().await? {
+            size += chunk.len();
+            if let Some((_, file)) = temp.as_mut() {
+                file.write_all(&chunk).await.map_err(MultipartError::Io)?;
+                continue;
+            }
+            buf.extend_from_slice(&chunk);
+            if buf.len() > spill {
+                let (path, mut file) = TempFile::create().await.map_err(MultipartError::Io)?;
+                file.write_all(&buf).await.map_err(MultipartError::Io)?;
+                buf = Vec::new();
+                temp = Some((path, file));
+            }
+        }
+        let data = match temp {
+            Some((path, mut file)) => {
+                file.flush().await.map_err(MultipartError::Io)?;
+                Data::Temp(path)
+            }
+            None => Data::Memory(Bytes::from(buf)),
+        };
+        Ok(Self {
+            file_name,
+            content_type,
+            size,
+            data,
+        })
+    }
+
+    /// Get the file name sent by the client.
+    pub fn file_name(&self) -> Option<&str> {
+        self.file_name.as_deref()
+    }
+
+    /// Get the content type sent by the client.
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    /// Get the size of the file in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Get the path of the temporary file if the upload was spilled to disk.
+    pub fn path(&self) -> Option<&Path> {
+        match &self.data {
+            Data::Memory(_) => None,
+            Data::Temp(temp) => Some(&temp.0),
+        }
+    }
+
+    /// Read the contents of the file.
+    pub async fn bytes(&self) -> io::Result<Bytes> {
+        match &self.data {
+            Data::Memory(bytes) => Ok(bytes.clone()),
+            Data::Temp(temp) => tokio::fs::read(&temp.0).await.map(Bytes::from),
+        }
+    }
+
+    /// Store the file as the object `key` of `storage`.
+    pub async fn store(self, storage: &dyn Storage, key: &str) -> io::Result<()> {
+        let body = match &self.data {
+            Data::Memory(bytes) => Body::from(bytes.clone()),
+            Data::Temp(temp) => storage::file_body(tokio::fs::File::open(&temp.0).await?),
+        };
+        storage.put(key, body, self.size as u64).await
+    }
+
+    /// Write the file to `path`, moving the temporary file there if possible.
+    pub async fn persist(self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        match &self.data {
+            Data::Memory(bytes) => tokio::fs::write(path, bytes).await,
+            Data::Temp(temp) => {
+                // Renaming fails across file systems, so fall back to copying.
+                if tokio::fs::rename(&temp.0, path).await.is_err() {
+                    tokio::fs::copy(&temp.0, path).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A temporary file which is removed when dropped.
+#[derive(Debug)]
+struct TempFile(PathBuf);
+
+impl TempFile {
+    async fn create() -> io::Result<(Self, tokio::fs::File)> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.subsec_nanos());
+        let path = std::env::temp_dir().join(format!(
+            "keiro-upload-{}-{}-{}",
+            std::process::id(),
+            nanos,
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await?;
+        Ok((Self(path), file))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // The file is already gone if it was persisted by renaming it.
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,384 @@
+//! Content and language negotiation.
+//!
+//! [`Accept`] parses the `Accept` header of a request, and [`Negotiate`] answers it with the
+//! representation the client prefers among those a handler offers, so one handler can serve
+//! JSON to API clients and HTML to browsers. Representations are only rendered if they are
+//! picked. Ties are broken by the order the representations are offered in, and requests
+//! without an `Accept` header get the first one. Requests accepting none are answered with
+//! `406 Not Acceptable`.
+//!
+//! [`AcceptLanguage`] parses the `Accept-Language` header. With the [`Locales`] a service
+//! supports set with [`Router::locales`](crate::Router::locales), every request gets the
+//! [`Locale`] it prefers, or the default one, in its extensions.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::negotiation::Negotiate;
+//!
+//! #[derive(serde::Serialize)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! async fn user(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let user = User {
+//!         name: "giraffate".to_string(),
+//!     };
+//!     Ok(Negotiate::new()
+//!         .json(&user)
+//!         .html(|| format!("<h1>{}</h1>", user.name))
+//!         .text(|| user.name.clone())
+//!         .respond(&req))
+//! }
+//! ```
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::negotiation::{Locale, Locales};
+//! use keiro::prelude::*;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.locales(Locales::new(&["en", "de", "pt-BR"]));
+//! router.get("/", greeting);
+//!
+//! async fn greeting(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let greeting = match req.extract::<Locale>().unwrap().as_str() {
+//!         "de" => "Hallo!",
+//!         "pt-BR" => "Olá!",
+//!         _ => "Hello!",
+//!     };
+//!     Ok(Response::text(greeting))
+//! }
+//! ```
+
+use std::fmt;
+
+use hyper::header::{HeaderValue, ACCEPT, ACCEPT_LANGUAGE, VARY};
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::ext::ResponseExt;
+use crate::extract::{FromRequest, Rejection};
+
+/// The parsed `Accept` header of a request.
+#[derive(Debug, Clone, Default)]
+pub struct Accept {
+    /// Media ranges in lowercase, without parameters, with their quality values.
+    ranges: Vec<(String, f32)>,
+}
+
+impl Accept {
+    /// Parse the value of an `Accept` header.
+    pub fn parse(value: &str) -> Self {
+        Self {
+            ranges: parse_ranges(value, |range| range.contains('/')),
+        }
+    }
+
+    /// Get the `Accept` header of `req`, joining repeated headers.
+    pub(crate) fn from_headers(req: &Request<Body>) -> Self {
+        Self::parse(&header_value(req, ACCEPT))
+    }
+
+    /// Get the quality value of `media_type`, such as `text/html`, between `0.0` (not
+    /// acceptable) and `1.0`, from the most specific range matching it. Every type is
+    /// acceptable if the header is empty.
+    pub fn quality(&self, media_type: &str) -> f32 {
+        if self.ranges.is_empty() {
+            return 1.0;
+        }
+        let media_type = media_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let kind = media_type.split('/').next().unwrap_or_default();
+        let mut best: Option<(u8, f32)> = None;
+        for (range, quality) in &self.ranges {
+            let specificity = if *range == media_type {
+                2
+            } else if range.strip_suffix("/*") == Some(kind) {
+                1
+            } else if range == "*/*" {
+                0
+            } else {
+                continue;
+            };
+            if best.is_none_or(|(s, _)| specificity > s) {
+                best = Some((specificity, *quality));
+            }
+        }
+        best.map_or(0.0, |(_, quality)| quality)
+    }
+
+    /// Pick the acceptable type from `supported` with the highest quality value, preferring
+    /// earlier ones on ties, or `None` if none is acceptable.
+    pub fn negotiate<'a>(&self, supported: &[&'a str]) -> Option<&'a str> {
+        let mut best: Option<(&str, f32)> = None;
+        for &media_type in supported {
+            let quality = self.quality(media_type);
+            if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+                best = Some((media_type, quality));
+            }
+        }
+        best.map(|(media_type, _)| media_type)
+    }
+}
+
+impl FromRequest for Accept {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        Ok(Self::from_headers(req))
+    }
+}
+
+/// Answers a request with the representation it prefers.
+#[derive(Default)]
+pub struct Negotiate<'a> {
+    #[allow(clippy::type_complexity)]
+    representations: Vec<(&'static str, Box<dyn FnOnce() -> Response<Body> + 'a>)>,
+}
+
+impl<'a> Negotiate<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Offer `value` serialized into JSON as `application/json`.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(self, value: &'a T) -> Self {
+        self.with("application/json", move || Response::json(value))
+    }
+
+    /// Offer `value` serialized into MessagePack as `application/msgpack`.
+    #[cfg(feature = "msgpack")]
+    pub fn msgpack<T: serde::Serialize + ?Sized>(self, value: &'a T) -> Self {
+        self.with("application/msgpack", move || Response::msgpack(value))
+    }
+
+    /// Offer `value` serialized into CBOR as `application/cbor`.
+    #[cfg(feature = "cbor")]
+    pub fn cbor<T: serde::Serialize + ?Sized>(self, value: &'a T) -> Self {
+        self.with("application/cbor", move || Response::cbor(value))
+    }
+
+    /// Offer `value` serialized into XML as `application/xml`.
+    #[cfg(feature = "xml")]
+    pub fn xml<T: serde::Serialize + ?Sized>(self, value: &'a T) -> Self {
+        self.with("application/xml", move || Response::xml(value))
+    }
+
+    /// Offer `message` encoded into Protocol Buffers as `application/x-protobuf`.
+    #[cfg(feature = "protobuf")]
+    pub fn protobuf<T: prost::Message>(self, message: &'a T) -> Self {
+        self.with("application/x-protobuf", move || {
+            Response::protobuf(message)
+        })
+    }
+
+    /// Offer the HTML document rendered by `render` as `text/html`.
+    pub fn html<B: Into<Body>>(self, render: impl FnOnce() -> B + 'a) -> Self {
+        self.with("text/html", move || Response::html(render()))
+    }
+
+    /// Offer the text rendered by `render` as `text/plain`.
+    pub fn text<B: Into<Body>>(self, render: impl FnOnce() -> B + 'a) -> Self {
+        self.with("text/plain", move || Response::text(render()))
+    }
+
+    /// Offer the response rendered by `render` as `media_type`, which should set its
+    /// `Content-Type`.
+    pub fn with(
+        mut self,
+        media_type: &'static str,
+        render: impl FnOnce() -> Response<Body> + 'a,
+    ) -> Self {
+        self.representations.push((media_type, Box::new(render)));
+        self
+    }
+
+    /// Render the representation `req` prefers, or answer with `406 Not Acceptable`.
+    pub fn respond(self, req: &Request<Body>) -> Response<Body> {
+        let accept = Accept::from_request(req).unwrap_or_default();
+        let supported = self
+            .representations
+            .iter()
+            .map(|(media_type, _)| *media_type)
+            .collect::<Vec<_>>();
+        let picked = accept.negotiate(&supported);
+        let mut res = match self
+            .representations
+            .into_iter()
+            .find(|(media_type, _)| Some(*media_type) == picked)
+        {
+            Some((_, render)) => render(),
+            None => {
+                let mut res = Response::new(Body::empty());
+                *res.status_mut() = StatusCode::NOT_ACCEPTABLE;
+                res
+            }
+        };
+        res.headers_mut()
+            .append(VARY, HeaderValue::from_static("accept"));
+        res
+    }
+}
+
+/// The parsed `Accept-Language` header of a request.
+#[derive(Debug, Clone, Default)]
+pub struct AcceptLanguage {
+    /// Language ranges in lowercase with their quality values, the most preferred first.
+    ranges: Vec<(String, f32)>,
+}
+
+impl AcceptLanguage {
+    /// Parse the value of an `Accept-Language` header.
+    pub fn parse(value: &str) -> Self {
+        let mut ranges = parse_ranges(value, |range| !range.is_empty());
+        ranges.retain(|(_, quality)| *quality > 0.0);
+        // Stable, so ranges of equal quality keep the order of the header.
+        ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        Self { ranges }
+    }
+
+    /// Pick the language tag from `supported` matching the most preferred range, or `None` if
+    /// no range matches. A range matches a tag equal to it, a tag it's a prefix of, such as
+    /// `en` for `en-GB`, or, falling back, a prefix of it, such as `en-US` for `en`. `*`
+    /// matches the first tag.
+    pub fn negotiate<'a>(&self, supported: &[&'a str]) -> Option<&'a str> {
+        self.ranges.iter().find_map(|(range, _)| {
+            if range == "*" {
+                return supported.first().copied();
+            }
+            let exact = supported.iter().find(|tag| tag.eq_ignore_ascii_case(range));
+            let narrower = || {
+                supported.iter().find(|tag| {
+                    tag.len() > range.len()
+                        && tag[..range.len()].eq_ignore_ascii_case(range)
+                        && tag.as_bytes()[range.len()] == b'-'
+                })
+            };
+            let broader = || {
+                let mut prefix = range.as_str();
+                while let Some((rest, _)) = prefix.rsplit_once('-') {
+                    prefix = rest;
+                    if let Some(tag) = supported
+                        .iter()
+                        .find(|tag| tag.eq_ignore_ascii_case(prefix))
+                    {
+                        return Some(tag);
+                    }
+                }
+                None
+            };
+            exact.or_else(narrower).or_else(broader).copied()
+        })
+    }
+}
+
+impl FromRequest for AcceptLanguage {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        Ok(Self::parse(&header_value(req, ACCEPT_LANGUAGE)))
+    }
+}
+
+/// The language tags a service supports, the first being the default. See
+/// [`Router::locales`](crate::Router::locales).
+#[derive(Debug, Clone)]
+pub struct Locales {
+    supported: Vec<String>,
+}
+
+impl Locales {
+    /// Support the language tags `supported`, such as `en` or `pt-BR`, defaulting to the first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `supported` is empty.
+    pub fn new(supported: &[&str]) -> Self {
+        assert!(
+            !supported.is_empty(),
+            "at least one locale must be supported"
+        );
+        Self {
+            supported: supported.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    /// Pick the locale `req` prefers, or the default one.
+    pub fn negotiate(&self, req: &Request<Body>) -> Locale {
+        let supported = self
+            .supported
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let accept = AcceptLanguage::parse(&header_value(req, ACCEPT_LANGUAGE));
+        let tag = accept.negotiate(&supported).unwrap_or(supported[0]);
+        Locale(tag.to_string())
+    }
+}
+
+/// The locale picked for a request from the [`Locales`] of the router, one of the supported
+/// language tags.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Locale(pub String);
+
+impl Locale {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromRequest for Locale {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        req.extensions().get::<Locale>().cloned().ok_or_else(|| {
+            Rejection::new(StatusCode::INTERNAL_SERVER_ERROR, "no locales configured")
+        })
+    }
+}
+
+/// Parse the comma-separated ranges with quality values of an `Accept` style header, keeping
+/// those `valid` accepts.
+fn parse_ranges(value: &str, valid: impl Fn(&str) -> bool) -> Vec<(String, f32)> {
+    value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let range = parts.next()?.trim().to_ascii_lowercase();
+            if !valid(&range) {
+                return None;
+            }
+            let quality = parts
+                .filter_map(|param| {
+                    let (key, value) = param.split_once('=')?;
+                    if key.trim().eq_ignore_ascii_case("q") {
+                        value.trim().parse::<f32>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .unwrap_or(1.0);
+            Some((range, quality))
+        })
+        .collect()
+}
+
+fn header_value(req: &Request<Body>, name: hyper::header::HeaderName) -> String {
+    req.headers()
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -0,0 +1,243 @@
+//! Trace context propagation with W3C Trace Context and B3 headers.
+//!
+//! With [`Propagation`] set with [`Router::propagation`](crate::Router::propagation), every
+//! request gets a [`SpanContext`] for the work done serving it: a child of the context in its
+//! `traceparent` and `tracestate` headers, or of its B3 headers if enabled, or else the root
+//! of a new trace. Handlers get it with
+//! [`RequestExt::span_context`](crate::ext::RequestExt::span_context) and pass a
+//! [child](SpanContext::child) of it on to outbound calls with [`SpanContext::inject`], and
+//! [`Proxy`](crate::proxy::Proxy) does so for the requests it forwards.
+//!
+//! With the `tracing` feature, the `request` span of the [trace context](crate::trace) records
+//! the `trace_id`, `span_id` and `parent_span_id`, linking the logs of handlers to the traces
+//! of upstream services.
+//!
+//! Requires the `otel` feature.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Client, Request, Response};
+//! use keiro::otel::Propagation;
+//! use keiro::prelude::*;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.propagation(Propagation::new().b3());
+//! router.get("/orders/:id", order);
+//!
+//! async fn order(req: Request<Body>) -> Result<Response<Body>, hyper::Error> {
+//!     let mut outbound = Request::get("http://inventory.internal/stock")
+//!         .body(Body::empty())
+//!         .unwrap();
+//!     if let Some(context) = req.span_context() {
+//!         context.child().inject(outbound.headers_mut());
+//!     }
+//!     Client::new().request(outbound).await
+//! }
+//! ```
+
+use std::fmt;
+
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::{Body, Request};
+
+use crate::util::random_u64;
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+
+/// The identity of a span in a distributed trace, and whether the trace is sampled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpanContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+    /// The span this one is a child of, if it was propagated from upstream.
+    pub parent_span_id: Option<u64>,
+    pub sampled: bool,
+    /// The vendor-specific `tracestate`, passed on unchanged.
+    pub trace_state: Option<String>,
+}
+
+impl SpanContext {
+    /// Start a new trace with a sampled root span.
+    pub fn root() -> Self {
+        let trace_id = u128::from(random_u64()) << 64 | u128::from(random_u64());
+        Self {
+            trace_id: trace_id.max(1),
+            span_id: random_u64().max(1),
+            parent_span_id: None,
+            sampled: true,
+            trace_state: None,
+        }
+    }
+
+    /// Create a span of the same trace which is a child of this one.
+    pub fn child(&self) -> Self {
+        Self {
+            trace_id: self.trace_id,
+            span_id: random_u64().max(1),
+            parent_span_id: Some(self.span_id),
+            sampled: self.sampled,
+            trace_state: self.trace_state.clone(),
+        }
+    }
+
+    /// Read the context from the `traceparent` and `tracestate` headers, or return `None` if
+    /// they are missing or invalid.
+    pub fn from_traceparent(headers: &HeaderMap) -> Option<Self> {
+        let value = headers.get(TRACEPARENT)?.to_str().ok()?.trim();
+        let mut parts = value.split('-');
+        let version = parts.next()?;
+        let trace_id = parts.next()?;
+        let span_id = parts.next()?;
+        let flags = parts.next()?;
+        // Later versions may add fields, but version 00 has exactly four.
+        if byte(version)? == 0xff || (version == "00" && parts.next().is_some()) {
+            return None;
+        }
+        let trace_state = headers
+            .get_all(TRACESTATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect::<Vec<_>>()
+            .join(",");
+        Some(Self {
+            trace_id: hex(trace_id, 32)?,
+            span_id: hex(span_id, 16)? as u64,
+            parent_span_id: None,
+            sampled: byte(flags)? & 1 == 1,
+            trace_state: Some(trace_state).filter(|state| !state.is_empty()),
+        })
+    }
+
+    /// Read the context from the single `b3` header or the multiple `X-B3-*` headers, or
+    /// return `None` if they are missing or invalid.
+    pub fn from_b3(headers: &HeaderMap) -> Option<Self> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let (trace_id, span_id, sampled) = match header("b3") {
+            Some(value) => {
+                let mut parts = value.trim().split('-');
+                (parts.next()?, parts.next()?, parts.next())
+            }
+            None => (
+                header("x-b3-traceid")?,
+                header("x-b3-spanid")?,
+                header("x-b3-flags")
+                    .filter(|flags| *flags == "1")
+                    .map(|_| "d")
+                    .or_else(|| header("x-b3-sampled")),
+            ),
+        };
+        // 64-bit trace ids are padded to 128 bits.
+        let trace_id = match trace_id.len() {
+            16 | 32 => hex(trace_id, trace_id.len())?,
+            _ => return None,
+        };
+        Some(Self {
+            trace_id,
+            span_id: hex(span_id, 16)? as u64,
+            parent_span_id: None,
+            // Without a decision, follow the W3C default of sampling.
+            sampled: !matches!(sampled, Some("0") | Some("false")),
+            trace_state: None,
+        })
+    }
+
+    /// Set the `traceparent` and `tracestate` headers of an outbound request to this context.
+    pub fn inject(&self, headers: &mut HeaderMap) {
+        let value = HeaderValue::from_str(&self.traceparent()).expect("traceparents are valid");
+        headers.insert(HeaderName::from_static(TRACEPARENT), value);
+        match self
+            .trace_state
+            .as_deref()
+            .and_then(|state| HeaderValue::from_str(state).ok())
+        {
+            Some(value) => {
+                headers.insert(HeaderName::from_static(TRACESTATE), value);
+            }
+            None => {
+                headers.remove(TRACESTATE);
+            }
+        }
+    }
+
+    /// Set the single `b3` header of an outbound request to this context.
+    pub fn inject_b3(&self, headers: &mut HeaderMap) {
+        let value = format!(
+            "{:032x}-{:016x}-{}",
+            self.trace_id,
+            self.span_id,
+            if self.sampled { "1" } else { "0" }
+        );
+        let value = HeaderValue::from_str(&value).expect("b3 values are valid");
+        headers.insert(HeaderName::from_static("b3"), value);
+    }
+
+    /// Format the context as a `traceparent` value, such as
+    /// `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`.
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id,
+            self.span_id,
+            u8::from(self.sampled)
+        )
+    }
+}
+
+impl fmt::Display for SpanContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.traceparent())
+    }
+}
+
+/// Which headers trace contexts are propagated from.
+#[derive(Debug, Clone, Default)]
+pub struct Propagation {
+    b3: bool,
+}
+
+impl Propagation {
+    /// Propagate contexts from W3C Trace Context headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Propagate contexts from B3 headers too, for requests without W3C headers, e.g. from
+    /// services instrumented with Zipkin.
+    pub fn b3(mut self) -> Self {
+        self.b3 = true;
+        self
+    }
+
+    /// Add the [`SpanContext`] serving `req` to its extensions.
+    pub(crate) fn apply(&self, req: &mut Request<Body>) {
+        let parent = SpanContext::from_traceparent(req.headers()).or_else(|| {
+            self.b3
+                .then(|| SpanContext::from_b3(req.headers()))
+                .flatten()
+        });
+        let context = match parent {
+            Some(parent) => parent.child(),
+            None => SpanContext::root(),
+        };
+        req.extensions_mut().insert(context);
+    }
+}
+
+/// Parse two hex digits.
+fn byte(value: &str) -> Option<u8> {
+    if value.len() != 2 || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u8::from_str_radix(value, 16).ok()
+}
+
+/// Parse `digits` lowercase or uppercase hex digits which aren't all zero.
+fn hex(value: &str, digits: usize) -> Option<u128> {
+    if value.len() != digits || !value.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u128::from_str_radix(value, 16).ok().filter(|id| *id != 0)
+}
@@ -0,0 +1,174 @@
+//! Paginated collections.
+//!
+//! [`Pagination`] is extracted from the `page` and `per_page` query parameters, defaulting to
+//! the first page and bounded by the [`PaginationLimits`] added with
+//! [`Router::manage`](crate::Router::manage). [`Pagination::apply`] adds a `Link` header
+//! (RFC 8288) to the response, pointing at the first, previous, next and last pages of the URL
+//! of a named route.
+//!
+//! ```rust,no_run
+//! use hyper::{Body, Request, Response};
+//! use keiro::pagination::{Pagination, PaginationLimits};
+//! use keiro::prelude::*;
+//! use keiro::Router;
+//!
+//! #[derive(Clone)]
+//! struct Urls {
+//!     issues: String,
+//! }
+//!
+//! let mut router = Router::new();
+//! router.get("/issues", issues).name("issues");
+//! router.manage(PaginationLimits::new().default_per_page(25).max_per_page(50));
+//! router.manage(Urls {
+//!     issues: router.url_for("issues", &[]).unwrap(),
+//! });
+//!
+//! async fn issues(req: Request<Body>) -> keiro::Result<Response<Body>> {
+//!     let pagination = req.extract::<Pagination>()?;
+//!     let total = 1234;
+//!     // Load `pagination.limit()` issues, skipping `pagination.offset()`...
+//!     let mut res = Response::new(Body::from("[]"));
+//!     pagination.apply(&mut res, &req.state::<Urls>().unwrap().issues, total);
+//!     Ok(res)
+//! }
+//! ```
+
+use hyper::header::{HeaderValue, LINK};
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::extract::{FromRequest, Rejection};
+use crate::query::QueryPairs;
+
+/// The page size of requests without `per_page`, and the largest page size requests may ask
+/// for. Requests asking for more get the largest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaginationLimits {
+    default_per_page: u64,
+    max_per_page: u64,
+}
+
+impl Default for PaginationLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PaginationLimits {
+    /// Create limits with 20 items per page by default and at most 100.
+    pub fn new() -> Self {
+        Self {
+            default_per_page: 20,
+            max_per_page: 100,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `per_page` is zero.
+    pub fn default_per_page(mut self, per_page: u64) -> Self {
+        assert!(per_page > 0, "pages can't be empty");
+        self.default_per_page = per_page;
+        self
+    }
+
+    /// # Panics
+    ///
+    /// Panics if `per_page` is zero.
+    pub fn max_per_page(mut self, per_page: u64) -> Self {
+        assert!(per_page > 0, "pages can't be empty");
+        self.max_per_page = per_page;
+        self
+    }
+}
+
+/// The page of a collection a request asks for, starting at page 1.
+///
+/// A `page` or `per_page` which isn't a positive number is a rejection with
+/// `400 Bad Request`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    pub page: u64,
+    pub per_page: u64,
+}
+
+impl Pagination {
+    /// Get the number of items before this page.
+    pub fn offset(&self) -> u64 {
+        self.page.saturating_sub(1).saturating_mul(self.per_page)
+    }
+
+    /// Get the number of items on this page, which is `per_page`.
+    pub fn limit(&self) -> u64 {
+        self.per_page
+    }
+
+    /// Get the number of pages of a collection with `total` items, at least 1.
+    pub fn pages(&self, total: u64) -> u64 {
+        total.div_ceil(self.per_page).max(1)
+    }
+
+    /// Build the value of a `Link` header for a collection with `total` items at `url`, with
+    /// `first`, `prev`, `next` and `last` links.
+    pub fn link(&self, url: &str, total: u64) -> String {
+        let last = self.pages(total);
+        let mut links = vec![(1, "first")];
+        if self.page > 1 {
+            links.push(((self.page - 1).min(last), "prev"));
+        }
+        if self.page < last {
+            links.push((self.page + 1, "next"));
+        }
+        links.push((last, "last"));
+
+        let separator = if url.contains('?') { '&' } else { '?' };
+        links
+            .iter()
+            .map(|(page, rel)| {
+                format!(
+                    "<{}{}page={}&per_page={}>; rel=\"{}\"",
+                    url, separator, page, self.per_page, rel
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Set the `Link` header of `res`. See [`Pagination::link`].
+    pub fn apply(&self, res: &mut Response<Body>, url: &str, total: u64) {
+        if let Ok(value) = HeaderValue::from_str(&self.link(url, total)) {
+            res.headers_mut().insert(LINK, value);
+        }
+    }
+}
+
+impl FromRequest for Pagination {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        let limits = req
+            .extensions()
+            .get::<PaginationLimits>()
+            .copied()
+            .unwrap_or_default();
+        let query = QueryPairs::from_uri(req);
+        let positive = |name: &str| match query.get(name) {
+            Some(value) => value
+                .parse::<u64>()
+                .ok()
+                .filter(|n| *n > 0)
+                .map(Some)
+                .ok_or_else(|| {
+                    Rejection::new(
+                        StatusCode::BAD_REQUEST,
+                        format!("{} must be a positive number", name),
+                    )
+                }),
+            None => Ok(None),
+        };
+        Ok(Self {
+            page: positive("page")?.unwrap_or(1),
+            per_page: positive("per_page")?
+                .unwrap_or(limits.default_per_page)
+                .min(limits.max_per_page),
+        })
+    }
+}
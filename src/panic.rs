@@ -0,0 +1,103 @@
+//! Recovery from panicking handlers.
+//!
+//! A handler panicking, while it's called or while its future is polled, would otherwise tear
+//! down the task of the connection, so the client gets no response and other requests on the
+//! connection fail. The router catches the panic instead and answers with
+//! `500 Internal Server Error`. The panic is reported to the hook set with
+//! [`Router::on_panic`](crate::Router::on_panic), and as an error event with the `tracing`
+//! feature.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.get("/", index);
+//! router.on_panic(|panic| eprintln!("{} {} panicked: {}", panic.method, panic.path, panic.message));
+//!
+//! async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     panic!("not implemented yet")
+//! }
+//! ```
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use futures_util::FutureExt;
+use hyper::{Body, Method, Response, StatusCode};
+
+use crate::error;
+
+pub(crate) type PanicHook = Arc<dyn Fn(&Panic) + Send + Sync>;
+
+/// A panic caught while handling a request.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Panic {
+    pub method: Method,
+    pub path: String,
+    /// The message the handler panicked with, if it was a string.
+    pub message: String,
+}
+
+/// Call `serve` and poll its future, answering with `500 Internal Server Error` if either
+/// panics.
+pub(crate) async fn catch<F, Fut, E>(
+    method: Method,
+    path: String,
+    on_panic: Option<PanicHook>,
+    serve: F,
+) -> Result<Response<Body>, E>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<Response<Body>, E>>,
+{
+    let payload = match std::panic::catch_unwind(AssertUnwindSafe(serve)) {
+        Ok(fut) => match AssertUnwindSafe(fut).catch_unwind().await {
+            Ok(res) => return res,
+            Err(payload) => payload,
+        },
+        Err(payload) => payload,
+    };
+    let panic = Panic {
+        method,
+        path,
+        message: message(&*payload),
+    };
+    #[cfg(feature = "tracing")]
+    tracing::error!(
+        method = %panic.method,
+        path = %panic.path,
+        message = %panic.message,
+        "handler panicked"
+    );
+    if let Some(on_panic) = on_panic {
+        on_panic(&panic);
+    }
+    #[cfg_attr(not(feature = "debug-errors"), allow(unused_mut))]
+    let mut res = Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body(Body::empty())
+        .unwrap();
+    #[cfg(feature = "debug-errors")]
+    res.extensions_mut()
+        .insert(crate::debug_errors::CaughtPanic {
+            panic,
+            backtrace: crate::debug_errors::take_panic_backtrace(),
+        });
+    Ok(error::generated(res))
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message.to_string(),
+        None => match payload.downcast_ref::<String>() {
+            Some(message) => message.clone(),
+            None => "Box<dyn Any>".to_string(),
+        },
+    }
+}
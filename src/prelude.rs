@@ -1 +1,2 @@
-pub use crate::ext::RequestExt;
+pub use crate::error::ResultExt;
+pub use crate::ext::{RequestExt, ResponseExt};
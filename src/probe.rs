@@ -0,0 +1,138 @@
+//! Filtering of bot and scanner traffic.
+//!
+//! Internet-facing services receive a constant stream of requests for paths like
+//! `/wp-login.php` or `/.env`. A [`ProbeFilter`] answers those requests directly,
+//! before the `not_found` handler is called.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response, StatusCode};
+//! use keiro::probe::ProbeFilter;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.get("/", index);
+//! router.probe_filter(
+//!     ProbeFilter::default()
+//!         .prefix("/admin.cgi")
+//!         .status(StatusCode::from_u16(444).unwrap()),
+//! );
+//!
+//! async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("Hello keiro!")))
+//! }
+//! ```
+
+use hyper::{Body, Response, StatusCode};
+
+use crate::tarpit::Tarpit;
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Prefix(String),
+    Suffix(String),
+    Segment(String),
+}
+
+impl Pattern {
+    fn is_match(&self, path: &str) -> bool {
+        match self {
+            Pattern::Prefix(prefix) => path.starts_with(prefix.as_str()),
+            Pattern::Suffix(suffix) => path.ends_with(suffix.as_str()),
+            Pattern::Segment(segment) => path.split('/').any(|s| s == segment),
+        }
+    }
+}
+
+/// A set of path patterns which are answered without calling any handler.
+#[derive(Debug, Clone)]
+pub struct ProbeFilter {
+    patterns: Vec<Pattern>,
+    status: StatusCode,
+    tarpit: Option<Tarpit>,
+}
+
+impl Default for ProbeFilter {
+    /// Create a filter for paths commonly requested by scanners.
+    fn default() -> Self {
+        let mut filter = Self::new();
+        for prefix in &[
+            "/wp-login.php",
+            "/wp-admin",
+            "/wp-content",
+            "/wp-includes",
+            "/xmlrpc.php",
+            "/phpmyadmin",
+            "/cgi-bin/",
+            "/vendor/phpunit",
+        ] {
+            filter = filter.prefix(prefix);
+        }
+        for suffix in &[".php", ".asp", ".aspx", ".jsp"] {
+            filter = filter.suffix(suffix);
+        }
+        for segment in &[".env", ".git", ".svn", ".htaccess", ".aws", ".DS_Store"] {
+            filter = filter.segment(segment);
+        }
+        filter
+    }
+}
+
+impl ProbeFilter {
+    /// Create a filter without any patterns.
+    pub fn new() -> Self {
+        Self {
+            patterns: Vec::new(),
+            status: StatusCode::NOT_FOUND,
+            tarpit: None,
+        }
+    }
+
+    /// Match paths starting with `prefix`.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.patterns.push(Pattern::Prefix(prefix.to_string()));
+        self
+    }
+
+    /// Match paths ending with `suffix`.
+    pub fn suffix(mut self, suffix: &str) -> Self {
+        self.patterns.push(Pattern::Suffix(suffix.to_string()));
+        self
+    }
+
+    /// Match paths containing `segment` as one of their segments.
+    pub fn segment(mut self, segment: &str) -> Self {
+        self.patterns.push(Pattern::Segment(segment.to_string()));
+        self
+    }
+
+    /// Set the status of responses to filtered requests. Defaults to `404 Not Found`.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Answer filtered requests slowly with `tarpit` instead of responding immediately, still
+    /// with the status of the filter.
+    pub fn tarpit(mut self, tarpit: Tarpit) -> Self {
+        self.tarpit = Some(tarpit);
+        self
+    }
+
+    /// Return `true` if `path` matches one of the patterns.
+    pub fn is_match(&self, path: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.is_match(path))
+    }
+
+    pub(crate) fn response(&self) -> Response<Body> {
+        let res = Response::builder()
+            .status(self.status)
+            .body(Body::empty())
+            .unwrap();
+        match &self.tarpit {
+            Some(tarpit) => tarpit.drip(res),
+            None => res,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+//! Problem details for HTTP APIs, as specified in [RFC 7807](https://www.rfc-editor.org/rfc/rfc7807).
+//!
+//! A [`Problem`] is answered as an `application/problem+json` document with the `type`,
+//! `title`, `status` and `detail` of the error, so clients of an API can handle errors
+//! without parsing messages. Handlers return problems by converting them into responses or
+//! with [`Problem::into_error`]. With [`Router::problem_details`](crate::Router::problem_details),
+//! the router answers unmatched requests and the [`Error`]s of handlers with problems too.
+//!
+//! Requires the `json` feature.
+//!
+//! ```rust,no_run
+//! use hyper::{Body, Request, Response, StatusCode};
+//! use keiro::problem::Problem;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.problem_details();
+//! router.post("/transfers", transfer);
+//!
+//! async fn transfer(_req: Request<Body>) -> keiro::Result<Response<Body>> {
+//!     let balance = 30;
+//!     Err(Problem::new(StatusCode::FORBIDDEN)
+//!         .type_uri("https://example.com/probs/out-of-credit")
+//!         .title("You do not have enough credit.")
+//!         .detail(format!("Your current balance is {}, but that costs 50.", balance))
+//!         .instance("/account/12345/transfers/abc")
+//!         .extension("balance", balance)
+//!         .into_error())
+//! }
+//! ```
+
+use std::error::Error as StdError;
+use std::fmt;
+
+use hyper::{header, Body, Response, StatusCode};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::Error;
+
+/// The media type of problem details.
+pub const CONTENT_TYPE: &str = "application/problem+json";
+
+/// The details of an error, answered as an `application/problem+json` document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    status: StatusCode,
+    type_uri: String,
+    title: String,
+    detail: Option<String>,
+    instance: Option<String>,
+    extensions: Map<String, Value>,
+}
+
+impl Problem {
+    /// Create a problem of the type `about:blank` for `status`, titled with its canonical
+    /// reason.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status,
+            type_uri: "about:blank".to_string(),
+            title: status
+                .canonical_reason()
+                .unwrap_or("Unknown Status")
+                .to_string(),
+            detail: None,
+            instance: None,
+            extensions: Map::new(),
+        }
+    }
+
+    /// Set the URI identifying the type of the problem, whose documentation should explain
+    /// it.
+    pub fn type_uri(mut self, type_uri: impl Into<String>) -> Self {
+        self.type_uri = type_uri.into();
+        self
+    }
+
+    /// Set the summary of the type of the problem, which shouldn't change between occurrences.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Set the explanation of this occurrence of the problem.
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Set the URI identifying this occurrence of the problem.
+    pub fn instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Add the member `key` with `value` to the document.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` can't be serialized into JSON, e.g. a map with non-string keys.
+    pub fn extension(mut self, key: impl Into<String>, value: impl Serialize) -> Self {
+        let value = serde_json::to_value(value).expect("problem extension must be valid JSON");
+        self.extensions.insert(key.into(), value);
+        self
+    }
+
+    /// Get the status of the response for this problem.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Convert the problem into an [`Error`], so handlers can return it with `?`.
+    pub fn into_error(self) -> Error {
+        Error::new(self)
+    }
+
+    fn to_json(&self) -> Value {
+        let mut document = Map::new();
+        document.insert("type".to_string(), self.type_uri.clone().into());
+        document.insert("title".to_string(), self.title.clone().into());
+        document.insert("status".to_string(), self.status.as_u16().into());
+        if let Some(detail) = &self.detail {
+            document.insert("detail".to_string(), detail.clone().into());
+        }
+        if let Some(instance) = &self.instance {
+            document.insert("instance".to_string(), instance.clone().into());
+        }
+        for (key, value) in &self.extensions {
+            document.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        Value::Object(document)
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.detail {
+            Some(detail) => write!(f, "{}: {}", self.title, detail),
+            None => f.write_str(&self.title),
+        }
+    }
+}
+
+impl StdError for Problem {}
+
+/// The problem of `err`, detailed with its message unless that's just the canonical reason of
+/// its status.
+impl From<&Error> for Problem {
+    fn from(err: &Error) -> Self {
+        if let Some(problem) = err.downcast_ref::<Problem>() {
+            return problem.clone();
+        }
+        let problem = Problem::new(err.status());
+        if Some(err.message()) == err.status().canonical_reason() {
+            problem
+        } else {
+            problem.detail(err.message())
+        }
+    }
+}
+
+impl From<Problem> for Response<Body> {
+    fn from(problem: Problem) -> Self {
+        Response::builder()
+            .status(problem.status)
+            .header(header::CONTENT_TYPE, CONTENT_TYPE)
+            .body(Body::from(problem.to_json().to_string()))
+            .unwrap()
+    }
+}
@@ -0,0 +1,237 @@
+//! Reverse proxying to upstream servers.
+//!
+//! A [`Proxy`] forwards requests to one of its upstreams and sends the upstream's response
+//! back. With several upstreams, requests are distributed round-robin unless an [`Affinity`]
+//! pins each client to one upstream.
+//!
+//! With the `tls` feature, [`UpstreamTls`] configures how HTTPS upstreams are verified. On
+//! Unix, [`Proxy::unix`] forwards to upstreams listening on Unix domain sockets.
+//!
+//! ```rust,no_run
+//! use hyper::header::HeaderName;
+//! use keiro::proxy::{Affinity, Proxy};
+//! use keiro::Router;
+//!
+//! let api = Proxy::new(vec![
+//!     "http://10.0.0.1:8080".parse().unwrap(),
+//!     "http://10.0.0.2:8080".parse().unwrap(),
+//! ])
+//! .affinity(Affinity::Cookie("keiro_upstream".to_string()));
+//!
+//! let reports = Proxy::new(vec!["http://10.0.1.1:8080".parse().unwrap()])
+//!     .affinity(Affinity::Header(HeaderName::from_static("x-tenant-id")));
+//!
+//! let mut router = Router::new();
+//! let api = api.handler();
+//! router.get("/api/*path", api.clone());
+//! router.post("/api/*path", api);
+//! router.get("/reports/*path", reports.handler());
+//! ```
+
+use std::collections::hash_map::DefaultHasher;
+use std::convert::Infallible;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use hyper::client::connect::Connect;
+use hyper::client::HttpConnector;
+use hyper::header::{HeaderName, HOST};
+use hyper::http::uri::{PathAndQuery, Uri};
+use hyper::{Body, Client, Request, Response, StatusCode};
+
+use crate::cookie::{Cookie, CookieJar, SameSite};
+use crate::ext::ResponseExt;
+use crate::util::SyncFuture;
+
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(unix)]
+mod unix;
+
+#[cfg(feature = "tls")]
+pub use self::tls::UpstreamTls;
+#[cfg(unix)]
+pub use self::unix::{UnixConnection, UnixConnector};
+
+/// Headers which only apply to a single connection and must not be forwarded.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// How clients are pinned to an upstream.
+#[derive(Debug, Clone)]
+pub enum Affinity {
+    /// Pin clients with a cookie of this name, which is set on the first response.
+    Cookie(String),
+    /// Pin clients by hashing the value of this request header, e.g. a session or tenant id.
+    /// Requests without the header are distributed round-robin.
+    Header(HeaderName),
+}
+
+/// A reverse proxy to a set of upstreams.
+pub struct Proxy<C = HttpConnector> {
+    upstreams: Vec<Uri>,
+    client: Client<C, Body>,
+    affinity: Option<Affinity>,
+    next: AtomicUsize,
+}
+
+impl Proxy {
+    /// Create a proxy to `upstreams`, e.g. `http://10.0.0.1:8080`. A path in an upstream URI
+    /// is prepended to the paths of forwarded requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `upstreams` is empty.
+    pub fn new(upstreams: Vec<Uri>) -> Self {
+        Self::with_client(upstreams, Client::new())
+    }
+}
+
+impl<C> Proxy<C>
+where
+    C: Connect + Clone + Send + Sync + 'static,
+{
+    /// Create a proxy which forwards requests with `client`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `upstreams` is empty.
+    pub fn with_client(upstreams: Vec<Uri>, client: Client<C, Body>) -> Self {
+        assert!(!upstreams.is_empty(), "a proxy needs at least one upstream");
+        Self {
+            upstreams,
+            client,
+            affinity: None,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Pin clients to an upstream with `affinity`.
+    pub fn affinity(mut self, affinity: Affinity) -> Self {
+        self.affinity = Some(affinity);
+        self
+    }
+
+    /// Turn the proxy into a handler which can be registered for routes.
+    #[allow(clippy::type_complexity)]
+    pub fn handler(
+        self,
+    ) -> impl Fn(
+        Request<Body>,
+    )
+        -> Pin<Box<dyn Future<Output = Result<Response<Body>, Infallible>> + Send + Sync>>
+           + Clone
+           + Send
+           + Sync
+           + 'static {
+        let proxy = Arc::new(self);
+        move |req| {
+            let proxy = proxy.clone();
+            Box::pin(SyncFuture::new(async move { Ok(proxy.forward(req).await) }))
+        }
+    }
+
+    /// Forward `req` to an upstream. Failures to reach the upstream are answered with
+    /// `502 Bad Gateway`.
+    pub async fn forward(&self, mut req: Request<Body>) -> Response<Body> {
+        let (index, pinned) = self.select(&req);
+        let upstream = &self.upstreams[index];
+
+        let uri = match target(upstream, &req) {
+            Some(uri) => uri,
+            None => return error_response(StatusCode::BAD_GATEWAY),
+        };
+        *req.uri_mut() = uri;
+        remove_hop_by_hop(req.headers_mut());
+        req.headers_mut().remove(HOST);
+        #[cfg(feature = "otel")]
+        if let Some(context) = req.extensions().get::<crate::otel::SpanContext>() {
+            let context = context.child();
+            context.inject(req.headers_mut());
+        }
+
+        let mut res = match self.client.request(req).await {
+            Ok(res) => res,
+            Err(_) => return error_response(StatusCode::BAD_GATEWAY),
+        };
+        remove_hop_by_hop(res.headers_mut());
+
+        if let (Some(Affinity::Cookie(name)), false) = (&self.affinity, pinned) {
+            res.set_cookie(
+                Cookie::new(name.as_str(), index.to_string())
+                    .path("/")
+                    .http_only()
+                    .same_site(SameSite::Lax),
+            );
+        }
+        res
+    }
+
+    /// Select an upstream, returning its index and whether the client was already pinned to it.
+    fn select(&self, req: &Request<Body>) -> (usize, bool) {
+        let count = self.upstreams.len();
+        match &self.affinity {
+            Some(Affinity::Cookie(name)) => {
+                let pinned = CookieJar::from_headers(req)
+                    .get(name)
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|index| *index < count);
+                if let Some(index) = pinned {
+                    return (index, true);
+                }
+            }
+            Some(Affinity::Header(name)) => {
+                if let Some(value) = req.headers().get(name) {
+                    let mut hasher = DefaultHasher::new();
+                    value.as_bytes().hash(&mut hasher);
+                    return ((hasher.finish() % count as u64) as usize, true);
+                }
+            }
+            None => {}
+        }
+        (self.next.fetch_add(1, Ordering::Relaxed) % count, false)
+    }
+}
+
+fn target(upstream: &Uri, req: &Request<Body>) -> Option<Uri> {
+    let base = upstream.path().trim_end_matches('/');
+    let path_and_query = req.uri().path_and_query().map_or("/", PathAndQuery::as_str);
+    let mut parts = upstream.clone().into_parts();
+    parts.path_and_query = Some(format!("{}{}", base, path_and_query).parse().ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+fn remove_hop_by_hop(headers: &mut hyper::HeaderMap) {
+    // Headers listed in `Connection` are hop-by-hop as well.
+    let listed: Vec<HeaderName> = headers
+        .get_all("connection")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| name.trim().parse().ok())
+        .collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(*name);
+    }
+}
+
+fn error_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,160 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use hyper::client::HttpConnector;
+use hyper::http::uri::Uri;
+use hyper::Client;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName};
+
+use super::Proxy;
+
+/// TLS settings for connections to upstreams.
+///
+/// By default, upstream certificates are verified against the Mozilla root certificates
+/// and the server name is taken from the upstream URI.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use keiro::proxy::{Proxy, UpstreamTls};
+///
+/// let tls = UpstreamTls::new()
+///     .without_public_roots()
+///     .ca_file("/etc/ssl/internal-ca.pem")
+///     .unwrap()
+///     .server_name("billing.internal");
+/// let proxy = Proxy::with_tls(vec!["https://10.0.0.1:8443".parse().unwrap()], tls);
+/// ```
+#[derive(Clone)]
+pub struct UpstreamTls {
+    roots: RootCertStore,
+    server_name: Option<String>,
+    skip_verify: bool,
+}
+
+impl Default for UpstreamTls {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UpstreamTls {
+    pub fn new() -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                anchor.subject,
+                anchor.spki,
+                anchor.name_constraints,
+            )
+        }));
+        Self {
+            roots,
+            server_name: None,
+            skip_verify: false,
+        }
+    }
+
+    /// Trust only the certificates added with [`UpstreamTls::ca_bundle`] or
+    /// [`UpstreamTls::ca_file`], not the Mozilla root certificates.
+    pub fn without_public_roots(mut self) -> Self {
+        self.roots = RootCertStore::empty();
+        self
+    }
+
+    /// Trust the PEM encoded certificates in `pem`, e.g. an internal CA.
+    pub fn ca_bundle(mut self, pem: &[u8]) -> io::Result<Self> {
+        let certs = rustls_pemfile::certs(&mut &*pem)?;
+        if certs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no certificates found in CA bundle",
+            ));
+        }
+        for cert in certs {
+            self.roots
+                .add(&Certificate(cert))
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        }
+        Ok(self)
+    }
+
+    /// Trust the PEM encoded certificates in the file at `path`.
+    pub fn ca_file(self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let pem = fs::read(path)?;
+        self.ca_bundle(&pem)
+    }
+
+    /// Send `server_name` as SNI and verify certificates against it, instead of the host of
+    /// the upstream URI. Useful when upstreams are addressed by IP.
+    pub fn server_name(mut self, server_name: impl Into<String>) -> Self {
+        self.server_name = Some(server_name.into());
+        self
+    }
+
+    /// Accept any upstream certificate without verification.
+    ///
+    /// This makes connections to upstreams vulnerable to man-in-the-middle attacks, so it
+    /// should only be used for local development.
+    pub fn danger_skip_verify(mut self) -> Self {
+        self.skip_verify = true;
+        self
+    }
+
+    fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(self.roots.clone())
+            .with_no_client_auth();
+        if self.skip_verify {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(SkipVerify));
+        }
+        config
+    }
+
+    /// Build an HTTPS connector with these settings, which also accepts plain HTTP upstreams.
+    pub fn connector(&self) -> HttpsConnector<HttpConnector> {
+        let builder = HttpsConnectorBuilder::new()
+            .with_tls_config(self.client_config())
+            .https_or_http();
+        let builder = match &self.server_name {
+            Some(server_name) => builder.with_server_name(server_name.clone()),
+            None => builder,
+        };
+        builder.enable_http1().enable_http2().build()
+    }
+}
+
+impl Proxy<HttpsConnector<HttpConnector>> {
+    /// Create a proxy which connects to `upstreams` with `tls`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `upstreams` is empty.
+    pub fn with_tls(upstreams: Vec<Uri>, tls: UpstreamTls) -> Self {
+        Self::with_client(upstreams, Client::builder().build(tls.connector()))
+    }
+}
+
+struct SkipVerify;
+
+impl ServerCertVerifier for SkipVerify {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
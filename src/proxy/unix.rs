@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::client::connect::{Connected, Connection};
+use hyper::http::uri::Uri;
+use hyper::service::Service;
+use hyper::Client;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::UnixStream;
+
+use super::Proxy;
+
+/// A connector for upstreams listening on Unix domain sockets.
+///
+/// Each socket is addressed by the authority of a URI, so the connector can be shared by
+/// several upstreams. [`Proxy::unix`] sets this up for a list of socket paths.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use hyper::Client;
+/// use keiro::proxy::{Proxy, UnixConnector};
+///
+/// // Forward to `/var/run/app.sock` under the base path `/v1`.
+/// let connector = UnixConnector::new().socket("app", "/var/run/app.sock");
+/// let client = Client::builder().build(connector);
+/// let proxy = Proxy::with_client(vec!["http://app/v1".parse().unwrap()], client);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UnixConnector {
+    sockets: Arc<HashMap<String, PathBuf>>,
+}
+
+impl UnixConnector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Connect to the socket at `path` for URIs with the authority `authority`.
+    pub fn socket(mut self, authority: impl Into<String>, path: impl Into<PathBuf>) -> Self {
+        Arc::make_mut(&mut self.sockets).insert(authority.into(), path.into());
+        self
+    }
+}
+
+impl Service<Uri> for UnixConnector {
+    type Response = UnixConnection;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<UnixConnection>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let path = uri
+            .authority()
+            .and_then(|authority| self.sockets.get(authority.as_str()))
+            .cloned();
+        Box::pin(async move {
+            let path = path.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no Unix socket for upstream {}", uri),
+                )
+            })?;
+            UnixStream::connect(path).await.map(UnixConnection)
+        })
+    }
+}
+
+/// A connection to an upstream over a Unix domain socket.
+#[derive(Debug)]
+pub struct UnixConnection(UnixStream);
+
+impl Connection for UnixConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for UnixConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.0.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl Proxy<UnixConnector> {
+    /// Create a proxy to upstreams listening on the Unix domain sockets at `paths`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `paths` is empty.
+    pub fn unix<P: Into<PathBuf>>(paths: Vec<P>) -> Self {
+        let mut connector = UnixConnector::new();
+        let mut upstreams = Vec::with_capacity(paths.len());
+        for (index, path) in paths.into_iter().enumerate() {
+            let authority = format!("unix-{}", index);
+            upstreams.push(format!("http://{}", authority).parse().unwrap());
+            connector = connector.socket(authority, path);
+        }
+        Self::with_client(upstreams, Client::builder().build(connector))
+    }
+}
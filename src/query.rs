@@ -0,0 +1,183 @@
+//! Parsed query strings.
+//!
+//! [`RequestExt::query_pairs`](crate::ext::RequestExt::query_pairs) decodes the query string
+//! of a request into [`QueryPairs`], keeping repeated keys and decoding `+` and
+//! percent-encoded characters. Routed requests parse it on the first call only, so
+//! middleware and handlers can all look at it without parsing it again.
+//!
+//! With the `query` feature, [`Query`] deserializes the query string into a typed value,
+//! including nested and array parameters such as `?filter[status]=open&ids[]=1&ids[]=2`.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::prelude::*;
+//!
+//! // e.g. `/search?q=giraffe+necks&tag=mammal&tag=africa`
+//! async fn search(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let query = req.query_pairs();
+//!     let q = query.get("q").unwrap_or_default();
+//!     let tags = query.get_all("tag").collect::<Vec<_>>();
+//!     Ok(Response::new(Body::from(format!("{} in {:?}", q, tags))))
+//! }
+//! ```
+
+#[cfg(feature = "query")]
+use std::ops::Deref;
+use std::sync::{Arc, OnceLock};
+
+#[cfg(feature = "query")]
+use hyper::StatusCode;
+use hyper::{Body, Request};
+
+use crate::extract::{FromRequest, Rejection};
+use crate::url;
+
+/// The decoded key-value pairs of a query string, in order, cheap to clone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueryPairs {
+    pairs: Arc<[(String, String)]>,
+}
+
+impl QueryPairs {
+    /// Parse a query string such as `a=1&b=two+words`. Keys without `=` have an empty value,
+    /// pairs which aren't valid UTF-8 once decoded are skipped.
+    pub fn parse(query: &str) -> Self {
+        let pairs = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .filter_map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                Some((decode(key)?, decode(value)?))
+            })
+            .collect();
+        Self { pairs }
+    }
+
+    /// Get the first value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.iter().find(|(k, _)| *k == key).map(|(_, value)| value)
+    }
+
+    /// Get every value of `key`, in order.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.iter()
+            .filter(move |(k, _)| *k == key)
+            .map(|(_, value)| value)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Iterate over all pairs, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Get the pairs of `req`, parsing them only once for routed requests.
+    pub(crate) fn from_uri(req: &Request<Body>) -> Self {
+        let parse = || Self::parse(req.uri().query().unwrap_or(""));
+        match req.extensions().get::<Cached>() {
+            Some(cached) => cached.0.get_or_init(parse).clone(),
+            None => parse(),
+        }
+    }
+}
+
+impl FromRequest for QueryPairs {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        Ok(Self::from_uri(req))
+    }
+}
+
+/// The pairs of a request, parsed on first use. Added by the router to routed requests.
+#[derive(Default)]
+pub(crate) struct Cached(OnceLock<QueryPairs>);
+
+fn decode(value: &str) -> Option<String> {
+    url::decode(&value.replace('+', " "))
+}
+
+/// A query string deserialized into `T`, with nested and array parameters as emitted by
+/// front-end libraries such as `qs`: `filter[status]=open` fills the field `status` of the
+/// field `filter`, and `ids[]=1&ids[]=2` or `ids[0]=1&ids[1]=2` fill a sequence. Brackets may
+/// be percent-encoded.
+///
+/// A query string which doesn't fit `T` is a rejection with `400 Bad Request`.
+///
+/// Requires the `query` feature.
+///
+/// ```rust,no_run
+/// use hyper::{Body, Request, Response};
+/// use keiro::prelude::*;
+/// use keiro::query::Query;
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct Filter {
+///     status: Option<String>,
+/// }
+///
+/// #[derive(Deserialize)]
+/// struct Search {
+///     #[serde(default)]
+///     ids: Vec<u64>,
+///     filter: Option<Filter>,
+/// }
+///
+/// // e.g. `/issues?filter[status]=open&ids[]=1&ids[]=2`
+/// async fn issues(req: Request<Body>) -> keiro::Result<Response<Body>> {
+///     let Query(search) = req.extract::<Query<Search>>()?;
+///     let status = search.filter.and_then(|filter| filter.status);
+///     Ok(Response::new(Body::from(format!("{:?} {:?}", search.ids, status))))
+/// }
+/// ```
+#[cfg(feature = "query")]
+#[derive(Debug, Clone)]
+pub struct Query<T>(pub T);
+
+#[cfg(feature = "query")]
+impl<T> Query<T> {
+    /// Get the deserialized value.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+#[cfg(feature = "query")]
+impl<T> Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+#[cfg(feature = "query")]
+impl<T: serde::de::DeserializeOwned> FromRequest for Query<T> {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        // Not strict, so brackets encoded as `%5B` and `%5D` are accepted too.
+        let config = serde_qs::Config::new(5, false);
+        config
+            .deserialize_str(req.uri().query().unwrap_or(""))
+            .map(Query)
+            .map_err(|err| {
+                Rejection::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid query string: {}", err),
+                )
+            })
+    }
+}
@@ -0,0 +1,344 @@
+//! Rate limiting with token buckets.
+//!
+//! A [`RateLimit`] set on a route with [`Route::rate_limit`](crate::Route::rate_limit), or on
+//! all routes of a router with [`Router::rate_limit`](crate::Router::rate_limit), gives every
+//! key a bucket of [`Quota::burst`] tokens, refilled at the rate of the quota. Each request
+//! takes a token; requests finding the bucket of their key empty are answered with
+//! `429 Too Many Requests` and a `Retry-After` header, before authentication and the handler.
+//! All responses tell the client its quota in the `X-RateLimit-Limit`, `X-RateLimit-Remaining`
+//! and `X-RateLimit-Reset` headers, the last being the seconds until the bucket is full again.
+//!
+//! Requests are keyed by a [`RateKey`], the client IP by default, and the buckets are kept in
+//! a [`RateLimitStore`]. [`MemoryStore`] keeps them in the process; with the `redis` feature,
+//! [`RedisStore`] shares them between the instances of a service. If the store fails, requests
+//! are let through rather than rejected.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::header::HeaderName;
+//! use hyper::{Body, Request, Response};
+//! use keiro::rate_limit::{ByHeader, Quota, RateLimit};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! // 100 requests per minute per client IP, in bursts of at most 20.
+//! router.rate_limit(RateLimit::new(Quota::per_minute(100).burst(20)));
+//! router
+//!     .post("/search", search)
+//!     .rate_limit(RateLimit::new(Quota::per_second(5)).key(ByHeader(HeaderName::from_static("x-api-key"))));
+//!
+//! async fn search(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::header::{HeaderName, HeaderValue, RETRY_AFTER};
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::ip_filter;
+use crate::MatchedPath;
+
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisStore;
+
+const LIMIT: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const REMAINING: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const RESET: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+/// The future returned by [`RateLimitStore::acquire`].
+pub type RateLimitFuture<'a> =
+    Pin<Box<dyn Future<Output = io::Result<Decision>> + Send + Sync + 'a>>;
+
+/// The rate at which a bucket is refilled, and how many tokens it holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    /// The time to refill one token.
+    pub interval: Duration,
+    /// The tokens of a full bucket.
+    pub burst: u32,
+}
+
+impl Quota {
+    /// Allow `count` requests per `period`, all of which may be made at once.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` is zero.
+    pub fn new(count: u32, period: Duration) -> Self {
+        assert!(count > 0, "a quota must allow requests");
+        Self {
+            interval: period / count,
+            burst: count,
+        }
+    }
+
+    pub fn per_second(count: u32) -> Self {
+        Self::new(count, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(count: u32) -> Self {
+        Self::new(count, Duration::from_secs(60))
+    }
+
+    pub fn per_hour(count: u32) -> Self {
+        Self::new(count, Duration::from_secs(60 * 60))
+    }
+
+    /// Allow at most `burst` requests at once, keeping the rate.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+}
+
+/// The state of a bucket after a request took, or failed to take, a token from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    /// The tokens left in the bucket.
+    pub remaining: u32,
+    /// The time until the bucket is full again.
+    pub reset: Duration,
+    /// The time until the next token, if the request was not allowed.
+    pub retry_after: Duration,
+}
+
+impl Decision {
+    /// Take a token from a bucket holding `tokens` after refilling, and return the decision
+    /// with the tokens left.
+    pub fn take(tokens: f64, quota: Quota) -> (Self, f64) {
+        let allowed = tokens >= 1.0;
+        let tokens = if allowed { tokens - 1.0 } else { tokens };
+        let interval = quota.interval.as_secs_f64();
+        let decision = Self {
+            allowed,
+            remaining: tokens as u32,
+            reset: Duration::from_secs_f64((f64::from(quota.burst) - tokens).max(0.0) * interval),
+            retry_after: if allowed {
+                Duration::ZERO
+            } else {
+                Duration::from_secs_f64((1.0 - tokens) * interval)
+            },
+        };
+        (decision, tokens)
+    }
+}
+
+/// Picks the bucket of a request.
+pub trait RateKey: Send + Sync + 'static {
+    /// The key of the bucket for `req`, or `None` if the request is not limited.
+    fn key(&self, req: &Request<Body>) -> Option<String>;
+}
+
+impl<F> RateKey for F
+where
+    F: Fn(&Request<Body>) -> Option<String> + Send + Sync + 'static,
+{
+    fn key(&self, req: &Request<Body>) -> Option<String> {
+        self(req)
+    }
+}
+
+/// Keys requests by the IP address of the client, from its
+/// [`ClientInfo`](crate::forwarded::ClientInfo) behind trusted proxies, or else its
+/// [`ConnectInfo`](crate::connect::ConnectInfo). IPv4 addresses mapped into IPv6 share the
+/// bucket of the IPv4 address.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ByClientIp;
+
+impl RateKey for ByClientIp {
+    fn key(&self, req: &Request<Body>) -> Option<String> {
+        Some(ip_filter::client_ip(req)?.to_string())
+    }
+}
+
+/// Keys requests by the value of a header, such as an API key. Requests without it are not
+/// limited, so combine it with authentication requiring the header.
+#[derive(Debug, Clone)]
+pub struct ByHeader(pub HeaderName);
+
+impl RateKey for ByHeader {
+    fn key(&self, req: &Request<Body>) -> Option<String> {
+        let value = req.headers().get(&self.0)?;
+        Some(String::from_utf8_lossy(value.as_bytes()).into_owned())
+    }
+}
+
+/// Keys requests by the pattern of the matched route, limiting the requests to a route from
+/// all clients together.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ByRoute;
+
+impl RateKey for ByRoute {
+    fn key(&self, req: &Request<Body>) -> Option<String> {
+        let matched = req.extensions().get::<MatchedPath>()?;
+        Some(matched.0.clone())
+    }
+}
+
+/// Storage for the buckets of a [`RateLimit`].
+pub trait RateLimitStore: Send + Sync + 'static {
+    /// Refill the bucket of `key` according to `quota`, creating a full one if there is none,
+    /// and take a token from it if there is one.
+    fn acquire(&self, key: &str, quota: Quota) -> RateLimitFuture<'_>;
+}
+
+/// A [`RateLimitStore`] keeping buckets in memory. Clones share the buckets.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore(Arc<Mutex<MemoryBuckets>>);
+
+#[derive(Debug, Default)]
+struct MemoryBuckets {
+    buckets: HashMap<String, Bucket>,
+    /// The number of buckets at which full ones are removed next.
+    sweep_at: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// The time when the bucket is full again, so it can be forgotten.
+    full_at: Instant,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn take(&self, key: &str, quota: Quota) -> Decision {
+        let mut state = match self.0.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let now = Instant::now();
+        if state.buckets.len() >= state.sweep_at {
+            state.buckets.retain(|_, bucket| bucket.full_at > now);
+            state.sweep_at = (state.buckets.len() * 2).max(1024);
+        }
+        let burst = f64::from(quota.burst);
+        let tokens = match state.buckets.get(key) {
+            Some(bucket) => {
+                let refilled =
+                    now.duration_since(bucket.updated).as_secs_f64() / quota.interval.as_secs_f64();
+                (bucket.tokens + refilled).min(burst)
+            }
+            None => burst,
+        };
+        let (decision, tokens) = Decision::take(tokens, quota);
+        state.buckets.insert(
+            key.to_string(),
+            Bucket {
+                tokens,
+                updated: now,
+                full_at: now + decision.reset,
+            },
+        );
+        decision
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn acquire(&self, key: &str, quota: Quota) -> RateLimitFuture<'_> {
+        let decision = self.take(key, quota);
+        Box::pin(async move { Ok(decision) })
+    }
+}
+
+/// Limits the rate of requests per key.
+#[derive(Clone)]
+pub struct RateLimit {
+    quota: Quota,
+    key: Arc<dyn RateKey>,
+    store: Arc<dyn RateLimitStore>,
+}
+
+impl RateLimit {
+    /// Limit requests to `quota` per client IP, keeping the buckets in a new [`MemoryStore`].
+    pub fn new(quota: Quota) -> Self {
+        Self {
+            quota,
+            key: Arc::new(ByClientIp),
+            store: Arc::new(MemoryStore::new()),
+        }
+    }
+
+    /// Pick the bucket of requests with `key` instead of the client IP.
+    pub fn key(mut self, key: impl RateKey) -> Self {
+        self.key = Arc::new(key);
+        self
+    }
+
+    /// Keep the buckets in `store`.
+    pub fn store(mut self, store: impl RateLimitStore) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Take a token for `req`, returning the response if it's rejected, or the decision to
+    /// note in the response otherwise. Requests without a key, or whose bucket can't be
+    /// retrieved, are let through without a decision.
+    pub(crate) async fn acquire(
+        &self,
+        key: Option<String>,
+    ) -> Result<Option<Decision>, Response<Body>> {
+        let key = match key {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        let decision = match self.store.acquire(&key, self.quota).await {
+            Ok(decision) => decision,
+            Err(_) => return Ok(None),
+        };
+        if decision.allowed {
+            return Ok(Some(decision));
+        }
+        let mut res = Response::builder()
+            .status(StatusCode::TOO_MANY_REQUESTS)
+            .body(Body::empty())
+            .unwrap();
+        let retry_after = seconds(decision.retry_after).max(1);
+        res.headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        self.annotate(&decision, &mut res);
+        Err(res)
+    }
+
+    pub(crate) fn key_of(&self, req: &Request<Body>) -> Option<String> {
+        self.key.key(req)
+    }
+
+    /// Add the rate limit headers for `decision` to `res`.
+    pub(crate) fn annotate(&self, decision: &Decision, res: &mut Response<Body>) {
+        let headers = res.headers_mut();
+        headers.insert(LIMIT, HeaderValue::from(self.quota.burst));
+        headers.insert(REMAINING, HeaderValue::from(decision.remaining));
+        headers.insert(RESET, HeaderValue::from(seconds(decision.reset)));
+    }
+}
+
+impl fmt::Debug for RateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RateLimit")
+            .field("quota", &self.quota)
+            .finish()
+    }
+}
+
+/// `duration` in whole seconds, rounded up.
+fn seconds(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
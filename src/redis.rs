@@ -0,0 +1,85 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use ::redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use ::redis::{Client, IntoConnectionInfo, RedisResult};
+use tokio::sync::OnceCell;
+
+/// A multiplexed connection to a Redis server, opened on the first command. Commands are
+/// pipelined on the connection instead of waiting for each other, and it reconnects in the
+/// background after failures. Clones share the connection.
+#[derive(Clone)]
+pub(crate) struct Connection {
+    pub(crate) url: String,
+    pub(crate) password: Option<String>,
+    pub(crate) timeout: Duration,
+    manager: Arc<OnceCell<ConnectionManager>>,
+}
+
+impl Connection {
+    pub(crate) fn new(url: String) -> Self {
+        Self {
+            url,
+            password: None,
+            timeout: Duration::from_secs(1),
+            manager: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// Run the commands of `f` on the connection, failing if they don't complete within the
+    /// timeout.
+    pub(crate) async fn run<T, F, Fut>(&self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(ConnectionManager) -> Fut + Send + 'static,
+        Fut: Future<Output = RedisResult<T>> + Send,
+        T: Send + 'static,
+    {
+        let this = self.clone();
+        // Spawned, as the futures of stores must be `Sync`, which those of `redis` aren't.
+        let task = tokio::spawn(async move {
+            tokio::time::timeout(this.timeout, async {
+                let conn = this.manager.get_or_try_init(|| this.connect()).await?;
+                f(conn.clone()).await
+            })
+            .await
+        });
+        match task.await {
+            Ok(Ok(result)) => result.map_err(io::Error::other),
+            Ok(Err(_)) => Err(io::Error::new(io::ErrorKind::TimedOut, "redis timed out")),
+            Err(err) => Err(io::Error::other(err)),
+        }
+    }
+
+    /// The URL with its credentials hidden, for debug output.
+    pub(crate) fn redacted_url(&self) -> String {
+        match self.url.rsplit_once('@') {
+            Some((credentials, host)) => match credentials.split_once("://") {
+                Some((scheme, _)) => format!("{}://***@{}", scheme, host),
+                None => format!("***@{}", host),
+            },
+            None => self.url.clone(),
+        }
+    }
+
+    async fn connect(&self) -> RedisResult<ConnectionManager> {
+        // Plain addresses such as `127.0.0.1:6379` are accepted too.
+        let mut info = if self.url.contains("://") {
+            self.url.as_str().into_connection_info()?
+        } else {
+            format!("redis://{}", self.url).into_connection_info()?
+        };
+        if let Some(password) = &self.password {
+            info.redis.password = Some(password.clone());
+        }
+        let config = ConnectionManagerConfig::new()
+            .set_connection_timeout(self.timeout)
+            .set_response_timeout(self.timeout);
+        ConnectionManager::new_with_config(Client::open(info)?, config).await
+    }
+}
+
+pub(crate) fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
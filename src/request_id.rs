@@ -0,0 +1,180 @@
+//! Request ids, to correlate logs and error reports with requests.
+//!
+//! With [`RequestIds`] set with [`Router::request_ids`](crate::Router::request_ids), every
+//! request gets a [`RequestId`]: the one in its `X-Request-Id` header, e.g. set by a proxy in
+//! front of the service, or a generated UUID or ULID. It's added to the request extensions and
+//! headers, so [error handlers](crate::Router::error_handler) and the
+//! [trace context](crate::trace) see it too, and to the headers of the response. Handlers get
+//! it with [`RequestExt::request_id`](crate::ext::RequestExt::request_id).
+//!
+//! Incoming ids longer than 128 characters or with characters other than visible ASCII are
+//! replaced with generated ones.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::prelude::*;
+//! use keiro::request_id::{IdFormat, RequestIds};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.request_ids(RequestIds::new().format(IdFormat::Ulid));
+//! router.get("/", index);
+//!
+//! async fn index(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     eprintln!("handling {}", req.request_id().unwrap());
+//!     Ok(Response::new(Body::from("Hello world!")))
+//! }
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Body, Request, Response};
+
+use crate::util::random_u64;
+
+/// The longest incoming id which is kept.
+const MAX_LEN: usize = 128;
+
+/// The id of a request, added to its extensions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId(Arc<str>);
+
+impl RequestId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// The format of generated request ids.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdFormat {
+    /// A random UUID, such as `0b5e7a9c-3f1d-4e2a-9c7b-5d8e1f2a3b4c`.
+    #[default]
+    Uuid,
+    /// A ULID, such as `01HQ3K5Z8XJ4T6V9W2Y7B1C0DE`, which sorts by creation time.
+    Ulid,
+}
+
+/// Where request ids are taken from and how missing ones are generated.
+#[derive(Debug, Clone)]
+pub struct RequestIds {
+    header: HeaderName,
+    format: IdFormat,
+    trust_incoming: bool,
+}
+
+impl Default for RequestIds {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RequestIds {
+    /// Take request ids from `X-Request-Id`, and generate UUIDs for requests without one.
+    pub fn new() -> Self {
+        Self {
+            header: HeaderName::from_static("x-request-id"),
+            format: IdFormat::Uuid,
+            trust_incoming: true,
+        }
+    }
+
+    /// Take request ids from the header `name` and set it on responses.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` isn't a valid header name.
+    pub fn header(mut self, name: &str) -> Self {
+        self.header = name.parse().expect("invalid header name");
+        self
+    }
+
+    /// Generate ids in `format`.
+    pub fn format(mut self, format: IdFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Generate ids for all requests, ignoring the ones sent by clients, e.g. for services
+    /// exposed without a proxy setting them.
+    pub fn always_generate(mut self) -> Self {
+        self.trust_incoming = false;
+        self
+    }
+
+    /// Add the [`RequestId`] of `req` to its extensions and headers, and return it.
+    pub(crate) fn apply(&self, req: &mut Request<Body>) -> RequestId {
+        let incoming = req
+            .headers()
+            .get(&self.header)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| self.trust_incoming && is_valid(id));
+        let id = match incoming {
+            Some(id) => RequestId(id.into()),
+            None => {
+                let id = self.generate();
+                let value = HeaderValue::from_str(&id).expect("generated ids are valid headers");
+                req.headers_mut().insert(self.header.clone(), value);
+                RequestId(id.into())
+            }
+        };
+        req.extensions_mut().insert(id.clone());
+        id
+    }
+
+    /// Set `id` on `res`, unless the handler set the header itself.
+    pub(crate) fn set_header(&self, res: &mut Response<Body>, id: &RequestId) {
+        if !res.headers().contains_key(&self.header) {
+            let value = HeaderValue::from_str(id.as_str()).expect("request ids are valid headers");
+            res.headers_mut().insert(self.header.clone(), value);
+        }
+    }
+
+    fn generate(&self) -> String {
+        let (high, low) = (random_u64(), random_u64());
+        match self.format {
+            IdFormat::Uuid => {
+                // Version 4, variant 1.
+                let high = (high & !0xf000) | 0x4000;
+                let low = (low & !(0b11 << 62)) | (0b10 << 62);
+                format!(
+                    "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+                    high >> 32,
+                    (high >> 16) & 0xffff,
+                    high & 0xffff,
+                    low >> 48,
+                    low & 0xffff_ffff_ffff
+                )
+            }
+            IdFormat::Ulid => {
+                let millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_millis());
+                // 48 bits of time, then 80 random bits.
+                let random = (u128::from(high) << 64 | u128::from(low)) & ((1 << 80) - 1);
+                let value = (millis & ((1 << 48) - 1)) << 80 | random;
+                (0..26)
+                    .rev()
+                    .map(|i| CROCKFORD[((value >> (i * 5)) & 0x1f) as usize] as char)
+                    .collect()
+            }
+        }
+    }
+}
+
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
@@ -0,0 +1,370 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::{Body, Method, Request, Response, StatusCode};
+use tokio::sync::Semaphore;
+
+use crate::api_key::ApiKey;
+use crate::basic_auth::BasicAuth;
+use crate::cache::{CachePolicy, ResponseCache};
+use crate::cache_control::CacheControl;
+use crate::concurrency::{AdaptiveLimit, AdaptiveLimiter};
+use crate::ip_filter::IpFilter;
+#[cfg(feature = "jwt")]
+use crate::jwt::JwtAuth;
+use crate::layout::MapResponse;
+use crate::rate_limit::RateLimit;
+use crate::sampling::Sampler;
+#[cfg(feature = "sessions")]
+use crate::sessions::Sessions;
+use crate::tarpit::Tarpit;
+use crate::Handler;
+
+pub(crate) struct Endpoint<E> {
+    pub(crate) method: Method,
+    pub(crate) pattern: String,
+    pub(crate) name: Option<String>,
+    pub(crate) handler: Box<dyn Handler<E>>,
+    pub(crate) options: RouteOptions,
+    /// The permits for concurrent requests to the endpoint.
+    pub(crate) concurrency: Option<Limiter>,
+    /// Whether requests exceeding the concurrency limit are answered immediately.
+    pub(crate) load_shed: bool,
+    pub(crate) cache: Option<ResponseCache>,
+    pub(crate) cache_policy: Option<CachePolicy>,
+    pub(crate) sampler: Option<Sampler>,
+}
+
+pub(crate) enum Limiter {
+    Fixed(Arc<Semaphore>),
+    Adaptive(Arc<AdaptiveLimiter>),
+}
+
+/// Options which can be set per route, falling back to the defaults of the router.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RouteOptions {
+    pub(crate) max_body_size: Option<usize>,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) timeout_status: Option<StatusCode>,
+    pub(crate) cache_control: Option<CacheControl>,
+    pub(crate) map_response: Option<MapResponse>,
+    pub(crate) ip_filter: Option<Arc<IpFilter>>,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) guard: Option<Guard>,
+    pub(crate) required_scopes: Vec<String>,
+    pub(crate) tarpit: Option<Tarpit>,
+    #[cfg(feature = "sessions")]
+    pub(crate) sessions: Option<Sessions>,
+    #[cfg(feature = "checksum")]
+    pub(crate) require_checksum: Option<bool>,
+    #[cfg(feature = "signed-urls")]
+    pub(crate) require_signature: Option<bool>,
+}
+
+impl RouteOptions {
+    /// Fill the options which are not set with `defaults`.
+    pub(crate) fn or(self, defaults: &RouteOptions) -> Self {
+        Self {
+            max_body_size: self.max_body_size.or(defaults.max_body_size),
+            timeout: self.timeout.or(defaults.timeout),
+            timeout_status: self.timeout_status.or(defaults.timeout_status),
+            cache_control: self
+                .cache_control
+                .or_else(|| defaults.cache_control.clone()),
+            map_response: self.map_response.or_else(|| defaults.map_response.clone()),
+            ip_filter: self.ip_filter.or_else(|| defaults.ip_filter.clone()),
+            rate_limit: self.rate_limit.or_else(|| defaults.rate_limit.clone()),
+            guard: self.guard.or_else(|| defaults.guard.clone()),
+            required_scopes: required_scopes(self.required_scopes, &defaults.required_scopes),
+            tarpit: self.tarpit.or_else(|| defaults.tarpit.clone()),
+            #[cfg(feature = "sessions")]
+            sessions: self.sessions.or_else(|| defaults.sessions.clone()),
+            #[cfg(feature = "checksum")]
+            require_checksum: self.require_checksum.or(defaults.require_checksum),
+            #[cfg(feature = "signed-urls")]
+            require_signature: self.require_signature.or(defaults.require_signature),
+        }
+    }
+}
+
+/// The scopes required by a route, `own`, and by its routers, `inherited`.
+fn required_scopes(mut own: Vec<String>, inherited: &[String]) -> Vec<String> {
+    for scope in inherited {
+        if !own.contains(scope) {
+            own.push(scope.clone());
+        }
+    }
+    own
+}
+
+/// The future of a [`Guard`], giving the request back if it passes the check.
+pub(crate) type GuardFuture =
+    Pin<Box<dyn Future<Output = Result<Request<Body>, Response<Body>>> + Send>>;
+
+/// A check of requests before their handler is called, answering the requests which fail it.
+#[derive(Clone)]
+pub(crate) struct Guard(Arc<dyn Fn(Request<Body>) -> GuardFuture + Send + Sync>);
+
+impl Guard {
+    pub(crate) fn new<F, Fut>(check: F) -> Self
+    where
+        F: Fn(Request<Body>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Request<Body>, Response<Body>>> + Send + 'static,
+    {
+        Self(Arc::new(move |req| Box::pin(check(req))))
+    }
+
+    pub(crate) fn check(&self, req: Request<Body>) -> GuardFuture {
+        (self.0)(req)
+    }
+}
+
+impl From<BasicAuth> for Guard {
+    fn from(auth: BasicAuth) -> Self {
+        Guard::new(move |mut req| {
+            let rejection = auth.check(&mut req);
+            async move {
+                match rejection {
+                    Some(res) => Err(res),
+                    None => Ok(req),
+                }
+            }
+        })
+    }
+}
+
+impl From<ApiKey> for Guard {
+    fn from(auth: ApiKey) -> Self {
+        Self(Arc::new(move |req| auth.check(req)))
+    }
+}
+
+#[cfg(feature = "jwt")]
+impl From<JwtAuth> for Guard {
+    fn from(auth: JwtAuth) -> Self {
+        let verifier = auth.verifier();
+        Guard::new(move |req| verifier.clone().check(req))
+    }
+}
+
+impl std::fmt::Debug for Guard {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("Guard")
+    }
+}
+
+/// A registered route, returned by the methods registering handlers such as
+/// [`Router::get`](crate::Router::get).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::convert::Infallible;
+/// # use std::time::Duration;
+/// # use hyper::{Body, Request, Response, StatusCode};
+/// use keiro::Router;
+///
+/// let mut router = Router::new();
+/// router.max_body_size(16 * 1024);
+/// router
+///     .post("/reports", upload)
+///     .concurrency_limit(4)
+///     .load_shed();
+/// router
+///     .post("/upload", upload)
+///     .max_body_size(64 * 1024 * 1024)
+///     .timeout(Duration::from_secs(60))
+///     .timeout_status(StatusCode::GATEWAY_TIMEOUT);
+///
+/// async fn upload(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+///     Ok(Response::new(Body::empty()))
+/// }
+/// ```
+pub struct Route<'a, E> {
+    endpoint: &'a mut Endpoint<E>,
+}
+
+impl<'a, E> Route<'a, E> {
+    pub(crate) fn new(endpoint: &'a mut Endpoint<E>) -> Self {
+        Self { endpoint }
+    }
+
+    /// Name the route, so its URL can be built with [`Router::url_for`](crate::Router::url_for).
+    pub fn name(self, name: impl Into<String>) -> Self {
+        self.endpoint.name = Some(name.into());
+        self
+    }
+
+    /// Set the maximum request body size in bytes for this route, overriding
+    /// [`Router::max_body_size`](crate::Router::max_body_size).
+    pub fn max_body_size(self, limit: usize) -> Self {
+        self.endpoint.options.max_body_size = Some(limit);
+        self
+    }
+
+    /// Cut off the handler if it doesn't respond within `timeout`, overriding
+    /// [`Router::timeout`](crate::Router::timeout).
+    /// The request is answered with `503 Service Unavailable` unless another status is set
+    /// with [`Route::timeout_status`].
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.endpoint.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the status of the response sent when the handler times out.
+    pub fn timeout_status(self, status: StatusCode) -> Self {
+        self.endpoint.options.timeout_status = Some(status);
+        self
+    }
+
+    /// Add `cache_control` to the responses of this route which are not errors and don't set
+    /// `Cache-Control` themselves, overriding
+    /// [`Router::cache_control`](crate::Router::cache_control).
+    pub fn cache(self, cache_control: CacheControl) -> Self {
+        self.endpoint.options.cache_control = Some(cache_control);
+        self
+    }
+
+    /// Answer requests to this route from `cache` while their response is fresh. See
+    /// [`cache`](crate::cache) for which requests and responses are cached.
+    pub fn response_cache(self, cache: ResponseCache) -> Self {
+        self.endpoint.cache = Some(cache);
+        self
+    }
+
+    /// Follow `policy` in the response cache of this route instead of the policy of the cache.
+    pub fn response_cache_policy(self, policy: CachePolicy) -> Self {
+        self.endpoint.cache_policy = Some(policy);
+        self
+    }
+
+    /// Capture the requests to this route picked by `sampler`. See
+    /// [`sampling`](crate::sampling).
+    pub fn sample(self, sampler: &Sampler) -> Self {
+        self.endpoint.sampler = Some(sampler.clone());
+        self
+    }
+
+    /// Require a `Content-MD5` or `Digest` header and verify the body against it while it is
+    /// read. Requests without a supported checksum are answered with `400 Bad Request`, and
+    /// reading a body which doesn't match fails at its end with
+    /// [`BodyError::Checksum`](crate::body::BodyError::Checksum).
+    ///
+    /// MD5, SHA-256 and SHA-512 are supported. Requires the `checksum` feature.
+    #[cfg(feature = "checksum")]
+    pub fn require_checksum(self) -> Self {
+        self.endpoint.options.require_checksum = Some(true);
+        self
+    }
+
+    /// Require the Basic credentials accepted by `auth`, overriding
+    /// [`Router::basic_auth`](crate::Router::basic_auth). See
+    /// [`basic_auth`](crate::basic_auth).
+    pub fn basic_auth(self, auth: BasicAuth) -> Self {
+        self.endpoint.options.guard = Some(auth.into());
+        self
+    }
+
+    /// Answer requests from the clients denied by `filter` with `403 Forbidden`, overriding
+    /// [`Router::ip_filter`](crate::Router::ip_filter). See [`ip_filter`](crate::ip_filter).
+    pub fn ip_filter(self, filter: IpFilter) -> Self {
+        self.endpoint.options.ip_filter = Some(Arc::new(filter));
+        self
+    }
+
+    /// Limit the rate of requests to this route with `limit`, overriding
+    /// [`Router::rate_limit`](crate::Router::rate_limit). See [`rate_limit`](crate::rate_limit).
+    pub fn rate_limit(self, limit: RateLimit) -> Self {
+        self.endpoint.options.rate_limit = Some(limit);
+        self
+    }
+
+    /// Load and save the sessions of requests to this route with `sessions`, overriding
+    /// [`Router::sessions`](crate::Router::sessions). See [`sessions`](crate::sessions).
+    ///
+    /// Requires the `sessions` feature.
+    #[cfg(feature = "sessions")]
+    pub fn sessions(self, sessions: Sessions) -> Self {
+        self.endpoint.options.sessions = Some(sessions);
+        self
+    }
+
+    /// Require the authenticated principal to have `scope`, besides the scopes required with
+    /// [`Router::require_scope`](crate::Router::require_scope). See [`authz`](crate::authz).
+    pub fn require_scope(self, scope: impl Into<String>) -> Self {
+        self.endpoint.options.required_scopes.push(scope.into());
+        self
+    }
+
+    /// Answer requests rejected by the authentication or the required scopes of this route
+    /// slowly with `tarpit`, overriding [`Router::tarpit`](crate::Router::tarpit). See
+    /// [`tarpit`](crate::tarpit).
+    pub fn tarpit(self, tarpit: Tarpit) -> Self {
+        self.endpoint.options.tarpit = Some(tarpit);
+        self
+    }
+
+    /// Require an API key accepted by `auth`, overriding
+    /// [`Router::api_key`](crate::Router::api_key). See [`api_key`](crate::api_key).
+    pub fn api_key(self, auth: ApiKey) -> Self {
+        self.endpoint.options.guard = Some(auth.into());
+        self
+    }
+
+    /// Require a bearer token accepted by `auth`, overriding [`Router::jwt`](crate::Router::jwt)
+    /// and [`Router::basic_auth`](crate::Router::basic_auth). See [`jwt`](crate::jwt).
+    ///
+    /// Requires the `jwt` feature.
+    #[cfg(feature = "jwt")]
+    pub fn jwt(self, auth: JwtAuth) -> Self {
+        self.endpoint.options.guard = Some(auth.into());
+        self
+    }
+
+    /// Only accept requests to URLs minted with
+    /// [`Router::signed_url_for`](crate::Router::signed_url_for) which haven't expired. Other
+    /// requests are answered with `403 Forbidden`.
+    ///
+    /// Requires the `signed-urls` feature.
+    #[cfg(feature = "signed-urls")]
+    pub fn require_signature(self) -> Self {
+        self.endpoint.options.require_signature = Some(true);
+        self
+    }
+
+    /// Handle at most `limit` requests to this route at the same time. Excess requests wait
+    /// for a slot, which counts towards the timeout of the route, unless
+    /// [`Route::load_shed`] is set.
+    pub fn concurrency_limit(self, limit: usize) -> Self {
+        self.limit_concurrency(Limiter::Fixed(Arc::new(Semaphore::new(limit))))
+    }
+
+    /// Limit the requests to this route handled at the same time to a limit adapting to
+    /// their latency, replacing [`Route::concurrency_limit`]. See
+    /// [`concurrency`](crate::concurrency).
+    pub fn adaptive_concurrency(self, limit: AdaptiveLimit) -> Self {
+        self.limit_concurrency(Limiter::Adaptive(AdaptiveLimiter::new(limit)))
+    }
+
+    fn limit_concurrency(self, limiter: Limiter) -> Self {
+        self.endpoint.concurrency = Some(limiter);
+        self
+    }
+
+    /// Answer requests exceeding the concurrency limit, static or adaptive, immediately with
+    /// `503 Service Unavailable` instead of queueing them. It can be set before or after the
+    /// limit.
+    pub fn load_shed(self) -> Self {
+        self.endpoint.load_shed = true;
+        self
+    }
+}
+
+/// A request prepared for the endpoint it matched.
+pub(crate) struct Matched<E> {
+    pub(crate) req: Request<Body>,
+    pub(crate) endpoint: Arc<Endpoint<E>>,
+    pub(crate) options: RouteOptions,
+}
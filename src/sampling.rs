@@ -0,0 +1,484 @@
+//! Capturing sampled requests and responses for debugging.
+//!
+//! A [`Sampler`] set on a route with [`Route::sample`](crate::Route::sample) captures a share
+//! of its requests with their responses, headers and bodies included, and hands them to a
+//! [`SampleSink`], such as a directory of files written by [`FileSink`]. Captures are bounded:
+//! bodies are cut off after [`Sampler::max_body_size`] bytes, and sampling stops after
+//! [`Sampler::limit`] samples. Credentials are redacted from the captured headers.
+//!
+//! Bodies are captured while they stream through, so the handler and client see them
+//! unchanged, and a sample is recorded once both the request and the response body are
+//! finished or dropped.
+//!
+//! Sampling starts out disabled and is switched at runtime, either with
+//! [`Sampler::set_rate`] or through the endpoints of [`Sampler::router`]:
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::basic_auth::BasicAuth;
+//! use keiro::sampling::{FileSink, Sampler};
+//! use keiro::Router;
+//!
+//! let sampler = Sampler::new(FileSink::new("/var/tmp/samples"))
+//!     .max_body_size(16 * 1024)
+//!     .redact_header("x-api-key");
+//!
+//! let mut admin = Router::new();
+//! admin.basic_auth(BasicAuth::users("admin", [("ops", "secret")]));
+//! admin.nest("/sampling/checkout", sampler.router());
+//!
+//! let mut router = Router::new();
+//! router.post("/checkout", checkout).sample(&sampler);
+//! router.nest("/admin", admin);
+//!
+//! // `PUT /admin/sampling/checkout?rate=0.01` captures 1% of the checkouts,
+//! // `PUT /admin/sampling/checkout?rate=0` stops again.
+//!
+//! async fn checkout(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use futures_util::StreamExt;
+use hyper::body::Bytes;
+use hyper::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_TYPE, COOKIE, PROXY_AUTHORIZATION,
+    SET_COOKIE,
+};
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
+
+use crate::{util, HandlerFuture, Router};
+
+/// The sampling rate is kept in parts per million, so it can be switched atomically.
+const PPM: f64 = 1_000_000.0;
+
+/// A captured request with its response.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct Sample {
+    /// The pattern of the sampled route.
+    pub route: String,
+    pub method: Method,
+    pub uri: Uri,
+    pub request_headers: HeaderMap,
+    /// The request body, as far as it was read by the handler and within the size limit.
+    pub request_body: Bytes,
+    pub request_truncated: bool,
+    /// The response status, or `None` if the handler failed.
+    pub status: Option<StatusCode>,
+    pub response_headers: HeaderMap,
+    pub response_body: Bytes,
+    pub response_truncated: bool,
+    /// When the request was received.
+    pub received: SystemTime,
+    /// The time until the response body was finished or dropped.
+    pub duration: Duration,
+}
+
+impl fmt::Display for Sample {
+    /// Format the sample as the HTTP/1.1 messages, with bodies which are not UTF-8 replaced by
+    /// their size.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {} HTTP/1.1", self.method, self.uri)?;
+        write_message(
+            f,
+            &self.request_headers,
+            &self.request_body,
+            self.request_truncated,
+        )?;
+        match self.status {
+            Some(status) => writeln!(f, "HTTP/1.1 {}", status)?,
+            None => writeln!(f, "(the handler failed)")?,
+        }
+        write_message(
+            f,
+            &self.response_headers,
+            &self.response_body,
+            self.response_truncated,
+        )
+    }
+}
+
+fn write_message(
+    f: &mut fmt::Formatter,
+    headers: &HeaderMap,
+    body: &Bytes,
+    truncated: bool,
+) -> fmt::Result {
+    for (name, value) in headers {
+        writeln!(f, "{}: {}", name, String::from_utf8_lossy(value.as_bytes()))?;
+    }
+    writeln!(f)?;
+    match std::str::from_utf8(body) {
+        Ok(body) => write!(f, "{}", body)?,
+        Err(_) => write!(f, "({} bytes of binary data)", body.len())?,
+    }
+    if truncated {
+        write!(f, "\n(truncated)")?;
+    }
+    writeln!(f)
+}
+
+/// A destination of captured samples.
+pub trait SampleSink: Send + Sync + 'static {
+    /// Store `sample`. Called from the task serving the request, so slow sinks should hand the
+    /// sample off instead of blocking.
+    fn record(&self, sample: Sample);
+}
+
+impl<F> SampleSink for F
+where
+    F: Fn(Sample) + Send + Sync + 'static,
+{
+    fn record(&self, sample: Sample) {
+        self(sample)
+    }
+}
+
+/// A [`SampleSink`] writing every sample to a file of its own in a directory, formatted as
+/// the HTTP messages.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    dir: PathBuf,
+    count: Arc<AtomicU64>,
+}
+
+impl FileSink {
+    /// Write samples to `dir`, which is created if it doesn't exist.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            count: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn write(dir: PathBuf, name: String, sample: Sample) -> io::Result<()> {
+        std::fs::create_dir_all(&dir)?;
+        let mut file = std::fs::File::create(dir.join(name))?;
+        write!(file, "{}", sample)
+    }
+}
+
+impl SampleSink for FileSink {
+    fn record(&self, sample: Sample) {
+        let millis = sample
+            .received
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let name = format!(
+            "{}-{}.http",
+            millis,
+            self.count.fetch_add(1, Ordering::Relaxed)
+        );
+        let dir = self.dir.clone();
+        // Samples are best effort, so failing writes are dropped.
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => {
+                runtime.spawn_blocking(move || Self::write(dir, name, sample));
+            }
+            Err(_) => {
+                let _ = Self::write(dir, name, sample);
+            }
+        }
+    }
+}
+
+/// Captures a share of the requests to the routes it's set on. Clones share the rate and the
+/// count of samples.
+#[derive(Clone)]
+pub struct Sampler {
+    sink: Arc<dyn SampleSink>,
+    state: Arc<State>,
+    max_body_size: usize,
+    limit: usize,
+    redacted: Arc<Vec<HeaderName>>,
+}
+
+struct State {
+    rate: AtomicU32,
+    seen: AtomicU64,
+    sampled: AtomicUsize,
+}
+
+impl Sampler {
+    /// Send samples to `sink`. Sampling is disabled until a rate is set.
+    ///
+    /// Bodies are captured up to 64 KiB, and at most 1000 samples are taken. The
+    /// `Authorization`, `Proxy-Authorization`, `Cookie` and `Set-Cookie` headers are redacted.
+    pub fn new(sink: impl SampleSink) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            state: Arc::new(State {
+                rate: AtomicU32::new(0),
+                seen: AtomicU64::new(0),
+                sampled: AtomicUsize::new(0),
+            }),
+            max_body_size: 64 * 1024,
+            limit: 1000,
+            redacted: Arc::new(vec![AUTHORIZATION, PROXY_AUTHORIZATION, COOKIE, SET_COOKIE]),
+        }
+    }
+
+    /// Capture at most `size` bytes of each body.
+    pub fn max_body_size(mut self, size: usize) -> Self {
+        self.max_body_size = size;
+        self
+    }
+
+    /// Stop sampling after `limit` samples. Setting the rate again resets the count.
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Redact the header `name` in captured requests and responses as well.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid header name.
+    pub fn redact_header(mut self, name: &str) -> Self {
+        let name = HeaderName::from_bytes(name.as_bytes()).expect("invalid header name");
+        Arc::make_mut(&mut self.redacted).push(name);
+        self
+    }
+
+    /// Capture the share `rate` of requests, from `0.0` for none to `1.0` for all, and reset
+    /// the count of samples towards the limit.
+    pub fn set_rate(&self, rate: f64) {
+        let rate = (rate.clamp(0.0, 1.0) * PPM).round() as u32;
+        self.state.sampled.store(0, Ordering::Relaxed);
+        self.state.rate.store(rate, Ordering::Relaxed);
+    }
+
+    pub fn rate(&self) -> f64 {
+        f64::from(self.state.rate.load(Ordering::Relaxed)) / PPM
+    }
+
+    /// Get the number of samples taken since the rate was last set.
+    pub fn sampled(&self) -> usize {
+        self.state.sampled.load(Ordering::Relaxed)
+    }
+
+    /// Create a router to control sampling through, answering `GET /` with the rate and the
+    /// number of samples taken, and switching the rate on `PUT /?rate=0.05`.
+    ///
+    /// The router doesn't restrict access, so protect it, e.g. with
+    /// [`Router::basic_auth`](crate::Router::basic_auth).
+    pub fn router<E>(&self) -> Router<E, ()>
+    where
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        let mut router: Router<E, ()> = Router::with_error_type(());
+        let sampler = self.clone();
+        router.get("/", move |_req| {
+            let res = sampler.status();
+            async { Ok::<_, E>(res) }
+        });
+        let sampler = self.clone();
+        router.put("/", move |req: Request<Body>| {
+            let rate = req
+                .uri()
+                .query()
+                .unwrap_or_default()
+                .split('&')
+                .find_map(|pair| {
+                    let value = pair.strip_prefix("rate=")?;
+                    value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|rate| (0.0..=1.0).contains(rate))
+                });
+            let res = match rate {
+                Some(rate) => {
+                    sampler.set_rate(rate);
+                    sampler.status()
+                }
+                None => Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body(Body::from("expected a rate between 0 and 1"))
+                    .unwrap(),
+            };
+            async { Ok::<_, E>(res) }
+        });
+        router
+    }
+
+    fn status(&self) -> Response<Body> {
+        let mut res = Response::new(Body::from(format!(
+            "rate={}\nsampled={}\nlimit={}\n",
+            self.rate(),
+            self.sampled(),
+            self.limit
+        )));
+        res.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; charset=utf-8"),
+        );
+        res
+    }
+
+    /// Decide whether to sample the next request. Requests are sampled at even intervals
+    /// rather than randomly, so a rate of `0.1` captures every tenth request.
+    fn should_sample(&self) -> bool {
+        let rate = u64::from(self.state.rate.load(Ordering::Relaxed));
+        if rate == 0 || self.state.sampled.load(Ordering::Relaxed) >= self.limit {
+            return false;
+        }
+        let seen = self.state.seen.fetch_add(1, Ordering::Relaxed);
+        let ppm = PPM as u64;
+        if (seen + 1) * rate / ppm == seen * rate / ppm {
+            return false;
+        }
+        self.state.sampled.fetch_add(1, Ordering::Relaxed) < self.limit
+    }
+
+    /// Call `call` with `req`, capturing both if the request is sampled.
+    pub(crate) fn capture<E: 'static>(
+        &self,
+        mut req: Request<Body>,
+        route: Option<&str>,
+        call: impl FnOnce(Request<Body>) -> HandlerFuture<E>,
+    ) -> HandlerFuture<E> {
+        if !self.should_sample() {
+            return call(req);
+        }
+        let capture = Arc::new(Capture {
+            sink: self.sink.clone(),
+            started: Instant::now(),
+            sample: Mutex::new(Sample {
+                route: route.unwrap_or_default().to_string(),
+                method: req.method().clone(),
+                uri: req.uri().clone(),
+                request_headers: self.redact(req.headers()),
+                request_body: Bytes::new(),
+                request_truncated: false,
+                status: None,
+                response_headers: HeaderMap::new(),
+                response_body: Bytes::new(),
+                response_truncated: false,
+                received: SystemTime::now(),
+                duration: Duration::default(),
+            }),
+        });
+        *req.body_mut() = self.tee(
+            std::mem::take(req.body_mut()),
+            capture.clone(),
+            Side::Request,
+        );
+        let fut = call(req);
+        let sampler = self.clone();
+        Box::pin(async move {
+            let mut res = fut.await?;
+            if let Ok(mut sample) = capture.sample.lock() {
+                sample.status = Some(res.status());
+                sample.response_headers = sampler.redact(res.headers());
+            }
+            *res.body_mut() = sampler.tee(std::mem::take(res.body_mut()), capture, Side::Response);
+            Ok(res)
+        })
+    }
+
+    fn redact(&self, headers: &HeaderMap) -> HeaderMap {
+        let mut headers = headers.clone();
+        for name in self.redacted.iter() {
+            if headers.contains_key(name) {
+                headers.insert(name, HeaderValue::from_static("[redacted]"));
+            }
+        }
+        headers
+    }
+
+    /// Copy up to the maximum body size of `body` into the `side` of `capture` while it's read.
+    fn tee(&self, body: Body, capture: Arc<Capture>, side: Side) -> Body {
+        let mut part = Part {
+            capture,
+            side,
+            body: Vec::new(),
+            truncated: false,
+        };
+        let limit = self.max_body_size;
+        Body::wrap_stream(body.map(move |chunk| {
+            let chunk = chunk.map_err(util::into_cause)?;
+            let room = limit - part.body.len();
+            if chunk.len() > room {
+                part.truncated = true;
+            }
+            part.body.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            Ok::<_, Box<dyn Error + Send + Sync>>(chunk)
+        }))
+    }
+}
+
+impl fmt::Debug for Sampler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sampler")
+            .field("rate", &self.rate())
+            .field("sampled", &self.sampled())
+            .field("max_body_size", &self.max_body_size)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+/// A sample in progress, recorded once both bodies are done with.
+struct Capture {
+    sink: Arc<dyn SampleSink>,
+    started: Instant,
+    sample: Mutex<Sample>,
+}
+
+impl Drop for Capture {
+    fn drop(&mut self) {
+        let mut sample = match self.sample.lock() {
+            Ok(sample) => sample.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        };
+        sample.duration = self.started.elapsed();
+        self.sink.record(sample);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Side {
+    Request,
+    Response,
+}
+
+/// The captured part of a body, stored into the sample when the body is dropped.
+struct Part {
+    capture: Arc<Capture>,
+    side: Side,
+    body: Vec<u8>,
+    truncated: bool,
+}
+
+impl Drop for Part {
+    fn drop(&mut self) {
+        let body = Bytes::from(std::mem::take(&mut self.body));
+        let mut sample = match self.capture.sample.lock() {
+            Ok(sample) => sample,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match self.side {
+            Side::Request => {
+                sample.request_body = body;
+                sample.request_truncated = self.truncated;
+            }
+            Side::Response => {
+                sample.response_body = body;
+                sample.response_truncated = self.truncated;
+            }
+        }
+    }
+}
@@ -0,0 +1,484 @@
+//! Serving routers.
+//!
+//! [`serve`] binds a router to an address and serves it in the background, returning a
+//! [`ServerHandle`] with the address it's bound to, e.g. to learn the port picked for port 0,
+//! and to shut it down gracefully:
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::Router;
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let mut router = Router::new();
+//!     router.get("/", index);
+//!
+//!     let server = keiro::serve(([127, 0, 0, 1], 0), router).await?;
+//!     println!("listening on {}", server.local_addr());
+//!
+//!     tokio::signal::ctrl_c().await?;
+//!     server.shutdown();
+//!     server.wait().await
+//! }
+//!
+//! async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("Hello keiro!")))
+//! }
+//! ```
+//!
+//! A [`ServerBuilder`] configures the server further, e.g. to listen on several addresses,
+//! limit the number of connections or time out slow clients:
+//!
+//! ```rust,no_run
+//! # use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! # use hyper::{Body, Request, Response};
+//! use keiro::server::ServerBuilder;
+//! use keiro::Router;
+//!
+//! #[tokio::main]
+//! async fn main() -> std::io::Result<()> {
+//!     let mut router = Router::new();
+//!     router.get("/", index);
+//!
+//!     ServerBuilder::new()
+//!         .bind(([0, 0, 0, 0], 8080))
+//!         .bind(([0, 0, 0, 0, 0, 0, 0, 0], 8080))
+//!         .header_read_timeout(Duration::from_secs(10))
+//!         .max_connections(10_000)
+//!         .shutdown_signal(async {
+//!             tokio::signal::ctrl_c().await.unwrap();
+//!         })
+//!         .serve(router)
+//!         .await?
+//!         .wait()
+//!         .await
+//! }
+//! # async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//! #     Ok(Response::new(Body::from("Hello keiro!")))
+//! # }
+//! ```
+//!
+//! With the `tls` feature, the server terminates TLS with a [`ServerTls`], e.g. with
+//! [`serve_tls`] or [`ServerBuilder::tls`]. Clients negotiate HTTP/2 or HTTP/1.1 with ALPN. An
+//! [`SniResolver`] serves the certificates of several domains on the same addresses, and
+//! [`ServerTls::require_client_cert`] authenticates clients by their certificates, which
+//! handlers get as [`PeerCertificates`](crate::connect::PeerCertificates).
+//!
+//! Shutting down works as with [`Router::serve_with_shutdown`]: the server stops accepting
+//! connections, fails the readiness endpoints of the router, and drains the requests in
+//! flight up to the [drain timeout](Router::drain_timeout).
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::future::{self, Either};
+use hyper::server::conn::Http;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{watch, Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+
+use crate::connect::ConnectedService;
+use crate::warmup::Readiness;
+use crate::{Router, RouterService};
+
+#[cfg(feature = "tls")]
+mod tls;
+
+#[cfg(feature = "tls")]
+pub use self::tls::{ServerTls, SniResolver};
+
+/// How long clients have to complete the TLS handshake.
+#[cfg(feature = "tls")]
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bind `router` to `addr` and serve it in the background until it's
+/// [shut down](ServerHandle::shutdown).
+///
+/// Fails if `addr` can't be bound, e.g. as it's in use.
+pub async fn serve<E, State>(
+    addr: impl Into<SocketAddr>,
+    router: Router<E, State>,
+) -> io::Result<ServerHandle>
+where
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    State: Clone + Send + Sync + 'static,
+{
+    ServerBuilder::new().bind(addr).serve(router).await
+}
+
+/// Bind `router` to `addr` and serve it over HTTPS with `tls` in the background, as with
+/// [`serve`].
+///
+/// Requires the `tls` feature.
+#[cfg(feature = "tls")]
+pub async fn serve_tls<E, State>(
+    addr: impl Into<SocketAddr>,
+    router: Router<E, State>,
+    tls: ServerTls,
+) -> io::Result<ServerHandle>
+where
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    State: Clone + Send + Sync + 'static,
+{
+    ServerBuilder::new().bind(addr).tls(tls).serve(router).await
+}
+
+/// The configuration of a server.
+pub struct ServerBuilder {
+    addrs: Vec<SocketAddr>,
+    http: Http,
+    versions: Versions,
+    #[cfg(feature = "tls")]
+    tls: Option<ServerTls>,
+    max_connections: Option<usize>,
+    signal: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerBuilder {
+    /// Create a server serving HTTP/1 and HTTP/2 on no addresses yet.
+    pub fn new() -> Self {
+        Self {
+            addrs: Vec::new(),
+            http: Http::new(),
+            versions: Versions::Both,
+            #[cfg(feature = "tls")]
+            tls: None,
+            max_connections: None,
+            signal: None,
+        }
+    }
+
+    /// Listen on `addr`, in addition to the addresses already bound.
+    pub fn bind(mut self, addr: impl Into<SocketAddr>) -> Self {
+        self.addrs.push(addr.into());
+        self
+    }
+
+    /// Only serve HTTP/1.
+    pub fn http1_only(mut self) -> Self {
+        self.http.http1_only(true);
+        self.versions = Versions::Http1;
+        self
+    }
+
+    /// Only serve HTTP/2, e.g. behind a proxy speaking HTTP/2 with prior knowledge.
+    pub fn http2_only(mut self) -> Self {
+        self.http.http2_only(true);
+        self.versions = Versions::Http2;
+        self
+    }
+
+    /// Terminate TLS with `tls`, serving HTTPS on all addresses.
+    ///
+    /// Requires the `tls` feature.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, tls: ServerTls) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Set whether HTTP/1 connections are kept open for further requests, which they are by
+    /// default.
+    pub fn keep_alive(mut self, keep_alive: bool) -> Self {
+        self.http.http1_keep_alive(keep_alive);
+        self
+    }
+
+    /// Send HTTP/2 pings every `interval`, closing connections which don't answer them, e.g.
+    /// to notice clients gone without closing their connections.
+    pub fn http2_keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.http.http2_keep_alive_interval(interval);
+        self
+    }
+
+    /// Close HTTP/1 connections whose request headers aren't received within `timeout`, so
+    /// slow clients can't hold connections open.
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.http.http1_header_read_timeout(timeout);
+        self
+    }
+
+    /// Serve at most `max` connections at once. Further connections wait to be accepted until
+    /// others are closed.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// Shut down gracefully once `signal` completes, as with [`ServerHandle::shutdown`].
+    pub fn shutdown_signal(mut self, signal: impl Future<Output = ()> + Send + 'static) -> Self {
+        self.signal = Some(Box::pin(signal));
+        self
+    }
+
+    /// Bind the addresses and serve `router` on them in the background.
+    ///
+    /// Fails if an address can't be bound, e.g. as it's in use.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no address was bound.
+    pub async fn serve<E, State>(self, router: Router<E, State>) -> io::Result<ServerHandle>
+    where
+        E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+        State: Clone + Send + Sync + 'static,
+    {
+        assert!(!self.addrs.is_empty(), "no address to serve on");
+        let mut listeners = Vec::with_capacity(self.addrs.len());
+        for addr in &self.addrs {
+            listeners.push(TcpListener::bind(addr).await?);
+        }
+        let local_addrs = listeners
+            .iter()
+            .map(TcpListener::local_addr)
+            .collect::<io::Result<_>>()?;
+
+        let shutdown = Arc::new(Notify::new());
+        let signal = {
+            let shutdown = shutdown.clone();
+            let signal = self.signal.unwrap_or_else(|| Box::pin(future::pending()));
+            async move {
+                future::select(Box::pin(shutdown.notified()), signal).await;
+            }
+        };
+        #[cfg(feature = "tls")]
+        let tls = {
+            let versions = self.versions;
+            self.tls
+                .map(|tls| tokio_rustls::TlsAcceptor::from(tls.config(versions.alpn())))
+        };
+        let server = Server {
+            http: self.http,
+            #[cfg(feature = "tls")]
+            tls,
+            limit: self
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max))),
+            drain_timeout: router.drain_timeout,
+            accepting: router.accepting.clone(),
+            service: RouterService::new(router),
+        };
+        Ok(ServerHandle {
+            local_addrs,
+            shutdown,
+            task: tokio::spawn(server.run(listeners, signal)),
+        })
+    }
+}
+
+impl fmt::Debug for ServerBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ServerBuilder")
+            .field("addrs", &self.addrs)
+            .field("http", &self.http)
+            .field("versions", &self.versions)
+            .field("max_connections", &self.max_connections)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The HTTP versions a server serves.
+#[derive(Debug, Clone, Copy)]
+enum Versions {
+    Both,
+    Http1,
+    Http2,
+}
+
+impl Versions {
+    /// Get the ALPN protocol ids of the versions, in order of preference.
+    #[cfg(feature = "tls")]
+    fn alpn(self) -> &'static [&'static [u8]] {
+        match self {
+            Versions::Both => &[b"h2", b"http/1.1"],
+            Versions::Http1 => &[b"http/1.1"],
+            Versions::Http2 => &[b"h2"],
+        }
+    }
+}
+
+/// A server started with [`serve`] or [`ServerBuilder::serve`].
+///
+/// Dropping the handle leaves the server running.
+#[derive(Debug)]
+pub struct ServerHandle {
+    local_addrs: Vec<SocketAddr>,
+    shutdown: Arc<Notify>,
+    task: JoinHandle<io::Result<()>>,
+}
+
+impl ServerHandle {
+    /// Get the address the server is bound to, or the first one if it's bound to several.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addrs[0]
+    }
+
+    /// Get the addresses the server is bound to, in the order they were added.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    /// Start shutting the server down gracefully. Use [`wait`](ServerHandle::wait) to wait
+    /// until it's done.
+    pub fn shutdown(&self) {
+        self.shutdown.notify_one();
+    }
+
+    /// Wait until the server stops, and return the error it stopped with, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server panicked.
+    pub async fn wait(self) -> io::Result<()> {
+        match self.task.await {
+            Ok(result) => result,
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+}
+
+struct Server<E, State> {
+    http: Http,
+    #[cfg(feature = "tls")]
+    tls: Option<tokio_rustls::TlsAcceptor>,
+    limit: Option<Arc<Semaphore>>,
+    drain_timeout: Duration,
+    accepting: Readiness,
+    service: RouterService<E, State>,
+}
+
+impl<E, State> Server<E, State>
+where
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    State: Clone + Send + Sync + 'static,
+{
+    /// Accept connections from `listeners` until `signal` completes, then drain them.
+    async fn run(
+        self,
+        listeners: Vec<TcpListener>,
+        signal: impl Future<Output = ()> + Send + 'static,
+    ) -> io::Result<()> {
+        // Connections hold a receiver and watch it for the start of the drain. Dropping the
+        // sender closes them.
+        let (draining, watch) = watch::channel(false);
+        let server = Arc::new(self);
+        // Accepting never stops on its own, and dropping it closes the listeners.
+        let accepting = future::join_all(
+            listeners
+                .into_iter()
+                .map(|listener| server.clone().accept(listener, watch.clone())),
+        );
+        drop(watch);
+        future::select(Box::pin(accepting), Box::pin(signal)).await;
+
+        server.accepting.set_not_ready();
+        let _ = draining.send(true);
+        let _ = tokio::time::timeout(server.drain_timeout, draining.closed()).await;
+        Ok(())
+    }
+
+    async fn accept(self: Arc<Self>, listener: TcpListener, watch: watch::Receiver<bool>) {
+        loop {
+            let permit = match &self.limit {
+                Some(limit) => limit.clone().acquire_owned().await.ok(),
+                None => None,
+            };
+            let accepted = listener.accept().await;
+            match accepted {
+                Ok((stream, remote_addr)) => {
+                    let server = self.clone();
+                    let watch = watch.clone();
+                    tokio::spawn(server.connect(stream, remote_addr, permit, watch));
+                }
+                // Connections closed by the client before they were accepted don't affect
+                // others.
+                Err(err) if is_connection_error(&err) => {}
+                // E.g. too many open files, which may go away once other connections close.
+                Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        }
+    }
+
+    async fn connect(
+        self: Arc<Self>,
+        stream: TcpStream,
+        remote_addr: SocketAddr,
+        permit: Option<OwnedSemaphorePermit>,
+        watch: watch::Receiver<bool>,
+    ) {
+        let service = ConnectedService::new(self.service.clone(), Some(remote_addr));
+        #[cfg(feature = "tls")]
+        if let Some(acceptor) = &self.tls {
+            let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream));
+            // Clients failing the handshake, e.g. as they don't trust the certificate, are
+            // simply disconnected.
+            if let Ok(Ok(stream)) = handshake.await {
+                let session = stream.get_ref().1;
+                // ACME servers only check the certificate of challenge handshakes.
+                #[cfg(feature = "acme")]
+                if session.alpn_protocol() == Some(crate::acme::ACME_TLS_ALPN) {
+                    return;
+                }
+                let peer_certificates = session
+                    .peer_certificates()
+                    .and_then(crate::connect::PeerCertificates::new);
+                let service = service.peer_certificates(peer_certificates);
+                self.serve_connection(stream, service, permit, watch).await;
+            }
+            return;
+        }
+        self.serve_connection(stream, service, permit, watch).await;
+    }
+
+    async fn serve_connection<I>(
+        self: Arc<Self>,
+        io: I,
+        service: ConnectedService<RouterService<E, State>>,
+        _permit: Option<OwnedSemaphorePermit>,
+        mut watch: watch::Receiver<bool>,
+    ) where
+        I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let mut conn = Box::pin(self.http.serve_connection(io, service).with_upgrades());
+        if *watch.borrow() {
+            conn.as_mut().graceful_shutdown();
+        }
+        loop {
+            let draining = match future::select(conn.as_mut(), Box::pin(watch.changed())).await {
+                Either::Left(_) => return,
+                Either::Right((changed, _)) => changed.is_ok(),
+            };
+            if !draining {
+                // The drain timed out.
+                return;
+            }
+            conn.as_mut().graceful_shutdown();
+        }
+    }
+}
+
+fn is_connection_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    )
+}
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient, ClientCertVerifier,
+    ClientHello, ResolvesServerCert,
+};
+use rustls::sign::CertifiedKey;
+use rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use rustls_pemfile::Item;
+
+/// TLS settings for serving HTTPS.
+///
+/// Unless the [`ServerConfig`] already sets ALPN protocols, clients negotiate HTTP/2 or
+/// HTTP/1.1 with ALPN, as far as the [`ServerBuilder`](super::ServerBuilder) serves them.
+///
+/// With [`require_client_cert`](ServerTls::require_client_cert), clients authenticate with
+/// certificates too, and handlers get the verified chain as
+/// [`PeerCertificates`](crate::connect::PeerCertificates).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use keiro::server::{ServerBuilder, ServerTls};
+///
+/// let tls = ServerTls::from_pem_files(
+///     "/etc/ssl/example.com/fullchain.pem",
+///     "/etc/ssl/example.com/privkey.pem",
+/// )
+/// .unwrap();
+/// let server = ServerBuilder::new().bind(([0, 0, 0, 0], 443)).tls(tls);
+/// ```
+#[derive(Clone)]
+pub struct ServerTls {
+    pub(crate) config: Arc<ServerConfig>,
+    /// Whether to offer ALPN for ACME TLS-ALPN-01 challenges.
+    #[cfg(feature = "acme")]
+    acme: bool,
+}
+
+impl ServerTls {
+    /// Serve the PEM encoded certificate `chain`, starting with the certificate of the server,
+    /// with the PEM encoded private `key`, in PKCS #8, PKCS #1 or SEC1 format.
+    pub fn from_pem(chain: &[u8], key: &[u8]) -> io::Result<Self> {
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_single_cert(certificates(chain)?, private_key(key)?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        Ok(Self::from_config(config))
+    }
+
+    /// Serve the PEM encoded certificate chain and private key in the files at `chain` and
+    /// `key`.
+    pub fn from_pem_files(chain: impl AsRef<Path>, key: impl AsRef<Path>) -> io::Result<Self> {
+        let chain = fs::read(chain)?;
+        let key = fs::read(key)?;
+        Self::from_pem(&chain, &key)
+    }
+
+    /// Serve the certificates chosen by `resolver`, such as an [`SniResolver`] or the resolver
+    /// of an [`Acme`](crate::acme::Acme).
+    pub fn from_resolver(resolver: Arc<dyn ResolvesServerCert>) -> Self {
+        let config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(resolver);
+        Self::from_config(config)
+    }
+
+    /// Serve the certificate provisioned by `acme`, answering its TLS-ALPN-01 challenges.
+    ///
+    /// Requires the `acme` feature.
+    #[cfg(feature = "acme")]
+    pub fn from_acme(acme: &crate::acme::Acme) -> Self {
+        let mut tls = Self::from_resolver(acme.resolver());
+        tls.acme = true;
+        tls
+    }
+
+    /// Serve with `config`, e.g. to resolve certificates with an
+    /// [`Acme`](crate::acme::Acme) or restrict the protocol versions.
+    pub fn from_config(config: ServerConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            #[cfg(feature = "acme")]
+            acme: false,
+        }
+    }
+
+    /// Require clients to authenticate with a certificate issued by one of the PEM encoded CA
+    /// certificates in `roots`, failing the handshake of clients without one.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use std::convert::Infallible;
+    ///
+    /// use hyper::{Body, Request, Response};
+    /// use keiro::connect::PeerCertificates;
+    /// use keiro::prelude::*;
+    /// use keiro::server::{ServerBuilder, ServerTls};
+    ///
+    /// # fn run() -> std::io::Result<()> {
+    /// let tls = ServerTls::from_pem_files("/etc/ssl/server.pem", "/etc/ssl/server.key")?
+    ///     .require_client_cert(&std::fs::read("/etc/ssl/clients-ca.pem")?)?;
+    /// let server = ServerBuilder::new().bind(([0, 0, 0, 0], 443)).tls(tls);
+    /// # Ok(())
+    /// # }
+    ///
+    /// async fn whoami(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    ///     let certs = match req.extract::<PeerCertificates>() {
+    ///         Ok(certs) => certs,
+    ///         Err(rejection) => return Ok(rejection.into()),
+    ///     };
+    ///     let der = &certs.end_entity().0;
+    ///     Ok(Response::new(Body::from(format!("{} byte certificate", der.len()))))
+    /// }
+    /// ```
+    pub fn require_client_cert(self, roots: &[u8]) -> io::Result<Self> {
+        let roots = root_store(roots)?;
+        Ok(self.client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed()))
+    }
+
+    /// Ask clients for a certificate issued by one of the PEM encoded CA certificates in
+    /// `roots`, but also accept clients without one. Requests of the latter have no
+    /// [`PeerCertificates`](crate::connect::PeerCertificates).
+    pub fn request_client_cert(self, roots: &[u8]) -> io::Result<Self> {
+        let roots = root_store(roots)?;
+        Ok(self.client_cert_verifier(AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed()))
+    }
+
+    /// Verify the certificates of clients with `verifier`.
+    ///
+    /// The certificates and settings of the config are kept, except for the protocol versions
+    /// and cipher suites of a config given to [`from_config`](ServerTls::from_config), which
+    /// are reset to the defaults. Set the verifier of such configs when building them instead.
+    pub fn client_cert_verifier(mut self, verifier: Arc<dyn ClientCertVerifier>) -> Self {
+        let old = &self.config;
+        let mut config = ServerConfig::builder()
+            .with_safe_defaults()
+            .with_client_cert_verifier(verifier)
+            .with_cert_resolver(old.cert_resolver.clone());
+        config.ignore_client_order = old.ignore_client_order;
+        config.max_fragment_size = old.max_fragment_size;
+        config.session_storage = old.session_storage.clone();
+        config.ticketer = old.ticketer.clone();
+        config.alpn_protocols = old.alpn_protocols.clone();
+        config.key_log = old.key_log.clone();
+        config.max_early_data_size = old.max_early_data_size;
+        config.send_half_rtt_data = old.send_half_rtt_data;
+        config.send_tls13_tickets = old.send_tls13_tickets;
+        self.config = Arc::new(config);
+        self
+    }
+
+    /// Get the config with the ALPN protocols `alpn`, unless it sets its own.
+    pub(crate) fn config(&self, alpn: &[&[u8]]) -> Arc<ServerConfig> {
+        if !self.config.alpn_protocols.is_empty() {
+            return self.config.clone();
+        }
+        let mut config = (*self.config).clone();
+        config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+        #[cfg(feature = "acme")]
+        if self.acme {
+            config
+                .alpn_protocols
+                .push(crate::acme::ACME_TLS_ALPN.to_vec());
+        }
+        Arc::new(config)
+    }
+}
+
+impl From<ServerConfig> for ServerTls {
+    fn from(config: ServerConfig) -> Self {
+        Self::from_config(config)
+    }
+}
+
+impl fmt::Debug for ServerTls {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ServerTls")
+            .field("alpn_protocols", &self.config.alpn_protocols)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Chooses the certificate to serve by the server name clients send with SNI, so one server
+/// can serve HTTPS for several domains.
+///
+/// Names are matched exactly, or by wildcards such as `*.example.com`, which match a single
+/// label. Clients sending no server name, or one without a certificate, get the
+/// [fallback](SniResolver::fallback) certificate if there is one, and fail the handshake
+/// otherwise.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use std::sync::Arc;
+///
+/// use keiro::server::{ServerBuilder, ServerTls, SniResolver};
+///
+/// # fn run() -> std::io::Result<()> {
+/// let resolver = SniResolver::new()
+///     .cert_files("example.com", "/etc/ssl/example.com.pem", "/etc/ssl/example.com.key")?
+///     .cert_files("*.example.org", "/etc/ssl/example.org.pem", "/etc/ssl/example.org.key")?;
+/// let server = ServerBuilder::new()
+///     .bind(([0, 0, 0, 0], 443))
+///     .tls(ServerTls::from_resolver(Arc::new(resolver)));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct SniResolver {
+    names: HashMap<String, Arc<dyn ResolvesServerCert>>,
+    fallback: Option<Arc<dyn ResolvesServerCert>>,
+}
+
+impl SniResolver {
+    /// Create a resolver without certificates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve the PEM encoded certificate `chain` with the PEM encoded private `key` for
+    /// `name`, as with [`ServerTls::from_pem`].
+    pub fn cert(self, name: &str, chain: &[u8], key: &[u8]) -> io::Result<Self> {
+        let key = certified_key(chain, key)?;
+        Ok(self.resolver(name, Arc::new(Fixed(Arc::new(key)))))
+    }
+
+    /// Serve the PEM encoded certificate chain and private key in the files at `chain` and
+    /// `key` for `name`.
+    pub fn cert_files(
+        self,
+        name: &str,
+        chain: impl AsRef<Path>,
+        key: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let chain = fs::read(chain)?;
+        let key = fs::read(key)?;
+        self.cert(name, &chain, &key)
+    }
+
+    /// Serve the certificates of `resolver` for `name`, e.g. the ones an
+    /// [`Acme`](crate::acme::Acme) provisions for it.
+    pub fn resolver(mut self, name: &str, resolver: Arc<dyn ResolvesServerCert>) -> Self {
+        self.names.insert(name.to_ascii_lowercase(), resolver);
+        self
+    }
+
+    /// Serve the certificates of `resolver` to clients without a matching server name.
+    pub fn fallback(mut self, resolver: Arc<dyn ResolvesServerCert>) -> Self {
+        self.fallback = Some(resolver);
+        self
+    }
+
+    fn find(&self, name: &str) -> Option<&Arc<dyn ResolvesServerCert>> {
+        let name = name.trim_end_matches('.').to_ascii_lowercase();
+        self.names.get(&name).or_else(|| {
+            let (_, parent) = name.split_once('.')?;
+            self.names.get(&format!("*.{}", parent))
+        })
+    }
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let resolver = client_hello
+            .server_name()
+            .and_then(|name| self.find(name))
+            .or(self.fallback.as_ref())?;
+        resolver.resolve(client_hello)
+    }
+}
+
+impl fmt::Debug for SniResolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&str> = self.names.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        f.debug_struct("SniResolver")
+            .field("names", &names)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+/// Resolves every client hello to the same certificate.
+struct Fixed(Arc<CertifiedKey>);
+
+impl ResolvesServerCert for Fixed {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.clone())
+    }
+}
+
+/// Load the PEM encoded certificate `chain` and private `key`.
+fn certified_key(chain: &[u8], key: &[u8]) -> io::Result<CertifiedKey> {
+    let key = rustls::sign::any_supported_type(&private_key(key)?)
+        .map_err(|_| invalid("unsupported private key"))?;
+    Ok(CertifiedKey::new(certificates(chain)?, key))
+}
+
+/// Load the PEM encoded CA certificates in `pem` to verify clients with.
+fn root_store(pem: &[u8]) -> io::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in certificates(pem)? {
+        roots
+            .add(&cert)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    }
+    Ok(roots)
+}
+
+fn certificates(chain: &[u8]) -> io::Result<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut &*chain)?;
+    if certs.is_empty() {
+        return Err(invalid("no certificates found in certificate chain"));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Read the first private key in `pem`.
+fn private_key(pem: &[u8]) -> io::Result<PrivateKey> {
+    let mut pem = pem;
+    loop {
+        match rustls_pemfile::read_one(&mut pem)? {
+            Some(Item::PKCS8Key(key)) | Some(Item::RSAKey(key)) | Some(Item::ECKey(key)) => {
+                return Ok(PrivateKey(key));
+            }
+            Some(_) => {}
+            None => return Err(invalid("no private key found")),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
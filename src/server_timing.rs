@@ -0,0 +1,140 @@
+//! Latency breakdowns in the `Server-Timing` response header.
+//!
+//! With [`Router::server_timing`](crate::Router::server_timing), responses get a
+//! `Server-Timing` header which browsers show in their developer tools next to the request:
+//!
+//! ```text
+//! Server-Timing: match;dur=0.012, auth;dur=1.204, handler;dur=23.517, db;dur=18.2, total;dur=24.91
+//! ```
+//!
+//! The router records the time it took to match the route (`match`), to check the rate limit
+//! (`rate-limit`) and authenticate the request (`auth`) if the route does so, to run the
+//! handler (`handler`) and to answer the request as a whole (`total`). Handlers add their own
+//! timings to the [`ServerTiming`] of the request, which they get with
+//! [`RequestExt::server_timing`](crate::ext::RequestExt::server_timing).
+//!
+//! The timings tell clients how the service spends its time, so only enable them for
+//! development or trusted clients.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::prelude::*;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.server_timing();
+//! router.get("/reports/:id", report);
+//!
+//! async fn report(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let timer = req.server_timing().map(|timing| timing.start("db"));
+//!     // Query the database.
+//!     drop(timer);
+//!     Ok(Response::new(Body::from("report")))
+//! }
+//! ```
+
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::Response;
+
+use crate::HandlerFuture;
+
+/// The timings of a request, added to its extensions.
+#[derive(Debug, Clone, Default)]
+pub struct ServerTiming(Arc<Mutex<Vec<Metric>>>);
+
+#[derive(Debug)]
+struct Metric {
+    name: String,
+    description: Option<String>,
+    duration: Duration,
+}
+
+impl ServerTiming {
+    /// Add the timing `name`. Names are tokens, such as `db` or `cache-lookup`.
+    pub fn record(&self, name: &str, duration: Duration) {
+        self.push(name, None, duration);
+    }
+
+    /// Add the timing `name` with a `description` shown instead of the name by browsers.
+    pub fn record_described(&self, name: &str, description: &str, duration: Duration) {
+        self.push(name, Some(description.to_string()), duration);
+    }
+
+    /// Start timing `name`, which is recorded once the returned timer is dropped.
+    pub fn start(&self, name: &str) -> Timer {
+        Timer {
+            timing: self.clone(),
+            name: name.to_string(),
+            start: Instant::now(),
+        }
+    }
+
+    fn push(&self, name: &str, description: Option<String>, duration: Duration) {
+        self.0.lock().unwrap().push(Metric {
+            name: name.to_string(),
+            description,
+            duration,
+        });
+    }
+
+    /// Set the `Server-Timing` header of `res` to the timings.
+    pub(crate) fn apply<B>(&self, res: &mut Response<B>) {
+        let mut value = String::new();
+        for metric in self.0.lock().unwrap().iter() {
+            if !is_token(&metric.name) {
+                continue;
+            }
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+            value.push_str(&metric.name);
+            if let Some(description) = &metric.description {
+                let description = description.replace('\\', "\\\\").replace('"', "\\\"");
+                let _ = write!(value, ";desc=\"{}\"", description);
+            }
+            let _ = write!(value, ";dur={:.3}", metric.duration.as_secs_f64() * 1000.0);
+        }
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            res.headers_mut()
+                .append(HeaderName::from_static("server-timing"), value);
+        }
+    }
+}
+
+/// A running timing started with [`ServerTiming::start`].
+pub struct Timer {
+    timing: ServerTiming,
+    name: String,
+    start: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.timing.record(&self.name, self.start.elapsed());
+    }
+}
+
+/// Record the time `fut` takes as `name` in `timing`.
+pub(crate) fn timed<E: 'static>(
+    fut: HandlerFuture<E>,
+    timing: ServerTiming,
+    name: &'static str,
+) -> HandlerFuture<E> {
+    Box::pin(async move {
+        let _timer = timing.start(name);
+        fut.await
+    })
+}
+
+fn is_token(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
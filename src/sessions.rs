@@ -0,0 +1,462 @@
+//! Server-side sessions.
+//!
+//! [`Sessions`] set on a router with [`Router::sessions`](crate::Router::sessions), or on a
+//! route with [`Route::sessions`](crate::Route::sessions), load the session of each request
+//! from a [`SessionStore`] by the ID in its session cookie before the handler is called, and
+//! save it again afterwards if the handler changed it. Handlers get the [`Session`] with
+//! [`RequestExt::session`](crate::ext::RequestExt::session) and read and write typed values,
+//! stored as JSON.
+//!
+//! New sessions get a random ID and a cookie once something is stored in them. Sessions
+//! expire after their time to live without changes, which is one day by default. Requests are
+//! answered with `503 Service Unavailable` if their session can't be loaded, and with
+//! `500 Internal Server Error` if it can't be saved, so changes are never lost silently.
+//!
+//! Flash messages added with [`Session::flash`], or
+//! [`RequestExt::flash`](crate::ext::RequestExt::flash), are shown once: they're taken out of
+//! the session by the next request, which gets them with [`Session::flashes`], e.g. to
+//! confirm a form submission after redirecting.
+//!
+//! [`MemoryStore`] keeps sessions in the process; with the `redis` feature, [`RedisStore`]
+//! shares them between the instances of a service.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::prelude::*;
+//! use keiro::sessions::{MemoryStore, Sessions};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.sessions(Sessions::new(MemoryStore::new()).secure());
+//! router.get("/", index);
+//!
+//! async fn index(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let session = req.session().unwrap();
+//!     let visits = session.get::<u64>("visits").unwrap_or(0) + 1;
+//!     session.insert("visits", &visits).unwrap();
+//!     Ok(Response::text(format!("visit #{}", visits)))
+//! }
+//! ```
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response, StatusCode};
+//! use keiro::prelude::*;
+//!
+//! async fn save_profile(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     // Save the profile...
+//!     req.flash("Saved!");
+//!     Ok(Response::redirect("/profile", StatusCode::SEE_OTHER))
+//! }
+//!
+//! async fn profile(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let flashes = req.session().unwrap().flashes();
+//!     Ok(Response::html(format!("<p>{}</p>", flashes.join("</p><p>"))))
+//! }
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hyper::{Body, Request, Response, StatusCode};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::cookie::{Cookie, CookieJar, SameSite};
+use crate::ext::ResponseExt;
+use crate::extract::{FromRequest, Rejection};
+
+#[cfg(feature = "redis")]
+mod redis;
+
+#[cfg(feature = "redis")]
+pub use self::redis::RedisStore;
+
+/// The key of the flash messages for the next request in the data of sessions.
+const FLASH: &str = "keiro.flash";
+
+/// The length of session IDs, 32 random bytes encoded in base64.
+const ID_LEN: usize = 43;
+
+/// The future returned by the methods of [`SessionStore`].
+pub type SessionFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + Sync + 'a>>;
+
+/// Storage for the data of sessions, serialized into JSON, by session ID.
+pub trait SessionStore: Send + Sync + 'static {
+    /// Load the data of the session `id`, or `None` if there is none or it expired.
+    fn load(&self, id: &str) -> SessionFuture<'_, Option<String>>;
+
+    /// Store `data` as the data of the session `id`, expiring after `ttl`.
+    fn store(&self, id: &str, data: &str, ttl: Duration) -> SessionFuture<'_, ()>;
+
+    /// Remove the session `id`.
+    fn remove(&self, id: &str) -> SessionFuture<'_, ()>;
+}
+
+/// A [`SessionStore`] keeping sessions in memory, so they are lost on restarts. Clones share
+/// the sessions.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore(Arc<Mutex<MemorySessions>>);
+
+#[derive(Debug, Default)]
+struct MemorySessions {
+    sessions: HashMap<String, (String, Instant)>,
+    /// The number of sessions at which expired ones are removed next.
+    sweep_at: usize,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MemorySessions> {
+        match self.0.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> SessionFuture<'_, Option<String>> {
+        let data = self
+            .lock()
+            .sessions
+            .get(id)
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(data, _)| data.clone());
+        Box::pin(async move { Ok(data) })
+    }
+
+    fn store(&self, id: &str, data: &str, ttl: Duration) -> SessionFuture<'_, ()> {
+        let mut state = self.lock();
+        let now = Instant::now();
+        if state.sessions.len() >= state.sweep_at {
+            state.sessions.retain(|_, (_, expires)| *expires > now);
+            state.sweep_at = (state.sessions.len() * 2).max(1024);
+        }
+        state
+            .sessions
+            .insert(id.to_string(), (data.to_string(), now + ttl));
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove(&self, id: &str) -> SessionFuture<'_, ()> {
+        self.lock().sessions.remove(id);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// The session of a request. Clones share the session.
+#[derive(Clone)]
+pub struct Session(Arc<Mutex<SessionState>>);
+
+#[derive(Default)]
+struct SessionState {
+    /// The ID the session was loaded with, or `None` for new sessions.
+    id: Option<String>,
+    data: Map<String, Value>,
+    /// The flash messages added by the previous request.
+    flashes: Vec<String>,
+    changed: bool,
+    regenerate: bool,
+    destroyed: bool,
+}
+
+impl Session {
+    fn lock(&self) -> MutexGuard<'_, SessionState> {
+        match self.0.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Get the value of `key`, or `None` if there is none or it doesn't fit `T`.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let state = self.lock();
+        T::deserialize(state.data.get(key)?).ok()
+    }
+
+    /// Set the value of `key`, failing if `value` can't be serialized into JSON.
+    pub fn insert<T: Serialize + ?Sized>(
+        &self,
+        key: impl Into<String>,
+        value: &T,
+    ) -> Result<(), serde_json::Error> {
+        let value = serde_json::to_value(value)?;
+        let mut state = self.lock();
+        state.data.insert(key.into(), value);
+        state.changed = true;
+        Ok(())
+    }
+
+    /// Remove the value of `key`, returning whether there was one.
+    pub fn remove(&self, key: &str) -> bool {
+        let mut state = self.lock();
+        let removed = state.data.remove(key).is_some();
+        state.changed |= removed;
+        removed
+    }
+
+    /// Remove all values, keeping the session.
+    pub fn clear(&self) {
+        let mut state = self.lock();
+        state.changed |= !state.data.is_empty();
+        state.data.clear();
+    }
+
+    /// Give the session a new ID when it's saved, e.g. after logging in, so an ID an attacker
+    /// planted before can't be used to take over the session.
+    pub fn regenerate(&self) {
+        let mut state = self.lock();
+        state.regenerate = true;
+        state.changed = true;
+    }
+
+    /// Remove the session from the store and its cookie from the client.
+    pub fn destroy(&self) {
+        let mut state = self.lock();
+        state.data.clear();
+        state.destroyed = true;
+    }
+
+    /// Add a flash message for the next request of the client.
+    pub fn flash(&self, message: impl Into<String>) {
+        let mut state = self.lock();
+        let flashes = state
+            .data
+            .entry(FLASH)
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(flashes) = flashes {
+            flashes.push(Value::String(message.into()));
+        }
+        state.changed = true;
+    }
+
+    /// Get the flash messages added by the previous request, which are gone on the next.
+    pub fn flashes(&self) -> Vec<String> {
+        self.lock().flashes.clone()
+    }
+
+    /// Get the ID the session was loaded with, or `None` for new sessions.
+    pub fn id(&self) -> Option<String> {
+        self.lock().id.clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().data.is_empty()
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.lock();
+        f.debug_struct("Session")
+            .field("keys", &state.data.keys().collect::<Vec<_>>())
+            .finish_non_exhaustive()
+    }
+}
+
+impl FromRequest for Session {
+    fn from_request(req: &Request<Body>) -> Result<Self, Rejection> {
+        req.extensions().get::<Session>().cloned().ok_or_else(|| {
+            Rejection::new(StatusCode::INTERNAL_SERVER_ERROR, "no sessions configured")
+        })
+    }
+}
+
+/// Loads and saves the sessions of requests.
+#[derive(Clone)]
+pub struct Sessions {
+    store: Arc<dyn SessionStore>,
+    cookie_name: String,
+    ttl: Duration,
+    path: String,
+    domain: Option<String>,
+    secure: bool,
+    same_site: SameSite,
+}
+
+impl Sessions {
+    /// Keep sessions in `store`, identified by the cookie `keiro_session`, for one day.
+    pub fn new(store: impl SessionStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            cookie_name: "keiro_session".to_string(),
+            ttl: Duration::from_secs(24 * 60 * 60),
+            path: "/".to_string(),
+            domain: None,
+            secure: false,
+            same_site: SameSite::Lax,
+        }
+    }
+
+    /// Name the session cookie `name` instead of `keiro_session`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid cookie name.
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = Cookie::new(name, "").name().to_string();
+        self
+    }
+
+    /// Expire sessions after `ttl` without changes.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Send the session cookie for paths under `path` only, instead of all paths.
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = path.into();
+        self
+    }
+
+    /// Send the session cookie to `domain` and its subdomains too.
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Only send the session cookie over HTTPS.
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    /// Set the `SameSite` attribute of the session cookie instead of `Lax`.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Load the session of `req` and add it to the request.
+    pub(crate) async fn load(&self, req: &mut Request<Body>) -> Result<(), Response<Body>> {
+        let id = CookieJar::from_headers(req)
+            .get(&self.cookie_name)
+            .filter(|id| is_id(id))
+            .map(str::to_string);
+        let mut state = SessionState::default();
+        if let Some(id) = id {
+            let data = self
+                .store
+                .load(&id)
+                .await
+                .map_err(|_| status(StatusCode::SERVICE_UNAVAILABLE))?;
+            if let Some(data) = data {
+                state.data = serde_json::from_str(&data).unwrap_or_default();
+                state.id = Some(id);
+                if let Some(flashes) = state.data.remove(FLASH) {
+                    state.flashes = serde_json::from_value(flashes).unwrap_or_default();
+                    state.changed = true;
+                }
+            }
+        }
+        req.extensions_mut()
+            .insert(Session(Arc::new(Mutex::new(state))));
+        Ok(())
+    }
+
+    /// Save `session` if it changed, setting or removing the session cookie of `res`.
+    pub(crate) async fn save(&self, session: &Session, res: &mut Response<Body>) {
+        if self.store_session(session, res).await.is_err() {
+            *res = status(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
+    async fn store_session(&self, session: &Session, res: &mut Response<Body>) -> io::Result<()> {
+        let (old_id, data, regenerate, destroyed) = {
+            let state = session.lock();
+            if !state.changed && !state.destroyed {
+                return Ok(());
+            }
+            let data = serde_json::to_string(&state.data)?;
+            (state.id.clone(), data, state.regenerate, state.destroyed)
+        };
+        if destroyed || (session.is_empty() && old_id.is_some()) {
+            if let Some(id) = &old_id {
+                self.store.remove(id).await?;
+            }
+            res.set_cookie(self.cookie(Cookie::removal(self.cookie_name.as_str())));
+            return Ok(());
+        }
+        if session.is_empty() {
+            return Ok(());
+        }
+        let id = match old_id {
+            Some(id) if !regenerate => id,
+            old_id => {
+                if let Some(id) = &old_id {
+                    self.store.remove(id).await?;
+                }
+                new_id()?
+            }
+        };
+        self.store.store(&id, &data, self.ttl).await?;
+        res.set_cookie(self.cookie(Cookie::new(self.cookie_name.as_str(), id).max_age(self.ttl)));
+        Ok(())
+    }
+
+    fn cookie(&self, cookie: Cookie) -> Cookie {
+        let mut cookie = cookie
+            .path(self.path.as_str())
+            .http_only()
+            .same_site(self.same_site);
+        if let Some(domain) = &self.domain {
+            cookie = cookie.domain(domain.as_str());
+        }
+        if self.secure {
+            cookie = cookie.secure();
+        }
+        cookie
+    }
+}
+
+impl fmt::Debug for Sessions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sessions")
+            .field("cookie_name", &self.cookie_name)
+            .field("ttl", &self.ttl)
+            .field("path", &self.path)
+            .field("domain", &self.domain)
+            .field("secure", &self.secure)
+            .field("same_site", &self.same_site)
+            .finish()
+    }
+}
+
+fn new_id() -> io::Result<String> {
+    let mut id = [0; 32];
+    SystemRandom::new()
+        .fill(&mut id)
+        .map_err(|_| io::Error::other("no random numbers"))?;
+    Ok(URL_SAFE_NO_PAD.encode(id))
+}
+
+fn is_id(id: &str) -> bool {
+    id.len() == ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
@@ -0,0 +1,169 @@
+//! Server-Sent Events.
+//!
+//! [`Sse`] turns a stream of [`Event`]s into a `text/event-stream` response, which is sent to
+//! the client as the events are produced.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::sse::{Event, Sse};
+//!
+//! async fn metrics(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let events = futures_util::stream::iter((1..=3).map(|n| {
+//!         Ok::<_, Infallible>(Event::new().event("load").id(n.to_string()).data(n.to_string()))
+//!     }));
+//!     Ok(Sse::new(events)
+//!         .keep_alive(Duration::from_secs(15))
+//!         .into())
+//! }
+//! ```
+
+use std::error::Error;
+use std::fmt::Write;
+use std::time::Duration;
+
+use futures_util::future::{self, Either};
+use futures_util::{Stream, StreamExt};
+use hyper::body::Bytes;
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Response};
+
+use crate::streaming;
+
+/// A single event of an event stream.
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    buf: String,
+}
+
+impl Event {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the event type, which selects the listener on the client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `event` contains a line break.
+    pub fn event(self, event: impl AsRef<str>) -> Self {
+        self.field("event", event.as_ref())
+    }
+
+    /// Add `data` to the event. Data with several lines is sent as several `data` fields,
+    /// which the client joins with line breaks again.
+    pub fn data(mut self, data: impl AsRef<str>) -> Self {
+        for line in data.as_ref().split('\n') {
+            self = self.field("data", line.strip_suffix('\r').unwrap_or(line));
+        }
+        self
+    }
+
+    /// Add `data` serialized as JSON to the event.
+    #[cfg(feature = "json")]
+    pub fn json_data<T: serde::Serialize>(self, data: &T) -> Result<Self, serde_json::Error> {
+        Ok(self.field("data", &serde_json::to_string(data)?))
+    }
+
+    /// Set the event id, which the client sends back in `Last-Event-ID` when it reconnects.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` contains a line break or a null character.
+    pub fn id(self, id: impl AsRef<str>) -> Self {
+        let id = id.as_ref();
+        assert!(
+            !id.contains('\0'),
+            "event ids must not contain null characters"
+        );
+        self.field("id", id)
+    }
+
+    /// Tell the client how long to wait before reconnecting.
+    pub fn retry(self, retry: Duration) -> Self {
+        self.field("retry", &retry.as_millis().to_string())
+    }
+
+    /// Add a comment, which clients ignore.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `comment` contains a line break.
+    pub fn comment(self, comment: impl AsRef<str>) -> Self {
+        self.field("", comment.as_ref())
+    }
+
+    fn field(mut self, name: &str, value: &str) -> Self {
+        assert!(
+            !value.contains(['\r', '\n']),
+            "event fields must not contain line breaks"
+        );
+        let _ = writeln!(self.buf, "{}: {}", name, value);
+        self
+    }
+
+    fn into_bytes(mut self) -> Bytes {
+        self.buf.push('\n');
+        Bytes::from(self.buf)
+    }
+}
+
+/// A `text/event-stream` response from a stream of events.
+pub struct Sse<S> {
+    events: S,
+    keep_alive: Option<Duration>,
+}
+
+impl<S, E> Sse<S>
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Into<Box<dyn Error + Send + Sync>> + 'static,
+{
+    pub fn new(events: S) -> Self {
+        Self {
+            events,
+            keep_alive: None,
+        }
+    }
+
+    /// Send a comment whenever no event was sent for `interval`, so proxies don't close idle
+    /// connections.
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+}
+
+impl<S, E> From<Sse<S>> for Response<Body>
+where
+    S: Stream<Item = Result<Event, E>> + Send + 'static,
+    E: Into<Box<dyn Error + Send + Sync>> + 'static,
+{
+    fn from(sse: Sse<S>) -> Self {
+        let keep_alive = sse.keep_alive;
+        let events = Box::pin(sse.events.map(|event| event.map(Event::into_bytes)));
+        let stream = futures_util::stream::unfold(events, move |mut events| async move {
+            let interval = match keep_alive {
+                Some(interval) => interval,
+                None => return events.next().await.map(|event| (event, events)),
+            };
+            let next = events.next();
+            let sleep = Box::pin(tokio::time::sleep(interval));
+            let item = match future::select(next, sleep).await {
+                Either::Left((event, _)) => event?,
+                Either::Right(_) => Ok(Bytes::from_static(b":\n\n")),
+            };
+            Some((item, events))
+        });
+
+        let mut res = Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(Body::wrap_stream(stream))
+            .unwrap();
+        streaming::must_stream(&mut res);
+        res
+    }
+}
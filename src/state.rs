@@ -0,0 +1,32 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+
+use hyper::http::Extensions;
+
+trait StateEntry: Send + Sync {
+    fn inject(&self, extensions: &mut Extensions);
+}
+
+struct Entry<T>(T);
+
+impl<T: Clone + Send + Sync + 'static> StateEntry for Entry<T> {
+    fn inject(&self, extensions: &mut Extensions) {
+        extensions.insert(self.0.clone());
+    }
+}
+
+/// A type map of shared states which are injected into request extensions.
+#[derive(Default)]
+pub(crate) struct StateMap(HashMap<TypeId, Box<dyn StateEntry>>);
+
+impl StateMap {
+    pub(crate) fn insert<T: Clone + Send + Sync + 'static>(&mut self, value: T) {
+        self.0.insert(TypeId::of::<T>(), Box::new(Entry(value)));
+    }
+
+    pub(crate) fn inject(&self, extensions: &mut Extensions) {
+        for entry in self.0.values() {
+            entry.inject(extensions);
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Storage backends for serving and storing files.
+//!
+//! [`ServeDir`](crate::files::ServeDir) serves files from a [`Storage`], and uploads such as
+//! multipart files can be stored in one, so the same routes work against [`LocalStorage`] in
+//! development and object storage in production. With the `s3` feature, [`S3Storage`] stores
+//! objects in S3 or an S3-compatible service.
+//!
+//! Objects are addressed by keys such as `images/logo.png`, which are relative and use `/` as
+//! separator.
+//!
+//! ```rust,no_run
+//! use keiro::files::ServeDir;
+//! use keiro::storage::LocalStorage;
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.nest("/assets", ServeDir::new(LocalStorage::new("public")).router());
+//! ```
+
+use std::future::Future;
+use std::io;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use futures_util::StreamExt;
+use hyper::body::Bytes;
+use hyper::Body;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+#[cfg(feature = "s3")]
+mod s3;
+
+#[cfg(feature = "s3")]
+pub use self::s3::S3Storage;
+
+/// The future returned by [`Storage`] methods.
+pub type StorageFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + Sync + 'a>>;
+
+/// The metadata of a stored object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    /// The size in bytes.
+    pub size: u64,
+    /// The time of the last modification, if the backend knows it.
+    pub modified: Option<SystemTime>,
+    /// The content type, if the backend stores one.
+    pub content_type: Option<String>,
+}
+
+/// A stored object with its contents.
+#[derive(Debug)]
+pub struct Object {
+    pub metadata: Metadata,
+    pub body: Body,
+}
+
+/// An entry of a listing, either an object or a prefix of further objects.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// The name relative to the listed prefix, without a trailing `/`.
+    pub name: String,
+    /// Whether the entry is a prefix ("directory") rather than an object.
+    pub is_dir: bool,
+    /// The metadata of an object.
+    pub metadata: Option<Metadata>,
+}
+
+/// A backend storing objects by key.
+pub trait Storage: Send + Sync + 'static {
+    /// Get the metadata of the object `key`, or `None` if it doesn't exist.
+    fn head(&self, key: &str) -> StorageFuture<'_, Option<Metadata>>;
+
+    /// Get the object `key`, or `None` if it doesn't exist.
+    fn get(&self, key: &str) -> StorageFuture<'_, Option<Object>>;
+
+    /// Get the bytes in `range` of the object `key`, or `None` if it doesn't exist. The range
+    /// must lie within the object, and the metadata describes the whole object.
+    ///
+    /// The default implementation reads the object with [`Storage::get`] and skips the bytes
+    /// outside of `range`.
+    fn get_range(&self, key: &str, range: Range<u64>) -> StorageFuture<'_, Option<Object>> {
+        let object = self.get(key);
+        Box::pin(async move {
+            Ok(object.await?.map(|object| Object {
+                metadata: object.metadata,
+                body: slice(object.body, range),
+            }))
+        })
+    }
+
+    /// Store `body` of `length` bytes as the object `key`, replacing an existing object.
+    fn put(&self, key: &str, body: Body, length: u64) -> StorageFuture<'_, ()>;
+
+    /// Remove the object `key`. Removing a missing object succeeds.
+    fn delete(&self, key: &str) -> StorageFuture<'_, ()>;
+
+    /// List the objects and prefixes directly below `prefix`, e.g. `images/`. An empty prefix
+    /// lists the top level.
+    fn list(&self, prefix: &str) -> StorageFuture<'_, Vec<Entry>>;
+}
+
+/// A [`Storage`] keeping objects as files below a directory.
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Resolve `key` below the root, refusing keys which would escape it.
+    fn path(&self, key: &str) -> Option<PathBuf> {
+        let relative = Path::new(key.trim_start_matches('/'));
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return None;
+        }
+        Some(self.root.join(relative))
+    }
+}
+
+fn metadata(metadata: &std::fs::Metadata) -> Metadata {
+    Metadata {
+        size: metadata.len(),
+        modified: metadata.modified().ok(),
+        content_type: None,
+    }
+}
+
+/// Get the metadata of the file at `path`, treating directories as missing.
+async fn file_metadata(path: &Path) -> io::Result<Option<std::fs::Metadata>> {
+    match tokio::fs::metadata(path).await {
+        Ok(metadata) if metadata.is_file() => Ok(Some(metadata)),
+        Ok(_) => Ok(None),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+impl Storage for LocalStorage {
+    fn head(&self, key: &str) -> StorageFuture<'_, Option<Metadata>> {
+        let path = self.path(key);
+        Box::pin(async move {
+            let path = match path {
+                Some(path) => path,
+                None => return Ok(None),
+            };
+            Ok(file_metadata(&path).await?.as_ref().map(metadata))
+        })
+    }
+
+    fn get(&self, key: &str) -> StorageFuture<'_, Option<Object>> {
+        let path = self.path(key);
+        Box::pin(async move {
+            let path = match path {
+                Some(path) => path,
+                None => return Ok(None),
+            };
+            let metadata = match file_metadata(&path).await? {
+                Some(file) => metadata(&file),
+                None => return Ok(None),
+            };
+            let file = tokio::fs::File::open(&path).await?;
+            Ok(Some(Object {
+                metadata,
+                body: file_body(file),
+            }))
+        })
+    }
+
+    fn get_range(&self, key: &str, range: Range<u64>) -> StorageFuture<'_, Option<Object>> {
+        let path = self.path(key);
+        Box::pin(async move {
+            let path = match path {
+                Some(path) => path,
+                None => return Ok(None),
+            };
+            let metadata = match file_metadata(&path).await? {
+                Some(file) => metadata(&file),
+                None => return Ok(None),
+            };
+            let mut file = tokio::fs::File::open(&path).await?;
+            file.seek(io::SeekFrom::Start(range.start)).await?;
+            Ok(Some(Object {
+                metadata,
+                body: file_body(file.take(range.end - range.start)),
+            }))
+        })
+    }
+
+    fn put(&self, key: &str, mut body: Body, _length: u64) -> StorageFuture<'_, ()> {
+        let path = self.path(key);
+        Box::pin(async move {
+            let path = path.ok_or_else(invalid_key)?;
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            // Write to a temporary file first, so readers never see a partial object.
+            static COUNTER: AtomicUsize = AtomicUsize::new(0);
+            let mut temp = path.clone().into_os_string();
+            temp.push(format!(
+                ".{}-{}.tmp",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed)
+            ));
+            let temp = PathBuf::from(temp);
+            let result = async {
+                let mut file = tokio::fs::File::create(&temp).await?;
+                while let Some(chunk) = body.next().await {
+                    file.write_all(&chunk.map_err(io::Error::other)?).await?;
+                }
+                file.flush().await?;
+                tokio::fs::rename(&temp, &path).await
+            }
+            .await;
+            if result.is_err() {
+                let _ = tokio::fs::remove_file(&temp).await;
+            }
+            result
+        })
+    }
+
+    fn delete(&self, key: &str) -> StorageFuture<'_, ()> {
+        let path = self.path(key);
+        Box::pin(async move {
+            let path = path.ok_or_else(invalid_key)?;
+            match tokio::fs::remove_file(&path).await {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn list(&self, prefix: &str) -> StorageFuture<'_, Vec<Entry>> {
+        let path = self.path(prefix.trim_end_matches('/'));
+        Box::pin(async move {
+            let path = path.ok_or_else(invalid_key)?;
+            let mut dir = match tokio::fs::read_dir(&path).await {
+                Ok(dir) => dir,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(err) => return Err(err),
+            };
+            let mut entries = Vec::new();
+            while let Some(entry) = dir.next_entry().await? {
+                let name = match entry.file_name().into_string() {
+                    Ok(name) => name,
+                    Err(_) => continue,
+                };
+                let file = entry.metadata().await?;
+                entries.push(Entry {
+                    name,
+                    is_dir: file.is_dir(),
+                    metadata: Some(metadata(&file)).filter(|_| file.is_file()),
+                });
+            }
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(entries)
+        })
+    }
+}
+
+fn invalid_key() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "invalid storage key")
+}
+
+/// Skip the bytes of `body` outside of `range`.
+fn slice(body: Body, range: Range<u64>) -> Body {
+    let stream = futures_util::stream::unfold(Some((body, 0)), move |state| {
+        let range = range.clone();
+        async move {
+            let (mut body, mut position) = state?;
+            while position < range.end {
+                let chunk = match body.next().await? {
+                    Ok(chunk) => chunk,
+                    Err(err) => return Some((Err(err), None)),
+                };
+                let (start, end) = (position, position + chunk.len() as u64);
+                position = end;
+                if end <= range.start {
+                    continue;
+                }
+                let from = range.start.saturating_sub(start) as usize;
+                let to = (range.end.min(end) - start) as usize;
+                return Some((Ok(chunk.slice(from..to)), Some((body, position))));
+            }
+            None
+        }
+    });
+    Body::wrap_stream(stream)
+}
+
+/// Stream the contents of `file` as a body.
+pub(crate) fn file_body(file: impl AsyncRead + Send + Unpin + 'static) -> Body {
+    let stream = futures_util::stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut buf = Vec::with_capacity(64 * 1024);
+        match file.read_buf(&mut buf).await {
+            Ok(0) => None,
+            Ok(_) => Some((Ok(Bytes::from(buf)), Some(file))),
+            Err(err) => Some((Err(err), None)),
+        }
+    });
+    Body::wrap_stream(stream)
+}
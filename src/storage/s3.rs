@@ -0,0 +1,482 @@
+use std::convert::TryFrom;
+use std::io;
+use std::ops::Range;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use hyper::client::HttpConnector;
+use hyper::header::{
+    HeaderValue, AUTHORIZATION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, HOST, LAST_MODIFIED,
+    RANGE,
+};
+use hyper::http::uri::Uri;
+use hyper::{Body, Client, Method, Request, Response, StatusCode};
+use hyper_rustls::HttpsConnector;
+use sha2::{Digest, Sha256};
+
+use crate::proxy::UpstreamTls;
+
+use super::{Entry, Metadata, Object, Storage, StorageFuture};
+
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// A [`Storage`] keeping objects in an S3 bucket, or a bucket of an S3-compatible service such
+/// as MinIO. Requests are signed with AWS Signature Version 4 and use path-style URLs.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// use keiro::files::ServeDir;
+/// use keiro::storage::S3Storage;
+///
+/// let storage = S3Storage::new("assets", "eu-west-1", "AKIA...", "secret")
+///     .prefix("public/");
+/// let assets = ServeDir::new(storage);
+///
+/// let minio = S3Storage::new("uploads", "us-east-1", "minio", "minio-secret")
+///     .endpoint("http://127.0.0.1:9000".parse().unwrap());
+/// ```
+#[derive(Clone)]
+pub struct S3Storage {
+    client: Client<HttpsConnector<HttpConnector>, Body>,
+    endpoint: Uri,
+    bucket: String,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    prefix: String,
+}
+
+impl S3Storage {
+    /// Create a storage for `bucket` in `region` of AWS, authenticated with the given keys.
+    pub fn new(
+        bucket: impl Into<String>,
+        region: impl Into<String>,
+        access_key_id: impl Into<String>,
+        secret_access_key: impl Into<String>,
+    ) -> Self {
+        let region = region.into();
+        Self {
+            client: Client::builder().build(UpstreamTls::new().connector()),
+            endpoint: format!("https://s3.{}.amazonaws.com", region)
+                .parse()
+                .expect("invalid region"),
+            bucket: bucket.into(),
+            region,
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            prefix: String::new(),
+        }
+    }
+
+    /// Send requests to `endpoint` instead of AWS, e.g. `http://127.0.0.1:9000` for MinIO.
+    pub fn endpoint(mut self, endpoint: Uri) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// Prepend `prefix` to all keys, e.g. `public/`.
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    fn object_path(&self, key: &str) -> String {
+        format!(
+            "/{}/{}{}",
+            self.bucket,
+            self.prefix,
+            key.trim_start_matches('/')
+        )
+    }
+
+    /// Build a signed request to `path` with the query parameters `query`.
+    fn request(
+        &self,
+        method: Method,
+        path: &str,
+        query: &[(&str, &str)],
+        body: Body,
+    ) -> io::Result<Request<Body>> {
+        let now = SystemTime::now();
+        let host = self
+            .endpoint
+            .authority()
+            .map(|authority| authority.as_str().to_string())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid S3 endpoint"))?;
+        let canonical_path = encode(path, false);
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(key, value)| (encode(key, true), encode(value, true)))
+            .collect();
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let authorization =
+            self.authorization(&method, &canonical_path, &canonical_query, &host, now);
+        let mut uri = format!(
+            "{}://{}{}",
+            self.endpoint.scheme_str().unwrap_or("https"),
+            host,
+            canonical_path
+        );
+        if !canonical_query.is_empty() {
+            uri.push('?');
+            uri.push_str(&canonical_query);
+        }
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(HOST, host)
+            .header("x-amz-date", amz_date(now))
+            .header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .header(AUTHORIZATION, authorization)
+            .body(body)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    }
+
+    fn authorization(
+        &self,
+        method: &Method,
+        canonical_path: &str,
+        canonical_query: &str,
+        host: &str,
+        now: SystemTime,
+    ) -> String {
+        let amz_date = amz_date(now);
+        let date = &amz_date[..8];
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            canonical_path,
+            canonical_query,
+            host,
+            UNSIGNED_PAYLOAD,
+            amz_date,
+            "host;x-amz-content-sha256;x-amz-date",
+            UNSIGNED_PAYLOAD
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let key = format!("AWS4{}", self.secret_access_key);
+        let key = hmac(key.as_bytes(), date);
+        let key = hmac(&key, &self.region);
+        let key = hmac(&key, "s3");
+        let key = hmac(&key, "aws4_request");
+        let signature = hex(&hmac(&key, &string_to_sign));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+            self.access_key_id, scope, signature
+        )
+    }
+
+    async fn send(&self, req: Request<Body>) -> io::Result<Response<Body>> {
+        self.client.request(req).await.map_err(io::Error::other)
+    }
+}
+
+impl Storage for S3Storage {
+    fn head(&self, key: &str) -> StorageFuture<'_, Option<Metadata>> {
+        let path = self.object_path(key);
+        Box::pin(async move {
+            let req = self.request(Method::HEAD, &path, &[], Body::empty())?;
+            let res = self.send(req).await?;
+            match res.status() {
+                StatusCode::NOT_FOUND => Ok(None),
+                status if status.is_success() => Ok(Some(metadata(&res))),
+                status => Err(error(status)),
+            }
+        })
+    }
+
+    fn get(&self, key: &str) -> StorageFuture<'_, Option<Object>> {
+        let path = self.object_path(key);
+        Box::pin(async move {
+            let req = self.request(Method::GET, &path, &[], Body::empty())?;
+            let res = self.send(req).await?;
+            match res.status() {
+                StatusCode::NOT_FOUND => Ok(None),
+                status if status.is_success() => Ok(Some(Object {
+                    metadata: metadata(&res),
+                    body: res.into_body(),
+                })),
+                status => Err(error(status)),
+            }
+        })
+    }
+
+    fn get_range(&self, key: &str, range: Range<u64>) -> StorageFuture<'_, Option<Object>> {
+        let path = self.object_path(key);
+        Box::pin(async move {
+            let mut req = self.request(Method::GET, &path, &[], Body::empty())?;
+            let value = format!("bytes={}-{}", range.start, range.end.saturating_sub(1));
+            req.headers_mut().insert(
+                RANGE,
+                HeaderValue::from_str(&value)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+            );
+            let res = self.send(req).await?;
+            match res.status() {
+                StatusCode::NOT_FOUND => Ok(None),
+                StatusCode::PARTIAL_CONTENT => {
+                    let mut metadata = metadata(&res);
+                    // The size of the whole object follows the range, e.g. `bytes 0-99/1234`.
+                    if let Some(size) = res
+                        .headers()
+                        .get(CONTENT_RANGE)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.rsplit('/').next())
+                        .and_then(|size| size.parse().ok())
+                    {
+                        metadata.size = size;
+                    }
+                    Ok(Some(Object {
+                        metadata,
+                        body: res.into_body(),
+                    }))
+                }
+                status => Err(error(status)),
+            }
+        })
+    }
+
+    fn put(&self, key: &str, body: Body, length: u64) -> StorageFuture<'_, ()> {
+        let path = self.object_path(key);
+        Box::pin(async move {
+            let mut req = self.request(Method::PUT, &path, &[], body)?;
+            req.headers_mut().insert(CONTENT_LENGTH, length.into());
+            let res = self.send(req).await?;
+            match res.status() {
+                status if status.is_success() => Ok(()),
+                status => Err(error(status)),
+            }
+        })
+    }
+
+    fn delete(&self, key: &str) -> StorageFuture<'_, ()> {
+        let path = self.object_path(key);
+        Box::pin(async move {
+            let req = self.request(Method::DELETE, &path, &[], Body::empty())?;
+            let res = self.send(req).await?;
+            match res.status() {
+                status if status.is_success() || status == StatusCode::NOT_FOUND => Ok(()),
+                status => Err(error(status)),
+            }
+        })
+    }
+
+    fn list(&self, prefix: &str) -> StorageFuture<'_, Vec<Entry>> {
+        let mut prefix = format!("{}{}", self.prefix, prefix.trim_start_matches('/'));
+        if !prefix.is_empty() && !prefix.ends_with('/') {
+            prefix.push('/');
+        }
+        Box::pin(async move {
+            let path = format!("/{}", self.bucket);
+            let mut entries = Vec::new();
+            let mut token: Option<String> = None;
+            loop {
+                let mut query = vec![
+                    ("list-type", "2"),
+                    ("delimiter", "/"),
+                    ("prefix", prefix.as_str()),
+                ];
+                if let Some(token) = &token {
+                    query.push(("continuation-token", token));
+                }
+                let req = self.request(Method::GET, &path, &query, Body::empty())?;
+                let res = self.send(req).await?;
+                if !res.status().is_success() {
+                    return Err(error(res.status()));
+                }
+                let body = hyper::body::to_bytes(res.into_body())
+                    .await
+                    .map_err(io::Error::other)?;
+                let xml = String::from_utf8_lossy(&body);
+
+                for prefixes in elements(&xml, "CommonPrefixes") {
+                    if let Some(name) = element(prefixes, "Prefix") {
+                        let name = name.strip_prefix(prefix.as_str()).unwrap_or(&name);
+                        entries.push(Entry {
+                            name: name.trim_end_matches('/').to_string(),
+                            is_dir: true,
+                            metadata: None,
+                        });
+                    }
+                }
+                for contents in elements(&xml, "Contents") {
+                    let key = match element(contents, "Key") {
+                        Some(key) => key,
+                        None => continue,
+                    };
+                    let name = key.strip_prefix(prefix.as_str()).unwrap_or(&key);
+                    if name.is_empty() {
+                        continue;
+                    }
+                    entries.push(Entry {
+                        name: name.to_string(),
+                        is_dir: false,
+                        metadata: Some(Metadata {
+                            size: element(contents, "Size")
+                                .and_then(|size| size.parse().ok())
+                                .unwrap_or(0),
+                            modified: element(contents, "LastModified")
+                                .and_then(|modified| parse_iso8601(&modified)),
+                            content_type: None,
+                        }),
+                    });
+                }
+
+                token = match element(&xml, "IsTruncated").as_deref() {
+                    Some("true") => element(&xml, "NextContinuationToken"),
+                    _ => None,
+                };
+                if token.is_none() {
+                    break;
+                }
+            }
+            entries.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(entries)
+        })
+    }
+}
+
+fn metadata(res: &Response<Body>) -> Metadata {
+    let header = |name| {
+        res.headers()
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+    };
+    Metadata {
+        size: header(CONTENT_LENGTH)
+            .and_then(|length| length.parse().ok())
+            .unwrap_or(0),
+        modified: header(LAST_MODIFIED)
+            .and_then(|modified| httpdate::parse_http_date(modified).ok()),
+        content_type: header(CONTENT_TYPE).map(str::to_string),
+    }
+}
+
+fn error(status: StatusCode) -> io::Error {
+    let kind = match status {
+        StatusCode::FORBIDDEN | StatusCode::UNAUTHORIZED => io::ErrorKind::PermissionDenied,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(kind, format!("S3 responded with {}", status))
+}
+
+/// URI-encode `value` as required by Signature Version 4. Slashes are kept in paths.
+fn encode(value: &str, slash: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Format `time` as `YYYYMMDD'T'HHMMSS'Z'`.
+fn amz_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Parse a timestamp such as `2009-10-12T17:50:30.000Z`.
+fn parse_iso8601(value: &str) -> Option<SystemTime> {
+    let number = |range: std::ops::Range<usize>| value.get(range)?.parse::<u64>().ok();
+    let days = days_from_civil(number(0..4)? as i64, number(5..7)?, number(8..10)?);
+    let secs = number(11..13)? * 3600 + number(14..16)? * 60 + number(17..19)?;
+    let secs = u64::try_from(days).ok()? * 86400 + secs;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+// Conversions between days since the epoch and dates, after Howard Hinnant's algorithms.
+fn civil_from_days(days: i64) -> (i64, u64, u64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe as i64 + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u64, day: u64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400) as u64;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe as i64 - 719_468
+}
+
+/// Get the contents of all `<tag>` elements in `xml`.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut found = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        match after.find(&close) {
+            Some(end) => {
+                found.push(&after[..end]);
+                rest = &after[end + close.len()..];
+            }
+            None => break,
+        }
+    }
+    found
+}
+
+/// Get the unescaped text of the first `<tag>` element in `xml`.
+fn element(xml: &str, tag: &str) -> Option<String> {
+    let text = elements(xml, tag).into_iter().next()?;
+    Some(
+        text.replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
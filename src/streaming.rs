@@ -0,0 +1,253 @@
+//! Streamed responses.
+//!
+//! Keiro never buffers a response body on its own: the router, [`Accounting`] and the
+//! middleware built into keiro pass the body through chunk by chunk as the handler produces
+//! it. Layers which would otherwise buffer, such as caching and compression, leave responses
+//! marked with [`must_stream`] alone, or flush every chunk as soon as it is produced.
+//!
+//! Mark responses which are consumed while they are produced, such as progress updates or
+//! event streams, so that they keep streaming when new middleware is added.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::body::Bytes;
+//! use hyper::{Body, Request, Response};
+//! use keiro::streaming;
+//!
+//! async fn progress(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let (mut sender, body) = Body::channel();
+//!     tokio::spawn(async move {
+//!         for percent in (0..=100).step_by(10) {
+//!             let line = format!("{}%\n", percent);
+//!             if sender.send_data(Bytes::from(line)).await.is_err() {
+//!                 break;
+//!             }
+//!         }
+//!     });
+//!
+//!     let mut res = Response::new(body);
+//!     streaming::must_stream(&mut res);
+//!     Ok(res)
+//! }
+//! ```
+//!
+//! For incremental output produced by the handler itself, [`writer`] returns a response
+//! whose body is written with a [`ResponseWriter`]. Writes fail with [`Disconnected`] once
+//! the client has gone away, so long exports can stop their work instead of producing output
+//! nobody reads.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::streaming;
+//!
+//! async fn export(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let (writer, res) = streaming::writer();
+//!     tokio::spawn(async move {
+//!         for row in 0..1_000_000 {
+//!             // Stop querying once the client has disconnected.
+//!             if writer.write(format!("{}\n", row)).await.is_err() {
+//!                 return;
+//!             }
+//!         }
+//!     });
+//!     Ok(res)
+//! }
+//! ```
+//!
+//! With the `json` feature, [`ndjson`] streams the items of a stream as newline-delimited
+//! JSON, one line per item, serializing each item only once the client has received the
+//! previous ones.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::streaming;
+//!
+//! #[derive(serde::Serialize)]
+//! struct Event {
+//!     id: u64,
+//! }
+//!
+//! async fn events(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let events = futures_util::stream::iter((0..1_000_000).map(|id| Event { id }));
+//!     Ok(streaming::ndjson(events))
+//! }
+//! ```
+//!
+//! With the `csv` feature, [`csv()`] streams the items of a stream as a CSV download, for
+//! report exports.
+//!
+//! [`Accounting`]: crate::accounting::Accounting
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use hyper::body::Bytes;
+use hyper::{Body, Response};
+use tokio::sync::mpsc;
+
+/// A response extension which tells middleware never to buffer the response body.
+#[derive(Debug, Clone, Copy)]
+pub struct MustStream;
+
+/// Mark `res` so its body is sent chunk by chunk as it is produced.
+pub fn must_stream(res: &mut Response<Body>) {
+    res.extensions_mut().insert(MustStream);
+}
+
+/// Check whether `res` is marked with [`must_stream`].
+pub fn is_must_stream(res: &Response<Body>) -> bool {
+    res.extensions().get::<MustStream>().is_some()
+}
+
+/// The error of a [`ResponseWriter`] whose client has disconnected, or whose response was
+/// dropped before it was sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Disconnected;
+
+impl fmt::Display for Disconnected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the client has disconnected")
+    }
+}
+
+impl Error for Disconnected {}
+
+/// A writer for the body of a response created with [`writer`].
+///
+/// The body ends when the writer and its clones are dropped. At most one chunk is buffered, so writing waits
+/// until the client has received the previous ones.
+#[derive(Debug, Clone)]
+pub struct ResponseWriter {
+    sender: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl ResponseWriter {
+    /// Write `data` to the body, failing if the client has disconnected.
+    pub async fn write(&self, data: impl Into<Bytes>) -> Result<(), Disconnected> {
+        self.sender
+            .send(Ok(data.into()))
+            .await
+            .map_err(|_| Disconnected)
+    }
+
+    /// Check whether the client has disconnected.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Wait until the client has disconnected, e.g. to cancel work between writes with
+    /// `tokio::select!`.
+    pub async fn closed(&self) {
+        self.sender.closed().await
+    }
+
+    /// End the body with an error instead of completing it, so the client sees a truncated
+    /// response rather than one which looks complete, e.g. when an export fails halfway.
+    pub async fn abort(self, reason: impl Into<Box<dyn Error + Send + Sync>>) {
+        let err = io::Error::other(reason);
+        let _ = self.sender.send(Err(err)).await;
+    }
+}
+
+/// Create a response streaming what is written with the returned [`ResponseWriter`]. The
+/// response is marked with [`must_stream`].
+pub fn writer() -> (ResponseWriter, Response<Body>) {
+    let (sender, receiver) = mpsc::channel(1);
+    let stream = futures_util::stream::unfold(receiver, |mut receiver| async move {
+        let chunk = receiver.recv().await?;
+        Some((chunk, receiver))
+    });
+    let mut res = Response::new(Body::wrap_stream(stream));
+    must_stream(&mut res);
+    (ResponseWriter { sender }, res)
+}
+
+/// Create a response streaming the items of `stream` as newline-delimited JSON with the
+/// content type `application/x-ndjson`. The response is marked with [`must_stream`]. If an
+/// item can't be serialized, the body ends with an error, so the client sees a truncated
+/// response.
+///
+/// Requires the `json` feature.
+#[cfg(feature = "json")]
+pub fn ndjson<S>(stream: S) -> Response<Body>
+where
+    S: futures_util::Stream + Send + 'static,
+    S::Item: serde::Serialize,
+{
+    use futures_util::StreamExt;
+
+    let lines = stream.map(|item| {
+        let mut line = serde_json::to_vec(&item).map_err(io::Error::other)?;
+        line.push(b'\n');
+        Ok::<_, io::Error>(Bytes::from(line))
+    });
+    let mut res = Response::new(Body::wrap_stream(lines));
+    res.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/x-ndjson"),
+    );
+    must_stream(&mut res);
+    res
+}
+
+/// Create a response streaming the items of `stream` as rows of CSV with the content type
+/// `text/csv`, offered as a download saved as `filename`. Items are structs, maps or tuples;
+/// the field names of the first struct or map item are written as the header row, so bodies
+/// of empty streams are empty. The response is marked with [`must_stream`]. If an item can't
+/// be serialized, e.g. one with nested fields, the body ends with an error, so the client
+/// sees a truncated response.
+///
+/// Requires the `csv` feature.
+///
+/// ```rust,no_run
+/// use std::convert::Infallible;
+///
+/// use hyper::{Body, Request, Response};
+/// use keiro::streaming;
+///
+/// #[derive(serde::Serialize)]
+/// struct Sale {
+///     id: u64,
+///     amount: f64,
+/// }
+///
+/// async fn report(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+///     let sales = futures_util::stream::iter((0..1_000_000).map(|id| Sale { id, amount: 9.99 }));
+///     Ok(streaming::csv(sales, "sales.csv"))
+/// }
+/// ```
+#[cfg(feature = "csv")]
+pub fn csv<S>(stream: S, filename: &str) -> Response<Body>
+where
+    S: futures_util::Stream + Send + 'static,
+    S::Item: serde::Serialize,
+{
+    use futures_util::StreamExt;
+
+    let mut first = true;
+    let rows = stream.map(move |item| {
+        let mut writer = ::csv::WriterBuilder::new()
+            .has_headers(std::mem::replace(&mut first, false))
+            .from_writer(Vec::new());
+        writer.serialize(item).map_err(io::Error::other)?;
+        let row = writer.into_inner().map_err(|err| err.into_error())?;
+        Ok::<_, io::Error>(Bytes::from(row))
+    });
+    let mut res = Response::new(Body::wrap_stream(rows));
+    res.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("text/csv; charset=utf-8"),
+    );
+    res.headers_mut().insert(
+        hyper::header::CONTENT_DISPOSITION,
+        crate::util::content_disposition("attachment", filename),
+    );
+    must_stream(&mut res);
+    res
+}
@@ -0,0 +1,130 @@
+//! Sections of the route table which can be replaced at runtime.
+//!
+//! A router's routes are fixed once it's served, so matching a request never takes a lock. A
+//! [`Swappable`] nested with [`Router::nest_swappable`](crate::Router::nest_swappable) is the
+//! exception: requests under its prefix are routed by its current router, which can be
+//! replaced with [`Swappable::swap`] while the service runs, e.g. when routes are generated
+//! from configuration. Only requests to the swappable section pay for reading its current
+//! router; the rest of the table stays fixed.
+//!
+//! Requests which were already routed finish with the handler they matched.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::swap::Swappable;
+//! use keiro::Router;
+//!
+//! fn plugins(names: &[&str]) -> Router {
+//!     let mut router = Router::new();
+//!     for name in names {
+//!         router.get(&format!("/{}", name), plugin);
+//!     }
+//!     router
+//! }
+//!
+//! let section = Swappable::new(plugins(&["search"]));
+//! let mut router = Router::new();
+//! router.get("/", plugin);
+//! router.nest_swappable("/plugins", &section);
+//!
+//! // Later, once the configuration changed:
+//! section.swap(plugins(&["search", "export"]));
+//!
+//! async fn plugin(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::empty()))
+//! }
+//! ```
+
+use std::error::Error;
+use std::sync::{Arc, RwLock};
+
+use hyper::{Body, Method, Request};
+
+use crate::explain::Step;
+use crate::route::Matched;
+use crate::{Router, Routes};
+
+/// A router nested in another one which can be replaced while it's served.
+///
+/// Clones share the current router, so a section swapped through one clone is swapped in every
+/// router it's nested in.
+pub struct Swappable<E> {
+    current: Arc<RwLock<Arc<dyn Routes<E>>>>,
+}
+
+impl<E> Clone for Swappable<E> {
+    fn clone(&self) -> Self {
+        Self {
+            current: self.current.clone(),
+        }
+    }
+}
+
+impl<E> Swappable<E>
+where
+    E: Into<Box<dyn Error + Send + Sync>> + 'static,
+{
+    pub fn new<S>(router: Router<E, S>) -> Self
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Self {
+            current: Arc::new(RwLock::new(Arc::new(router))),
+        }
+    }
+
+    /// Route the requests to this section with `router` from now on.
+    pub fn swap<S>(&self, router: Router<E, S>)
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        let router: Arc<dyn Routes<E>> = Arc::new(router);
+        match self.current.write() {
+            Ok(mut current) => *current = router,
+            Err(poisoned) => *poisoned.into_inner() = router,
+        }
+    }
+
+    fn current(&self) -> Arc<dyn Routes<E>> {
+        // The lock is only held to clone the router, so the old one is dropped outside of it.
+        match self.current.read() {
+            Ok(current) => current.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+}
+
+impl<E> Routes<E> for Swappable<E>
+where
+    E: Into<Box<dyn Error + Send + Sync>> + 'static,
+{
+    fn route(
+        &self,
+        req: Request<Body>,
+        path: &str,
+        params: route_recognizer::Params,
+        prefix: &str,
+    ) -> Result<Matched<E>, Request<Body>> {
+        self.current().route(req, path, params, prefix)
+    }
+
+    fn named(&self, name: &str) -> Option<String> {
+        self.current().named(name)
+    }
+
+    fn explain_route(
+        &self,
+        method: &Method,
+        path: &str,
+        prefix: &str,
+        steps: &mut Vec<Step>,
+    ) -> bool {
+        self.current().explain_route(method, path, prefix, steps)
+    }
+
+    fn methods(&self, path: &str, methods: &mut Vec<Method>) {
+        self.current().methods(path, methods)
+    }
+}
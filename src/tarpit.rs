@@ -0,0 +1,165 @@
+//! Slow responses for suspicious requests.
+//!
+//! A [`Tarpit`] answers a request with a response body which is dripped out a few bytes
+//! at a time, keeping scanners busy for a long time at a small cost to the server. The
+//! status and headers of the response are sent right away. Only rejected requests are
+//! affected:
+//!
+//! - requests matching a [`ProbeFilter`](crate::probe::ProbeFilter) set with
+//!   [`ProbeFilter::tarpit`](crate::probe::ProbeFilter::tarpit), with the status of the
+//!   filter;
+//! - requests rejected by the authentication or the required scopes of a route, with
+//!   [`Route::tarpit`](crate::Route::tarpit) or [`Router::tarpit`](crate::Router::tarpit), with
+//!   the status and headers of the rejection.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::basic_auth::BasicAuth;
+//! use keiro::probe::ProbeFilter;
+//! use keiro::tarpit::Tarpit;
+//! use keiro::Router;
+//!
+//! let tarpit = Tarpit::new()
+//!     .interval(Duration::from_secs(5))
+//!     .duration(Duration::from_secs(300));
+//! let mut router = Router::new();
+//! router.get("/", index);
+//! router
+//!     .get("/admin", index)
+//!     .basic_auth(BasicAuth::users("admin", vec![("admin", "secret")]))
+//!     .tarpit(tarpit.clone());
+//! router.probe_filter(ProbeFilter::default().tarpit(tarpit));
+//!
+//! async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("Hello keiro!")))
+//! }
+//! ```
+
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, Response};
+
+use crate::streaming;
+
+/// Configuration for dripping responses to suspicious requests.
+#[derive(Debug, Clone)]
+pub struct Tarpit {
+    interval: Duration,
+    duration: Duration,
+    chunk: Bytes,
+    max_concurrent: usize,
+    active: Arc<AtomicUsize>,
+}
+
+impl Default for Tarpit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tarpit {
+    /// Create a tarpit which sends one byte every 10 seconds for 10 minutes.
+    pub fn new() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            duration: Duration::from_secs(600),
+            chunk: Bytes::from_static(b" "),
+            max_concurrent: 64,
+            active: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Set the interval between chunks.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set how long a response is dripped before it ends.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// Set the bytes sent on every interval.
+    pub fn chunk(mut self, chunk: impl Into<Bytes>) -> Self {
+        self.chunk = chunk.into();
+        self
+    }
+
+    /// Set how many requests can be held at the same time. Once the limit is reached,
+    /// requests are answered immediately, so the tarpit can't exhaust the server itself.
+    /// Tarpits cloned from one another share the limit.
+    pub fn max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = max_concurrent;
+        self
+    }
+
+    /// Replace the body of `res` with a dripping one, unless too many requests are already
+    /// held.
+    pub(crate) fn drip(&self, res: Response<Body>) -> Response<Body> {
+        let slot = match Slot::acquire(&self.active, self.max_concurrent) {
+            Some(slot) => slot,
+            None => return res,
+        };
+        let interval = self.interval;
+        let chunk = self.chunk.clone();
+        let count = if interval.as_nanos() == 0 {
+            0
+        } else {
+            self.duration.as_nanos() / interval.as_nanos()
+        };
+
+        let stream = futures_util::stream::unfold((slot, count), move |(slot, remaining)| {
+            let chunk = chunk.clone();
+            async move {
+                if remaining == 0 {
+                    return None;
+                }
+                tokio::time::sleep(interval).await;
+                Some((Ok::<_, Infallible>(chunk), (slot, remaining - 1)))
+            }
+        });
+
+        let (mut parts, _) = res.into_parts();
+        parts.headers.remove(CONTENT_LENGTH);
+        parts
+            .headers
+            .entry(CONTENT_TYPE)
+            .or_insert(HeaderValue::from_static("text/html"));
+        let mut res = Response::from_parts(parts, Body::wrap_stream(stream));
+        streaming::must_stream(&mut res);
+        res
+    }
+}
+
+struct Slot(Arc<AtomicUsize>);
+
+impl Slot {
+    fn acquire(active: &Arc<AtomicUsize>, max: usize) -> Option<Self> {
+        active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| {
+                if n < max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .ok()
+            .map(|_| Slot(active.clone()))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
@@ -0,0 +1,133 @@
+//! Server-side rendered pages.
+//!
+//! With the `askama` feature, [`Page`] wraps a compiled askama template, so handlers answer
+//! with the rendered HTML by converting it into a response. With the `tera` feature,
+//! [`Templates`] holds a Tera engine loaded once at startup. It's added to the router with
+//! [`Router::manage`](crate::Router::manage) and extracted by handlers.
+//!
+//! Templates failing to render are answered with `500 Internal Server Error`, or fail with an
+//! [`Error`] with [`Page::render`] and [`Templates::render`], so the cause shows up in the
+//! [error handler](crate::Router::error_handler) and development error pages.
+
+#[cfg(feature = "tera")]
+use std::sync::Arc;
+
+use hyper::{Body, Response};
+
+use crate::ext::ResponseExt;
+#[cfg(feature = "tera")]
+use crate::extract::{FromRequest, Rejection};
+use crate::Error;
+
+/// A compiled askama template, answered as an HTML page.
+///
+/// Requires the `askama` feature.
+///
+/// ```rust,no_run
+/// use std::convert::Infallible;
+///
+/// use askama::Template;
+/// use hyper::{Body, Request, Response};
+/// use keiro::templates::Page;
+///
+/// #[derive(Template)]
+/// #[template(source = "<h1>Hello {{ name }}!</h1>", ext = "html")]
+/// struct Hello {
+///     name: String,
+/// }
+///
+/// async fn hello(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+///     Ok(Page(Hello {
+///         name: "giraffate".to_string(),
+///     })
+///     .into())
+/// }
+/// ```
+#[cfg(feature = "askama")]
+#[derive(Debug, Clone)]
+pub struct Page<T>(pub T);
+
+#[cfg(feature = "askama")]
+impl<T: askama::Template> Page<T> {
+    /// Render the template into an HTML response, failing with the error of the template.
+    pub fn render(&self) -> Result<Response<Body>, Error> {
+        let html = self.0.render().map_err(Error::new)?;
+        Ok(Response::html(html))
+    }
+}
+
+#[cfg(feature = "askama")]
+impl<T: askama::Template> From<Page<T>> for Response<Body> {
+    fn from(page: Page<T>) -> Self {
+        page.render().unwrap_or_else(Response::from)
+    }
+}
+
+/// A Tera engine shared by handlers, cheap to clone.
+///
+/// Requires the `tera` feature.
+///
+/// ```rust,no_run
+/// use hyper::{Body, Request, Response};
+/// use keiro::prelude::*;
+/// use keiro::templates::Templates;
+/// use keiro::Router;
+///
+/// let mut router = Router::new();
+/// router.manage(Templates::new("templates/**/*.html").unwrap());
+/// router.get("/hello/:name", hello);
+///
+/// async fn hello(req: Request<Body>) -> keiro::Result<Response<Body>> {
+///     let templates = req.extract::<Templates>()?;
+///     let mut context = tera::Context::new();
+///     context.insert("name", req.params().unwrap().find("name").unwrap());
+///     templates.render("hello.html", &context)
+/// }
+/// ```
+#[cfg(feature = "tera")]
+#[derive(Debug, Clone)]
+pub struct Templates {
+    tera: Arc<tera::Tera>,
+}
+
+#[cfg(feature = "tera")]
+impl Templates {
+    /// Load the templates matching the glob `pattern`, such as `templates/**/*.html`, failing
+    /// if one can't be parsed.
+    pub fn new(pattern: &str) -> Result<Self, tera::Error> {
+        Ok(tera::Tera::new(pattern)?.into())
+    }
+
+    /// Render the template `name` with `context` into an HTML response, failing with the error
+    /// of the template.
+    pub fn render(&self, name: &str, context: &tera::Context) -> Result<Response<Body>, Error> {
+        let html = self.tera.render(name, context).map_err(Error::new)?;
+        Ok(Response::html(html))
+    }
+
+    /// Get the engine, e.g. to render templates which aren't pages.
+    pub fn tera(&self) -> &tera::Tera {
+        &self.tera
+    }
+}
+
+#[cfg(feature = "tera")]
+impl From<tera::Tera> for Templates {
+    fn from(tera: tera::Tera) -> Self {
+        Self {
+            tera: Arc::new(tera),
+        }
+    }
+}
+
+#[cfg(feature = "tera")]
+impl FromRequest for Templates {
+    fn from_request(req: &hyper::Request<Body>) -> Result<Self, Rejection> {
+        req.extensions().get::<Templates>().cloned().ok_or_else(|| {
+            Rejection::new(
+                hyper::StatusCode::INTERNAL_SERVER_ERROR,
+                "no templates configured",
+            )
+        })
+    }
+}
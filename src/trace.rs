@@ -0,0 +1,230 @@
+//! Request context in log fields.
+//!
+//! With a [`TraceContext`] installed by [`Router::trace_context`](crate::Router::trace_context),
+//! every handler runs inside a `request` span carrying the request id, the matched route
+//! template, the tenant and the principal, so every event logged by the handler has them as
+//! fields without passing them around.
+//!
+//! The request id is the [`RequestId`] of the request if the router has
+//! [request ids](crate::request_id), or else taken from the request id header, or generated
+//! and set on the request if it's missing. The tenant is taken from a header if one is
+//! configured. Both can be recorded later on with [`record_tenant`] and [`record_principal`],
+//! e.g. once a handler has authenticated the client.
+//!
+//! With [trace context propagation](crate::otel), it also has the `trace_id`, `span_id` and
+//! `parent_span_id` of the request in its distributed trace.
+//!
+//! Once the handler is done, the span gets the `status` of the response, the `latency_ms` of
+//! the handler, and the `error` it returned, if any. Its `otel.name` is the method and the
+//! route template, such as `GET /reports/:id`, or just the method for requests which didn't
+//! match a route, so tracing backends name spans with few distinct values rather than after
+//! every path.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::trace::{self, TraceContext};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.trace_context(TraceContext::new().tenant_header("x-tenant-id"));
+//! router.get("/reports/:id", report);
+//!
+//! async fn report(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     trace::record_principal("giraffate");
+//!     // Logged with `request_id`, `route="/reports/:id"`, `tenant` and `principal`.
+//!     tracing::info!("report requested");
+//!     Ok(Response::new(Body::from("report")))
+//! }
+//! ```
+
+use std::any::Any;
+use std::collections::hash_map::RandomState;
+use std::error::Error as StdError;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Body, Request};
+use tracing::field::{display, Empty};
+use tracing::{Instrument, Span};
+
+use crate::request_id::RequestId;
+use crate::{Error, HandlerFuture};
+
+/// Which request headers the fields of the `request` span are taken from.
+#[derive(Debug, Clone)]
+pub struct TraceContext {
+    request_id_header: HeaderName,
+    tenant_header: Option<HeaderName>,
+    ids: RandomState,
+}
+
+impl Default for TraceContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceContext {
+    /// Take request ids from `x-request-id` and don't take tenants from a header.
+    pub fn new() -> Self {
+        Self {
+            request_id_header: HeaderName::from_static("x-request-id"),
+            tenant_header: None,
+            ids: RandomState::new(),
+        }
+    }
+
+    /// Take request ids from the header `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` isn't a valid header name.
+    pub fn request_id_header(mut self, name: &str) -> Self {
+        self.request_id_header = name.parse().expect("invalid header name");
+        self
+    }
+
+    /// Take tenants from the header `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` isn't a valid header name.
+    pub fn tenant_header(mut self, name: &str) -> Self {
+        self.tenant_header = Some(name.parse().expect("invalid header name"));
+        self
+    }
+
+    /// Create the span for `req`, setting a generated request id on `req` if it has none.
+    fn span(&self, req: &mut Request<Body>, route: Option<&str>) -> Span {
+        let request_id = match req.extensions().get::<RequestId>() {
+            Some(id) => Some(id.to_string()),
+            None => header(req, &self.request_id_header).map(str::to_string),
+        };
+        let request_id = match request_id {
+            Some(id) => id,
+            None => {
+                let id = self.generate_id();
+                if let Ok(value) = HeaderValue::from_str(&id) {
+                    req.headers_mut()
+                        .insert(self.request_id_header.clone(), value);
+                }
+                id
+            }
+        };
+        let name = match route {
+            Some(route) => format!("{} {}", req.method(), route),
+            None => req.method().to_string(),
+        };
+        let span = tracing::info_span!(
+            "request",
+            otel.name = %name,
+            method = %req.method(),
+            request_id = %request_id,
+            trace_id = Empty,
+            span_id = Empty,
+            parent_span_id = Empty,
+            route = Empty,
+            tenant = Empty,
+            principal = Empty,
+            status = Empty,
+            latency_ms = Empty,
+            error = Empty,
+        );
+        if let Some(route) = route {
+            span.record("route", route);
+        }
+        #[cfg(feature = "otel")]
+        if let Some(context) = req.extensions().get::<crate::otel::SpanContext>() {
+            span.record(
+                "trace_id",
+                display(format_args!("{:032x}", context.trace_id)),
+            );
+            span.record("span_id", display(format_args!("{:016x}", context.span_id)));
+            if let Some(parent) = context.parent_span_id {
+                span.record("parent_span_id", display(format_args!("{:016x}", parent)));
+            }
+        }
+        if let Some(tenant) = self
+            .tenant_header
+            .as_ref()
+            .and_then(|name| header(req, name))
+        {
+            span.record("tenant", tenant);
+        }
+        span
+    }
+
+    fn generate_id(&self) -> String {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let mut hasher = self.ids.build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+/// Record `tenant` on the `request` span of the current handler.
+pub fn record_tenant(tenant: &str) {
+    Span::current().record("tenant", tenant);
+}
+
+/// Record `principal`, e.g. the authenticated user, on the `request` span of the current
+/// handler.
+pub fn record_principal(principal: &str) {
+    Span::current().record("principal", principal);
+}
+
+fn header<'a>(req: &'a Request<Body>, name: &HeaderName) -> Option<&'a str> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+}
+
+/// Run the future returned by `call` for `req` inside the `request` span of `context`. The
+/// `route` field is left empty for requests which didn't match a route.
+pub(crate) fn instrument<E: 'static>(
+    context: &TraceContext,
+    mut req: Request<Body>,
+    route: Option<&str>,
+    call: impl FnOnce(Request<Body>) -> HandlerFuture<E>,
+) -> HandlerFuture<E> {
+    let span = context.span(&mut req, route);
+    let started = Instant::now();
+    // Enter the span while calling, so handlers which log before their first await
+    // are covered as well.
+    let fut = span.in_scope(|| call(req));
+    let outcome = span.clone();
+    Box::pin(
+        async move {
+            let result = fut.await;
+            outcome.record("latency_ms", started.elapsed().as_secs_f64() * 1000.0);
+            match &result {
+                Ok(res) => {
+                    outcome.record("status", res.status().as_u16());
+                }
+                Err(err) => record_error(&outcome, err),
+            }
+            result
+        }
+        .instrument(span),
+    )
+}
+
+/// Record `err` on `span`, with its message if it's one of keiro's errors or a boxed error.
+fn record_error<E: 'static>(span: &Span, err: &E) {
+    let err = err as &dyn Any;
+    if let Some(err) = err.downcast_ref::<Error>() {
+        span.record("status", err.status().as_u16());
+        span.record("error", display(err));
+    } else if let Some(err) = err.downcast_ref::<Box<dyn StdError + Send + Sync>>() {
+        span.record("status", 500);
+        span.record("error", display(err));
+    } else {
+        span.record("status", 500);
+        span.record("error", true);
+    }
+}
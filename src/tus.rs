@@ -0,0 +1,364 @@
+//! Resumable uploads with the [tus](https://tus.io/protocols/resumable-upload) protocol.
+//!
+//! [`Tus`] provides the handlers creating uploads, reporting their offsets and appending to
+//! them, so clients can resume large uploads after a dropped connection. Uploads are kept in an
+//! [`UploadStore`], such as a [`DiskStore`].
+//!
+//! Requires the `tus` feature.
+//!
+//! ```rust,no_run
+//! use keiro::tus::{DiskStore, Tus};
+//! use keiro::Router;
+//!
+//! let tus = Tus::new(DiskStore::new("/var/lib/uploads")).max_size(4 * 1024 * 1024 * 1024);
+//!
+//! let mut router = Router::new();
+//! router.nest("/uploads", tus.router());
+//! ```
+
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use futures_util::StreamExt;
+use hyper::header::{HeaderName, HeaderValue, CACHE_CONTROL, CONTENT_TYPE, LOCATION};
+use hyper::{Body, Request, Response, StatusCode};
+use tokio::io::AsyncWriteExt;
+
+use crate::limits::{self, LengthLimitExceeded};
+use crate::prelude::*;
+use crate::Router;
+
+const VERSION: &str = "1.0.0";
+const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+const TUS_VERSION: HeaderName = HeaderName::from_static("tus-version");
+const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+
+/// The future returned by [`UploadStore`] methods.
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + Sync + 'a>>;
+
+/// The state of an upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadInfo {
+    /// The bytes received so far.
+    pub offset: u64,
+    /// The size of the complete upload.
+    pub length: u64,
+    /// The raw `Upload-Metadata` header sent when the upload was created.
+    pub metadata: Option<String>,
+}
+
+/// Storage for uploads in progress.
+pub trait UploadStore: Send + Sync + 'static {
+    /// Create an empty upload with the random `id`.
+    fn create(&self, id: &str, length: u64, metadata: Option<String>) -> StoreFuture<'_, ()>;
+
+    /// Get the state of the upload `id`, or `None` if it doesn't exist.
+    fn info(&self, id: &str) -> StoreFuture<'_, Option<UploadInfo>>;
+
+    /// Append `body` to the upload `id`, which has received `offset` bytes so far, and return
+    /// the new offset. Data received before `body` fails must be kept, so the client can
+    /// resume from there.
+    fn append(&self, id: &str, offset: u64, body: Body) -> StoreFuture<'_, u64>;
+}
+
+/// An [`UploadStore`] keeping uploads as files in a directory.
+#[derive(Debug, Clone)]
+pub struct DiskStore {
+    dir: PathBuf,
+}
+
+impl DiskStore {
+    /// Store uploads in `dir`, which must exist.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Get the path of the data of the upload `id`, e.g. to move it once it is complete.
+    pub fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.info", id))
+    }
+}
+
+impl UploadStore for DiskStore {
+    fn create(&self, id: &str, length: u64, metadata: Option<String>) -> StoreFuture<'_, ()> {
+        let id = id.to_string();
+        Box::pin(async move {
+            let info = format!("{}\n{}", length, metadata.unwrap_or_default());
+            tokio::fs::write(self.info_path(&id), info).await?;
+            tokio::fs::File::create(self.path(&id)).await?;
+            Ok(())
+        })
+    }
+
+    fn info(&self, id: &str) -> StoreFuture<'_, Option<UploadInfo>> {
+        let id = id.to_string();
+        Box::pin(async move {
+            let info = match tokio::fs::read_to_string(self.info_path(&id)).await {
+                Ok(info) => info,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            let (length, metadata) = info.split_once('\n').unwrap_or((&info, ""));
+            let length = length
+                .parse()
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            let offset = tokio::fs::metadata(self.path(&id)).await?.len();
+            Ok(Some(UploadInfo {
+                offset,
+                length,
+                metadata: Some(metadata.to_string()).filter(|metadata| !metadata.is_empty()),
+            }))
+        })
+    }
+
+    fn append(&self, id: &str, offset: u64, mut body: Body) -> StoreFuture<'_, u64> {
+        let id = id.to_string();
+        Box::pin(async move {
+            let mut file = tokio::fs::OpenOptions::new()
+                .append(true)
+                .open(self.path(&id))
+                .await?;
+            let mut offset = offset;
+            while let Some(chunk) = body.next().await {
+                let chunk = chunk.map_err(io::Error::other)?;
+                file.write_all(&chunk).await?;
+                offset += chunk.len() as u64;
+            }
+            file.flush().await?;
+            Ok(offset)
+        })
+    }
+}
+
+/// The handlers of the tus protocol, with the creation extension.
+pub struct Tus {
+    store: Arc<dyn UploadStore>,
+    max_size: Option<u64>,
+}
+
+impl Tus {
+    pub fn new(store: impl UploadStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            max_size: None,
+        }
+    }
+
+    /// Reject uploads larger than `max_size` bytes.
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Build a router with the tus endpoints, to be nested at the upload URL with
+    /// [`Router::nest`](crate::Router::nest). Uploads are created by POST requests to the
+    /// upload URL, and their offsets are reported and appended to at the URL of each upload.
+    pub fn router<E>(self) -> Router<E, ()>
+    where
+        E: Into<Box<dyn Error + Send + Sync>> + 'static,
+    {
+        let tus = Arc::new(self);
+        let mut router: Router<E, ()> = Router::with_error_type(());
+        let options = tus.clone();
+        router.options("/", move |req| {
+            let tus = options.clone();
+            async move { Ok::<_, E>(tus.options(req)) }
+        });
+        let create = tus.clone();
+        router.post("/", move |req| {
+            let tus = create.clone();
+            async move { Ok::<_, E>(tus.create(req).await) }
+        });
+        let head = tus.clone();
+        router.head("/:id", move |req| {
+            let tus = head.clone();
+            async move { Ok::<_, E>(tus.head(req).await) }
+        });
+        router.patch("/:id", move |req| {
+            let tus = tus.clone();
+            async move { Ok::<_, E>(tus.append(req).await) }
+        });
+        router
+    }
+
+    fn options(&self, _req: Request<Body>) -> Response<Body> {
+        let mut res = response(StatusCode::NO_CONTENT);
+        let headers = res.headers_mut();
+        headers.insert(TUS_VERSION, HeaderValue::from_static(VERSION));
+        headers.insert(TUS_EXTENSION, HeaderValue::from_static("creation"));
+        if let Some(max_size) = self.max_size {
+            headers.insert(TUS_MAX_SIZE, max_size.into());
+        }
+        res
+    }
+
+    async fn create(&self, req: Request<Body>) -> Response<Body> {
+        if let Some(res) = check_version(&req) {
+            return res;
+        }
+        let length = match header::<u64>(&req, &UPLOAD_LENGTH) {
+            Some(length) => length,
+            None => return response(StatusCode::BAD_REQUEST),
+        };
+        if self.max_size.is_some_and(|max_size| length > max_size) {
+            return response(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        let metadata = req
+            .headers()
+            .get(UPLOAD_METADATA)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let id = random_id();
+        if self.store.create(&id, length, metadata).await.is_err() {
+            return response(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+        let location = format!("{}/{}", req.uri().path().trim_end_matches('/'), id);
+        let mut res = response(StatusCode::CREATED);
+        if let Ok(location) = HeaderValue::from_str(&location) {
+            res.headers_mut().insert(LOCATION, location);
+        }
+        res
+    }
+
+    async fn head(&self, req: Request<Body>) -> Response<Body> {
+        if let Some(res) = check_version(&req) {
+            return res;
+        }
+        let info = match self.info(&req).await {
+            Ok(info) => info,
+            Err(res) => return res,
+        };
+        let mut res = response(StatusCode::OK);
+        let headers = res.headers_mut();
+        headers.insert(UPLOAD_OFFSET, info.offset.into());
+        headers.insert(UPLOAD_LENGTH, info.length.into());
+        if let Some(metadata) = info
+            .metadata
+            .and_then(|metadata| HeaderValue::from_str(&metadata).ok())
+        {
+            headers.insert(UPLOAD_METADATA, metadata);
+        }
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        res
+    }
+
+    async fn append(&self, mut req: Request<Body>) -> Response<Body> {
+        if let Some(res) = check_version(&req) {
+            return res;
+        }
+        let content_type = req.headers().get(CONTENT_TYPE);
+        if content_type.is_none_or(|value| value != "application/offset+octet-stream") {
+            return response(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        }
+        let info = match self.info(&req).await {
+            Ok(info) => info,
+            Err(res) => return res,
+        };
+        if header::<u64>(&req, &UPLOAD_OFFSET) != Some(info.offset) {
+            return response(StatusCode::CONFLICT);
+        }
+
+        // Data beyond the declared length is rejected.
+        let remaining = info.length - info.offset.min(info.length);
+        if let Some(res) = limits::limit_body(&mut req, remaining as usize) {
+            return res;
+        }
+        let id = id(&req);
+        let body = std::mem::take(req.body_mut());
+        match self.store.append(&id, info.offset, body).await {
+            Ok(offset) => {
+                let mut res = response(StatusCode::NO_CONTENT);
+                res.headers_mut().insert(UPLOAD_OFFSET, offset.into());
+                res
+            }
+            Err(err) if exceeded(&err) => response(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(_) => response(StatusCode::INTERNAL_SERVER_ERROR),
+        }
+    }
+
+    async fn info(&self, req: &Request<Body>) -> Result<UploadInfo, Response<Body>> {
+        match self.store.info(&id(req)).await {
+            Ok(Some(info)) => Ok(info),
+            Ok(None) => Err(response(StatusCode::NOT_FOUND)),
+            Err(_) => Err(response(StatusCode::INTERNAL_SERVER_ERROR)),
+        }
+    }
+}
+
+fn id(req: &Request<Body>) -> String {
+    req.params()
+        .and_then(|params| params.find("id"))
+        .unwrap_or("")
+        .to_string()
+}
+
+fn random_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    // `RandomState` is seeded randomly, so the ids can't be guessed.
+    let mut id = String::new();
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        COUNTER.fetch_add(1, Ordering::Relaxed).hash(&mut hasher);
+        SystemTime::now().hash(&mut hasher);
+        id.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    id
+}
+
+fn header<T: std::str::FromStr>(req: &Request<Body>, name: &HeaderName) -> Option<T> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+/// Return `412 Precondition Failed` if the client doesn't speak the supported version.
+fn check_version(req: &Request<Body>) -> Option<Response<Body>> {
+    if req
+        .headers()
+        .get(TUS_RESUMABLE)
+        .is_some_and(|value| value == VERSION)
+    {
+        return None;
+    }
+    let mut res = response(StatusCode::PRECONDITION_FAILED);
+    res.headers_mut()
+        .insert(TUS_VERSION, HeaderValue::from_static(VERSION));
+    Some(res)
+}
+
+fn exceeded(err: &io::Error) -> bool {
+    let mut source = err.get_ref().map(|err| err as &(dyn Error + 'static));
+    while let Some(err) = source {
+        if err.is::<LengthLimitExceeded>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+fn response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(TUS_RESUMABLE, VERSION)
+        .body(Body::empty())
+        .unwrap()
+}
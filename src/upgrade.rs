@@ -0,0 +1,118 @@
+//! Taking over connections for other protocols.
+//!
+//! [`Upgrade`] checks that a request asks to switch protocols and answers it with
+//! `101 Switching Protocols`. Once hyper has sent the response, the connection is handed to
+//! a callback which can speak any protocol over it.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::upgrade::Upgrade;
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! async fn echo(mut req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     let upgrade = match Upgrade::from_request(&mut req) {
+//!         Ok(upgrade) if upgrade.accepts("echo") => upgrade,
+//!         Ok(_) => return Ok(Response::builder().status(400).body(Body::empty()).unwrap()),
+//!         Err(rejection) => return Ok(rejection.into()),
+//!     };
+//!     Ok(upgrade.on_upgrade("echo", |mut io| async move {
+//!         let mut buf = [0; 1024];
+//!         while let Ok(n) = io.read(&mut buf).await {
+//!             if n == 0 || io.write_all(&buf[..n]).await.is_err() {
+//!                 break;
+//!             }
+//!         }
+//!     }))
+//! }
+//! ```
+
+use std::future::Future;
+
+use hyper::header::{HeaderValue, CONNECTION, UPGRADE};
+use hyper::upgrade::OnUpgrade;
+use hyper::{Body, Request, Response, StatusCode};
+
+use crate::extract::Rejection;
+
+pub use hyper::upgrade::Upgraded;
+
+/// A request to switch the connection to another protocol.
+pub struct Upgrade {
+    on_upgrade: OnUpgrade,
+    protocols: Vec<String>,
+}
+
+impl Upgrade {
+    /// Take the upgrade out of `req`. Requests which don't ask for an upgrade with the
+    /// `Connection` and `Upgrade` headers are rejected with `426 Upgrade Required`.
+    pub fn from_request(req: &mut Request<Body>) -> Result<Self, Rejection> {
+        let connection_upgrade = req
+            .headers()
+            .get_all(CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+        let protocols: Vec<String> = req
+            .headers()
+            .get_all(UPGRADE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|protocol| protocol.trim().to_string())
+            .filter(|protocol| !protocol.is_empty())
+            .collect();
+        if !connection_upgrade || protocols.is_empty() {
+            return Err(Rejection::new(
+                StatusCode::UPGRADE_REQUIRED,
+                "the request doesn't ask for a protocol upgrade",
+            ));
+        }
+
+        Ok(Self {
+            on_upgrade: hyper::upgrade::on(req),
+            protocols,
+        })
+    }
+
+    /// Get the protocols the client asked for, in order of preference.
+    pub fn protocols(&self) -> impl Iterator<Item = &str> {
+        self.protocols.iter().map(String::as_str)
+    }
+
+    /// Check whether the client asked for `protocol`, ignoring case.
+    pub fn accepts(&self, protocol: &str) -> bool {
+        self.protocols()
+            .any(|requested| requested.eq_ignore_ascii_case(protocol))
+    }
+
+    /// Switch the connection to `protocol`, returning the `101 Switching Protocols` response
+    /// to send. Once it is sent, `callback` is run on a new task with the connection.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `protocol` is not a valid header value.
+    pub fn on_upgrade<F, Fut>(self, protocol: &str, callback: F) -> Response<Body>
+    where
+        F: FnOnce(Upgraded) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let protocol = HeaderValue::from_str(protocol).expect("invalid protocol");
+        let on_upgrade = self.on_upgrade;
+        tokio::spawn(async move {
+            // The upgrade fails when the client goes away before the response is sent.
+            if let Ok(upgraded) = on_upgrade.await {
+                callback(upgraded).await;
+            }
+        });
+
+        Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, protocol)
+            .body(Body::empty())
+            .unwrap()
+    }
+}
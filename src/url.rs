@@ -0,0 +1,222 @@
+//! URLs for named routes.
+//!
+//! Routes named with [`Route::name`](crate::Route::name) can be turned back into URLs with
+//! [`Router::url_for`](crate::Router::url_for). With the `signed-urls` feature,
+//! [`Router::signed_url_for`](crate::Router::signed_url_for) mints URLs which expire, for
+//! temporary download or upload links, and routes marked with
+//! [`Route::require_signature`](crate::Route::require_signature) only accept such URLs.
+//!
+//! ```rust,no_run
+//! # #[cfg(feature = "signed-urls")]
+//! # {
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//!
+//! use hyper::{Body, Request, Response};
+//! use keiro::Router;
+//!
+//! let mut router = Router::new();
+//! router.signing_key(b"a long random secret".to_vec());
+//! router
+//!     .get("/files/:id", download)
+//!     .name("download")
+//!     .require_signature();
+//!
+//! // e.g. `/files/42?expires=1700000000&signature=...`
+//! let url = router
+//!     .signed_url_for("download", &[("id", "42")], Duration::from_secs(600))
+//!     .unwrap();
+//!
+//! async fn download(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("file contents")))
+//! }
+//! # }
+//! ```
+
+use std::error::Error;
+use std::fmt;
+
+/// An error while building the URL of a named route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UrlError {
+    /// No route has this name.
+    UnknownRoute(String),
+    /// The route pattern has a parameter with this name which wasn't given.
+    MissingParam(String),
+    /// Signed URLs were requested but the router has no signing key.
+    #[cfg(feature = "signed-urls")]
+    NoSigningKey,
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UrlError::UnknownRoute(name) => write!(f, "no route is named {:?}", name),
+            UrlError::MissingParam(name) => write!(f, "missing route parameter {:?}", name),
+            #[cfg(feature = "signed-urls")]
+            UrlError::NoSigningKey => f.write_str("the router has no signing key"),
+        }
+    }
+}
+
+impl Error for UrlError {}
+
+/// Fill the parameters of `pattern` with `params`. Values of `:params` are percent-encoded,
+/// values of wildcards may contain slashes.
+pub(crate) fn build(pattern: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+    let find = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| UrlError::MissingParam(name.to_string()))
+    };
+
+    let mut url = String::new();
+    for (i, segment) in pattern.split('/').enumerate() {
+        if i > 0 {
+            url.push('/');
+        }
+        if let Some(name) = segment.strip_prefix(':') {
+            encode(&mut url, find(name)?, false);
+        } else if let Some(name) = segment.strip_prefix('*') {
+            encode(&mut url, find(name)?, true);
+        } else {
+            url.push_str(segment);
+        }
+    }
+    Ok(url)
+}
+
+pub(crate) fn encode(url: &mut String, value: &str, keep_slashes: bool) {
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                url.push(byte as char)
+            }
+            b'/' if keep_slashes => url.push('/'),
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+}
+
+/// Decode the percent-encoded `value`, or return `None` if it is not valid UTF-8.
+pub(crate) fn decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = value
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+#[cfg(feature = "signed-urls")]
+pub(crate) use self::signed::{sign, verify};
+
+#[cfg(feature = "signed-urls")]
+mod signed {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use hmac::{Hmac, Mac};
+    use hyper::{Body, Request, Response, StatusCode};
+    use sha2::Sha256;
+
+    const EXPIRES: &str = "expires";
+    const SIGNATURE: &str = "signature";
+
+    fn mac(key: &[u8], message: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+        mac.update(message.as_bytes());
+        mac
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+
+    /// Append an expiry time and a signature over the whole URL to `url`.
+    pub(crate) fn sign(url: &str, key: &[u8], ttl: Duration) -> String {
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let url = format!(
+            "{}{}{}={}",
+            url,
+            separator,
+            EXPIRES,
+            now().saturating_add(ttl.as_secs())
+        );
+        let signature: String = mac(key, &url)
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}&{}={}", url, SIGNATURE, signature)
+    }
+
+    /// Return `403 Forbidden` unless `req` has a valid signature which hasn't expired.
+    pub(crate) fn verify(req: &Request<Body>, key: Option<&[u8]>) -> Option<Response<Body>> {
+        let forbidden = |message: &'static str| {
+            Some(
+                Response::builder()
+                    .status(StatusCode::FORBIDDEN)
+                    .body(Body::from(message))
+                    .unwrap(),
+            )
+        };
+        let key = match key {
+            Some(key) => key,
+            None => return forbidden("invalid signature"),
+        };
+
+        let query = req.uri().query().unwrap_or("");
+        let mut signature = None;
+        let mut expires = None;
+        let mut signed = Vec::new();
+        for pair in query.split('&') {
+            match pair.split_once('=') {
+                Some((SIGNATURE, value)) => signature = Some(value),
+                Some((EXPIRES, value)) => {
+                    expires = value.parse::<u64>().ok();
+                    signed.push(pair);
+                }
+                _ => signed.push(pair),
+            }
+        }
+        let signature = match signature.and_then(decode_hex) {
+            Some(signature) => signature,
+            None => return forbidden("invalid signature"),
+        };
+        let url = format!("{}?{}", req.uri().path(), signed.join("&"));
+        if mac(key, &url).verify_slice(&signature).is_err() {
+            return forbidden("invalid signature");
+        }
+        match expires {
+            Some(expires) if expires >= now() => None,
+            _ => forbidden("the signed URL has expired"),
+        }
+    }
+
+    fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+        // An odd trailing digit fails `get`.
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect()
+    }
+}
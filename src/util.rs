@@ -0,0 +1,106 @@
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::task::{Context, Poll};
+
+/// A future which is `Sync` as long as the wrapped future is `Send`, so `Send`-only futures
+/// such as hyper's client responses can be returned from handlers.
+pub(crate) struct SyncFuture<F>(Mutex<Pin<Box<F>>>);
+
+impl<F: Future> SyncFuture<F> {
+    pub(crate) fn new(fut: F) -> Self {
+        Self(Mutex::new(Box::pin(fut)))
+    }
+}
+
+impl<F: Future> Future for SyncFuture<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        // `poll` takes `&mut self`, so the lock is never contended.
+        let mut fut = match self.0.lock() {
+            Ok(fut) => fut,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        fut.as_mut().poll(cx)
+    }
+}
+
+/// Unwrap the error of a body stream, so errors such as a body exceeding its limit are still
+/// recognizable after the body is wrapped again.
+pub(crate) fn into_cause(err: hyper::Error) -> Box<dyn Error + Send + Sync> {
+    if err.source().is_none() {
+        return Box::new(err);
+    }
+    err.into_cause()
+        .expect("an error with a source has a cause")
+}
+
+/// Compare `a` and `b` in time independent of where they differ, for comparing secrets.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Decode standard, padded base64, as used by `Authorization: Basic`.
+pub(crate) fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    if input.len() % 4 != 0 {
+        return None;
+    }
+    let value = |b: u8| match b {
+        b'A'..=b'Z' => Some(b - b'A'),
+        b'a'..=b'z' => Some(b - b'a' + 26),
+        b'0'..=b'9' => Some(b - b'0' + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    };
+    let mut output = Vec::with_capacity(input.len() / 4 * 3);
+    for (i, chunk) in input.chunks(4).enumerate() {
+        let last = i == input.len() / 4 - 1;
+        let padding = chunk.iter().rev().take_while(|b| **b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut bits = 0u32;
+        for b in &chunk[..4 - padding] {
+            bits = (bits << 6) | u32::from(value(*b)?);
+        }
+        bits <<= 6 * padding as u32;
+        let bytes = bits.to_be_bytes();
+        output.extend_from_slice(&bytes[1..4 - padding]);
+    }
+    Some(output)
+}
+
+/// Build a `Content-Disposition` value of the type `disposition`, `attachment` or `inline`,
+/// naming the file `filename`, RFC 5987 encoded, with an ASCII fallback for clients not
+/// supporting `filename*`.
+pub(crate) fn content_disposition(disposition: &str, filename: &str) -> hyper::header::HeaderValue {
+    let fallback = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    let mut value = format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''",
+        disposition, fallback
+    );
+    crate::url::encode(&mut value, filename, false);
+    hyper::header::HeaderValue::from_str(&value).expect("the value is visible ASCII")
+}
+
+/// Generate 64 unpredictable bits for ids, which don't need to be cryptographically secure.
+pub(crate) fn random_u64() -> u64 {
+    static STATE: OnceLock<RandomState> = OnceLock::new();
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = STATE.get_or_init(RandomState::new).build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
@@ -0,0 +1,278 @@
+//! Warming up a service before it reports ready.
+//!
+//! The first requests after a deploy are often slow, as caches are empty and connection pools
+//! still have to be filled. A [`Warmup`] replays a set of internal requests through the
+//! service once it's started, and the [`Readiness`] answered to the load balancer only flips
+//! to ready afterwards, so real traffic never hits a cold instance.
+//!
+//! Handlers can recognize warmup requests with [`is_warmup`], e.g. to leave them out of
+//! analytics.
+//!
+//! ```rust,no_run
+//! use std::convert::Infallible;
+//! use std::net::SocketAddr;
+//!
+//! use hyper::{Body, Request, Response, Server};
+//! use keiro::warmup::{Readiness, Warmup};
+//! use keiro::Router;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let readiness = Readiness::new();
+//!     let mut router = Router::new();
+//!     router.get("/products", products);
+//!     router.get("/ready", readiness.handler());
+//!     let svc = keiro::RouterService::new(router);
+//!
+//!     let addr = SocketAddr::from(([0, 0, 0, 0], 8080));
+//!     let server = Server::bind(&addr).serve(keiro::MakeRouterService { inner: svc.clone() });
+//!
+//!     let warmup = Warmup::new()
+//!         .get("/products")
+//!         .request(
+//!             Request::post("/search")
+//!                 .header("content-type", "application/json")
+//!                 .body(r#"{"query":"shoes"}"#)
+//!                 .unwrap(),
+//!         )
+//!         .repeat(10)
+//!         .concurrency(4);
+//!     tokio::spawn(async move {
+//!         let report = warmup.run(svc).await;
+//!         println!("warmed up with {} requests", report.requests);
+//!         readiness.set_ready();
+//!     });
+//!
+//!     server.await.unwrap();
+//! }
+//!
+//! async fn products(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+//!     Ok(Response::new(Body::from("[]")))
+//! }
+//! ```
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::StreamExt;
+use hyper::body::Bytes;
+use hyper::header::HeaderMap;
+use hyper::service::Service;
+use hyper::{Body, Method, Request, Response, StatusCode, Uri};
+
+/// A request extension marking requests sent by a [`Warmup`].
+#[derive(Debug, Clone, Copy)]
+pub struct WarmupRequest;
+
+/// Check whether `req` was sent by a [`Warmup`].
+pub fn is_warmup(req: &Request<Body>) -> bool {
+    req.extensions().get::<WarmupRequest>().is_some()
+}
+
+#[derive(Debug, Clone)]
+struct Template {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+/// A set of requests which are replayed to warm up a service.
+#[derive(Debug, Clone)]
+pub struct Warmup {
+    requests: Vec<Template>,
+    repeat: usize,
+    concurrency: usize,
+}
+
+/// The outcome of a [`Warmup`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct WarmupReport {
+    /// The number of requests sent.
+    pub requests: usize,
+    /// The requests which failed, answered with a server error or failing in the service.
+    pub failures: Vec<WarmupFailure>,
+    pub elapsed: Duration,
+}
+
+/// A failed warmup request.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct WarmupFailure {
+    pub method: Method,
+    pub uri: Uri,
+    /// The status of the response, or `None` if the service failed.
+    pub status: Option<StatusCode>,
+}
+
+impl Default for Warmup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Warmup {
+    /// Create a warmup without requests, which sends every request once, one at a time.
+    pub fn new() -> Self {
+        Self {
+            requests: Vec::new(),
+            repeat: 1,
+            concurrency: 1,
+        }
+    }
+
+    /// Add a GET request to `path`, e.g. `/products?page=1`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path` isn't a valid URI.
+    pub fn get(self, path: &str) -> Self {
+        let uri = path.parse().expect("invalid warmup URI");
+        self.request(Request::get::<Uri>(uri).body(Bytes::new()).unwrap())
+    }
+
+    /// Add `req`, which is sent with its method, URI, headers and body.
+    pub fn request<B: Into<Bytes>>(mut self, req: Request<B>) -> Self {
+        let (parts, body) = req.into_parts();
+        self.requests.push(Template {
+            method: parts.method,
+            uri: parts.uri,
+            headers: parts.headers,
+            body: body.into(),
+        });
+        self
+    }
+
+    /// Send every request `times` times. Defaults to once.
+    pub fn repeat(mut self, times: usize) -> Self {
+        self.repeat = times;
+        self
+    }
+
+    /// Send up to `limit` requests at the same time. Defaults to one.
+    pub fn concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = limit.max(1);
+        self
+    }
+
+    /// Send the requests through `svc`, waiting for every response body to be read.
+    pub async fn run<S>(&self, svc: S) -> WarmupReport
+    where
+        S: Service<Request<Body>, Response = Response<Body>> + Clone,
+    {
+        let start = Instant::now();
+        let templates: Vec<Template> = (0..self.repeat)
+            .flat_map(|_| self.requests.iter().cloned())
+            .collect();
+        let outcomes: Vec<_> = futures_util::stream::iter(templates)
+            .map(|template| {
+                let svc = svc.clone();
+                async move {
+                    let status = send(svc, &template).await;
+                    (template, status)
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+
+        let requests = outcomes.len();
+        let failures = outcomes
+            .into_iter()
+            .filter(|(_, status)| status.is_none_or(|status| status.is_server_error()))
+            .map(|(template, status)| WarmupFailure {
+                method: template.method,
+                uri: template.uri,
+                status,
+            })
+            .collect();
+        WarmupReport {
+            requests,
+            failures,
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+/// Send `template` through `svc` once, returning the status of the response.
+async fn send<S>(mut svc: S, template: &Template) -> Option<StatusCode>
+where
+    S: Service<Request<Body>, Response = Response<Body>>,
+{
+    let mut req = Request::new(Body::from(template.body.clone()));
+    *req.method_mut() = template.method.clone();
+    *req.uri_mut() = template.uri.clone();
+    *req.headers_mut() = template.headers.clone();
+    req.extensions_mut().insert(WarmupRequest);
+
+    futures_util::future::poll_fn(|cx| svc.poll_ready(cx))
+        .await
+        .ok()?;
+    let res = svc.call(req).await.ok()?;
+    let status = res.status();
+    // Read the body, so streamed responses do their work as well.
+    hyper::body::to_bytes(res.into_body()).await.ok()?;
+    Some(status)
+}
+
+/// Whether the service is ready for traffic, answered to readiness checks.
+#[derive(Debug, Clone, Default)]
+pub struct Readiness(Arc<AtomicBool>);
+
+impl Readiness {
+    /// Create a readiness which starts out as not ready.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn ready() -> Self {
+        Self(Arc::new(AtomicBool::new(true)))
+    }
+
+    pub fn set_ready(&self) {
+        self.0.store(true, Ordering::Release);
+    }
+
+    /// Report not ready again, e.g. while shutting down.
+    pub fn set_not_ready(&self) {
+        self.0.store(false, Ordering::Release);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    /// Turn the readiness into a handler answering `200 OK` when ready and
+    /// `503 Service Unavailable` otherwise.
+    #[allow(clippy::type_complexity)]
+    pub fn handler(
+        &self,
+    ) -> impl Fn(
+        Request<Body>,
+    )
+        -> Pin<Box<dyn Future<Output = Result<Response<Body>, Infallible>> + Send + Sync>>
+           + Clone
+           + Send
+           + Sync
+           + 'static {
+        let readiness = self.clone();
+        move |_req| {
+            let status = if readiness.is_ready() {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            Box::pin(async move {
+                Ok(Response::builder()
+                    .status(status)
+                    .body(Body::empty())
+                    .unwrap())
+            })
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! Handlers for well-known URIs.
+//!
+//! [`WellKnown`] collects the endpoints under `/.well-known/` a service commonly has to answer,
+//! and [`Router::well_known`](crate::Router::well_known) registers them in one call:
+//!
+//! - `/.well-known/health`, answering `200 OK`, or `503 Service Unavailable` while a
+//!   [`Readiness`] isn't ready or the router
+//!   [drains its connections](crate::Router::serve_with_shutdown).
+//! - `/.well-known/security.txt`, describing how to report vulnerabilities as defined by
+//!   [RFC 9116](https://www.rfc-editor.org/rfc/rfc9116).
+//! - `/.well-known/acme-challenge/:token`, answering ACME HTTP-01 challenges with the key
+//!   authorizations of a [`ChallengeStore`], which the ACME client fills while it validates
+//!   domains.
+//!
+//! ```rust,no_run
+//! use std::time::{Duration, SystemTime};
+//!
+//! use keiro::warmup::Readiness;
+//! use keiro::well_known::{MemoryChallenges, SecurityTxt, WellKnown};
+//! use keiro::Router;
+//!
+//! let readiness = Readiness::new();
+//! let challenges = MemoryChallenges::new();
+//! let expires = SystemTime::now() + Duration::from_secs(365 * 24 * 60 * 60);
+//!
+//! let mut router = Router::new();
+//! router.well_known(
+//!     WellKnown::new()
+//!         .health(&readiness)
+//!         .security_txt(
+//!             SecurityTxt::new("mailto:security@example.com", expires)
+//!                 .policy("https://example.com/security-policy")
+//!                 .preferred_languages("en, de"),
+//!         )
+//!         .acme_challenges(challenges.clone()),
+//! );
+//!
+//! // While validating the domain:
+//! challenges.insert("token", "token.thumbprint");
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::{Body, Response, StatusCode};
+
+use crate::warmup::Readiness;
+
+/// The future returned by the methods of [`ChallengeStore`].
+pub type ChallengeFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + Sync + 'a>>;
+
+/// Storage for the key authorizations of pending ACME HTTP-01 challenges.
+///
+/// Services running several instances behind a load balancer need a store shared by all of
+/// them, as the ACME server may send the challenge request to any instance.
+pub trait ChallengeStore: Send + Sync + 'static {
+    /// Get the key authorization for `token`, or `None` if there is no such challenge.
+    fn get(&self, token: &str) -> ChallengeFuture<'_, Option<String>>;
+
+    /// Store the key authorization for `token` until the challenge is validated.
+    fn put(&self, token: &str, key_authorization: &str) -> ChallengeFuture<'_, ()>;
+
+    /// Remove the challenge for `token`. Removing a missing challenge succeeds.
+    fn remove(&self, token: &str) -> ChallengeFuture<'_, ()>;
+}
+
+/// A [`ChallengeStore`] keeping challenges in memory. Clones share the challenges.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryChallenges(Arc<RwLock<HashMap<String, String>>>);
+
+impl MemoryChallenges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, token: impl Into<String>, key_authorization: impl Into<String>) {
+        let mut challenges = match self.0.write() {
+            Ok(challenges) => challenges,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        challenges.insert(token.into(), key_authorization.into());
+    }
+
+    pub fn remove(&self, token: &str) {
+        let mut challenges = match self.0.write() {
+            Ok(challenges) => challenges,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        challenges.remove(token);
+    }
+
+    pub fn get(&self, token: &str) -> Option<String> {
+        let challenges = match self.0.read() {
+            Ok(challenges) => challenges,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        challenges.get(token).cloned()
+    }
+}
+
+impl ChallengeStore for MemoryChallenges {
+    fn get(&self, token: &str) -> ChallengeFuture<'_, Option<String>> {
+        let key_authorization = MemoryChallenges::get(self, token);
+        Box::pin(async move { Ok(key_authorization) })
+    }
+
+    fn put(&self, token: &str, key_authorization: &str) -> ChallengeFuture<'_, ()> {
+        self.insert(token, key_authorization);
+        Box::pin(async { Ok(()) })
+    }
+
+    fn remove(&self, token: &str) -> ChallengeFuture<'_, ()> {
+        MemoryChallenges::remove(self, token);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+/// The contents of a `security.txt` file.
+#[derive(Debug, Clone)]
+pub struct SecurityTxt {
+    contacts: Vec<String>,
+    expires: SystemTime,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl SecurityTxt {
+    /// Create a file naming `contact`, a `mailto:`, `tel:` or `https:` URI, which is valid
+    /// until `expires`. RFC 9116 recommends expiring within a year.
+    pub fn new(contact: impl Into<String>, expires: SystemTime) -> Self {
+        Self {
+            contacts: vec![contact.into()],
+            expires,
+            fields: Vec::new(),
+        }
+    }
+
+    /// Add another contact, in order of preference.
+    pub fn contact(mut self, contact: impl Into<String>) -> Self {
+        self.contacts.push(contact.into());
+        self
+    }
+
+    /// Link the key reporters should encrypt their messages with.
+    pub fn encryption(self, uri: impl Into<String>) -> Self {
+        self.field("Encryption", uri)
+    }
+
+    /// Link the page thanking reporters.
+    pub fn acknowledgments(self, uri: impl Into<String>) -> Self {
+        self.field("Acknowledgments", uri)
+    }
+
+    /// Set the languages reports may be written in, as a comma separated list of language
+    /// tags.
+    pub fn preferred_languages(self, languages: impl Into<String>) -> Self {
+        self.field("Preferred-Languages", languages)
+    }
+
+    /// Set the URI the file is published at, so copies can be recognized.
+    pub fn canonical(self, uri: impl Into<String>) -> Self {
+        self.field("Canonical", uri)
+    }
+
+    /// Link the vulnerability disclosure policy.
+    pub fn policy(self, uri: impl Into<String>) -> Self {
+        self.field("Policy", uri)
+    }
+
+    /// Link the security-related job openings.
+    pub fn hiring(self, uri: impl Into<String>) -> Self {
+        self.field("Hiring", uri)
+    }
+
+    fn field(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.fields.push((name, value.into()));
+        self
+    }
+}
+
+impl fmt::Display for SecurityTxt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for contact in &self.contacts {
+            writeln!(f, "Contact: {}", contact)?;
+        }
+        writeln!(f, "Expires: {}", rfc3339(self.expires))?;
+        for (name, value) in &self.fields {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// The well-known endpoints to register with
+/// [`Router::well_known`](crate::Router::well_known).
+#[derive(Default)]
+pub struct WellKnown {
+    pub(crate) health: Option<Option<Readiness>>,
+    pub(crate) security_txt: Option<String>,
+    pub(crate) challenges: Option<Arc<dyn ChallengeStore>>,
+}
+
+impl WellKnown {
+    /// Create a set of no endpoints yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer `/.well-known/health` according to `readiness`.
+    pub fn health(mut self, readiness: &Readiness) -> Self {
+        self.health = Some(Some(readiness.clone()));
+        self
+    }
+
+    /// Answer `/.well-known/health` with `200 OK` whenever the service is running.
+    pub fn liveness(mut self) -> Self {
+        self.health = Some(None);
+        self
+    }
+
+    /// Serve `security_txt` at `/.well-known/security.txt`.
+    pub fn security_txt(mut self, security_txt: SecurityTxt) -> Self {
+        self.security_txt = Some(security_txt.to_string());
+        self
+    }
+
+    /// Answer ACME HTTP-01 challenges at `/.well-known/acme-challenge/:token` from `store`.
+    pub fn acme_challenges(mut self, store: impl ChallengeStore) -> Self {
+        self.challenges = Some(Arc::new(store));
+        self
+    }
+}
+
+impl fmt::Debug for WellKnown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WellKnown")
+            .field("health", &self.health)
+            .field("security_txt", &self.security_txt)
+            .field("acme_challenges", &self.challenges.is_some())
+            .finish()
+    }
+}
+
+pub(crate) fn health(ready: bool) -> Response<Body> {
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+pub(crate) fn text(body: String) -> Response<Body> {
+    let mut res = Response::new(Body::from(body));
+    res.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    res
+}
+
+/// Answer the challenge for `token` from `store`.
+pub(crate) async fn challenge(store: &dyn ChallengeStore, token: &str) -> Response<Body> {
+    let is_token = !token.is_empty()
+        && token
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    let key_authorization = if is_token {
+        store.get(token).await
+    } else {
+        Ok(None)
+    };
+    match key_authorization {
+        Ok(Some(key_authorization)) => {
+            let mut res = Response::new(Body::from(key_authorization));
+            res.headers_mut().insert(
+                CONTENT_TYPE,
+                HeaderValue::from_static("application/octet-stream"),
+            );
+            res
+        }
+        Ok(None) => status(StatusCode::NOT_FOUND),
+        Err(_) => status(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+/// Format `time` as an RFC 3339 timestamp in UTC, e.g. `2024-12-31T23:59:59Z`.
+fn rfc3339(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    let (days, secs) = (secs / 86400, secs % 86400);
+
+    // Convert days since the epoch to a civil date, after Howard Hinnant's `civil_from_days`.
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
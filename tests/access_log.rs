@@ -0,0 +1,35 @@
+//! The lines passed to the sink of a [`keiro::access_log::AccessLog`].
+#![cfg(feature = "access-log")]
+
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use hyper::{Body, Request, Response};
+use keiro::access_log::{AccessLog, LogFormat};
+use keiro::{Router, RouterService};
+use tower::ServiceExt;
+
+#[tokio::test]
+async fn lines_are_passed_to_the_sink() {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let mut router = Router::new();
+    router.access_log(AccessLog::new(LogFormat::Common, {
+        let lines = lines.clone();
+        move |line| lines.lock().unwrap().push(line.to_string())
+    }));
+    router.get("/", |_req| async {
+        Ok::<_, Infallible>(Response::new(Body::from("Hello world!")))
+    });
+
+    let req = Request::get("/?q=1").body(Body::empty()).unwrap();
+    let res = RouterService::new(router).oneshot(req).await.unwrap();
+    hyper::body::to_bytes(res.into_body()).await.unwrap();
+
+    let lines = lines.lock().unwrap();
+    assert_eq!(lines.len(), 1);
+    assert!(
+        lines[0].ends_with("\"GET /?q=1 HTTP/1.1\" 200 12"),
+        "{}",
+        lines[0]
+    );
+}
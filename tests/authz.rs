@@ -0,0 +1,63 @@
+//! The requests authorized by the scopes of their principal.
+
+use std::convert::Infallible;
+
+use hyper::{Body, Request, Response, StatusCode};
+use keiro::api_key::ApiKey;
+use keiro::authz::Principal;
+use keiro::{Router, RouterService};
+use tower::ServiceExt;
+
+#[derive(Clone)]
+struct Client;
+
+async fn handler(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    Ok(Response::new(Body::empty()))
+}
+
+async fn status(router: Router, key: Option<&str>) -> StatusCode {
+    let mut req = Request::get("/");
+    if let Some(key) = key {
+        req = req.header("x-api-key", key);
+    }
+    let req = req.body(Body::empty()).unwrap();
+    RouterService::new(router)
+        .oneshot(req)
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn routes_without_scopes_need_no_principal() {
+    let router = || {
+        let mut router = Router::new();
+        // Keys resolving to clients which aren't principals.
+        router.api_key(ApiKey::new(|key: String| async move {
+            (key == "secret").then_some(Client)
+        }));
+        router.get("/", handler);
+        router
+    };
+    assert_eq!(status(router(), Some("secret")).await, StatusCode::OK);
+    assert_eq!(status(router(), None).await, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn routes_with_scopes_need_a_principal_granted_them() {
+    let router = || {
+        let mut router = Router::new();
+        router.api_key(ApiKey::new(|key: String| async move {
+            match key.as_str() {
+                "deploy" => Some(Principal::new("deploy-bot").scope("deploy")),
+                "read" => Some(Principal::new("reader").scope("read")),
+                _ => None,
+            }
+        }));
+        router.get("/", handler).require_scope("deploy");
+        router
+    };
+    assert_eq!(status(router(), Some("deploy")).await, StatusCode::OK);
+    assert_eq!(status(router(), Some("read")).await, StatusCode::FORBIDDEN);
+    assert_eq!(status(router(), None).await, StatusCode::UNAUTHORIZED);
+}
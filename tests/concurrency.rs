@@ -0,0 +1,68 @@
+//! Load shedding of routes with a concurrency limit.
+
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::{Body, Request, Response, StatusCode};
+use keiro::{Route, Router, RouterService};
+use tokio::sync::{mpsc, oneshot};
+use tower::ServiceExt;
+
+/// Serve `/` with a handler which signals `started`, then waits to be released. The route is
+/// configured by `configure`.
+fn service(
+    configure: impl FnOnce(Route<'_, keiro::Error>),
+    started: mpsc::UnboundedSender<()>,
+    release: Arc<Mutex<Option<oneshot::Receiver<()>>>>,
+) -> RouterService<keiro::Error, ()> {
+    let mut router = Router::new();
+    configure(router.get("/", move |_req: Request<Body>| {
+        let started = started.clone();
+        let release = release.lock().unwrap().take();
+        async move {
+            started.send(()).unwrap();
+            if let Some(release) = release {
+                let _ = release.await;
+            }
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        }
+    }));
+    RouterService::new(router)
+}
+
+async fn shed(configure: impl FnOnce(Route<'_, keiro::Error>)) {
+    let (started, mut wait) = mpsc::unbounded_channel();
+    let (release, released) = oneshot::channel();
+    let svc = service(configure, started, Arc::new(Mutex::new(Some(released))));
+    let req = || Request::get("/").body(Body::empty()).unwrap();
+
+    let first = tokio::spawn(svc.clone().oneshot(req()));
+    wait.recv().await.unwrap();
+    let res = tokio::time::timeout(Duration::from_secs(5), svc.clone().oneshot(req()))
+        .await
+        .expect("the request was queued")
+        .unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    release.send(()).unwrap();
+    assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+    let res = svc.oneshot(req()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn load_shed_after_the_limit() {
+    shed(|route| {
+        route.concurrency_limit(1).load_shed();
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn load_shed_before_the_limit() {
+    shed(|route| {
+        route.load_shed().concurrency_limit(1);
+    })
+    .await;
+}
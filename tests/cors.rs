@@ -0,0 +1,22 @@
+//! The configurations rejected by [`keiro::cors::Cors`].
+
+use keiro::cors::Cors;
+
+#[test]
+#[should_panic(expected = "credentials can't be allowed for any origin")]
+fn credentials_for_any_origin_are_rejected() {
+    Cors::new().allow_any_origin().allow_credentials();
+}
+
+#[test]
+#[should_panic(expected = "credentials can't be allowed for any origin")]
+fn any_origin_with_credentials_is_rejected() {
+    Cors::new().allow_credentials().allow_any_origin();
+}
+
+#[test]
+fn credentials_for_listed_origins_are_allowed() {
+    Cors::new()
+        .allow_origin("https://app.example.com")
+        .allow_credentials();
+}
@@ -0,0 +1,252 @@
+//! The client resolved by [`keiro::forwarded::TrustedProxies`] from forwarding headers.
+
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+use hyper::{Body, Request, Response};
+use keiro::connect::ConnectInfo;
+use keiro::forwarded::{ClientInfo, ForwardingHeaders, TrustedProxies};
+use keiro::prelude::*;
+use keiro::{Router, RouterService};
+use tower::ServiceExt;
+
+/// The client info of a request from `peer` with `headers`, behind `proxies`.
+async fn client_info(
+    proxies: TrustedProxies,
+    peer: &str,
+    headers: &[(&str, &str)],
+) -> Option<ClientInfo> {
+    let mut router = Router::new();
+    router.trusted_proxies(proxies);
+    router.get("/", |req: Request<Body>| async move {
+        let info = req.client_info().cloned();
+        let mut res = Response::new(Body::empty());
+        res.extensions_mut().insert(info);
+        Ok::<_, Infallible>(res)
+    });
+
+    let mut req = Request::get("/").header("host", "app.example");
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    let mut req = req.body(Body::empty()).unwrap();
+    let peer = SocketAddr::new(peer.parse().unwrap(), 443);
+    req.extensions_mut().insert(ConnectInfo(peer));
+    let res = RouterService::new(router).oneshot(req).await.unwrap();
+    res.extensions()
+        .get::<Option<ClientInfo>>()
+        .cloned()
+        .unwrap()
+}
+
+fn ip(ip: &str) -> IpAddr {
+    ip.parse().unwrap()
+}
+
+/// Proxies adding the `Forwarded` header.
+fn forwarded(hops: usize) -> TrustedProxies {
+    TrustedProxies::hops(hops).headers(ForwardingHeaders::Forwarded)
+}
+
+/// The address of the client of a request from `peer` with `headers`, behind `proxies`.
+async fn client_ip(proxies: TrustedProxies, peer: &str, headers: &[(&str, &str)]) -> IpAddr {
+    client_info(proxies, peer, headers).await.unwrap().ip
+}
+
+#[tokio::test]
+async fn forwarded_host_and_proto_come_from_the_trusted_hop() {
+    let info = client_info(
+        forwarded(1),
+        "10.0.0.1",
+        &[(
+            "forwarded",
+            "for=1.2.3.4;host=evil.example;proto=ftp, for=5.6.7.8;host=app.example;proto=HTTPS",
+        )],
+    )
+    .await
+    .unwrap();
+    assert_eq!(info.ip, ip("5.6.7.8"));
+    assert_eq!(info.scheme, "https");
+    assert_eq!(info.host.as_deref(), Some("app.example"));
+}
+
+#[tokio::test]
+async fn forwarded_host_of_the_client_is_ignored() {
+    // The client sends a host, and the proxy only appends `for`.
+    let info = client_info(
+        forwarded(1),
+        "10.0.0.1",
+        &[(
+            "forwarded",
+            "for=1.2.3.4;host=evil.example;proto=https, for=5.6.7.8",
+        )],
+    )
+    .await
+    .unwrap();
+    assert_eq!(info.ip, ip("5.6.7.8"));
+    assert_eq!(info.scheme, "http");
+    assert_eq!(info.host.as_deref(), Some("app.example"));
+}
+
+#[tokio::test]
+async fn x_forwarded_host_of_the_client_is_ignored() {
+    // The client sends an address and a host, and the proxy only appends to
+    // `X-Forwarded-For`.
+    let info = client_info(
+        TrustedProxies::hops(1),
+        "10.0.0.1",
+        &[
+            ("x-forwarded-for", "1.2.3.4, 5.6.7.8"),
+            ("x-forwarded-host", "evil.example"),
+            ("x-forwarded-proto", "https"),
+        ],
+    )
+    .await
+    .unwrap();
+    assert_eq!(info.ip, ip("5.6.7.8"));
+    assert_eq!(info.scheme, "http");
+    assert_eq!(info.host.as_deref(), Some("app.example"));
+}
+
+#[tokio::test]
+async fn x_forwarded_entries_are_matched_by_position() {
+    let info = client_info(
+        TrustedProxies::hops(1),
+        "10.0.0.1",
+        &[
+            ("x-forwarded-for", "1.2.3.4, 5.6.7.8"),
+            ("x-forwarded-host", "evil.example, app.example"),
+            ("x-forwarded-proto", "ftp, https"),
+        ],
+    )
+    .await
+    .unwrap();
+    assert_eq!(info.ip, ip("5.6.7.8"));
+    assert_eq!(info.scheme, "https");
+    assert_eq!(info.host.as_deref(), Some("app.example"));
+}
+
+#[tokio::test]
+async fn entries_of_trusted_hops_are_skipped() {
+    let headers = [("x-forwarded-for", "6.6.6.6, 1.2.3.4, 10.0.0.2")];
+    let cases = [
+        (0, "10.0.0.1"),
+        (1, "10.0.0.2"),
+        (2, "1.2.3.4"),
+        (3, "6.6.6.6"),
+        // The client connected to an inner proxy directly.
+        (5, "6.6.6.6"),
+    ];
+    for (hops, expected) in cases {
+        let proxies = TrustedProxies::hops(hops);
+        assert_eq!(
+            client_ip(proxies, "10.0.0.1", &headers).await,
+            ip(expected),
+            "{}",
+            hops
+        );
+    }
+}
+
+#[tokio::test]
+async fn entries_are_counted_across_header_lines() {
+    let headers = [
+        ("x-forwarded-for", "6.6.6.6"),
+        ("x-forwarded-for", "1.2.3.4, 10.0.0.2"),
+    ];
+    let client = client_ip(TrustedProxies::hops(2), "10.0.0.1", &headers).await;
+    assert_eq!(client, ip("1.2.3.4"));
+    let headers = [
+        ("forwarded", "for=6.6.6.6, for=1.2.3.4"),
+        ("forwarded", "for=10.0.0.2"),
+    ];
+    assert_eq!(
+        client_ip(forwarded(2), "10.0.0.1", &headers).await,
+        ip("1.2.3.4")
+    );
+}
+
+#[tokio::test]
+async fn headers_of_untrusted_peers_are_ignored() {
+    let proxies = || TrustedProxies::hops(1).trust("10.0.0.0/8");
+    let headers = [
+        ("x-forwarded-for", "6.6.6.6"),
+        ("x-forwarded-proto", "https"),
+        ("x-forwarded-host", "evil.example"),
+    ];
+    let info = client_info(proxies(), "203.0.113.9", &headers)
+        .await
+        .unwrap();
+    assert_eq!(info.ip, ip("203.0.113.9"));
+    assert_eq!(info.scheme, "http");
+    assert_eq!(info.host.as_deref(), Some("app.example"));
+
+    let info = client_info(proxies(), "10.0.0.1", &headers).await.unwrap();
+    assert_eq!(info.ip, ip("6.6.6.6"));
+    assert_eq!(info.scheme, "https");
+    assert_eq!(info.host.as_deref(), Some("evil.example"));
+}
+
+#[tokio::test]
+async fn peers_are_the_client_without_headers() {
+    let info = client_info(TrustedProxies::hops(2), "192.0.2.1", &[])
+        .await
+        .unwrap();
+    assert_eq!(info.ip, ip("192.0.2.1"));
+    assert_eq!(info.scheme, "http");
+    assert_eq!(info.host.as_deref(), Some("app.example"));
+}
+
+#[tokio::test]
+async fn headers_the_proxies_dont_add_are_ignored() {
+    // Proxies adding `X-Forwarded-For` pass on the `Forwarded` header of the client.
+    let headers = [
+        ("forwarded", "for=6.6.6.6;proto=https;host=evil.example"),
+        ("x-forwarded-for", "1.2.3.4"),
+    ];
+    let info = client_info(TrustedProxies::hops(1), "10.0.0.1", &headers)
+        .await
+        .unwrap();
+    assert_eq!(info.ip, ip("1.2.3.4"));
+    assert_eq!(info.scheme, "http");
+    assert_eq!(info.host.as_deref(), Some("app.example"));
+
+    // And the other way around.
+    let headers = [
+        ("x-forwarded-for", "6.6.6.6"),
+        ("x-forwarded-host", "evil.example"),
+        ("forwarded", "for=1.2.3.4"),
+    ];
+    let info = client_info(forwarded(1), "10.0.0.1", &headers)
+        .await
+        .unwrap();
+    assert_eq!(info.ip, ip("1.2.3.4"));
+    assert_eq!(info.host.as_deref(), Some("app.example"));
+}
+
+#[tokio::test]
+async fn unknown_nodes_fall_back_to_the_next_trusted_hop() {
+    // The address of the outermost proxy is hidden, so the client isn't known any closer
+    // than the proxy itself, which the inner one connected from.
+    let headers = [("forwarded", "for=6.6.6.6, for=unknown, for=10.0.0.2")];
+    let client = client_ip(forwarded(2), "10.0.0.1", &headers).await;
+    assert_eq!(client, ip("10.0.0.2"));
+    let headers = [("forwarded", "for=6.6.6.6, for=_hidden")];
+    assert_eq!(
+        client_ip(forwarded(1), "10.0.0.1", &headers).await,
+        ip("10.0.0.1")
+    );
+}
+
+#[tokio::test]
+async fn nodes_with_ports_are_parsed() {
+    let headers = [("forwarded", "for=\"[2001:db8::1]:4711\"")];
+    let client = client_ip(forwarded(1), "10.0.0.1", &headers).await;
+    assert_eq!(client, ip("2001:db8::1"));
+    let headers = [("forwarded", "For=\"[2001:db8::2]\"")];
+    let client = client_ip(forwarded(1), "10.0.0.1", &headers).await;
+    assert_eq!(client, ip("2001:db8::2"));
+    let headers = [("x-forwarded-for", "192.0.2.43:47011")];
+    let client = client_ip(TrustedProxies::hops(1), "10.0.0.1", &headers).await;
+    assert_eq!(client, ip("192.0.2.43"));
+}
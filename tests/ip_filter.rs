@@ -0,0 +1,168 @@
+//! The addresses matched by [`keiro::ip_filter::IpRange`] and allowed by
+//! [`keiro::ip_filter::IpFilter`].
+
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+use hyper::{Body, Request, Response, StatusCode};
+use keiro::connect::ConnectInfo;
+use keiro::ip_filter::{InvalidIpRange, IpFilter, IpRange};
+use keiro::{Router, RouterService};
+use tower::ServiceExt;
+
+fn range(range: &str) -> IpRange {
+    range.parse().unwrap()
+}
+
+fn ip(addr: &str) -> IpAddr {
+    addr.parse().unwrap()
+}
+
+#[test]
+fn ranges_contain_the_addresses_sharing_their_prefix() {
+    let cases = [
+        ("10.0.0.0/8", "10.0.0.0", true),
+        ("10.0.0.0/8", "10.255.255.255", true),
+        ("10.0.0.0/8", "11.0.0.0", false),
+        ("10.0.0.0/8", "9.255.255.255", false),
+        ("192.168.4.0/22", "192.168.7.255", true),
+        ("192.168.4.0/22", "192.168.8.0", false),
+        ("192.168.4.0/22", "192.168.3.255", false),
+        ("127.0.0.1", "127.0.0.1", true),
+        ("127.0.0.1", "127.0.0.2", false),
+        ("127.0.0.1/32", "127.0.0.0", false),
+        ("0.0.0.0/0", "255.255.255.255", true),
+        ("0.0.0.0/0", "0.0.0.0", true),
+        ("2001:db8::/32", "2001:db8:ffff::1", true),
+        ("2001:db8::/32", "2001:db9::", false),
+        ("fd00::/8", "fdff:ffff::1", true),
+        ("fd00::/8", "fe00::", false),
+        ("2001:db8::1/127", "2001:db8::", true),
+        ("2001:db8::1/127", "2001:db8::2", false),
+        ("::1", "::1", true),
+        ("::1", "::2", false),
+        ("::/0", "ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff", true),
+    ];
+    for (r, addr, contained) in cases {
+        assert_eq!(range(r).contains(ip(addr)), contained, "{} {}", r, addr);
+    }
+}
+
+#[test]
+fn families_never_match_each_other() {
+    assert!(!range("0.0.0.0/0").contains(ip("::1")));
+    assert!(!range("::/0").contains(ip("10.0.0.1")));
+    // IPv4-compatible addresses aren't mapped ones.
+    assert!(!range("10.0.0.0/8").contains(ip("::10.0.0.1")));
+}
+
+#[test]
+fn mapped_addresses_match_ipv4_ranges() {
+    assert!(range("10.0.0.0/8").contains(ip("::ffff:10.1.2.3")));
+    assert!(!range("10.0.0.0/8").contains(ip("::ffff:11.1.2.3")));
+    assert!(range("127.0.0.1").contains(ip("::ffff:127.0.0.1")));
+    // Matched as IPv4, they are only in the IPv6 ranges which are mapped ranges.
+    assert!(range("::ffff:0:0/96").contains(ip("::ffff:10.1.2.3")));
+    assert!(!range("::/0").contains(ip("::ffff:10.1.2.3")));
+}
+
+#[test]
+fn mapped_ranges_are_the_ipv4_ranges_they_map() {
+    assert_eq!(range("::ffff:10.0.0.0/104"), range("10.0.0.0/8"));
+    assert_eq!(range("::ffff:10.0.0.1"), range("10.0.0.1/32"));
+    assert_eq!(range("::ffff:0.0.0.0/96"), range("0.0.0.0/0"));
+    assert!(range("::ffff:10.0.0.0/104").contains(ip("10.20.30.40")));
+    assert!(range("::ffff:10.0.0.0/104").contains(ip("::ffff:10.20.30.40")));
+    assert_eq!("::ffff:10.0.0.0/95".parse::<IpRange>(), Err(InvalidIpRange));
+}
+
+#[test]
+fn invalid_ranges_are_rejected() {
+    for r in [
+        "",
+        "10.0.0.0/",
+        "10.0.0.0/33",
+        "::/129",
+        "10.0.0.0/-1",
+        "10.0.0.0/8/8",
+        "10.0.0/8",
+        "localhost",
+        "10.0.0.0 /8",
+        "/8",
+    ] {
+        assert_eq!(r.parse::<IpRange>(), Err(InvalidIpRange), "{}", r);
+    }
+}
+
+#[test]
+#[should_panic(expected = "invalid IP address or CIDR range: \"10.0.0.0/33\"")]
+fn filters_panic_on_invalid_ranges() {
+    IpFilter::new().allow("10.0.0.0/33");
+}
+
+#[test]
+fn denied_ranges_take_precedence() {
+    let filter = IpFilter::new()
+        .allow("10.0.0.0/8")
+        .allow("::1")
+        .deny("10.13.0.0/16");
+    assert!(filter.is_allowed(ip("10.1.0.1")));
+    assert!(filter.is_allowed(ip("::ffff:10.1.0.1")));
+    assert!(filter.is_allowed(ip("::1")));
+    assert!(!filter.is_allowed(ip("10.13.0.1")));
+    assert!(!filter.is_allowed(ip("::ffff:10.13.0.1")));
+    assert!(!filter.is_allowed(ip("192.168.0.1")));
+
+    let filter = IpFilter::new().deny("192.0.2.0/24");
+    assert!(filter.is_allowed(ip("198.51.100.1")));
+    assert!(!filter.is_allowed(ip("::ffff:192.0.2.1")));
+    assert!(IpFilter::new().is_allowed(ip("192.0.2.1")));
+}
+
+async fn status(filter: IpFilter, peer: Option<&str>) -> StatusCode {
+    let mut router = Router::new();
+    router
+        .get("/", |_req| async {
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        })
+        .ip_filter(filter);
+    let mut req = Request::get("/").body(Body::empty()).unwrap();
+    if let Some(peer) = peer {
+        let peer: SocketAddr = peer.parse().unwrap();
+        req.extensions_mut().insert(ConnectInfo(peer));
+    }
+    RouterService::new(router)
+        .oneshot(req)
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn routes_filter_the_peers_of_connections() {
+    let filter = || IpFilter::new().allow("10.0.0.0/8");
+    assert_eq!(
+        status(filter(), Some("10.0.0.1:1234")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(filter(), Some("[::ffff:10.0.0.1]:1234")).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(filter(), Some("192.168.0.1:1234")).await,
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[tokio::test]
+async fn unknown_peers_are_denied_by_allow_lists() {
+    assert_eq!(
+        status(IpFilter::new().allow("10.0.0.0/8"), None).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status(IpFilter::new().deny("10.0.0.0/8"), None).await,
+        StatusCode::OK
+    );
+}
@@ -0,0 +1,357 @@
+//! The tokens accepted by [`keiro::jwt::JwtAuth`].
+#![cfg(feature = "jwt")]
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hyper::header::WWW_AUTHENTICATE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use keiro::jwt::JwtAuth;
+use keiro::prelude::*;
+use keiro::{Router, RouterService};
+use ring::hmac;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+const SECRET: &[u8] = b"a secret of at least thirty-two bytes";
+
+#[derive(Deserialize)]
+struct User {
+    sub: String,
+}
+
+fn b64(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// A token with `header` and `claims`, signed by `sign`.
+fn token(header: Value, claims: Value, sign: impl FnOnce(&[u8]) -> Vec<u8>) -> String {
+    let message = format!(
+        "{}.{}",
+        b64(header.to_string().as_bytes()),
+        b64(claims.to_string().as_bytes())
+    );
+    let sig = sign(message.as_bytes());
+    format!("{}.{}", message, b64(&sig))
+}
+
+fn hs256(secret: &[u8], claims: Value) -> String {
+    token(json!({"alg": "HS256", "typ": "JWT"}), claims, |message| {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+        hmac::sign(&key, message).as_ref().to_vec()
+    })
+}
+
+/// Send a request with `token` to a route requiring `auth`, returning the status and the
+/// challenge or the subject of the token.
+async fn send(auth: JwtAuth, token: Option<&str>) -> (StatusCode, String) {
+    let mut router = Router::new();
+    router
+        .get("/", |req: Request<Body>| async move {
+            let user = req.claims::<User>().unwrap();
+            Ok::<_, Infallible>(Response::new(Body::from(user.sub)))
+        })
+        .jwt(auth);
+    let mut req = Request::get("/");
+    if let Some(token) = token {
+        req = req.header("authorization", format!("Bearer {}", token));
+    }
+    let req = req.body(Body::empty()).unwrap();
+    let res = RouterService::new(router).oneshot(req).await.unwrap();
+    let status = res.status();
+    if status == StatusCode::OK {
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        return (status, String::from_utf8(body.to_vec()).unwrap());
+    }
+    let challenge = res.headers()[WWW_AUTHENTICATE]
+        .to_str()
+        .unwrap()
+        .to_string();
+    (status, challenge)
+}
+
+fn rejected(description: &str) -> (StatusCode, String) {
+    (
+        StatusCode::UNAUTHORIZED,
+        format!(
+            "Bearer error=\"invalid_token\", error_description=\"{}\"",
+            description
+        ),
+    )
+}
+
+fn accepted() -> (StatusCode, String) {
+    (StatusCode::OK, "alice".to_string())
+}
+
+#[tokio::test]
+async fn tokens_signed_with_the_secret_are_accepted() {
+    let token = hs256(SECRET, json!({"sub": "alice", "exp": now() + 60}));
+    let auth = JwtAuth::new().hmac_secret(SECRET);
+    assert_eq!(send(auth, Some(&token)).await, accepted());
+}
+
+#[tokio::test]
+async fn requests_without_tokens_are_challenged() {
+    let auth = JwtAuth::new().hmac_secret(SECRET);
+    let (status, challenge) = send(auth, None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(challenge, "Bearer");
+}
+
+#[tokio::test]
+async fn tokens_signed_with_another_secret_are_rejected() {
+    let token = hs256(b"another secret", json!({"sub": "alice"}));
+    let auth = JwtAuth::new().hmac_secret(SECRET);
+    assert_eq!(
+        send(auth, Some(&token)).await,
+        rejected("invalid signature")
+    );
+}
+
+#[tokio::test]
+async fn tampered_claims_are_rejected() {
+    let token = hs256(SECRET, json!({"sub": "alice"}));
+    let mut parts = token.split('.').collect::<Vec<_>>();
+    let claims = b64(json!({"sub": "admin"}).to_string().as_bytes());
+    parts[1] = &claims;
+    let auth = JwtAuth::new().hmac_secret(SECRET);
+    assert_eq!(
+        send(auth, Some(&parts.join("."))).await,
+        rejected("invalid signature")
+    );
+}
+
+#[tokio::test]
+async fn unsigned_tokens_are_rejected() {
+    let token = token(json!({"alg": "none"}), json!({"sub": "alice"}), |_| {
+        Vec::new()
+    });
+    let auth = JwtAuth::new().hmac_secret(SECRET);
+    assert_eq!(
+        send(auth, Some(&token)).await,
+        rejected("unsupported algorithm")
+    );
+}
+
+#[tokio::test]
+async fn malformed_tokens_are_rejected() {
+    let auth = || JwtAuth::new().hmac_secret(SECRET);
+    for token in &["a.b", "a.b.c.d", "!.!.!"] {
+        assert_eq!(
+            send(auth(), Some(token)).await,
+            rejected("malformed token"),
+            "{}",
+            token
+        );
+    }
+}
+
+#[tokio::test]
+async fn expiry_is_checked_with_leeway() {
+    let auth = || {
+        JwtAuth::new()
+            .hmac_secret(SECRET)
+            .leeway(Duration::from_secs(10))
+    };
+    let expired = hs256(SECRET, json!({"sub": "alice", "exp": now() - 20}));
+    assert_eq!(
+        send(auth(), Some(&expired)).await,
+        rejected("token expired")
+    );
+    let skewed = hs256(SECRET, json!({"sub": "alice", "exp": now() - 5}));
+    assert_eq!(send(auth(), Some(&skewed)).await, accepted());
+    let early = hs256(SECRET, json!({"sub": "alice", "nbf": now() + 20}));
+    assert_eq!(
+        send(auth(), Some(&early)).await,
+        rejected("token not yet valid")
+    );
+    let skewed = hs256(SECRET, json!({"sub": "alice", "nbf": now() + 5}));
+    assert_eq!(send(auth(), Some(&skewed)).await, accepted());
+    let invalid = hs256(SECRET, json!({"sub": "alice", "exp": "tomorrow"}));
+    assert_eq!(
+        send(auth(), Some(&invalid)).await,
+        rejected("malformed token")
+    );
+}
+
+#[tokio::test]
+async fn issuer_and_audience_are_checked() {
+    let auth = || {
+        JwtAuth::new()
+            .hmac_secret(SECRET)
+            .issuer("https://auth.example.com/")
+            .audience("reports")
+            .audience("billing")
+    };
+    let claims = |iss: &str, aud: Value| json!({"sub": "alice", "iss": iss, "aud": aud});
+
+    let token = hs256(
+        SECRET,
+        claims("https://auth.example.com/", json!("billing")),
+    );
+    assert_eq!(send(auth(), Some(&token)).await, accepted());
+    let token = hs256(
+        SECRET,
+        claims("https://auth.example.com/", json!(["other", "reports"])),
+    );
+    assert_eq!(send(auth(), Some(&token)).await, accepted());
+    let token = hs256(
+        SECRET,
+        claims("https://evil.example.com/", json!("reports")),
+    );
+    assert_eq!(send(auth(), Some(&token)).await, rejected("invalid issuer"));
+    let token = hs256(
+        SECRET,
+        claims("https://auth.example.com/", json!(["other"])),
+    );
+    assert_eq!(
+        send(auth(), Some(&token)).await,
+        rejected("invalid audience")
+    );
+    let token = hs256(
+        SECRET,
+        json!({"sub": "alice", "iss": "https://auth.example.com/"}),
+    );
+    assert_eq!(
+        send(auth(), Some(&token)).await,
+        rejected("invalid audience")
+    );
+}
+
+/// Serve `jwks` over HTTP, returning its URL.
+async fn serve_jwks(jwks: Value) -> String {
+    let jwks = jwks.to_string();
+    let make = make_service_fn(move |_| {
+        let jwks = jwks.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |_req| {
+                let jwks = jwks.clone();
+                async move { Ok::<_, Infallible>(Response::new(Body::from(jwks))) }
+            }))
+        }
+    });
+    let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make);
+    let url = format!("http://{}/jwks.json", server.local_addr());
+    tokio::spawn(server);
+    url
+}
+
+struct EcKey {
+    pair: EcdsaKeyPair,
+    rng: SystemRandom,
+}
+
+impl EcKey {
+    fn generate() -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng).unwrap();
+        let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+            .unwrap();
+        Self { pair, rng }
+    }
+
+    fn jwk(&self, kid: &str) -> Value {
+        // The uncompressed point: 0x04, then x and y.
+        let point = self.pair.public_key().as_ref();
+        json!({
+            "kty": "EC",
+            "crv": "P-256",
+            "kid": kid,
+            "use": "sig",
+            "x": b64(&point[1..33]),
+            "y": b64(&point[33..]),
+        })
+    }
+
+    fn sign(&self, kid: &str, claims: Value) -> String {
+        token(json!({"alg": "ES256", "kid": kid}), claims, |message| {
+            self.pair
+                .sign(&self.rng, message)
+                .unwrap()
+                .as_ref()
+                .to_vec()
+        })
+    }
+}
+
+#[tokio::test]
+async fn tokens_signed_by_keys_of_the_jwks_are_accepted() {
+    let key = EcKey::generate();
+    let url = serve_jwks(json!({"keys": [key.jwk("key-1")]})).await;
+    let token = key.sign("key-1", json!({"sub": "alice"}));
+    assert_eq!(
+        send(JwtAuth::new().jwks_url(url), Some(&token)).await,
+        accepted()
+    );
+}
+
+#[tokio::test]
+async fn tokens_signed_by_other_keys_are_rejected() {
+    let key = EcKey::generate();
+    let other = EcKey::generate();
+    let url = serve_jwks(json!({"keys": [key.jwk("key-1")]})).await;
+    let auth = || JwtAuth::new().jwks_url(url.clone());
+
+    let token = other.sign("key-1", json!({"sub": "alice"}));
+    assert_eq!(
+        send(auth(), Some(&token)).await,
+        rejected("invalid signature")
+    );
+    let token = other.sign("key-2", json!({"sub": "alice"}));
+    assert_eq!(
+        send(auth(), Some(&token)).await,
+        rejected("unknown signing key")
+    );
+}
+
+#[tokio::test]
+async fn public_keys_are_not_used_as_hmac_secrets() {
+    let key = EcKey::generate();
+    let url = serve_jwks(json!({"keys": [key.jwk("key-1")]})).await;
+    // An attacker knowing the public key signs with it as an HMAC secret.
+    let token = token(
+        json!({"alg": "HS256", "kid": "key-1"}),
+        json!({"sub": "alice"}),
+        |message| {
+            let secret = hmac::Key::new(hmac::HMAC_SHA256, key.pair.public_key().as_ref());
+            hmac::sign(&secret, message).as_ref().to_vec()
+        },
+    );
+    assert_eq!(
+        send(JwtAuth::new().jwks_url(url), Some(&token)).await,
+        rejected("unknown signing key")
+    );
+}
+
+#[tokio::test]
+async fn oct_keys_of_the_jwks_are_ignored() {
+    let url =
+        serve_jwks(json!({"keys": [{"kty": "oct", "kid": "shared", "k": b64(SECRET)}]})).await;
+    let token = token(
+        json!({"alg": "HS256", "kid": "shared"}),
+        json!({"sub": "alice"}),
+        |message| {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, SECRET);
+            hmac::sign(&key, message).as_ref().to_vec()
+        },
+    );
+    assert_eq!(
+        send(JwtAuth::new().jwks_url(url), Some(&token)).await,
+        rejected("unknown signing key")
+    );
+}
@@ -0,0 +1,212 @@
+//! The token buckets of [`keiro::rate_limit::RateLimit`].
+
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use hyper::header::{HeaderName, RETRY_AFTER};
+use hyper::{Body, Request, Response, StatusCode};
+use keiro::connect::ConnectInfo;
+use keiro::rate_limit::{
+    ByHeader, ByRoute, Decision, MemoryStore, Quota, RateLimit, RateLimitFuture, RateLimitStore,
+};
+use keiro::{Router, RouterService};
+use tower::ServiceExt;
+
+#[test]
+fn quotas_spread_the_period_over_the_requests() {
+    let quota = Quota::per_minute(120);
+    assert_eq!(quota.interval, Duration::from_millis(500));
+    assert_eq!(quota.burst, 120);
+    assert_eq!(quota.burst(10).burst, 10);
+    assert_eq!(quota.burst(10).interval, quota.interval);
+    assert_eq!(quota.burst(0).burst, 1);
+}
+
+#[test]
+#[should_panic(expected = "a quota must allow requests")]
+fn empty_quotas_are_rejected() {
+    Quota::per_second(0);
+}
+
+#[test]
+fn decisions_take_whole_tokens() {
+    let quota = Quota::per_second(4);
+    let (decision, tokens) = Decision::take(4.0, quota);
+    assert!(decision.allowed);
+    assert_eq!(tokens, 3.0);
+    assert_eq!(decision.remaining, 3);
+    assert_eq!(decision.reset, Duration::from_millis(250));
+    assert_eq!(decision.retry_after, Duration::ZERO);
+
+    let (decision, tokens) = Decision::take(1.5, quota);
+    assert!(decision.allowed);
+    assert_eq!(tokens, 0.5);
+    assert_eq!(decision.remaining, 0);
+
+    let (decision, tokens) = Decision::take(0.5, quota);
+    assert!(!decision.allowed);
+    assert_eq!(tokens, 0.5);
+    assert_eq!(decision.remaining, 0);
+    assert_eq!(decision.reset, Duration::from_millis(875));
+    assert_eq!(decision.retry_after, Duration::from_millis(125));
+}
+
+async fn acquire(store: &MemoryStore, key: &str, quota: Quota) -> Decision {
+    store.acquire(key, quota).await.unwrap()
+}
+
+#[tokio::test]
+async fn buckets_hold_a_burst_of_tokens() {
+    let store = MemoryStore::new();
+    let quota = Quota::per_hour(100).burst(3);
+    for remaining in [2, 1, 0] {
+        let decision = acquire(&store, "a", quota).await;
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, remaining);
+    }
+    let decision = acquire(&store, "a", quota).await;
+    assert!(!decision.allowed);
+    assert!(decision.retry_after > Duration::from_secs(35));
+    assert!(decision.retry_after <= Duration::from_secs(36));
+    // Rejected requests don't take tokens, and other keys have buckets of their own.
+    assert!(!acquire(&store, "a", quota).await.allowed);
+    assert!(acquire(&store, "b", quota).await.allowed);
+    // Clones share the buckets.
+    assert!(!acquire(&store.clone(), "a", quota).await.allowed);
+}
+
+#[tokio::test]
+async fn buckets_are_refilled_up_to_the_burst() {
+    let store = MemoryStore::new();
+    let quota = Quota::new(1, Duration::from_millis(50)).burst(2);
+    assert!(acquire(&store, "a", quota).await.allowed);
+    assert!(acquire(&store, "a", quota).await.allowed);
+    assert!(!acquire(&store, "a", quota).await.allowed);
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert!(acquire(&store, "a", quota).await.allowed);
+    assert!(!acquire(&store, "a", quota).await.allowed);
+
+    // Idle buckets don't save more than a burst.
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(acquire(&store, "a", quota).await.remaining, 1);
+    assert!(acquire(&store, "a", quota).await.allowed);
+    assert!(!acquire(&store, "a", quota).await.allowed);
+}
+
+fn router(limit: RateLimit) -> RouterService<keiro::Error, ()> {
+    let mut router = Router::new();
+    router.rate_limit(limit);
+    router.get("/a", |_req| async {
+        Ok::<_, Infallible>(Response::new(Body::empty()))
+    });
+    router.get("/b", |_req| async {
+        Ok::<_, Infallible>(Response::new(Body::empty()))
+    });
+    RouterService::new(router)
+}
+
+fn request(path: &str, peer: Option<&str>, api_key: Option<&str>) -> Request<Body> {
+    let mut req = Request::get(path);
+    if let Some(api_key) = api_key {
+        req = req.header("x-api-key", api_key);
+    }
+    let mut req = req.body(Body::empty()).unwrap();
+    if let Some(peer) = peer {
+        let peer = SocketAddr::new(peer.parse().unwrap(), 1234);
+        req.extensions_mut().insert(ConnectInfo(peer));
+    }
+    req
+}
+
+async fn send(svc: &RouterService<keiro::Error, ()>, req: Request<Body>) -> Response<Body> {
+    svc.clone().oneshot(req).await.unwrap()
+}
+
+#[tokio::test]
+async fn clients_are_limited_by_ip() {
+    let svc = router(RateLimit::new(Quota::per_hour(60).burst(2)));
+    let res = send(&svc, request("/a", Some("192.0.2.1"), None)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["x-ratelimit-limit"], "2");
+    assert_eq!(res.headers()["x-ratelimit-remaining"], "1");
+    assert_eq!(res.headers()["x-ratelimit-reset"], "60");
+    // The buckets are shared by the routes of the router.
+    let res = send(&svc, request("/b", Some("192.0.2.1"), None)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["x-ratelimit-remaining"], "0");
+
+    let res = send(&svc, request("/a", Some("192.0.2.1"), None)).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(res.headers()[RETRY_AFTER], "60");
+    assert_eq!(res.headers()["x-ratelimit-remaining"], "0");
+    assert_eq!(res.headers()["x-ratelimit-reset"], "120");
+
+    // Mapped addresses are the same clients.
+    let res = send(&svc, request("/a", Some("::ffff:192.0.2.1"), None)).await;
+    assert_eq!(res.status(), StatusCode::TOO_MANY_REQUESTS);
+    let res = send(&svc, request("/a", Some("192.0.2.2"), None)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn requests_without_keys_are_not_limited() {
+    let svc = router(RateLimit::new(Quota::per_hour(1)));
+    for _ in 0..3 {
+        let res = send(&svc, request("/a", None, None)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(!res.headers().contains_key("x-ratelimit-limit"));
+    }
+}
+
+#[tokio::test]
+async fn requests_are_keyed_by_header_or_route() {
+    let key = ByHeader(HeaderName::from_static("x-api-key"));
+    let svc = router(RateLimit::new(Quota::per_hour(1)).key(key));
+    let status = |res: Response<Body>| res.status();
+    assert_eq!(
+        status(send(&svc, request("/a", Some("192.0.2.1"), Some("k1"))).await),
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(send(&svc, request("/b", Some("192.0.2.2"), Some("k1"))).await),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        status(send(&svc, request("/a", Some("192.0.2.1"), Some("k2"))).await),
+        StatusCode::OK
+    );
+
+    let svc = router(RateLimit::new(Quota::per_hour(1)).key(ByRoute));
+    assert_eq!(
+        status(send(&svc, request("/a", Some("192.0.2.1"), None)).await),
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(send(&svc, request("/a", Some("192.0.2.2"), None)).await),
+        StatusCode::TOO_MANY_REQUESTS
+    );
+    assert_eq!(
+        status(send(&svc, request("/b", Some("192.0.2.1"), None)).await),
+        StatusCode::OK
+    );
+}
+
+struct FailingStore;
+
+impl RateLimitStore for FailingStore {
+    fn acquire(&self, _key: &str, _quota: Quota) -> RateLimitFuture<'_> {
+        Box::pin(async { Err(io::Error::other("unavailable")) })
+    }
+}
+
+#[tokio::test]
+async fn requests_are_let_through_when_the_store_fails() {
+    let svc = router(RateLimit::new(Quota::per_hour(1)).store(FailingStore));
+    for _ in 0..3 {
+        let res = send(&svc, request("/a", Some("192.0.2.1"), None)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,187 @@
+//! The values read back by [`keiro::cookie::SignedCookies`] and
+//! [`keiro::cookie::PrivateCookies`].
+#![cfg(feature = "secure-cookies")]
+
+use hyper::{Body, Request, Response, StatusCode};
+use keiro::cookie::{Cookie, Key, PrivateCookies, SignedCookies};
+use keiro::prelude::*;
+use keiro::{Router, RouterService};
+use tower::ServiceExt;
+
+const SECRET: &[u8] = b"a long random secret of at least 32 bytes";
+
+/// A request sending `cookies` as formatted for `Set-Cookie`, to a router with the key of
+/// `secret`.
+fn request(secret: &[u8], cookies: &[Cookie]) -> Request<Body> {
+    let header = cookies
+        .iter()
+        .map(|cookie| cookie.to_string())
+        .collect::<Vec<_>>()
+        .join("; ");
+    let mut req = Request::get("/")
+        .header("cookie", header)
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut().insert(Key::new(secret));
+    req
+}
+
+fn signed() -> SignedCookies {
+    request(SECRET, &[]).extract().unwrap()
+}
+
+fn private() -> PrivateCookies {
+    request(SECRET, &[]).extract().unwrap()
+}
+
+/// `cookie` with its value replaced by `value`.
+fn with_value(cookie: &Cookie, value: impl Into<String>) -> Cookie {
+    Cookie::new(cookie.name(), value)
+}
+
+#[test]
+fn signed_values_are_read_back() {
+    let cookie = signed().sign(Cookie::new("user", "42; admin=1"));
+    let cookies = request(SECRET, &[cookie])
+        .extract::<SignedCookies>()
+        .unwrap();
+    assert_eq!(cookies.get("user"), Some("42; admin=1"));
+    assert_eq!(cookies.get("other"), None);
+}
+
+#[test]
+fn signed_values_can_be_read_but_not_changed() {
+    let cookie = signed().sign(Cookie::new("user", "42"));
+    assert!(cookie.value().ends_with("42"));
+
+    let changed = with_value(&cookie, cookie.value().replace("42", "43"));
+    let cookies = request(SECRET, &[changed])
+        .extract::<SignedCookies>()
+        .unwrap();
+    assert_eq!(cookies.get("user"), None);
+}
+
+#[test]
+fn signatures_are_bound_to_the_name() {
+    let cookie = signed().sign(Cookie::new("user", "42"));
+    let renamed = Cookie::new("admin", cookie.value());
+    let cookies = request(SECRET, &[renamed])
+        .extract::<SignedCookies>()
+        .unwrap();
+    assert_eq!(cookies.get("admin"), None);
+}
+
+#[test]
+fn signatures_of_other_keys_are_rejected() {
+    let cookie = signed().sign(Cookie::new("user", "42"));
+    let other = b"another long random secret of 32 bytes";
+    let cookies = request(other, &[cookie])
+        .extract::<SignedCookies>()
+        .unwrap();
+    assert_eq!(cookies.get("user"), None);
+}
+
+#[test]
+fn unsigned_values_are_rejected() {
+    let values = ["", "42", "é", &"é".repeat(40), &"A".repeat(43)];
+    for value in values {
+        let cookies = request(SECRET, &[Cookie::new("user", value)])
+            .extract::<SignedCookies>()
+            .unwrap();
+        assert_eq!(cookies.get("user"), None, "{}", value);
+    }
+}
+
+#[test]
+fn private_values_are_read_back_but_hidden() {
+    let cookie = private().encrypt(Cookie::new("session", "user 42"));
+    assert!(!cookie.value().contains("user 42"));
+    let cookies = request(SECRET, &[cookie])
+        .extract::<PrivateCookies>()
+        .unwrap();
+    assert_eq!(cookies.get("session").as_deref(), Some("user 42"));
+}
+
+#[test]
+fn private_values_are_encrypted_with_fresh_nonces() {
+    let first = private().encrypt(Cookie::new("session", "user 42"));
+    let second = private().encrypt(Cookie::new("session", "user 42"));
+    assert_ne!(first.value(), second.value());
+}
+
+#[test]
+fn private_values_can_not_be_changed() {
+    let cookie = private().encrypt(Cookie::new("session", "user 42"));
+    // Flip each character of the value in turn.
+    for i in 0..cookie.value().len() {
+        let mut value = cookie.value().to_string().into_bytes();
+        value[i] = if value[i] == b'A' { b'B' } else { b'A' };
+        let changed = with_value(&cookie, String::from_utf8(value).unwrap());
+        let cookies = request(SECRET, &[changed])
+            .extract::<PrivateCookies>()
+            .unwrap();
+        assert_eq!(cookies.get("session"), None, "{}", i);
+    }
+}
+
+#[test]
+fn private_values_are_bound_to_the_name_and_key() {
+    let cookie = private().encrypt(Cookie::new("session", "user 42"));
+    let renamed = Cookie::new("admin", cookie.value());
+    let cookies = request(SECRET, &[renamed])
+        .extract::<PrivateCookies>()
+        .unwrap();
+    assert_eq!(cookies.get("admin"), None);
+
+    let other = b"another long random secret of 32 bytes";
+    let cookies = request(other, &[cookie])
+        .extract::<PrivateCookies>()
+        .unwrap();
+    assert_eq!(cookies.get("session"), None);
+}
+
+#[test]
+fn truncated_private_values_are_rejected() {
+    for value in ["", "AAAA", "!!!!", &"A".repeat(16)] {
+        let cookies = request(SECRET, &[Cookie::new("session", value)])
+            .extract::<PrivateCookies>()
+            .unwrap();
+        assert_eq!(cookies.get("session"), None, "{}", value);
+    }
+}
+
+#[test]
+#[should_panic(expected = "cookie keys need at least 32 bytes")]
+fn short_secrets_are_rejected() {
+    Key::new(b"too short");
+}
+
+#[tokio::test]
+async fn routers_without_keys_fail_to_extract() {
+    let mut router = Router::new();
+    router.get("/", |req: Request<Body>| async move {
+        req.extract::<SignedCookies>()?;
+        Ok::<_, keiro::Error>(Response::new(Body::empty()))
+    });
+    let req = Request::get("/").body(Body::empty()).unwrap();
+    let res = RouterService::new(router).oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn routers_pass_their_key_to_handlers() {
+    let mut router = Router::new();
+    router.cookie_key(SECRET);
+    router.get("/", |req: Request<Body>| async move {
+        let cookies = req.extract::<SignedCookies>()?;
+        Ok::<_, keiro::Error>(Response::new(Body::from(
+            cookies.get("user").unwrap_or("nobody").to_string(),
+        )))
+    });
+    let cookie = signed().sign(Cookie::new("user", "42"));
+    let mut req = request(SECRET, &[cookie]);
+    req.extensions_mut().clear();
+    let res = RouterService::new(router).oneshot(req).await.unwrap();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"42");
+}
@@ -0,0 +1,219 @@
+//! The session IDs given out by [`keiro::sessions::Sessions`].
+#![cfg(feature = "sessions")]
+
+use std::convert::Infallible;
+use std::io;
+use std::time::Duration;
+
+use hyper::header::{COOKIE, SET_COOKIE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use keiro::prelude::*;
+use keiro::sessions::{MemoryStore, SessionFuture, SessionStore, Sessions};
+use keiro::{Router, RouterService};
+use tower::ServiceExt;
+
+/// A valid session ID the store doesn't know.
+const PLANTED: &str = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+fn router(store: impl SessionStore) -> RouterService<keiro::Error, ()> {
+    let mut router = Router::new();
+    router.sessions(Sessions::new(store));
+    router.get("/", |req: Request<Body>| async move {
+        let user = req.session().unwrap().get::<String>("user");
+        Ok::<_, Infallible>(Response::new(Body::from(user.unwrap_or_default())))
+    });
+    router.post("/cart", |req: Request<Body>| async move {
+        req.session().unwrap().insert("cart", "book").unwrap();
+        Ok::<_, Infallible>(Response::new(Body::empty()))
+    });
+    router.post("/login", |req: Request<Body>| async move {
+        let session = req.session().unwrap();
+        session.regenerate();
+        session.insert("user", "alice").unwrap();
+        Ok::<_, Infallible>(Response::new(Body::empty()))
+    });
+    router.post("/logout", |req: Request<Body>| async move {
+        req.session().unwrap().destroy();
+        Ok::<_, Infallible>(Response::new(Body::empty()))
+    });
+    RouterService::new(router)
+}
+
+async fn send(
+    svc: &RouterService<keiro::Error, ()>,
+    method: Method,
+    path: &str,
+    id: Option<&str>,
+) -> Response<Body> {
+    let mut req = Request::builder().method(method).uri(path);
+    if let Some(id) = id {
+        req = req.header(COOKIE, format!("keiro_session={}", id));
+    }
+    let req = req.body(Body::empty()).unwrap();
+    svc.clone().oneshot(req).await.unwrap()
+}
+
+/// The session ID set by `res`, or `None` if it sets none.
+fn session_id(res: &Response<Body>) -> Option<String> {
+    let cookie = res.headers().get(SET_COOKIE)?.to_str().unwrap();
+    let id = cookie.strip_prefix("keiro_session=")?.split(';').next()?;
+    Some(id.to_string())
+}
+
+async fn body(res: Response<Body>) -> String {
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn cookies_are_only_set_once_sessions_have_data() {
+    let svc = router(MemoryStore::new());
+    let res = send(&svc, Method::GET, "/", None).await;
+    assert!(!res.headers().contains_key(SET_COOKIE));
+
+    let res = send(&svc, Method::POST, "/cart", None).await;
+    let cookie = res.headers()[SET_COOKIE].to_str().unwrap();
+    assert!(cookie.contains("; Path=/"), "{}", cookie);
+    assert!(cookie.contains("; Max-Age=86400"), "{}", cookie);
+    assert!(cookie.contains("; HttpOnly"), "{}", cookie);
+    assert!(cookie.contains("; SameSite=Lax"), "{}", cookie);
+    let id = session_id(&res).unwrap();
+    assert_eq!(id.len(), 43);
+
+    // Unchanged sessions keep their cookie.
+    let res = send(&svc, Method::GET, "/", Some(&id)).await;
+    assert!(!res.headers().contains_key(SET_COOKIE));
+    // Changed ones keep their ID.
+    let res = send(&svc, Method::POST, "/cart", Some(&id)).await;
+    assert_eq!(session_id(&res).as_deref(), Some(id.as_str()));
+}
+
+#[tokio::test]
+async fn ids_the_store_does_not_know_are_replaced() {
+    let store = MemoryStore::new();
+    let svc = router(store.clone());
+    let res = send(&svc, Method::POST, "/cart", Some(PLANTED)).await;
+    let id = session_id(&res).unwrap();
+    assert_ne!(id, PLANTED);
+    assert_eq!(store.load(PLANTED).await.unwrap(), None);
+    assert!(store.load(&id).await.unwrap().is_some());
+
+    // IDs which can't have been given out aren't even looked up.
+    for planted in [
+        "short",
+        "../../etc/passwd",
+        &"A".repeat(44),
+        &"%".repeat(43),
+    ] {
+        let res = send(&svc, Method::POST, "/cart", Some(planted)).await;
+        assert_eq!(session_id(&res).unwrap().len(), 43, "{}", planted);
+    }
+}
+
+#[tokio::test]
+async fn logging_in_regenerates_the_id() {
+    let store = MemoryStore::new();
+    let svc = router(store.clone());
+    // The attacker gets an ID, and makes the victim's browser use it.
+    let res = send(&svc, Method::POST, "/cart", None).await;
+    let planted = session_id(&res).unwrap();
+
+    let res = send(&svc, Method::POST, "/login", Some(&planted)).await;
+    let id = session_id(&res).unwrap();
+    assert_ne!(id, planted);
+    assert_eq!(store.load(&planted).await.unwrap(), None);
+    assert_eq!(
+        body(send(&svc, Method::GET, "/", Some(&planted)).await).await,
+        ""
+    );
+    assert_eq!(
+        body(send(&svc, Method::GET, "/", Some(&id)).await).await,
+        "alice"
+    );
+    // The data of the session moves to the new ID.
+    let data = store.load(&id).await.unwrap().unwrap();
+    assert!(data.contains("\"cart\":\"book\""), "{}", data);
+}
+
+#[tokio::test]
+async fn destroyed_sessions_are_forgotten() {
+    let store = MemoryStore::new();
+    let svc = router(store.clone());
+    let res = send(&svc, Method::POST, "/login", None).await;
+    let id = session_id(&res).unwrap();
+
+    let res = send(&svc, Method::POST, "/logout", Some(&id)).await;
+    assert_eq!(session_id(&res).as_deref(), Some(""));
+    let cookie = res.headers()[SET_COOKIE].to_str().unwrap();
+    assert!(cookie.contains("; Max-Age=0"), "{}", cookie);
+    assert_eq!(store.load(&id).await.unwrap(), None);
+    assert_eq!(
+        body(send(&svc, Method::GET, "/", Some(&id)).await).await,
+        ""
+    );
+}
+
+#[tokio::test]
+async fn sessions_expire_after_their_ttl() {
+    let store = MemoryStore::new();
+    let mut router = Router::new();
+    router.sessions(Sessions::new(store.clone()).ttl(Duration::from_millis(50)));
+    router.post("/cart", |req: Request<Body>| async move {
+        let session = req.session().unwrap();
+        let visits = session.get::<u32>("visits").unwrap_or(0) + 1;
+        session.insert("visits", &visits).unwrap();
+        Ok::<_, Infallible>(Response::new(Body::from(visits.to_string())))
+    });
+    let svc = RouterService::new(router);
+    let res = send(&svc, Method::POST, "/cart", None).await;
+    let id = session_id(&res).unwrap();
+    let res = send(&svc, Method::POST, "/cart", Some(&id)).await;
+    assert_eq!(body(res).await, "2");
+
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    let res = send(&svc, Method::POST, "/cart", Some(&id)).await;
+    assert_ne!(session_id(&res).unwrap(), id);
+    assert_eq!(body(res).await, "1");
+}
+
+/// A store failing to load or to store sessions.
+struct FailingStore {
+    load: bool,
+}
+
+impl SessionStore for FailingStore {
+    fn load(&self, _id: &str) -> SessionFuture<'_, Option<String>> {
+        let load = self.load;
+        Box::pin(async move {
+            if load {
+                Err(io::Error::other("unavailable"))
+            } else {
+                Ok(Some("{}".to_string()))
+            }
+        })
+    }
+
+    fn store(&self, _id: &str, _data: &str, _ttl: Duration) -> SessionFuture<'_, ()> {
+        Box::pin(async { Err(io::Error::other("unavailable")) })
+    }
+
+    fn remove(&self, _id: &str) -> SessionFuture<'_, ()> {
+        Box::pin(async { Err(io::Error::other("unavailable")) })
+    }
+}
+
+#[tokio::test]
+async fn failing_stores_fail_requests() {
+    let svc = router(FailingStore { load: true });
+    let res = send(&svc, Method::GET, "/", Some(PLANTED)).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let svc = router(FailingStore { load: false });
+    let res = send(&svc, Method::POST, "/cart", Some(PLANTED)).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(!res.headers().contains_key(SET_COOKIE));
+    // Failing to remove the old session fails the regeneration.
+    let res = send(&svc, Method::POST, "/login", Some(PLANTED)).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(!res.headers().contains_key(SET_COOKIE));
+}
@@ -0,0 +1,185 @@
+//! The URLs accepted by routes requiring a signature.
+#![cfg(feature = "signed-urls")]
+
+use std::convert::Infallible;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use hyper::{Body, Request, Response, StatusCode};
+use keiro::url::UrlError;
+use keiro::{Router, RouterService};
+use sha2::Sha256;
+use tower::ServiceExt;
+
+const KEY: &[u8] = b"a long random secret";
+
+fn router() -> Router {
+    let mut router = Router::new();
+    router.signing_key(KEY.to_vec());
+    router
+        .get("/files/:id", |_req| async {
+            Ok::<_, Infallible>(Response::new(Body::from("file contents")))
+        })
+        .name("download")
+        .require_signature();
+    router.get("/public", |_req| async {
+        Ok::<_, Infallible>(Response::new(Body::empty()))
+    });
+    router
+}
+
+fn signed_url(ttl: Duration) -> String {
+    router()
+        .signed_url_for("download", &[("id", "42")], ttl)
+        .unwrap()
+}
+
+/// Request `url` from `router`, returning the status and the body.
+async fn get(router: Router, url: &str) -> (StatusCode, String) {
+    let req = Request::get(url).body(Body::empty()).unwrap();
+    let res = RouterService::new(router).oneshot(req).await.unwrap();
+    let status = res.status();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+fn forbidden(message: &str) -> (StatusCode, String) {
+    (StatusCode::FORBIDDEN, message.to_string())
+}
+
+/// Sign `url` with `key` the way signed URLs are, with an explicit expiry time.
+fn forge(url: &str, key: &[u8], expires: u64) -> String {
+    let url = format!("{}?expires={}", url, expires);
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    mac.update(url.as_bytes());
+    let signature: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}&signature={}", url, signature)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[tokio::test]
+async fn signed_urls_are_accepted() {
+    let url = signed_url(Duration::from_secs(600));
+    assert!(url.starts_with("/files/42?expires="), "{}", url);
+    assert_eq!(
+        get(router(), &url).await,
+        (StatusCode::OK, "file contents".to_string())
+    );
+}
+
+#[tokio::test]
+async fn unsigned_urls_are_rejected() {
+    assert_eq!(
+        get(router(), "/files/42").await,
+        forbidden("invalid signature")
+    );
+    let url = format!("/files/42?expires={}", now() + 600);
+    assert_eq!(get(router(), &url).await, forbidden("invalid signature"));
+    // Routes without the requirement don't check signatures.
+    assert_eq!(get(router(), "/public").await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn signatures_are_bound_to_the_path() {
+    let url = signed_url(Duration::from_secs(600)).replace("/files/42", "/files/43");
+    assert_eq!(get(router(), &url).await, forbidden("invalid signature"));
+}
+
+#[tokio::test]
+async fn signatures_cover_the_query() {
+    let url = signed_url(Duration::from_secs(600));
+
+    let extended = url.replace("?", "?admin=1&");
+    assert_eq!(
+        get(router(), &extended).await,
+        forbidden("invalid signature")
+    );
+    let appended = format!("{}&admin=1", url);
+    assert_eq!(
+        get(router(), &appended).await,
+        forbidden("invalid signature")
+    );
+
+    // Extending the lifetime of the URL, by replacing or repeating the expiry time.
+    let expires = url.split(['=', '&']).nth(1).unwrap();
+    let later = (expires.parse::<u64>().unwrap() + 3600).to_string();
+    let extended = url.replace(expires, &later);
+    assert_eq!(
+        get(router(), &extended).await,
+        forbidden("invalid signature")
+    );
+    let repeated = format!("{}&expires={}", url, later);
+    assert_eq!(
+        get(router(), &repeated).await,
+        forbidden("invalid signature")
+    );
+}
+
+#[tokio::test]
+async fn malformed_signatures_are_rejected() {
+    let url = signed_url(Duration::from_secs(600));
+    let (unsigned, signature) = url.split_once("&signature=").unwrap();
+    let last = if signature.ends_with('0') { "1" } else { "0" };
+    let flipped = format!("{}{}", &signature[..signature.len() - 1], last);
+    for signature in [
+        "",
+        &signature[1..],
+        &signature[..signature.len() - 2],
+        &flipped,
+        "zz",
+        "%C3%A9",
+    ] {
+        let url = format!("{}&signature={}", unsigned, signature);
+        assert_eq!(
+            get(router(), &url).await,
+            forbidden("invalid signature"),
+            "{}",
+            url
+        );
+    }
+}
+
+#[tokio::test]
+async fn signatures_of_other_keys_are_rejected() {
+    let url = forge("/files/42", b"another secret", now() + 600);
+    assert_eq!(get(router(), &url).await, forbidden("invalid signature"));
+    let url = forge("/files/42", KEY, now() + 600);
+    assert_eq!(get(router(), &url).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn expired_urls_are_rejected() {
+    let url = forge("/files/42", KEY, now() - 1);
+    assert_eq!(
+        get(router(), &url).await,
+        forbidden("the signed URL has expired")
+    );
+}
+
+#[tokio::test]
+async fn routers_without_keys_reject_every_url() {
+    let url = signed_url(Duration::from_secs(600));
+    let mut router = Router::new();
+    router
+        .get("/files/:id", |_req| async {
+            Ok::<_, Infallible>(Response::new(Body::empty()))
+        })
+        .name("download")
+        .require_signature();
+    assert_eq!(
+        router.signed_url_for("download", &[("id", "42")], Duration::from_secs(600)),
+        Err(UrlError::NoSigningKey)
+    );
+    assert_eq!(get(router, &url).await, forbidden("invalid signature"));
+}
@@ -0,0 +1,132 @@
+//! The keys [`keiro::storage::LocalStorage`] refuses to resolve outside of its root.
+
+use std::fs;
+use std::path::PathBuf;
+
+use hyper::{Body, Request, StatusCode};
+use keiro::files::ServeDir;
+use keiro::storage::{LocalStorage, Storage};
+use keiro::{Router, RouterService};
+use tower::ServiceExt;
+
+/// Keys naming `secret.txt` next to the root, or files elsewhere.
+const ESCAPING: &[&str] = &[
+    "../secret.txt",
+    "a/../../secret.txt",
+    "./../secret.txt",
+    "root/../../secret.txt",
+    "..",
+    "./public.txt",
+    "a/..",
+];
+
+/// A directory holding `secret.txt` and the root `root` with `public.txt`, removed on drop.
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("keiro-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("root/a")).unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+        fs::write(dir.join("root/public.txt"), "public").unwrap();
+        Self(dir)
+    }
+
+    fn storage(&self) -> LocalStorage {
+        LocalStorage::new(self.0.join("root"))
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[tokio::test]
+async fn keys_are_relative_to_the_root() {
+    let dir = Dir::new("relative");
+    let storage = dir.storage();
+    assert!(storage.head("public.txt").await.unwrap().is_some());
+    // A leading `/` doesn't make keys absolute.
+    assert!(storage.head("/public.txt").await.unwrap().is_some());
+    assert!(storage.head("a/./../public.txt").await.unwrap().is_none());
+    let absolute = dir.0.join("secret.txt");
+    let absolute = absolute.to_str().unwrap();
+    assert!(storage.head(absolute).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn escaping_keys_are_not_read() {
+    let dir = Dir::new("read");
+    let storage = dir.storage();
+    for key in ESCAPING {
+        assert!(storage.head(key).await.unwrap().is_none(), "{}", key);
+        assert!(storage.get(key).await.unwrap().is_none(), "{}", key);
+        assert!(
+            storage.get_range(key, 0..1).await.unwrap().is_none(),
+            "{}",
+            key
+        );
+    }
+}
+
+#[tokio::test]
+async fn escaping_keys_are_not_written_or_deleted() {
+    let dir = Dir::new("write");
+    let storage = dir.storage();
+    for key in ESCAPING {
+        let err = storage
+            .put(key, Body::from("overwritten"), 11)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", key);
+        let err = storage.delete(key).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", key);
+        let err = storage.list(key).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", key);
+    }
+    assert_eq!(
+        fs::read_to_string(dir.0.join("secret.txt")).unwrap(),
+        "secret"
+    );
+    assert!(!dir.0.join("overwritten").exists());
+    assert_eq!(fs::read_dir(&dir.0).unwrap().count(), 2);
+}
+
+#[tokio::test]
+async fn escaping_urls_are_not_served() {
+    let dir = Dir::new("serve");
+    let mut router = Router::new();
+    router.nest(
+        "/assets",
+        ServeDir::new(dir.storage()).listing(true).router(),
+    );
+    let svc = RouterService::new(router);
+    let get = |path: &str| {
+        let req = Request::get(path).body(Body::empty()).unwrap();
+        svc.clone().oneshot(req)
+    };
+
+    let res = get("/assets/public.txt").await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    for path in [
+        "/assets/../secret.txt",
+        "/assets/..%2fsecret.txt",
+        "/assets/%2e%2e/secret.txt",
+        "/assets/%2e%2e%2fsecret.txt",
+        "/assets/a/..%2f..%2fsecret.txt",
+        "/assets/%2e%2e/",
+        "/assets/a/%2e%2e%2f%2e%2e/",
+    ] {
+        let res = get(path).await.unwrap();
+        assert!(res.status().is_client_error(), "{} {}", path, res.status());
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        assert!(
+            !String::from_utf8_lossy(&body).contains("secret"),
+            "{}",
+            path
+        );
+    }
+}
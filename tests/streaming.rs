@@ -0,0 +1,158 @@
+//! The streaming guarantees of [`keiro::streaming`]: responses pass through the router and the
+//! middleware built into keiro chunk by chunk, and responses marked with `must_stream` are
+//! never buffered.
+#![cfg(feature = "compression")]
+
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hyper::body::{Bytes, HttpBody};
+use hyper::header::{HeaderValue, ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING};
+use hyper::{Body, Request, Response};
+use keiro::accounting::{Accounting, Usage};
+use keiro::cache::ResponseCache;
+use keiro::compression::Compression;
+use keiro::sampling::{Sample, Sampler};
+use keiro::{streaming, Router, RouterService};
+use tokio::sync::oneshot;
+use tower::ServiceExt;
+
+type Release = Arc<Mutex<Option<oneshot::Sender<()>>>>;
+
+/// Serve `/events` with a channel body which sends one chunk, then waits for the test to
+/// release it before sending the last one and dropping the sender. Responses which aren't
+/// marked with `must_stream` are `no-store`, as the cache buffers the ones it stores.
+fn service(
+    must_stream: bool,
+    release: Release,
+    calls: Arc<AtomicUsize>,
+    cache: ResponseCache,
+    usages: Arc<Mutex<Vec<Usage>>>,
+    samples: Arc<Mutex<Vec<Sample>>>,
+) -> Accounting<RouterService<keiro::Error, ()>> {
+    let sampler = Sampler::new(move |sample| samples.lock().unwrap().push(sample));
+    sampler.set_rate(1.0);
+
+    let mut router = Router::new();
+    router.compression(Compression::new());
+    router
+        .get("/events", move |_req: Request<Body>| {
+            let release = release.clone();
+            calls.fetch_add(1, Ordering::SeqCst);
+            async move {
+                let (released, wait) = oneshot::channel();
+                *release.lock().unwrap() = Some(released);
+                let (mut sender, body) = Body::channel();
+                tokio::spawn(async move {
+                    sender.send_data(Bytes::from("data: first\n\n")).await?;
+                    let _ = wait.await;
+                    sender.send_data(Bytes::from("data: last\n\n")).await
+                });
+                let mut res = Response::new(body);
+                if must_stream {
+                    streaming::must_stream(&mut res);
+                } else {
+                    res.headers_mut()
+                        .insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+                }
+                Ok::<_, Infallible>(res)
+            }
+        })
+        .response_cache(cache)
+        .sample(&sampler);
+    Accounting::new(RouterService::new(router), move |usage| {
+        usages.lock().unwrap().push(usage)
+    })
+}
+
+fn request(gzip: bool) -> Request<Body> {
+    let mut req = Request::get("/events").body(Body::empty()).unwrap();
+    if gzip {
+        req.headers_mut()
+            .insert(ACCEPT_ENCODING, "gzip".parse().unwrap());
+    }
+    req
+}
+
+/// Read the first chunk, which must arrive while the handler is still producing the body.
+async fn first_chunk(body: &mut Body) -> Bytes {
+    tokio::time::timeout(Duration::from_secs(5), body.data())
+        .await
+        .expect("the first chunk was buffered")
+        .expect("the body ended early")
+        .unwrap()
+}
+
+async fn rest(mut body: Body, release: &Release) -> Vec<u8> {
+    let released = release.lock().unwrap().take().unwrap();
+    released.send(()).unwrap();
+    let mut rest = Vec::new();
+    while let Some(chunk) = body.data().await {
+        rest.extend_from_slice(&chunk.unwrap());
+    }
+    rest
+}
+
+#[tokio::test]
+async fn uncached_responses_stream_unchanged() {
+    let release = Release::default();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let cache = ResponseCache::new(Duration::from_secs(60));
+    let usages = Arc::default();
+    let samples = Arc::default();
+    let svc = service(
+        false,
+        release.clone(),
+        calls,
+        cache,
+        Arc::clone(&usages),
+        Arc::clone(&samples),
+    );
+
+    let res = svc.oneshot(request(false)).await.unwrap();
+    let mut body = res.into_body();
+    assert_eq!(first_chunk(&mut body).await, "data: first\n\n");
+    assert!(usages.lock().unwrap().is_empty());
+    assert!(samples.lock().unwrap().is_empty());
+
+    assert_eq!(rest(body, &release).await, b"data: last\n\n");
+    let usages = usages.lock().unwrap();
+    assert_eq!(usages.len(), 1);
+    assert!(usages[0].response_bytes >= 26);
+    let samples = samples.lock().unwrap();
+    assert_eq!(samples.len(), 1);
+    assert_eq!(samples[0].response_body, "data: first\n\ndata: last\n\n");
+}
+
+#[tokio::test]
+async fn must_stream_responses_are_never_buffered() {
+    let release = Release::default();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let cache = ResponseCache::new(Duration::from_secs(60));
+    let usages = Arc::default();
+    let samples = Arc::default();
+    let svc = service(
+        true,
+        release.clone(),
+        Arc::clone(&calls),
+        cache.clone(),
+        Arc::clone(&usages),
+        Arc::clone(&samples),
+    );
+
+    for expected_calls in 1..=2 {
+        let res = svc.clone().oneshot(request(true)).await.unwrap();
+        assert_eq!(res.headers()[CONTENT_ENCODING], "gzip");
+        let mut body = res.into_body();
+        // Compressed chunks are flushed as they are produced.
+        assert!(!first_chunk(&mut body).await.is_empty());
+        assert!(!rest(body, &release).await.is_empty());
+        // The response isn't cached, so the handler is called again.
+        assert!(cache.is_empty());
+        assert_eq!(calls.load(Ordering::SeqCst), expected_calls);
+    }
+    assert_eq!(usages.lock().unwrap().len(), 2);
+    assert_eq!(samples.lock().unwrap().len(), 2);
+}
@@ -0,0 +1,106 @@
+//! Rejected requests answered slowly by a [`keiro::tarpit::Tarpit`].
+
+use std::convert::Infallible;
+use std::time::{Duration, Instant};
+
+use hyper::header::{CONTENT_LENGTH, WWW_AUTHENTICATE};
+use hyper::{Body, Request, Response, StatusCode};
+use keiro::basic_auth::BasicAuth;
+use keiro::probe::ProbeFilter;
+use keiro::tarpit::Tarpit;
+use keiro::{Router, RouterService};
+use tower::ServiceExt;
+
+/// A tarpit sending `.` three times, 20 milliseconds apart.
+fn tarpit() -> Tarpit {
+    Tarpit::new()
+        .interval(Duration::from_millis(20))
+        .duration(Duration::from_millis(60))
+        .chunk(".")
+}
+
+async fn index(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    Ok(Response::new(Body::from("Hello keiro!")))
+}
+
+async fn send(router: Router, req: Request<Body>) -> Response<Body> {
+    RouterService::new(router).oneshot(req).await.unwrap()
+}
+
+/// Read the body of `res`, returning it and how long it took.
+async fn read(res: Response<Body>) -> (Vec<u8>, Duration) {
+    let started = Instant::now();
+    let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+    (body.to_vec(), started.elapsed())
+}
+
+#[tokio::test]
+async fn probes_are_dripped_with_the_status_of_the_filter() {
+    let mut router = Router::new();
+    router.get("/", index);
+    router.probe_filter(
+        ProbeFilter::default()
+            .status(StatusCode::from_u16(444).unwrap())
+            .tarpit(tarpit()),
+    );
+
+    let req = Request::get("/wp-login.php").body(Body::empty()).unwrap();
+    let res = send(router, req).await;
+    assert_eq!(res.status().as_u16(), 444);
+    assert!(!res.headers().contains_key(CONTENT_LENGTH));
+    let (body, elapsed) = read(res).await;
+    assert_eq!(body, b"...");
+    assert!(elapsed >= Duration::from_millis(60));
+}
+
+#[tokio::test]
+async fn authentication_failures_of_routes_are_dripped() {
+    let mut router = Router::new();
+    router
+        .get("/admin", index)
+        .basic_auth(BasicAuth::users("admin", vec![("admin", "secret")]))
+        .tarpit(tarpit());
+
+    let req = Request::get("/admin").body(Body::empty()).unwrap();
+    let res = send(router, req).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(res.headers().contains_key(WWW_AUTHENTICATE));
+    let (body, elapsed) = read(res).await;
+    assert_eq!(body, b"...");
+    assert!(elapsed >= Duration::from_millis(60));
+}
+
+#[tokio::test]
+async fn authenticated_requests_are_answered_normally() {
+    let mut router = Router::new();
+    router.tarpit(tarpit());
+    router
+        .get("/admin", index)
+        .basic_auth(BasicAuth::users("admin", vec![("admin", "secret")]));
+
+    // `admin:secret`
+    let req = Request::get("/admin")
+        .header("authorization", "Basic YWRtaW46c2VjcmV0")
+        .body(Body::empty())
+        .unwrap();
+    let res = send(router, req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(read(res).await.0, b"Hello keiro!");
+}
+
+#[tokio::test]
+async fn tarpits_answer_immediately_once_full() {
+    let tarpit = tarpit().max_concurrent(1);
+    let mut router = Router::new();
+    router.probe_filter(ProbeFilter::default().tarpit(tarpit));
+    let router = RouterService::new(router);
+    let req = || Request::get("/.env").body(Body::empty()).unwrap();
+
+    let held = router.clone().oneshot(req()).await.unwrap();
+    let res = router.clone().oneshot(req()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(read(res).await.0, b"");
+    drop(held);
+    let res = router.oneshot(req()).await.unwrap();
+    assert_eq!(read(res).await.0, b"...");
+}